@@ -1,5 +1,7 @@
 use libipt_sys::{pt_asid, pt_asid_no_cr3 as NO_CR3, pt_asid_no_vmcs as NO_VMCS};
-use std::mem;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem;
 
 #[cfg(test)]
 mod test {
@@ -12,10 +14,19 @@ mod test {
         assert_eq!(asid.vmcs(), Some(2));
 
         // -1 because max would be NO_CR3
-        asid.set_cr3(std::u64::MAX - 1);
-        asid.set_vmcs(std::i64::MAX as u64);
-        assert_eq!(asid.cr3(), Some(std::u64::MAX - 1));
-        assert_eq!(asid.vmcs(), Some(std::i64::MAX as u64));
+        asid.set_cr3(u64::MAX - 1);
+        asid.set_vmcs(i64::MAX as u64);
+        assert_eq!(asid.cr3(), Some(u64::MAX - 1));
+        assert_eq!(asid.vmcs(), Some(i64::MAX as u64));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_asid_serde() {
+        let json = serde_json::to_string(&Asid::new(Some(0x1000), None)).unwrap();
+        assert_eq!(json, r#"{"cr3":4096,"vmcs":null}"#);
+        let asid: Asid = serde_json::from_str(&json).unwrap();
+        assert_eq!(asid, Asid::new(Some(0x1000), None));
     }
 
     #[test]
@@ -52,6 +63,15 @@ mod test {
         assert_ne!(asid, asid2);
     }
 
+    #[test]
+    fn test_asid_matches() {
+        let any: Asid = Default::default();
+        assert!(any.matches(Asid::new(Some(1), Some(2))));
+        assert!(Asid::new(Some(1), None).matches(Asid::new(Some(1), Some(2))));
+        assert!(!Asid::new(Some(1), None).matches(Asid::new(Some(3), Some(2))));
+        assert!(!Asid::new(Some(1), Some(4)).matches(Asid::new(Some(1), Some(2))));
+    }
+
     #[test]
     fn test_asid_from() {
         let asid: Asid = Default::default();
@@ -66,6 +86,19 @@ mod test {
         assert_eq!(asid2.cr3(), Some(666));
         assert_eq!(raw.cr3, NO_CR3);
     }
+
+    #[test]
+    fn test_asid_builder_display() {
+        let asid = AsidBuilder::new().cr3(0x1000).finish();
+        assert_eq!(asid, Asid::new(Some(0x1000), None));
+        assert_eq!(asid.to_string(), "cr3=0x1000, vmcs=any");
+
+        let mut asid = AsidBuilder::new().cr3(1).vmcs(0x2000).finish();
+        assert_eq!(asid.to_string(), "cr3=0x1, vmcs=0x2000");
+        asid.set_cr3(Asid::NO_CR3);
+        assert_eq!(asid.cr3(), None);
+        assert_eq!(Asid::default().to_string(), "cr3=any, vmcs=any");
+    }
 }
 
 /// An Intel PT address space identifier.
@@ -73,8 +106,18 @@ mod test {
 /// This identifies a particular address space when adding file sections or
 /// when reading memory.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "AsidRepr", into = "AsidRepr")
+)]
 pub struct Asid(pub(crate) pt_asid);
 impl Asid {
+    /// The cr3 value of an address space that matches any cr3
+    pub const NO_CR3: u64 = NO_CR3;
+    /// The vmcs value of an address space that matches any vmcs
+    pub const NO_VMCS: u64 = NO_VMCS;
+
     #[inline]
     pub fn new(cr3: Option<u64>, vmcs: Option<u64>) -> Self {
         Asid(pt_asid {
@@ -91,6 +134,8 @@ impl Asid {
     }
 
     /// The CR3 value.
+    ///
+    /// NO_CR3 clears it.
     #[inline]
     pub fn set_cr3(&mut self, cr3: u64) { self.0.cr3 = cr3 }
 
@@ -101,8 +146,19 @@ impl Asid {
     }
 
     /// The VMCS Base address.
+    ///
+    /// NO_VMCS clears it.
     #[inline]
     pub fn set_vmcs(&mut self, vmcs: u64) { self.0.vmcs = vmcs }
+
+    /// Check if two address spaces may be the same.
+    ///
+    /// Like libipt, only the fields that are valid in both are compared.
+    #[inline]
+    pub fn matches(self, other: Asid) -> bool {
+        let same = |a: Option<u64>, b: Option<u64>| a.zip(b).map(|(a, b)| a == b) != Some(false);
+        same(self.cr3(), other.cr3()) && same(self.vmcs(), other.vmcs())
+    }
 }
 
 impl Default for Asid {
@@ -118,3 +174,68 @@ impl PartialEq for Asid {
         self.cr3() == other.cr3() && self.vmcs() == other.vmcs()
     }
 }
+
+impl Eq for Asid {}
+
+impl Hash for Asid {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cr3().hash(state);
+        self.vmcs().hash(state);
+    }
+}
+
+impl fmt::Display for Asid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cr3() {
+            Some(cr3) => write!(f, "cr3={:#x}", cr3)?,
+            None => f.write_str("cr3=any")?,
+        }
+        match self.vmcs() {
+            Some(vmcs) => write!(f, ", vmcs={:#x}", vmcs),
+            None => f.write_str(", vmcs=any"),
+        }
+    }
+}
+
+// an asid is serialized as its cr3 and vmcs values, None matches any
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct AsidRepr {
+    cr3: Option<u64>,
+    vmcs: Option<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl From<AsidRepr> for Asid {
+    fn from(r: AsidRepr) -> Self { Asid::new(r.cr3, r.vmcs) }
+}
+
+#[cfg(feature = "serde")]
+impl From<Asid> for AsidRepr {
+    fn from(asid: Asid) -> Self { AsidRepr { cr3: asid.cr3(), vmcs: asid.vmcs() } }
+}
+
+/// Builds an `Asid` from the values that are known
+///
+/// Fields that are not set match any address space.
+#[derive(Default)]
+pub struct AsidBuilder(Asid);
+impl AsidBuilder {
+    pub fn new() -> Self { AsidBuilder(Asid::default()) }
+
+    /// The CR3 value.
+    #[inline]
+    pub fn cr3(&mut self, cr3: u64) -> &mut Self {
+        self.0.set_cr3(cr3);
+        self
+    }
+
+    /// The VMCS Base address.
+    #[inline]
+    pub fn vmcs(&mut self, vmcs: u64) -> &mut Self {
+        self.0.set_vmcs(vmcs);
+        self
+    }
+
+    pub fn finish(&self) -> Asid { self.0 }
+}
@@ -1,7 +1,13 @@
 use crate::insn::Class;
 use crate::event::ExecModeType;
-use std::convert::TryFrom;
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use libipt_sys::pt_block;
+#[cfg(feature = "serde")]
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use libipt_sys::{pt_exec_mode, pt_insn_class};
 
 #[cfg(test)]
 mod test {
@@ -38,6 +44,59 @@ mod test {
        assert!(!blk.speculative());
     }
 
+    #[test]
+    fn test_block_eq() {
+        let blk = |raw| Block(pt_block {
+            ip: 1,
+            end_ip: 2,
+            isid: 3,
+            mode: pt_exec_mode_ptem_32bit,
+            iclass: pt_insn_class_ptic_error,
+            ninsn: 4,
+            raw,
+            size: 2,
+            _bitfield_1: pt_block::new_bitfield_1(0, 1),
+            __bindgen_padding_0: Default::default()
+        });
+
+        // only the bytes of the truncated instruction count
+        let mut other = [17; 15];
+        other[2] = 0;
+        assert_eq!(blk([17; 15]), blk(other));
+        other[1] = 0;
+        assert_ne!(blk([17; 15]), blk(other));
+
+        let blocks = [blk([17; 15]), blk([17; 15]), blk(other)];
+        assert_eq!(blocks.into_iter().collect::<std::collections::HashSet<_>>().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_block_serde() {
+        let blk = Block(pt_block {
+            ip: 1,
+            end_ip: 2,
+            isid: 3,
+            mode: pt_exec_mode_ptem_32bit,
+            iclass: pt_insn_class_ptic_error,
+            ninsn: 4,
+            raw: [17; 15],
+            size: 2,
+            _bitfield_1: pt_block::new_bitfield_1(0, 1),
+            __bindgen_padding_0: Default::default()
+        });
+
+        let json = serde_json::to_string(&blk).unwrap();
+        assert_eq!(json, r#"{"ip":1,"end_ip":2,"isid":3,"mode":"Bit32","class":"Error","ninsn":4,"raw":[17,17],"speculative":false,"truncated":true}"#);
+        let blk: Block = serde_json::from_str(&json).unwrap();
+        assert_eq!(blk.raw(), [17, 17]);
+        assert_eq!(blk.mode(), ExecModeType::Bit32);
+        assert!(blk.truncated());
+
+        let long = json.replace("[17,17]", &format!("{:?}", [0; 16]).replace(' ', ""));
+        assert!(serde_json::from_str::<Block>(&long).is_err());
+    }
+
     #[test]
     fn test_block_notruncate() {
         let data: [u8; 15] = [17; 15];
@@ -71,7 +130,12 @@ mod test {
 /// Instructions in this block are executed sequentially but are not necessarily
 /// contiguous in memory.  Users are expected to follow direct branches.
 #[derive(Clone, Copy)]
-pub struct Block(pub(super) pt_block);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "BlockRepr", into = "BlockRepr")
+)]
+pub struct Block(pub(crate) pt_block);
 impl Block {
     /// The IP of the first instruction in this block.
     pub fn ip(&self) -> u64 { self.0.ip }
@@ -131,3 +195,105 @@ impl Block {
     /// its size in \@size in this case.
     pub fn truncated(&self) -> bool { self.0.truncated() > 0 }
 }
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Block")
+            .field("ip", &self.ip())
+            .field("end_ip", &self.end_ip())
+            .field("isid", &self.isid())
+            .field("mode", &self.mode())
+            .field("class", &self.class())
+            .field("ninsn", &self.ninsn())
+            .field("raw", &self.raw())
+            .field("speculative", &self.speculative())
+            .field("truncated", &self.truncated())
+            .finish()
+    }
+}
+
+// blocks are equal if all their getters are, like in `Debug`
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        self.ip() == other.ip()
+            && self.end_ip() == other.end_ip()
+            && self.isid() == other.isid()
+            && self.mode() == other.mode()
+            && self.class() == other.class()
+            && self.ninsn() == other.ninsn()
+            && self.raw() == other.raw()
+            && self.speculative() == other.speculative()
+            && self.truncated() == other.truncated()
+    }
+}
+
+impl Eq for Block {}
+
+impl Hash for Block {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ip().hash(state);
+        self.end_ip().hash(state);
+        self.isid().hash(state);
+        self.mode().hash(state);
+        self.class().hash(state);
+        self.ninsn().hash(state);
+        self.raw().hash(state);
+        self.speculative().hash(state);
+        self.truncated().hash(state);
+    }
+}
+
+// a block is serialized as the values of its getters
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BlockRepr {
+    ip: u64,
+    end_ip: u64,
+    isid: i32,
+    mode: ExecModeType,
+    class: Class,
+    ninsn: u16,
+    raw: Vec<u8>,
+    speculative: bool,
+    truncated: bool,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BlockRepr> for Block {
+    type Error = &'static str;
+
+    fn try_from(r: BlockRepr) -> Result<Self, Self::Error> {
+        let mut blk: pt_block = unsafe { core::mem::zeroed() };
+        if r.raw.len() > blk.raw.len() {
+            return Err("the raw instruction bytes are too long");
+        }
+        blk.ip = r.ip;
+        blk.end_ip = r.end_ip;
+        blk.isid = r.isid;
+        blk.mode = r.mode as pt_exec_mode;
+        blk.iclass = r.class as pt_insn_class;
+        blk.ninsn = r.ninsn;
+        blk.raw[..r.raw.len()].copy_from_slice(&r.raw);
+        blk.size = r.raw.len() as u8;
+        blk.set_speculative(r.speculative as u32);
+        blk.set_truncated(r.truncated as u32);
+        Ok(Block(blk))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Block> for BlockRepr {
+    fn from(blk: Block) -> Self {
+        BlockRepr {
+            ip: blk.ip(),
+            end_ip: blk.end_ip(),
+            isid: blk.isid(),
+            mode: blk.mode(),
+            class: blk.class(),
+            ninsn: blk.ninsn(),
+            raw: blk.raw().to_vec(),
+            speculative: blk.speculative(),
+            truncated: blk.truncated(),
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use super::{Block, Blocks, Decoded};
+use crate::error::DecodeError;
+use crate::event::{Event, Payload};
+use crate::insn::Class;
+use alloc::collections::VecDeque;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{BlockDecoder, OverflowPolicy};
+    use crate::config::{BlockFlags, ConfigBuilder};
+    use crate::image::Image;
+    use crate::packet::{self, Compression, Encoder, Exec, Fup, Mode, Psb, Psbend, Tip, Tnt8};
+    use alloc::vec::Vec;
+    use super::super::test::block;
+    use libipt_sys::{
+        pt_event, pt_event__bindgen_ty_1__bindgen_ty_4, pt_event_type_ptev_async_branch,
+        pt_insn_class_ptic_call, pt_insn_class_ptic_far_return, pt_insn_class_ptic_other,
+        pt_insn_class_ptic_return,
+    };
+    use core::mem;
+
+    fn interrupt(from: u64, to: u64) -> Event {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_async_branch;
+        evt.variant.async_branch = pt_event__bindgen_ty_1__bindgen_ty_4 { from, to };
+        Event(evt)
+    }
+
+    #[test]
+    fn test_calls_and_returns() {
+        let mut t = CallStackTracker::new();
+        assert_eq!(t.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 1)), 0);
+        assert_eq!(t.block(&block(0x2000, 0x2008, pt_insn_class_ptic_call, 1)), 1);
+        assert_eq!(t.depth(), 2);
+        assert_eq!(t.current().unwrap().entry, None);
+        assert_eq!(t.block(&block(0x3000, 0x3004, pt_insn_class_ptic_return, 1)), 2);
+        assert_eq!(t.current().unwrap().entry, Some(0x3000));
+
+        let frame = t.frames()[0];
+        assert_eq!(frame.kind, FrameKind::Call);
+        assert_eq!(frame.from, 0x1010);
+        assert_eq!(frame.entry, Some(0x2000));
+        // the length of the call is only known for truncated blocks
+        assert_eq!(frame.return_ip, None);
+        assert!(frame.returns_to(0x1015));
+        assert!(!frame.returns_to(0x1010));
+        assert!(!frame.returns_to(0x1020));
+
+        // returns to 0x200d in the first callee
+        assert_eq!(t.block(&block(0x200d, 0x2010, pt_insn_class_ptic_return, 1)), 1);
+        assert_eq!(t.block(&block(0x1015, 0x1020, pt_insn_class_ptic_other, 1)), 0);
+        assert_eq!(t.underflows(), 0);
+
+        // the trace started inside a function
+        t.block(&block(0x1020, 0x1024, pt_insn_class_ptic_return, 1));
+        assert_eq!(t.block(&block(0x500, 0x510, pt_insn_class_ptic_other, 1)), 0);
+        assert_eq!(t.underflows(), 1);
+    }
+
+    #[test]
+    fn test_unwind() {
+        let mut t = CallStackTracker::new();
+        t.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 1));
+        t.block(&block(0x2000, 0x2010, pt_insn_class_ptic_call, 1));
+        t.block(&block(0x3000, 0x3010, pt_insn_class_ptic_call, 1));
+        // e.g. a longjmp back into the first function
+        t.block(&block(0x4000, 0x4004, pt_insn_class_ptic_return, 1));
+        assert_eq!(t.block(&block(0x1015, 0x1020, pt_insn_class_ptic_other, 1)), 0);
+
+        t.gap();
+        t.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 1));
+        assert_eq!(t.depth(), 1);
+    }
+
+    #[test]
+    fn test_async() {
+        let mut t = CallStackTracker::new().with_max_depth(2);
+        t.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 1));
+        t.event(&interrupt(0x2004, 0xf000));
+        assert_eq!(t.current().unwrap().kind, FrameKind::Async);
+        assert_eq!(t.block(&block(0xf000, 0xf010, pt_insn_class_ptic_far_return, 1)), 2);
+        assert_eq!(t.block(&block(0x2004, 0x2010, pt_insn_class_ptic_call, 1)), 1);
+
+        // the oldest frame is dropped
+        assert_eq!(t.depth(), 2);
+        t.block(&block(0x3000, 0x3010, pt_insn_class_ptic_call, 1));
+        assert_eq!(t.depth(), 2);
+        assert_eq!(t.frames()[0].from, 0x2010);
+    }
+
+    // the code at 0x1000 calls 0x2000, which calls 0x3000,
+    // which returns straight to the jz loop after the first call
+    fn nested_code(mem: &mut [u8], ip: u64) -> i32 {
+        let (base, code): (u64, &[u8]) = match ip {
+            0x1000..=0x1006 => (0x1000, &[0xe8, 0xfb, 0x0f, 0, 0, 0x74, 0xfe]),
+            0x2000..=0x2004 => (0x2000, &[0xe8, 0xfb, 0x0f, 0, 0]),
+            0x3000 => (0x3000, &[0xc3]),
+            _ => return 0,
+        };
+        let code = &code[(ip - base) as usize..];
+        let len = code.len().min(mem.len());
+        mem[..len].copy_from_slice(&code[..len]);
+        len as i32
+    }
+
+    #[test]
+    fn test_decoded_unwind() {
+        let buf = &mut [0; 64];
+        let size = {
+            let mut cfg = ConfigBuilder::new(buf).unwrap().finish();
+            let mut enc = Encoder::new(&mut cfg).unwrap();
+            let mut size = enc.next(Psb::new()).unwrap();
+            size += enc.next(Mode::new(packet::Payload::Exec(Exec::CSL))).unwrap();
+            size += enc.next(Fup::new(0x1000, Compression::Sext48)).unwrap();
+            size += enc.next(Psbend::new()).unwrap();
+            size += enc.next(Tip::new(0x1005, Compression::Sext48)).unwrap();
+            size += enc.next(Tnt8::new(1, 1)).unwrap();
+            size as usize
+        };
+        let mut img = Image::new(None).unwrap();
+        img.set_callback(Some(|mem: &mut [u8], ip: u64, _| nested_code(mem, ip)))
+            .unwrap();
+
+        let cfg = ConfigBuilder::new(&mut buf[..size])
+            .unwrap()
+            .flags(BlockFlags::END_ON_CALL)
+            .finish();
+        let mut b = BlockDecoder::new(&cfg).unwrap();
+        b.set_image(Some(&mut img)).unwrap();
+        b.sync_forward().unwrap();
+
+        let mut stacks = b.blocks(OverflowPolicy::Gap).call_stacks();
+        let mut depths = Vec::new();
+        for item in &mut stacks {
+            if let (Decoded::Block(blk, _), depth) = item.unwrap() {
+                depths.push((blk.ip(), depth));
+            }
+        }
+        assert_eq!(depths, [(0x1000, 0), (0x2000, 1), (0x3000, 2), (0x1005, 0)]);
+        // both frames are gone, though the return didn't come from the inner one
+        assert_eq!(stacks.tracker().depth(), 0);
+        assert_eq!(stacks.tracker().underflows(), 0);
+    }
+}
+
+/// How a frame of a `CallStackTracker` was entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameKind {
+    /// A near call
+    Call,
+    /// A call-like far transfer, e.g. SYSCALL
+    FarCall,
+    /// An asynchronous transfer, e.g. an interrupt or a fault
+    Async,
+}
+
+/// A frame on the shadow stack of a `CallStackTracker`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StackFrame {
+    /// How the frame was entered
+    pub kind: FrameKind,
+    /// The address of the call or the interrupted instruction
+    pub from: u64,
+    /// The address the frame was entered at, e.g. the called function.
+    ///
+    /// None if it is not known (yet).
+    pub entry: Option<u64>,
+    /// The address execution continues at when the frame returns, if known.
+    ///
+    /// libipt only provides the length of the last instruction
+    /// of truncated blocks, so this is usually None for calls.
+    pub return_ip: Option<u64>,
+}
+
+// the longest x86 instruction, libipt's pt_max_insn_size
+const MAX_INSN_SIZE: u64 = 15;
+
+impl StackFrame {
+    /// Does a return to @ip return from this frame?
+    ///
+    /// Without a @return_ip, any address after @from that is within
+    /// the maximum length of the call instruction matches.
+    pub fn returns_to(&self, ip: u64) -> bool {
+        match self.return_ip {
+            Some(r) => r == ip,
+            None => ip > self.from && ip - self.from <= MAX_INSN_SIZE,
+        }
+    }
+}
+
+/// Reconstructs the call stack from the decoded blocks
+///
+/// Calls push a frame onto a shadow stack, returns pop it again.
+/// A return is matched against the return addresses on the stack,
+/// or, where libipt doesn't tell the length of the call, against the
+/// range of addresses right after it, see `StackFrame::returns_to`.
+/// So frames that are left without a return, e.g. by a longjmp or an
+/// exception, are removed once a frame further up returns.
+/// Returns that don't match any frame remove the innermost one.
+/// Returns beyond the start of the trace leave the stack empty
+/// and are counted as underflows.
+///
+/// Interrupts and other asynchronous branches push an `Async` frame if
+/// their destination is traced, it is popped by the far return
+/// that resumes the interrupted code.
+/// A call into code that isn't traced pushes no frame.
+/// Overflows keep the stack, since the stack is repaired by the
+/// returns after the overflow.
+///
+/// A block may continue at the target of a direct call,
+/// set `BlockFlags::END_ON_CALL` to see every call.
+#[derive(Debug, Clone)]
+pub struct CallStackTracker {
+    frames: VecDeque<StackFrame>,
+    max_depth: usize,
+    underflows: u64,
+    // the top frame was pushed by the last block, its entry is the next ip
+    entering: bool,
+    // the last block returned, the next ip tells where to
+    returning: bool,
+}
+
+impl Default for CallStackTracker {
+    fn default() -> Self {
+        CallStackTracker::new()
+    }
+}
+
+impl CallStackTracker {
+    /// An empty call stack
+    pub fn new() -> Self {
+        CallStackTracker {
+            frames: VecDeque::new(),
+            max_depth: 4096,
+            underflows: 0,
+            entering: false,
+            returning: false,
+        }
+    }
+
+    /// Keep at most @max_depth frames, 4096 by default.
+    ///
+    /// The outermost frames are dropped first,
+    /// e.g. for deep recursion or calls that never return.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.max(1);
+        self
+    }
+
+    /// The number of frames on the stack
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The frames on the stack, the innermost one last
+    pub fn frames(&self) -> &VecDeque<StackFrame> {
+        &self.frames
+    }
+
+    /// The innermost frame, if there is one
+    pub fn current(&self) -> Option<&StackFrame> {
+        self.frames.back()
+    }
+
+    /// The number of returns that didn't have a frame to return from
+    pub fn underflows(&self) -> u64 {
+        self.underflows
+    }
+
+    /// Update the stack with @block.
+    ///
+    /// Returns the depth @block executed at.
+    pub fn block(&mut self, block: &Block) -> usize {
+        self.arrive(block.ip());
+        let depth = self.depth();
+
+        let from = block.end_ip();
+        let return_ip = block.truncated().then(|| from + block.raw().len() as u64);
+        match block.class() {
+            Class::Call => self.push(FrameKind::Call, from, None, return_ip),
+            Class::FarCall => self.push(FrameKind::FarCall, from, None, return_ip),
+            Class::Return | Class::FarReturn => self.returning = true,
+            _ => (),
+        }
+        depth
+    }
+
+    /// Update the stack with @evt.
+    ///
+    /// Handles asynchronous branches, overflows and tracing being disabled.
+    pub fn event(&mut self, evt: &Event) {
+        match evt.payload() {
+            Payload::AsyncBranch(_) => {
+                let t = evt.async_transfer().unwrap();
+                self.arrive(t.from);
+                // the handler isn't traced and returns before tracing resumes
+                if t.to.is_some() {
+                    self.push(FrameKind::Async, t.from, t.to, Some(t.from));
+                }
+            }
+            Payload::Disabled(_) | Payload::AsyncDisabled(_) if self.entering => {
+                // called into code that isn't traced
+                self.frames.pop_back();
+                self.entering = false;
+            }
+            Payload::Overflow(_) => self.gap(),
+            _ => (),
+        }
+    }
+
+    /// Handle a gap in the trace, see `Decoded::Gap`.
+    pub fn gap(&mut self) {
+        self.entering = false;
+        self.returning = false;
+    }
+
+    // updates the stack with a decoded block, event or gap
+    // and returns the depth afterwards
+    pub(super) fn decoded(&mut self, item: &Decoded) -> usize {
+        match item {
+            Decoded::Block(b, _) => return self.block(b),
+            Decoded::Event(evt) => self.event(evt),
+            Decoded::Gap(_) => self.gap(),
+        }
+        self.depth()
+    }
+
+    /// Forget all frames, e.g. when switching to another thread.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.gap();
+    }
+
+    #[inline]
+    fn push(&mut self, kind: FrameKind, from: u64, entry: Option<u64>, return_ip: Option<u64>) {
+        if self.frames.len() >= self.max_depth {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(StackFrame {
+            kind,
+            from,
+            entry,
+            return_ip,
+        });
+        self.entering = entry.is_none();
+    }
+
+    // execution continues at @ip
+    fn arrive(&mut self, ip: u64) {
+        if self.entering {
+            self.entering = false;
+            if let Some(top) = self.frames.back_mut() {
+                top.entry = Some(ip);
+            }
+        }
+
+        if self.returning {
+            self.returning = false;
+            match self.frames.iter().rposition(|f| f.returns_to(ip)) {
+                Some(i) => self.frames.truncate(i),
+                None if self.frames.pop_back().is_none() => self.underflows += 1,
+                None => (),
+            }
+        }
+    }
+}
+
+/// An iterator over the items of a `Blocks` iterator
+/// together with their call stack depth
+///
+/// The depth of an event is the depth after the preceding block.
+/// The frames of the current call stack are available through `tracker`
+/// in between items.
+/// See `CallStackTracker`.
+pub struct CallStacks<I> {
+    items: I,
+    tracker: CallStackTracker,
+}
+
+impl<I> CallStacks<I>
+where
+    I: Iterator<Item = Result<Decoded, DecodeError>>,
+{
+    /// Track the call stack of @items with @tracker.
+    pub fn new(items: I, tracker: CallStackTracker) -> Self {
+        CallStacks { items, tracker }
+    }
+
+    /// The call stack after the last item
+    pub fn tracker(&self) -> &CallStackTracker {
+        &self.tracker
+    }
+}
+
+impl<I> Iterator for CallStacks<I>
+where
+    I: Iterator<Item = Result<Decoded, DecodeError>>,
+{
+    type Item = Result<(Decoded, usize), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = match self.items.next()? {
+            Ok(item) => item,
+            Err(e) => return Some(Err(e)),
+        };
+        let depth = self.tracker.decoded(&item);
+        Some(Ok((item, depth)))
+    }
+}
+
+impl<'d, 'a, T> Blocks<'d, 'a, T> {
+    /// Track the call stack of the decoded blocks.
+    ///
+    /// See `CallStacks`.
+    pub fn call_stacks(self) -> CallStacks<Self> {
+        CallStacks::new(self, CallStackTracker::new())
+    }
+}
@@ -0,0 +1,193 @@
+use super::{Block, BlockDecoder, Blocks, Decoded, OverflowPolicy};
+use crate::error::DecodeError;
+use crate::event::{Event, Payload};
+use crate::flags::Status;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new(20);
+        assert_eq!(clock.tick(100, 0, 0).cycles, None);
+        assert_eq!(
+            clock.tick(120, 0, 0),
+            BlockCost {
+                cycles: Some(20),
+                lost_mtc: 0,
+                lost_cyc: 0
+            }
+        );
+
+        clock.cbr(40);
+        let cost = clock.tick(130, 1, 0);
+        assert_eq!(cost.cycles, Some(20));
+        assert_eq!(cost.lost_mtc, 1);
+        assert!(!cost.exact());
+        assert!(clock.tick(140, 1, 0).exact());
+
+        clock.gap();
+        assert_eq!(clock.tick(1000, 1, 0).cycles, None);
+    }
+}
+
+/// The estimated cost of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCost {
+    /// The core cycles since the previous block.
+    ///
+    /// None for the first block after synchronizing or a gap in the trace.
+    pub cycles: Option<u64>,
+    /// The number of MTC packets lost since the previous block
+    pub lost_mtc: u32,
+    /// The number of CYC packets lost since the previous block
+    pub lost_cyc: u32,
+}
+
+impl BlockCost {
+    /// Returns true if the cycles are known and no timing packets were lost.
+    pub fn exact(&self) -> bool {
+        self.cycles.is_some() && self.lost_mtc == 0 && self.lost_cyc == 0
+    }
+}
+
+// converts the decoder time into core cycles
+pub(super) struct Clock {
+    nom: u8,
+    cbr: Option<u16>,
+    // the time and lost packet counts at the previous block
+    last: Option<(u64, u32, u32)>,
+}
+
+impl Clock {
+    pub(super) fn new(nom: u8) -> Self {
+        Clock {
+            nom,
+            cbr: None,
+            last: None,
+        }
+    }
+
+    pub(super) fn cbr(&mut self, ratio: u16) {
+        self.cbr = Some(ratio);
+    }
+
+    pub(super) fn gap(&mut self) {
+        self.last = None;
+    }
+
+    // cbr events update the ratio, disabled tracing is a gap
+    pub(super) fn event(&mut self, evt: &Event) {
+        match evt.payload() {
+            Payload::Cbr(c) => self.cbr(c.ratio()),
+            Payload::Disabled(_) | Payload::AsyncDisabled(_) => self.gap(),
+            _ => (),
+        }
+    }
+
+    // a block completed at @tsc, with @lost_mtc and @lost_cyc packets lost in total
+    pub(super) fn tick(&mut self, tsc: u64, lost_mtc: u32, lost_cyc: u32) -> BlockCost {
+        let last = self.last.replace((tsc, lost_mtc, lost_cyc));
+        let (cycles, mtc, cyc) = match last {
+            Some((t, m, c)) => {
+                let ticks = tsc.saturating_sub(t);
+                let cycles = match self.cbr {
+                    Some(cbr) if self.nom > 0 => ticks * cbr as u64 / self.nom as u64,
+                    _ => ticks,
+                };
+                (Some(cycles), m, c)
+            }
+            None => (None, lost_mtc, lost_cyc),
+        };
+
+        BlockCost {
+            cycles,
+            lost_mtc: lost_mtc.saturating_sub(mtc),
+            lost_cyc: lost_cyc.saturating_sub(cyc),
+        }
+    }
+}
+
+/// An iterator over the blocks of a block decoder with their estimated cost
+///
+/// The time between two consecutive blocks is attributed to the later block.
+/// The time is measured with the decoder's time stamp count,
+/// which is cycle accurate if the trace contains CYC packets
+/// and the nominal frequency is set in the decoder config.
+/// TSC ticks are converted into core cycles with the core:bus ratio.
+/// Until the ratio is known, a TSC tick counts as one cycle.
+///
+/// Events are consumed, overflows are treated as gaps.
+pub struct Costed<'d, 'a, T> {
+    blocks: Blocks<'d, 'a, T>,
+    clock: Clock,
+}
+
+impl<'d, 'a, T> Costed<'d, 'a, T> {
+    /// Iterate over the blocks of @decoder for a processor
+    /// with the nominal core:bus ratio @nom.
+    pub fn new(decoder: &'d mut BlockDecoder<'a, T>, nom: u8) -> Self {
+        Costed {
+            blocks: Blocks::new(decoder, OverflowPolicy::Gap),
+            clock: Clock::new(nom),
+        }
+    }
+
+    /// Set the core:bus ratio until the next CBR event.
+    ///
+    /// Use this to continue with the ratio of an earlier segment of the trace,
+    /// see `TimeCal`.
+    pub fn cbr(&mut self, ratio: u16) {
+        self.clock.cbr(ratio);
+    }
+}
+
+// the next block of @blocks with its cost, as measured by @clock.
+// the events and gaps in front of it update @clock and are passed to @skipped,
+// this drives `Costed` as well as the profilers
+pub(super) fn next_costed<T>(
+    blocks: &mut Blocks<'_, '_, T>,
+    clock: &mut Clock,
+    mut skipped: impl FnMut(&Decoded),
+) -> Option<Result<(Block, Status, BlockCost), DecodeError>> {
+    loop {
+        let item = match blocks.next()? {
+            Ok(Decoded::Block(block, status)) => {
+                let decoder = blocks.decoder();
+                let time = decoder.time();
+                return Some(
+                    decoder
+                        .locate(time)
+                        .map(|t| (block, status, clock.tick(t.tsc, t.lost_mtc, t.lost_cyc))),
+                );
+            }
+            Ok(item) => item,
+            Err(e) => return Some(Err(e)),
+        };
+        match &item {
+            Decoded::Event(evt) => clock.event(evt),
+            Decoded::Gap(_) => clock.gap(),
+            Decoded::Block(..) => (),
+        }
+        skipped(&item);
+    }
+}
+
+impl<'d, 'a, T> Iterator for Costed<'d, 'a, T> {
+    type Item = Result<(Block, Status, BlockCost), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_costed(&mut self.blocks, &mut self.clock, |_| ())
+    }
+}
+
+impl<'a, T> BlockDecoder<'a, T> {
+    /// Iterate over the decoded blocks with their estimated cost
+    /// on a processor with the nominal core:bus ratio @nom.
+    ///
+    /// See `Costed`.
+    pub fn costed(&mut self, nom: u8) -> Costed<'_, 'a, T> {
+        Costed::new(self, nom)
+    }
+}
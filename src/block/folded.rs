@@ -0,0 +1,211 @@
+use super::cost::{next_costed, Clock};
+use super::profile::Names;
+use super::{Block, BlockDecoder, CallStackTracker, OverflowPolicy};
+use crate::error::PtError;
+use crate::event::Event;
+use crate::symbolize::Symbolizer;
+
+use core::fmt;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::symbolize::Frame;
+    use alloc::string::{String, ToString};
+    use super::super::test::block;
+    use libipt_sys::{
+        pt_insn_class_ptic_call, pt_insn_class_ptic_other, pt_insn_class_ptic_return,
+    };
+
+    fn names(ip: u64) -> Frame {
+        Frame {
+            ip,
+            function: match ip {
+                0x1000..=0x1fff => Some("main".to_string()),
+                0x2000..=0x2fff => Some("<[u8; 4] as Foo>::foo".to_string()),
+                _ => None,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_folded_time() {
+        let mut f = FoldedStacks::new(names, 0);
+        f.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 2), 100);
+        f.block(&block(0x2000, 0x2010, pt_insn_class_ptic_call, 2), 130);
+        f.block(&block(0x2000, 0x2010, pt_insn_class_ptic_return, 2), 140);
+        f.block(&block(0x2015, 0x2020, pt_insn_class_ptic_return, 2), 145);
+        f.block(&block(0x1015, 0x1020, pt_insn_class_ptic_other, 2), 150);
+
+        let mut out = String::new();
+        f.write(&mut out).unwrap();
+        assert_eq!(
+            out,
+            "main 5\n\
+             main;<[u8, 4] as Foo>::foo 35\n\
+             main;<[u8, 4] as Foo>::foo;<[u8, 4] as Foo>::foo 10\n"
+        );
+    }
+
+    #[test]
+    fn test_folded_instructions() {
+        let mut f = FoldedStacks::new(names, 0).with_weight(Weight::Instructions);
+        f.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 2), 0);
+        f.block(&block(0x5000, 0x5010, pt_insn_class_ptic_other, 2), 0);
+
+        let mut out = String::new();
+        f.write(&mut out).unwrap();
+        assert_eq!(out, "main 2\nmain;[unknown] 2\n");
+    }
+}
+
+/// What the counts of `FoldedStacks` measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Weight {
+    /// The estimated time, as described for `Costed`.
+    ///
+    /// In core cycles, or in TSC ticks for a nominal ratio of 0.
+    #[default]
+    Time,
+    /// The number of executed instructions, this needs no timing packets.
+    Instructions,
+}
+
+/// Collects the call stacks of a trace as folded stacks for flame graphs
+///
+/// Every distinct stack is written as one line of the function names
+/// from the outermost to the innermost function, separated by semicolons,
+/// followed by its count, e.g. `main;parse;read 1200`.
+/// This is the input format of flamegraph.pl and inferno.
+/// Semicolons in function names are replaced with commas.
+///
+/// The stacks are reconstructed as described for `CallStackTracker`,
+/// functions are named as described for `Profiler`.
+/// Set `BlockFlags::END_ON_CALL` in the decoder config to see every call.
+pub struct FoldedStacks<S> {
+    clock: Clock,
+    names: Names<S>,
+    stack: CallStackTracker,
+    weight: Weight,
+    counts: BTreeMap<Vec<usize>, u64>,
+    // the functions on the stack, reused between blocks
+    current: Vec<usize>,
+}
+
+impl<S: Symbolizer> FoldedStacks<S> {
+    /// Collect the stacks named with @symbolizer
+    /// for a processor with the nominal core:bus ratio @nom.
+    ///
+    /// This is the nominal frequency of the decoder config.
+    pub fn new(symbolizer: S, nom: u8) -> Self {
+        FoldedStacks {
+            clock: Clock::new(nom),
+            names: Names::new(symbolizer),
+            stack: CallStackTracker::new(),
+            weight: Weight::default(),
+            counts: BTreeMap::new(),
+            current: Vec::new(),
+        }
+    }
+
+    /// Count stacks by @weight instead of time.
+    pub fn with_weight(mut self, weight: Weight) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// The core:bus ratio changed to @ratio.
+    pub fn cbr(&mut self, ratio: u16) {
+        self.clock.cbr(ratio);
+    }
+
+    /// The trace has a gap, e.g. an overflow.
+    ///
+    /// The time until the next block is not counted.
+    pub fn gap(&mut self) {
+        self.clock.gap();
+        self.stack.gap();
+    }
+
+    /// Update the call stack and timing with @evt.
+    pub fn event(&mut self, evt: &Event) {
+        self.clock.event(evt);
+        self.stack.event(evt);
+    }
+
+    /// @block was completed at the time stamp count @tsc.
+    pub fn block(&mut self, block: &Block, tsc: u64) {
+        let time = self.clock.tick(tsc, 0, 0).cycles.unwrap_or(0);
+        self.count(block, time);
+    }
+
+    // adds @block to the call stack and counts its stack, @time is its cost
+    fn count(&mut self, block: &Block, time: u64) {
+        let count = match self.weight {
+            Weight::Time => time,
+            Weight::Instructions => block.ninsn() as u64,
+        };
+        let depth = self.stack.block(block);
+        if count == 0 {
+            return;
+        }
+
+        self.current.clear();
+        for i in 0..depth {
+            let from = self.stack.frames()[i].from;
+            self.current.push(self.names.function(from));
+        }
+        self.current.push(self.names.function(block.ip()));
+        match self.counts.get_mut(&self.current) {
+            Some(c) => *c += count,
+            None => {
+                self.counts.insert(self.current.clone(), count);
+            }
+        }
+    }
+
+    /// The call stack at the last block
+    pub fn stack(&self) -> &CallStackTracker {
+        &self.stack
+    }
+
+    /// The symbolizer, e.g. to reuse its caches for another trace.
+    pub fn symbolizer(&mut self) -> &mut S {
+        self.names.symbolizer()
+    }
+
+    /// Write the folded stacks to @out, one per line.
+    pub fn write<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        for (stack, count) in &self.counts {
+            for (i, &f) in stack.iter().enumerate() {
+                if i > 0 {
+                    out.write_char(';')?;
+                }
+                for c in self.names.name(f).chars() {
+                    out.write_char(if c == ';' { ',' } else { c })?;
+                }
+            }
+            writeln!(out, " {}", count)?;
+        }
+        Ok(())
+    }
+
+    /// Collect the stacks of the remaining blocks of @decoder.
+    ///
+    /// The decoder needs to be synchronized.
+    /// Overflows are treated as gaps.
+    /// Returns the first decode error, other than reaching the end of the trace.
+    pub fn collect<T>(&mut self, decoder: &mut BlockDecoder<T>) -> Result<(), PtError> {
+        let mut blocks = decoder.blocks(OverflowPolicy::Gap);
+        while let Some(item) = next_costed(&mut blocks, &mut self.clock, |item| {
+            self.stack.decoded(item);
+        }) {
+            let (block, _, cost) = item?;
+            self.count(&block, cost.cycles.unwrap_or(0));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use super::{Block, BlockSink};
+use crate::flags::Status;
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::block;
+    use libipt_sys::pt_insn_class_ptic_other;
+
+    #[test]
+    fn test_interner() {
+        let mut i = BlockInterner::new();
+        let a = i.push(&block(0x1000, 0x1010, pt_insn_class_ptic_other, 4));
+        let b = i.push(&block(0x2000, 0x2004, pt_insn_class_ptic_other, 1));
+        // same range, different instruction count
+        let c = i.push(&block(0x1000, 0x1010, pt_insn_class_ptic_other, 3));
+        assert_eq!(i.push(&block(0x1000, 0x1010, pt_insn_class_ptic_other, 4)), a);
+        assert_eq!(i.push(&block(0x2000, 0x2004, pt_insn_class_ptic_other, 1)), b);
+
+        assert_ne!(a, c);
+        assert_eq!(i.blocks().len(), 3);
+        assert_eq!(i.sequence(), [a, b, c, a, b]);
+        assert_eq!(i.get(c).unwrap().ninsn(), 3);
+        assert_eq!(i.id(&block(0x2000, 0x2004, pt_insn_class_ptic_other, 1)), Some(b));
+        assert_eq!(i.id(&block(0x3000, 0x3004, pt_insn_class_ptic_other, 1)), None);
+        assert_eq!(
+            i.iter().map(|b| b.ip()).collect::<Vec<_>>(),
+            [0x1000, 0x2000, 0x1000, 0x1000, 0x2000]
+        );
+    }
+}
+
+/// The id of a block in a `BlockInterner`
+///
+/// Ids are assigned in the order the blocks are first seen, starting at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(u32);
+
+impl BlockId {
+    /// The index of the block in `BlockInterner::blocks`
+    #[inline]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// what makes two blocks the same
+type Key = (u64, u64, u16);
+
+fn key(block: &Block) -> Key {
+    (block.ip(), block.end_ip(), block.ninsn())
+}
+
+/// Deduplicates the blocks of a trace
+///
+/// Long traces execute the same few blocks over and over again.
+/// Instead of keeping every block, the interner keeps each distinct block once
+/// and records the execution as a sequence of 4 byte `BlockId`s.
+/// Blocks are the same if they have the same ip, end_ip and number of
+/// instructions, only the first occurrence is kept.
+/// Flags like `speculative` and `truncated` of later occurrences are lost.
+///
+/// Implements `BlockSink`, so it can be filled with `BlockDecoder::decode_into`.
+#[derive(Default)]
+pub struct BlockInterner {
+    ids: BTreeMap<Key, BlockId>,
+    blocks: Vec<Block>,
+    sequence: Vec<BlockId>,
+}
+
+impl BlockInterner {
+    /// An empty interner
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record the execution of @block.
+    ///
+    /// Returns the id of @block, which is new if it hasn't been seen before.
+    pub fn push(&mut self, block: &Block) -> BlockId {
+        let id = self.intern(block);
+        self.sequence.push(id);
+        id
+    }
+
+    /// Get the id of @block without recording its execution.
+    ///
+    /// Returns the id of @block, which is new if it hasn't been seen before.
+    pub fn intern(&mut self, block: &Block) -> BlockId {
+        let next = BlockId(self.blocks.len() as u32);
+        let id = *self.ids.entry(key(block)).or_insert(next);
+        if id == next {
+            self.blocks.push(*block);
+        }
+        id
+    }
+
+    /// The id of @block, if it has been seen before
+    pub fn id(&self, block: &Block) -> Option<BlockId> {
+        self.ids.get(&key(block)).copied()
+    }
+
+    /// The block with the id @id
+    pub fn get(&self, id: BlockId) -> Option<&Block> {
+        self.blocks.get(id.index())
+    }
+
+    /// The distinct blocks, indexed by their id
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// The ids of the blocks in execution order
+    pub fn sequence(&self) -> &[BlockId] {
+        &self.sequence
+    }
+
+    /// Iterate over the blocks in execution order.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> + '_ {
+        self.sequence.iter().map(|id| &self.blocks[id.index()])
+    }
+
+    /// Forget the recorded execution but keep the blocks and their ids.
+    pub fn clear_sequence(&mut self) {
+        self.sequence.clear();
+    }
+}
+
+impl BlockSink for BlockInterner {
+    fn block(&mut self, block: &Block, _: Status) -> ControlFlow<()> {
+        self.push(block);
+        ControlFlow::Continue(())
+    }
+}
@@ -0,0 +1,142 @@
+use super::cost::Clock;
+use super::{Block, BlockDecoder};
+use crate::error::PtError;
+
+use core::ops::Range;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::block;
+    use libipt_sys::pt_insn_class_ptic_other;
+
+    #[test]
+    fn test_ipc_counter() {
+        let mut c = IpcCounter::new(20);
+        c.add_region("hot", 0x1000..0x2000);
+
+        // the first block only starts the clock
+        c.block(&block(0x1000, 0x1000, pt_insn_class_ptic_other, 10), 100);
+        c.block(&block(0x1010, 0x1010, pt_insn_class_ptic_other, 40), 120);
+        c.cbr(40);
+        c.block(&block(0x1020, 0x1020, pt_insn_class_ptic_other, 20), 130);
+        c.gap();
+        c.block(&block(0x1030, 0x1030, pt_insn_class_ptic_other, 10), 1000);
+        c.block(&block(0x3000, 0x3000, pt_insn_class_ptic_other, 5), 1010);
+
+        let (name, range, hot) = c.regions().next().unwrap();
+        assert_eq!((name, range), ("hot", 0x1000..0x2000));
+        assert_eq!(hot.instructions, 80);
+        // 20 tsc ticks without a ratio, then 10 at twice the nominal ratio
+        assert_eq!(hot.cycles, 40);
+        assert_eq!(hot.ipc(), Some(2.0));
+
+        let other = c.unattributed();
+        assert_eq!((other.instructions, other.cycles), (5, 20));
+        assert_eq!(Ipc::default().ipc(), None);
+    }
+}
+
+/// The instructions and cycles attributed to a code region
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ipc {
+    /// The number of executed instructions
+    pub instructions: u64,
+    /// The estimated number of core cycles
+    pub cycles: u64,
+}
+
+impl Ipc {
+    /// The estimated instructions per cycle.
+    ///
+    /// Returns None if no cycles were attributed.
+    pub fn ipc(&self) -> Option<f64> {
+        (self.cycles > 0).then(|| self.instructions as f64 / self.cycles as f64)
+    }
+}
+
+/// Estimates the instructions per cycle of code regions
+///
+/// The cycles of a block are estimated as described for `Costed`.
+pub struct IpcCounter {
+    nom: u8,
+    clock: Clock,
+    regions: Vec<(String, Range<u64>, Ipc)>,
+    other: Ipc,
+}
+
+impl IpcCounter {
+    /// A counter for a processor with the nominal core:bus ratio @nom.
+    ///
+    /// This is the nominal frequency of the decoder config.
+    pub fn new(nom: u8) -> Self {
+        IpcCounter {
+            nom,
+            clock: Clock::new(nom),
+            regions: Vec::new(),
+            other: Ipc::default(),
+        }
+    }
+
+    /// Attribute blocks starting in @range to the region @name.
+    ///
+    /// If regions overlap, the one added first wins.
+    pub fn add_region(&mut self, name: &str, range: Range<u64>) {
+        self.regions.push((name.to_string(), range, Ipc::default()));
+    }
+
+    /// The core:bus ratio changed to @ratio.
+    pub fn cbr(&mut self, ratio: u16) {
+        self.clock.cbr(ratio);
+    }
+
+    /// The trace has a gap, e.g. an overflow or tracing was disabled.
+    ///
+    /// The time until the next block is not attributed.
+    pub fn gap(&mut self) {
+        self.clock.gap();
+    }
+
+    /// @block was completed at the time stamp count @tsc.
+    pub fn block(&mut self, block: &Block, tsc: u64) {
+        let cycles = self.clock.tick(tsc, 0, 0).cycles;
+        self.attribute(block, cycles);
+    }
+
+    // add @block and its @cycles to its region
+    fn attribute(&mut self, block: &Block, cycles: Option<u64>) {
+        let ip = block.ip();
+        let ipc = match self.regions.iter_mut().find(|(_, r, _)| r.contains(&ip)) {
+            Some((_, _, ipc)) => ipc,
+            None => &mut self.other,
+        };
+        ipc.instructions += block.ninsn() as u64;
+        ipc.cycles += cycles.unwrap_or(0);
+    }
+
+    /// The regions in the order they were added with their counts.
+    pub fn regions(&self) -> impl Iterator<Item = (&str, Range<u64>, Ipc)> {
+        self.regions
+            .iter()
+            .map(|(n, r, ipc)| (n.as_str(), r.clone(), *ipc))
+    }
+
+    /// The counts of the blocks outside of all regions.
+    pub fn unattributed(&self) -> Ipc {
+        self.other
+    }
+
+    /// Count the remaining blocks of @decoder.
+    ///
+    /// The decoder needs to be synchronized.
+    /// Returns the first decode error, other than reaching the end of the trace.
+    pub fn count<T>(&mut self, decoder: &mut BlockDecoder<T>) -> Result<(), PtError> {
+        for item in decoder.costed(self.nom) {
+            let (block, _, cost) = item?;
+            self.attribute(&block, cost.cycles);
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,48 @@
 mod block;
+mod callstack;
+mod cost;
 mod decoder;
+mod folded;
+mod intern;
+mod ipc;
+mod overflow;
+mod profile;
+mod recovery;
+mod sink;
+mod tsx;
 
 pub use block::*;
-pub use decoder::*;
\ No newline at end of file
+pub use callstack::*;
+pub use cost::*;
+pub use decoder::*;
+pub use folded::*;
+pub use intern::*;
+pub use ipc::*;
+pub use overflow::*;
+pub use profile::*;
+pub use recovery::*;
+pub use sink::*;
+pub use tsx::*;
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::Block;
+    use libipt_sys::{pt_block, pt_exec_mode_ptem_64bit, pt_insn_class};
+
+    /// A 64-bit block from @ip to @end_ip with @ninsn instructions,
+    /// the last one of class @iclass
+    pub(crate) fn block(ip: u64, end_ip: u64, iclass: pt_insn_class, ninsn: u16) -> Block {
+        Block(pt_block {
+            ip,
+            end_ip,
+            isid: 0,
+            mode: pt_exec_mode_ptem_64bit,
+            iclass,
+            ninsn,
+            raw: [0; 15],
+            size: 1,
+            _bitfield_1: pt_block::new_bitfield_1(0, 0),
+            __bindgen_padding_0: Default::default(),
+        })
+    }
+}
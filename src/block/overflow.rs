@@ -0,0 +1,196 @@
+use super::recovery::recover;
+use super::{Block, BlockDecoder, ErrorPolicy};
+use crate::error::{DecodeError, PtError, PtErrorCode};
+use crate::event::{Event, Payload};
+use crate::flags::Status;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libipt_sys::{
+        pt_event, pt_event__bindgen_ty_1__bindgen_ty_7, pt_event_type_ptev_overflow,
+        pt_event_type_ptev_stop,
+    };
+    use core::mem;
+
+    #[test]
+    fn test_overflow_policy() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_overflow;
+        evt.tsc = 5;
+        evt._bitfield_1 = pt_event::new_bitfield_1(0, 0, 1);
+        evt.variant.overflow = pt_event__bindgen_ty_1__bindgen_ty_7 { ip: 0x1000 };
+        let overflow = Event(evt);
+
+        let gap = Gap {
+            ip: Some(0x1000),
+            tsc: Some(5),
+        };
+        assert!(matches!(
+            handle(OverflowPolicy::Gap, overflow),
+            Some(Ok(Decoded::Gap(g))) if g == gap
+        ));
+        assert!(handle(OverflowPolicy::Resync, overflow).is_none());
+        assert!(matches!(
+            handle(OverflowPolicy::Stop, overflow),
+            Some(Err(e)) if e.code() == PtErrorCode::Overflow
+        ));
+
+        evt.type_ = pt_event_type_ptev_stop;
+        assert!(matches!(
+            handle(OverflowPolicy::Stop, Event(evt)),
+            Some(Ok(Decoded::Event(_)))
+        ));
+    }
+}
+
+/// How `Blocks` responds to an overflow of the trace
+///
+/// On overflow the processor dropped trace packets,
+/// so the execution in between is unknown.
+/// Treating the blocks before and after as contiguous gives wrong results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Return an Overflow error and stop iterating,
+    /// the `ErrorPolicy` doesn't apply to it.
+    Stop,
+    /// Continue where tracing resumes without reporting the overflow.
+    Resync,
+    /// Report the overflow as a `Decoded::Gap` and continue.
+    #[default]
+    Gap,
+}
+
+/// A gap in the trace caused by an overflow or a skipped decode error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// The address at which tracing resumes.
+    ///
+    /// None if the overflow resolved while tracing was disabled
+    /// or if decoding continues at the next synchronization point.
+    pub ip: Option<u64>,
+    /// The time stamp count of the overflow, if known
+    pub tsc: Option<u64>,
+}
+
+/// An item of `Blocks`
+#[derive(Clone, Copy)]
+pub enum Decoded {
+    /// A block of instructions
+    Block(Block, Status),
+    /// An event other than overflow
+    Event(Event),
+    /// The trace overflowed or an error was skipped,
+    /// see `OverflowPolicy` and `ErrorPolicy`
+    Gap(Gap),
+}
+
+// what to report for @evt under @policy
+fn handle(policy: OverflowPolicy, evt: Event) -> Option<Result<Decoded, PtError>> {
+    let o = match evt.payload() {
+        Payload::Overflow(o) => o,
+        _ => return Some(Ok(Decoded::Event(evt))),
+    };
+
+    match policy {
+        OverflowPolicy::Stop => Some(Err(PtError::new(
+            PtErrorCode::Overflow,
+            "the trace overflowed",
+        ))),
+        OverflowPolicy::Resync => None,
+        OverflowPolicy::Gap => Some(Ok(Decoded::Gap(Gap {
+            ip: (!evt.ip_suppressed()).then(|| o.ip()),
+            tsc: evt.timestamp(),
+        }))),
+    }
+}
+
+/// An iterator over the blocks and events of a block decoder
+///
+/// Pending events are drained before the next block is decoded,
+/// overflows are handled according to an `OverflowPolicy`.
+/// The decoder needs to be synchronized before iterating.
+/// Iteration ends at the end of the trace, decode errors are handled
+/// according to an `ErrorPolicy` which stops after the first one by default.
+pub struct Blocks<'d, 'a, T> {
+    decoder: &'d mut BlockDecoder<'a, T>,
+    policy: OverflowPolicy,
+    errors: ErrorPolicy,
+    done: bool,
+}
+
+impl<'d, 'a, T> Blocks<'d, 'a, T> {
+    /// Iterate over the blocks and events of @decoder.
+    pub fn new(decoder: &'d mut BlockDecoder<'a, T>, policy: OverflowPolicy) -> Self {
+        Blocks {
+            decoder,
+            policy,
+            errors: ErrorPolicy::default(),
+            done: false,
+        }
+    }
+
+    /// Handle decode errors according to @errors.
+    pub fn with_error_policy(mut self, errors: ErrorPolicy) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// The underlying decoder, e.g. to resynchronize after an error.
+    ///
+    /// Iteration continues after the decoder was synchronized again.
+    pub fn decoder(&mut self) -> &mut BlockDecoder<'a, T> {
+        self.done = false;
+        self.decoder
+    }
+}
+
+impl<'d, 'a, T> Iterator for Blocks<'d, 'a, T> {
+    type Item = Result<Decoded, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let res = if self.decoder.status().event_pending() {
+                match self.decoder.event() {
+                    Ok((evt, _)) => match handle(self.policy, evt) {
+                        Some(Ok(item)) => Ok(item),
+                        // the overflow policy stops, whatever the error policy
+                        Some(Err(e)) => {
+                            self.done = true;
+                            return Some(self.decoder.locate(Err(e)));
+                        }
+                        None => continue,
+                    },
+                    Err(e) => Err(e),
+                }
+            } else {
+                self.decoder.next().map(|(b, s)| Decoded::Block(b, s))
+            };
+
+            match res {
+                Err(e) if e.is_eos() => self.done = true,
+                Err(e) => {
+                    let decoder = &mut *self.decoder;
+                    let (item, more) = recover(self.errors, e, || {
+                        let res = decoder.sync_forward().map(|_| ());
+                        decoder.locate(res)
+                    });
+                    self.done = !more;
+                    return Some(item);
+                }
+                Ok(item) => return Some(Ok(item)),
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> BlockDecoder<'a, T> {
+    /// Iterate over the decoded blocks and events,
+    /// handling overflows according to @policy.
+    ///
+    /// See `Blocks`.
+    pub fn blocks(&mut self, policy: OverflowPolicy) -> Blocks<'_, 'a, T> {
+        Blocks::new(self, policy)
+    }
+}
@@ -0,0 +1,271 @@
+use super::cost::{next_costed, Clock};
+use super::{Block, BlockDecoder, CallStackTracker, OverflowPolicy};
+use crate::error::PtError;
+use crate::event::Event;
+use crate::symbolize::Symbolizer;
+
+use core::mem;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::symbolize::Frame;
+    use super::super::test::block;
+    use libipt_sys::{
+        pt_insn_class_ptic_call, pt_insn_class_ptic_other, pt_insn_class_ptic_return,
+    };
+
+    #[test]
+    fn test_profiler() {
+        let names = |ip: u64| Frame {
+            ip,
+            function: match ip {
+                0x1000..=0x1fff => Some("main".to_string()),
+                0x2000..=0x2fff => Some("foo".to_string()),
+                _ => None,
+            },
+            ..Default::default()
+        };
+        let mut p = Profiler::new(names, 0);
+        p.block(&block(0x1000, 0x1010, pt_insn_class_ptic_call, 2), 100);
+        p.block(&block(0x2000, 0x2010, pt_insn_class_ptic_return, 2), 130);
+        p.block(&block(0x1015, 0x1020, pt_insn_class_ptic_other, 2), 150);
+        p.gap();
+        p.block(&block(0x5000, 0x5010, pt_insn_class_ptic_other, 2), 900);
+        p.block(&block(0x5010, 0x5020, pt_insn_class_ptic_other, 2), 905);
+
+        let table = p.table();
+        assert_eq!(table.len(), 3);
+        assert_eq!(
+            table[0],
+            (
+                "foo",
+                FunctionTime {
+                    self_time: 30,
+                    total_time: 30,
+                    calls: 1,
+                    instructions: 2
+                }
+            )
+        );
+        assert_eq!(table[1].0, "main");
+        assert_eq!(table[1].1.self_time, 20);
+        assert_eq!(table[1].1.total_time, 50);
+        assert_eq!(table[1].1.calls, 0);
+        assert_eq!(table[2].0, UNKNOWN);
+        assert_eq!(table[2].1.self_time, 5);
+        assert_eq!(p.total(), 55);
+    }
+}
+
+/// The name under which addresses without a known function are collected
+pub const UNKNOWN: &str = "[unknown]";
+
+/// The time attributed to a function by a `Profiler`
+///
+/// Times are in core cycles, or in TSC ticks for a nominal ratio of 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionTime {
+    /// The time spent in the function itself
+    pub self_time: u64,
+    /// The time spent in the function and everything it called
+    pub total_time: u64,
+    /// The number of calls of the function seen in the trace
+    pub calls: u64,
+    /// The number of instructions executed in the function itself
+    pub instructions: u64,
+}
+
+/// Aggregates the time spent per function
+///
+/// The time of a block is estimated as described for `Costed`
+/// and attributed to the function the block starts in, see `Symbolizer`.
+/// The function and its callers on the call stack, as reconstructed
+/// by a `CallStackTracker`, are charged for it in their total time.
+/// Recursive functions are charged once per block.
+/// Blocks in functions without a name are collected under `UNKNOWN`.
+///
+/// Set `BlockFlags::END_ON_CALL` in the decoder config to see every call.
+pub struct Profiler<S> {
+    clock: Clock,
+    names: Names<S>,
+    stack: CallStackTracker,
+    times: Vec<FunctionTime>,
+    // the functions on the stack, reused between blocks
+    active: Vec<usize>,
+}
+
+// numbers the functions of a symbolizer in the order they are first seen
+pub(super) struct Names<S> {
+    symbolizer: S,
+    names: Vec<String>,
+    ids: BTreeMap<String, usize>,
+    // the function of every address symbolized so far
+    cache: BTreeMap<u64, usize>,
+}
+
+impl<S: Symbolizer> Names<S> {
+    pub(super) fn new(symbolizer: S) -> Self {
+        Names {
+            symbolizer,
+            names: Vec::new(),
+            ids: BTreeMap::new(),
+            cache: BTreeMap::new(),
+        }
+    }
+
+    // the index of the function containing @ip
+    pub(super) fn function(&mut self, ip: u64) -> usize {
+        if let Some(&f) = self.cache.get(&ip) {
+            return f;
+        }
+        let name = self
+            .symbolizer
+            .symbolize(ip)
+            .function
+            .unwrap_or_else(|| UNKNOWN.to_string());
+        let next = self.names.len();
+        let f = *self.ids.entry(name.clone()).or_insert(next);
+        if f == next {
+            self.names.push(name);
+        }
+        self.cache.insert(ip, f);
+        f
+    }
+
+    pub(super) fn name(&self, f: usize) -> &str {
+        &self.names[f]
+    }
+
+    pub(super) fn symbolizer(&mut self) -> &mut S {
+        &mut self.symbolizer
+    }
+}
+
+impl<S: Symbolizer> Profiler<S> {
+    /// A profiler that names functions with @symbolizer
+    /// for a processor with the nominal core:bus ratio @nom.
+    ///
+    /// This is the nominal frequency of the decoder config.
+    /// With @nom 0, times are TSC ticks instead of core cycles.
+    pub fn new(symbolizer: S, nom: u8) -> Self {
+        Profiler {
+            clock: Clock::new(nom),
+            names: Names::new(symbolizer),
+            stack: CallStackTracker::new(),
+            times: Vec::new(),
+            active: Vec::new(),
+        }
+    }
+
+    /// The core:bus ratio changed to @ratio.
+    pub fn cbr(&mut self, ratio: u16) {
+        self.clock.cbr(ratio);
+    }
+
+    /// The trace has a gap, e.g. an overflow.
+    ///
+    /// The time until the next block is not attributed.
+    pub fn gap(&mut self) {
+        self.clock.gap();
+        self.stack.gap();
+    }
+
+    /// Update the call stack and timing with @evt.
+    pub fn event(&mut self, evt: &Event) {
+        self.clock.event(evt);
+        self.stack.event(evt);
+    }
+
+    /// @block was completed at the time stamp count @tsc.
+    pub fn block(&mut self, block: &Block, tsc: u64) {
+        let time = self.clock.tick(tsc, 0, 0).cycles.unwrap_or(0);
+        self.charge(block, time);
+    }
+
+    // adds @block to the call stack and charges @time to the functions on it
+    fn charge(&mut self, block: &Block, time: u64) {
+        let entering = self.stack.current().is_some_and(|f| f.entry.is_none());
+        let depth = self.stack.block(block);
+        let called =
+            entering && depth > 0 && self.stack.frames()[depth - 1].entry == Some(block.ip());
+
+        let mut active = mem::take(&mut self.active);
+        active.clear();
+        for i in 0..depth {
+            let from = self.stack.frames()[i].from;
+            active.push(self.function(from));
+        }
+        let own = self.function(block.ip());
+        active.push(own);
+        active.sort_unstable();
+        active.dedup();
+        for &f in &active {
+            self.times[f].total_time += time;
+        }
+        self.active = active;
+
+        let own = &mut self.times[own];
+        own.self_time += time;
+        own.instructions += block.ninsn() as u64;
+        own.calls += called as u64;
+    }
+
+    // the index of the function containing @ip, with room for its time
+    fn function(&mut self, ip: u64) -> usize {
+        let f = self.names.function(ip);
+        if f >= self.times.len() {
+            self.times.resize(f + 1, FunctionTime::default());
+        }
+        f
+    }
+
+    /// The functions in the order they were first seen with their times.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, FunctionTime)> {
+        self.times
+            .iter()
+            .enumerate()
+            .map(|(f, t)| (self.names.name(f), *t))
+    }
+
+    /// The functions sorted by their self time, the most expensive first.
+    pub fn table(&self) -> Vec<(&str, FunctionTime)> {
+        let mut table: Vec<_> = self.functions().collect();
+        table.sort_by(|a, b| b.1.self_time.cmp(&a.1.self_time).then(a.0.cmp(b.0)));
+        table
+    }
+
+    /// The time attributed to all functions together
+    pub fn total(&self) -> u64 {
+        self.times.iter().map(|t| t.self_time).sum()
+    }
+
+    /// The call stack at the last block
+    pub fn stack(&self) -> &CallStackTracker {
+        &self.stack
+    }
+
+    /// The symbolizer, e.g. to reuse its caches for another trace.
+    pub fn symbolizer(&mut self) -> &mut S {
+        self.names.symbolizer()
+    }
+
+    /// Profile the remaining blocks of @decoder.
+    ///
+    /// The decoder needs to be synchronized.
+    /// Overflows are treated as gaps.
+    /// Returns the first decode error, other than reaching the end of the trace.
+    pub fn profile<T>(&mut self, decoder: &mut BlockDecoder<T>) -> Result<(), PtError> {
+        let mut blocks = decoder.blocks(OverflowPolicy::Gap);
+        while let Some(item) = next_costed(&mut blocks, &mut self.clock, |item| {
+            self.stack.decoded(item);
+        }) {
+            let (block, _, cost) = item?;
+            self.charge(&block, cost.cycles.unwrap_or(0));
+        }
+        Ok(())
+    }
+}
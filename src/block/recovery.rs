@@ -0,0 +1,94 @@
+use super::{Decoded, Gap};
+use crate::error::DecodeError;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::{PtError, PtErrorCode};
+
+    #[test]
+    fn test_error_policy() {
+        let err = DecodeError::new(
+            PtError::new(PtErrorCode::BadPacket, "bad packet"), Some(0x20), Some(0x10)
+        );
+        let eos = DecodeError::new(PtError::new(PtErrorCode::Eos, "end of the trace"), None, None);
+        let nosync = DecodeError::new(PtError::new(PtErrorCode::Nosync, "out of sync"), None, None);
+
+        assert!(matches!(
+            recover(ErrorPolicy::Strict, err, || panic!("strict must not resync")),
+            (Err(e), false) if e.code() == PtErrorCode::BadPacket
+        ));
+        assert!(matches!(
+            recover(ErrorPolicy::SkipToNextSync, err, || Ok(())),
+            (Err(e), true) if e.code() == PtErrorCode::BadPacket && e.offset() == Some(0x20)
+        ));
+        assert!(matches!(
+            recover(ErrorPolicy::BestEffort, err, || Ok(())),
+            (
+                Ok(Decoded::Gap(Gap {
+                    ip: None,
+                    tsc: None
+                })),
+                true
+            )
+        ));
+        assert!(matches!(
+            recover(ErrorPolicy::BestEffort, err, || Err(eos)),
+            (Ok(Decoded::Gap(_)), false)
+        ));
+        assert!(matches!(
+            recover(ErrorPolicy::BestEffort, err, || Err(nosync)),
+            (Err(e), false) if e.code() == PtErrorCode::Nosync
+        ));
+    }
+}
+
+/// How `Blocks` responds to a decode error
+///
+/// After an error the decoder state is unknown,
+/// decoding can only continue at the next synchronization point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Return the error and stop iterating.
+    ///
+    /// Suited for forensics where every error has to be looked at.
+    #[default]
+    Strict,
+    /// Return the error and continue at the next synchronization point.
+    SkipToNextSync,
+    /// Report the error as a `Decoded::Gap` and continue
+    /// at the next synchronization point.
+    ///
+    /// Suited for fuzzing and profiling where partial results are good enough.
+    BestEffort,
+}
+
+// what to report for the decode error @err under @policy.
+// @resync synchronizes the decoder again, Strict doesn't call it.
+// returns the item and whether iteration continues.
+pub(super) fn recover(
+    policy: ErrorPolicy,
+    err: DecodeError,
+    resync: impl FnOnce() -> Result<(), DecodeError>,
+) -> (Result<Decoded, DecodeError>, bool) {
+    if policy == ErrorPolicy::Strict {
+        return (Err(err), false);
+    }
+
+    let more = match resync() {
+        Ok(()) => true,
+        // no synchronization point left, report what went wrong before
+        Err(e) if e.is_eos() => false,
+        Err(e) => return (Err(e), false),
+    };
+    match policy {
+        ErrorPolicy::BestEffort => (
+            Ok(Decoded::Gap(Gap {
+                ip: None,
+                tsc: None,
+            })),
+            more,
+        ),
+        _ => (Err(err), more),
+    }
+}
@@ -0,0 +1,106 @@
+use super::{Block, BlockDecoder};
+use crate::error::DecodeError;
+use crate::event::Event;
+use crate::flags::Status;
+
+use core::ops::ControlFlow;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::config::ConfigBuilder;
+    use crate::error::ErrorKind;
+
+    struct Counter(u64);
+
+    impl BlockSink for Counter {
+        fn block(&mut self, block: &Block, _: Status) -> ControlFlow<()> {
+            self.0 += block.ninsn() as u64;
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let kek = &mut [1; 2];
+        let mut b = BlockDecoder::new(&ConfigBuilder::new(kek).unwrap().finish()).unwrap();
+        let mut sink = Counter(0);
+        assert!(b.decode_into(&mut sink).is_err());
+
+        let cancel = CancelToken::new();
+        b.set_cancel(Some(cancel.clone()));
+        cancel.cancel();
+        let sink: &mut dyn BlockSink = &mut sink;
+        assert_eq!(b.decode_into(sink).unwrap_err().kind(), ErrorKind::Cancelled);
+    }
+}
+
+/// Receives the blocks and events of `BlockDecoder::decode_into`
+///
+/// Blocks and events are passed by reference and are only valid
+/// for the duration of the call, nothing is buffered in between.
+/// Return `ControlFlow::Break` to stop decoding.
+pub trait BlockSink {
+    /// Called for every block with the status after decoding it
+    fn block(&mut self, block: &Block, status: Status) -> ControlFlow<()>;
+
+    /// Called for every event, including overflows.
+    ///
+    /// Events are ignored by default.
+    fn event(&mut self, event: &Event) -> ControlFlow<()> {
+        let _ = event;
+        ControlFlow::Continue(())
+    }
+}
+
+impl<'a, T> BlockDecoder<'a, T> {
+    /// Decode blocks and events into @sink until the end of the trace.
+    ///
+    /// Pending events are pushed before the next block is decoded,
+    /// in the same order `Blocks` returns them.
+    /// Nothing is allocated along the way,
+    /// use this to run the decoder in allocation-sensitive code.
+    /// To that end, no checkpoints for `step_back` are recorded during the call,
+    /// `step_back` replays from an earlier checkpoint or synchronization point.
+    /// With the `tracing` feature, the subscriber might still allocate
+    /// for the decoder's spans.
+    /// The decoder needs to be synchronized before.
+    /// Returns Ok when the end of the trace is reached or @sink stopped decoding.
+    /// Returns the first decode error otherwise,
+    /// the decoder can be synchronized again to continue.
+    pub fn decode_into(&mut self, sink: &mut (impl BlockSink + ?Sized)) -> Result<(), DecodeError> {
+        self.pause_marks(true);
+        let res = self.push_into(sink);
+        self.pause_marks(false);
+        res
+    }
+
+    // the loop of `decode_into`
+    fn push_into(&mut self, sink: &mut (impl BlockSink + ?Sized)) -> Result<(), DecodeError> {
+        loop {
+            let flow = if self.status().event_pending() {
+                match self.event() {
+                    Ok((evt, _)) => sink.event(&evt),
+                    Err(e) => return eos_ok(e),
+                }
+            } else {
+                match self.next() {
+                    Ok((blk, status)) => sink.block(&blk, status),
+                    Err(e) => return eos_ok(e),
+                }
+            };
+            if flow.is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// the end of the trace is where decode_into is supposed to stop
+fn eos_ok(e: DecodeError) -> Result<(), DecodeError> {
+    if e.is_eos() {
+        return Ok(());
+    }
+    Err(e)
+}
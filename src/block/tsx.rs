@@ -0,0 +1,220 @@
+use super::{Block, Blocks, Decoded};
+use crate::error::DecodeError;
+use crate::event::Payload;
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event::Event;
+    use crate::flags::Status;
+    use libipt_sys::{
+        pt_event, pt_event__bindgen_ty_1__bindgen_ty_9, pt_event_type_ptev_tsx,
+        pt_insn_class_ptic_other,
+    };
+    use core::mem;
+
+    fn blk(ip: u64, speculative: bool) -> Result<Decoded, DecodeError> {
+        let mut block = super::super::test::block(ip, ip, pt_insn_class_ptic_other, 1);
+        block.0.set_speculative(speculative as u32);
+        Ok(Decoded::Block(block, Status::empty()))
+    }
+
+    fn tsx(ip: u64, speculative: bool, aborted: bool) -> Result<Decoded, DecodeError> {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_tsx;
+        evt.variant.tsx = pt_event__bindgen_ty_1__bindgen_ty_9 {
+            ip,
+            _bitfield_1: pt_event__bindgen_ty_1__bindgen_ty_9::new_bitfield_1(
+                speculative as u32,
+                aborted as u32,
+            ),
+            __bindgen_padding_0: Default::default(),
+        };
+        Ok(Decoded::Event(Event(evt)))
+    }
+
+    #[test]
+    fn test_transactions() {
+        let items = vec![
+            blk(0x100, false),
+            tsx(0x200, true, false),
+            blk(0x200, true),
+            blk(0x210, true),
+            tsx(0x300, false, true),
+            blk(0x300, false),
+            tsx(0x400, true, false),
+            blk(0x400, true),
+            tsx(0x410, false, false),
+            blk(0x500, true),
+            blk(0x600, false),
+            blk(0x700, true),
+        ];
+
+        let out: Vec<_> = Transactions::new(items.into_iter())
+            .map(|t| match t.unwrap() {
+                TxItem::Decoded(Decoded::Block(b, _)) => (b.ip(), None, 0),
+                TxItem::Decoded(_) => unreachable!(),
+                TxItem::Transaction(t) => (t.blocks[0].ip(), Some(t.outcome), t.blocks.len()),
+            })
+            .collect();
+        assert_eq!(
+            out,
+            vec![
+                (0x100, None, 0),
+                (0x200, Some(TxOutcome::Aborted { ip: Some(0x300) }), 2),
+                (0x300, None, 0),
+                (0x400, Some(TxOutcome::Committed), 1),
+                (0x500, Some(TxOutcome::Incomplete), 1),
+                (0x600, None, 0),
+                (0x700, Some(TxOutcome::Incomplete), 1),
+            ]
+        );
+    }
+}
+
+/// How a transaction ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxOutcome {
+    /// The transaction committed, its work took effect.
+    Committed,
+    /// The transaction aborted, its work was rolled back.
+    ///
+    /// Execution continues at @ip, if known.
+    Aborted { ip: Option<u64> },
+    /// The trace ended or failed before the transaction did.
+    Incomplete,
+}
+
+/// The blocks executed speculatively inside one TSX transaction
+#[derive(Clone)]
+pub struct Transaction {
+    /// The speculatively executed blocks in order
+    pub blocks: Vec<Block>,
+    /// How the transaction ended
+    pub outcome: TxOutcome,
+    /// The time stamp count at which the transaction began, if known
+    pub begin_tsc: Option<u64>,
+    /// The time stamp count at which the transaction ended, if known
+    pub end_tsc: Option<u64>,
+}
+
+impl Transaction {
+    /// Returns true if the work of this transaction was rolled back.
+    pub fn rolled_back(&self) -> bool {
+        matches!(self.outcome, TxOutcome::Aborted { .. })
+    }
+}
+
+/// An item of `Transactions`
+#[derive(Clone)]
+pub enum TxItem {
+    /// A block outside of any transaction or an event
+    Decoded(Decoded),
+    /// A complete transaction
+    Transaction(Transaction),
+}
+
+/// An iterator that groups the speculatively executed blocks
+/// of a `Blocks` iterator into transactions.
+///
+/// The TSX events that begin and end a transaction are consumed.
+/// A transaction whose end is missing from the trace is reported as
+/// incomplete before the next non-speculative block.
+/// Other events inside a transaction are passed through before
+/// the transaction they belong to is reported.
+pub struct Transactions<I> {
+    items: I,
+    open: Option<Transaction>,
+    // an item to report after the open transaction
+    pending: Option<Result<TxItem, DecodeError>>,
+}
+
+impl<I> Transactions<I>
+where
+    I: Iterator<Item = Result<Decoded, DecodeError>>,
+{
+    /// Group the blocks of @items into transactions.
+    pub fn new(items: I) -> Self {
+        Transactions {
+            items,
+            open: None,
+            pending: None,
+        }
+    }
+
+    #[inline]
+    fn begin(&mut self, tsc: Option<u64>) -> &mut Transaction {
+        self.open.get_or_insert_with(|| Transaction {
+            blocks: Vec::new(),
+            outcome: TxOutcome::Incomplete,
+            begin_tsc: tsc,
+            end_tsc: None,
+        })
+    }
+}
+
+impl<I> Iterator for Transactions<I>
+where
+    I: Iterator<Item = Result<Decoded, DecodeError>>,
+{
+    type Item = Result<TxItem, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.pending.take() {
+            return Some(item);
+        }
+
+        loop {
+            let item = match self.items.next() {
+                Some(Ok(item)) => item,
+                Some(Err(e)) => match self.open.take() {
+                    Some(tx) => {
+                        self.pending = Some(Err(e));
+                        return Some(Ok(TxItem::Transaction(tx)));
+                    }
+                    None => return Some(Err(e)),
+                },
+                None => return self.open.take().map(|tx| Ok(TxItem::Transaction(tx))),
+            };
+
+            match item {
+                // the trace may start inside a transaction
+                Decoded::Block(b, _) if b.speculative() => self.begin(None).blocks.push(b),
+                // the end of the transaction got lost, e.g. in an overflow
+                Decoded::Block(..) if self.open.is_some() => {
+                    self.pending = Some(Ok(TxItem::Decoded(item)));
+                    return self.open.take().map(|tx| Ok(TxItem::Transaction(tx)));
+                }
+                Decoded::Event(evt) => match evt.payload() {
+                    Payload::Tsx(t) if t.speculative() => {
+                        self.begin(evt.timestamp());
+                    }
+                    Payload::Tsx(t) if self.open.is_some() => {
+                        let mut tx = self.open.take().unwrap();
+                        tx.outcome = if t.aborted() {
+                            TxOutcome::Aborted {
+                                ip: (!evt.ip_suppressed()).then(|| t.ip()),
+                            }
+                        } else {
+                            TxOutcome::Committed
+                        };
+                        tx.end_tsc = evt.timestamp();
+                        return Some(Ok(TxItem::Transaction(tx)));
+                    }
+                    _ => return Some(Ok(TxItem::Decoded(item))),
+                },
+                _ => return Some(Ok(TxItem::Decoded(item))),
+            }
+        }
+    }
+}
+
+impl<'d, 'a, T> Blocks<'d, 'a, T> {
+    /// Group the speculatively executed blocks into transactions.
+    ///
+    /// See `Transactions`.
+    pub fn transactions(self) -> Transactions<Self> {
+        Transactions::new(self)
+    }
+}
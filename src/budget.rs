@@ -0,0 +1,171 @@
+use crate::cancel::CancelToken;
+use crate::error::{ErrorKind, PtError};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_budget_unlimited() {
+        let mut m = Meter::new(Budget::new());
+        m.charge(u64::MAX, u64::MAX);
+        assert!(m.check().is_ok());
+    }
+
+    #[test]
+    fn test_budget_insns() {
+        let mut m = Meter::new(Budget::new().insns(10));
+        m.charge(9, 1000);
+        assert!(m.check().is_ok());
+        m.charge(1, 0);
+        assert_eq!(m.check().unwrap_err().kind(), ErrorKind::BudgetExhausted);
+    }
+
+    #[test]
+    fn test_budget_bytes() {
+        let mut m = Meter::new(Budget::new().bytes(64).insns(1000));
+        m.charge(1, 63);
+        assert!(m.check().is_ok());
+        m.charge(1, 1);
+        assert_eq!(m.check().unwrap_err().kind(), ErrorKind::BudgetExhausted);
+    }
+
+    #[test]
+    fn test_scan_limits() {
+        let mut l = ScanLimits::default();
+        assert_eq!(l.start(|| 5).unwrap(), None);
+
+        l.set_budget(Some(Budget::new().bytes(64)));
+        let start = l.start(|| 0x80).unwrap();
+        assert_eq!(start, Some(0x80));
+        l.charge(start, Some(0x40));
+        assert_eq!(l.start(|| 0x40).unwrap_err().kind(), ErrorKind::BudgetExhausted);
+        l.restart();
+        assert!(l.start(|| 0x40).is_ok());
+
+        let cancel = CancelToken::new();
+        l.set_cancel(Some(cancel.clone()));
+        cancel.cancel();
+        assert_eq!(l.start(|| 0).unwrap_err().kind(), ErrorKind::Cancelled);
+    }
+}
+
+/// Limits for the work a decoder may do.
+///
+/// libipt can't interrupt a decoder call, so the budget is checked before
+/// every `next` and synchronization call and charged afterwards.
+/// The query and packet decoders only check it before synchronizing,
+/// which scans the trace for a PSB packet, and only count bytes.
+/// A single call may overshoot the budget,
+/// all following calls fail with BudgetExhausted.
+/// Pending events can always be fetched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    insns: Option<u64>,
+    bytes: Option<u64>,
+}
+
+impl Budget {
+    /// A budget without any limits
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Limit the number of decoded instructions
+    #[inline]
+    pub fn insns(mut self, max: u64) -> Self {
+        self.insns = Some(max);
+        self
+    }
+
+    /// Limit the number of consumed trace bytes
+    #[inline]
+    pub fn bytes(mut self, max: u64) -> Self {
+        self.bytes = Some(max);
+        self
+    }
+}
+
+// tracks how much of a budget has been spent
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Meter {
+    budget: Budget,
+    insns: u64,
+    bytes: u64,
+}
+
+impl Meter {
+    pub(crate) fn new(budget: Budget) -> Self {
+        Meter {
+            budget,
+            insns: 0,
+            bytes: 0,
+        }
+    }
+
+    pub(crate) fn check(&self) -> Result<(), PtError> {
+        let over = |limit: Option<u64>, spent| limit.is_some_and(|l| spent >= l);
+        if over(self.budget.insns, self.insns) || over(self.budget.bytes, self.bytes) {
+            return Err(PtError::stopped(
+                ErrorKind::BudgetExhausted,
+                "the decode budget is exhausted",
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn restart(&mut self) {
+        self.insns = 0;
+        self.bytes = 0;
+    }
+
+    pub(crate) fn charge(&mut self, insns: u64, bytes: u64) {
+        self.insns = self.insns.saturating_add(insns);
+        self.bytes = self.bytes.saturating_add(bytes);
+    }
+}
+
+// the budget and cancel token of the query and packet decoders,
+// which only limit their synchronization scans
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ScanLimits {
+    meter: Option<Meter>,
+    cancel: Option<CancelToken>,
+}
+
+impl ScanLimits {
+    pub(crate) fn set_budget(&mut self, budget: Option<Budget>) {
+        self.meter = budget.map(Meter::new);
+    }
+
+    pub(crate) fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        self.cancel = cancel;
+    }
+
+    pub(crate) fn restart(&mut self) {
+        if let Some(m) = self.meter.as_mut() {
+            m.restart();
+        }
+    }
+
+    // checks for cancellation and the budget before a scan from @offset,
+    // returns the offset to charge consumed bytes from
+    pub(crate) fn start(&self, offset: impl FnOnce() -> u64) -> Result<Option<u64>, PtError> {
+        if let Some(c) = &self.cancel {
+            c.check()?;
+        }
+        match &self.meter {
+            Some(m) => m.check().map(|_| Some(offset())),
+            None => Ok(None),
+        }
+    }
+
+    // charges the bytes between @start and @end,
+    // backward synchronization moves the decoder back
+    pub(crate) fn charge(&mut self, start: Option<u64>, end: Option<u64>) {
+        if let (Some(start), Some(m)) = (start, self.meter.as_mut()) {
+            m.charge(0, end.unwrap_or(start).abs_diff(start));
+        }
+    }
+}
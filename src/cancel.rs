@@ -0,0 +1,62 @@
+use crate::error::{ErrorKind, PtError};
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let other = token.clone();
+        assert!(token.check().is_ok());
+        other.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check().unwrap_err().kind(), ErrorKind::Cancelled);
+    }
+}
+
+/// Cancels long running decodes from another thread.
+///
+/// All clones of a token share its state,
+/// give a clone to the decoder and keep one to cancel it.
+/// Decoders check the token before every `next` and synchronization call,
+/// once it is cancelled all following calls fail with Cancelled.
+/// The query and packet decoders only check it before synchronizing.
+/// A single libipt call can't be interrupted,
+/// so a call that is already running is finished first.
+/// Pending events can always be fetched.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// A token that is not cancelled yet
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Cancel all decodes using this token.
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Has the token been cancelled?
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), PtError> {
+        if self.is_cancelled() {
+            return Err(PtError::stopped(
+                ErrorKind::Cancelled,
+                "cancelled through a CancelToken",
+            ));
+        }
+        Ok(())
+    }
+}
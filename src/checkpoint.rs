@@ -0,0 +1,218 @@
+use crate::error::{PtError, PtErrorCode};
+
+use core::convert::TryInto;
+use core::mem;
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_props() {
+        let c = Checkpoint::new(1, 2, 3);
+        assert_eq!(c.sync_offset(), 1);
+        assert_eq!(c.offset(), 2);
+        assert_eq!(c.steps(), 3);
+    }
+
+    fn mark(sync_offset: u64, items: u64) -> Mark {
+        Mark {
+            sync_offset,
+            offset: sync_offset + 0x10,
+            steps: items + 2,
+            items,
+        }
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut m = Marks::new();
+        m.set_interval(10);
+        assert!(!m.due(9));
+        assert!(m.due(10));
+        // no synchronization point passed since the origin
+        m.record(0x100, mark(0x100, 10));
+        assert!(m.nearest(20).is_none());
+
+        m.record(0x100, mark(0x200, 12));
+        assert!(!m.due(21));
+        m.record(0x100, mark(0x200, 25));
+        m.record(0x100, mark(0x300, 30));
+        assert!(m.nearest(11).is_none());
+        assert_eq!(m.nearest(12), Some(mark(0x200, 12)));
+        assert_eq!(m.nearest(40), Some(mark(0x300, 30)));
+
+        let marks = m.take();
+        assert!(m.nearest(40).is_none());
+        m.restore(marks);
+        assert_eq!(m.nearest(40), Some(mark(0x300, 30)));
+        m.pause(true);
+        assert!(!m.due(100));
+        m.record(0x100, mark(0x400, 100));
+        assert_eq!(m.nearest(100), Some(mark(0x300, 30)));
+        m.pause(false);
+        assert!(m.due(100));
+        m.set_interval(0);
+        assert!(!m.due(100));
+        assert!(m.nearest(40).is_none());
+    }
+
+    #[test]
+    fn test_checkpoint_bytes() {
+        let c = Checkpoint::new(0x1337, 0x1400, 42);
+        let bytes = c.to_bytes();
+        assert_eq!(Checkpoint::from_bytes(&bytes).unwrap(), c);
+        assert_eq!(
+            Checkpoint::from_bytes(&bytes[1..]).unwrap_err().code(),
+            PtErrorCode::Invalid
+        );
+    }
+}
+
+/// A snapshot of a decoder's position in the trace.
+///
+/// libipt can't export the internal state of its decoders.
+/// A checkpoint therefore records the last synchronization point
+/// and the number of successful `next` and `event` calls since then.
+/// Restoring a checkpoint synchronizes the decoder at that point and
+/// replays the calls to rebuild the state (return stack, timing, etc.).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    sync_offset: u64,
+    offset: u64,
+    steps: u64,
+}
+
+impl Checkpoint {
+    /// The size of a serialized checkpoint in bytes
+    pub const SIZE: usize = 24;
+
+    #[inline]
+    pub(crate) fn new(sync_offset: u64, offset: u64, steps: u64) -> Self {
+        Checkpoint {
+            sync_offset,
+            offset,
+            steps,
+        }
+    }
+
+    /// The position of the synchronization point the checkpoint builds on
+    #[inline]
+    pub fn sync_offset(&self) -> u64 {
+        self.sync_offset
+    }
+
+    /// The decoder position at the time the checkpoint was taken
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The number of decoder steps since the synchronization point
+    #[inline]
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Serializes the checkpoint into little endian bytes
+    pub fn to_bytes(&self) -> [u8; Checkpoint::SIZE] {
+        let mut bytes = [0; Checkpoint::SIZE];
+        bytes[0..8].copy_from_slice(&self.sync_offset.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.steps.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes a checkpoint created by `to_bytes`.
+    ///
+    /// Returns Invalid if @bytes does not have the size of a checkpoint.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PtError> {
+        if bytes.len() != Checkpoint::SIZE {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "invalid checkpoint size",
+            ));
+        }
+
+        let field = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        Ok(Checkpoint::new(field(0), field(8), field(16)))
+    }
+}
+
+/// The default number of blocks or instructions between the checkpoints
+/// that `step_back` records
+pub const CHECKPOINT_INTERVAL: u64 = 1024;
+
+// a later synchronization point the decoder passed, along with the decoder
+// state after the first `next` call that moved the decoder to @offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Mark {
+    pub(crate) sync_offset: u64,
+    pub(crate) offset: u64,
+    // the steps and blocks or instructions since the decoder's origin
+    pub(crate) steps: u64,
+    pub(crate) items: u64,
+}
+
+// the checkpoints recorded since the last explicit synchronization, in trace order
+#[derive(Debug, Clone)]
+pub(crate) struct Marks {
+    interval: u64,
+    marks: Vec<Mark>,
+    // no checkpoints are recorded while paused, the recorded ones are kept
+    paused: bool,
+}
+
+impl Marks {
+    pub(crate) fn new() -> Self {
+        Marks {
+            interval: CHECKPOINT_INTERVAL,
+            marks: Vec::new(),
+            paused: false,
+        }
+    }
+
+    pub(crate) fn set_interval(&mut self, interval: u64) {
+        self.interval = interval;
+        if interval == 0 {
+            self.marks.clear();
+        }
+    }
+
+    pub(crate) fn pause(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    // is the decoder far enough from the last checkpoint after @items?
+    pub(crate) fn due(&self, items: u64) -> bool {
+        let last = self.marks.last().map_or(0, |m| m.items);
+        !self.paused && self.interval != 0 && items >= last + self.interval
+    }
+
+    // records @mark if the decoder passed a synchronization point since
+    // @origin and the last checkpoint
+    pub(crate) fn record(&mut self, origin: u64, mark: Mark) {
+        let last = self.marks.last().map_or(origin, |m| m.sync_offset);
+        if self.due(mark.items) && mark.sync_offset > last {
+            self.marks.push(mark);
+        }
+    }
+
+    // the last checkpoint at or before @items
+    pub(crate) fn nearest(&self, items: u64) -> Option<Mark> {
+        self.marks.iter().rev().find(|m| m.items <= items).copied()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.marks.clear();
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<Mark> {
+        mem::take(&mut self.marks)
+    }
+
+    pub(crate) fn restore(&mut self, marks: Vec<Mark>) {
+        self.marks = marks;
+    }
+}
@@ -0,0 +1,816 @@
+use crate::config::{
+    host_cpuid_0x15, AddrConfig, AddrFilter, AddrFilterBuilder, AddrRange, Config, ConfigBuilder,
+    Cpu, CpuVendor, Frequency,
+};
+use crate::block::Block;
+use crate::error::{PtError, PtErrorCode};
+use crate::event::Event;
+use crate::flags::Status;
+use crate::image::Image;
+use crate::ring;
+use crate::stream::{BlockStream, Streamed};
+use crate::TimeConverter;
+
+use std::fs;
+use core::mem;
+use core::ptr;
+use core::slice;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(parse_format("config:10\n"), Some((10, 10)));
+        assert_eq!(parse_format("config:14-17"), Some((14, 17)));
+        assert_eq!(parse_format("config1:3"), None);
+        assert_eq!(parse_format("config:x"), None);
+    }
+
+    #[test]
+    fn test_format_field() {
+        let f = Format { lo: 14, hi: 17 };
+        assert_eq!(f.encode(3), 3 << 14);
+        // values are truncated to the field's width
+        assert_eq!(f.encode(0x1f), 0xf << 14);
+    }
+
+    #[test]
+    fn test_builder_rejects_bad_sizes() {
+        let mut b = CollectorBuilder::new();
+        b.aux_pages(3);
+        assert_eq!(b.finish().err().unwrap().code(), PtErrorCode::Invalid);
+
+        let mut b = CollectorBuilder::new();
+        b.mtc(Some(16));
+        let e = b.finish().err().unwrap();
+        assert_eq!(e.code(), PtErrorCode::Invalid);
+        assert_eq!(e.msg(), "the mtc period must be between 0 and 15");
+    }
+
+    #[test]
+    fn test_builder_rejects_bad_cyc_thresh() {
+        let mut b = CollectorBuilder::new();
+        b.cyc(Some(16));
+        let e = b.finish().err().unwrap();
+        assert_eq!(e.code(), PtErrorCode::Invalid);
+        assert_eq!(e.msg(), "the cyc threshold must be between 0 and 15");
+    }
+}
+
+const PMU_DIR: &str = "/sys/bus/event_source/devices/intel_pt";
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 8;
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+const PERF_EVENT_IOC_SET_FILTER: libc::c_ulong = 0x4008_2406;
+
+const ATTR_DISABLED: u64 = 1 << 0;
+const ATTR_EXCLUDE_USER: u64 = 1 << 4;
+const ATTR_EXCLUDE_KERNEL: u64 = 1 << 5;
+const ATTR_EXCLUDE_HV: u64 = 1 << 6;
+
+// offsets into struct perf_event_mmap_page
+const PAGE_LOCK: usize = 8;
+const PAGE_CAPABILITIES: usize = 40;
+const PAGE_TIME_SHIFT: usize = 50;
+const PAGE_TIME_MULT: usize = 52;
+const PAGE_TIME_ZERO: usize = 64;
+const PAGE_DATA_OFFSET: usize = 1040;
+const PAGE_DATA_SIZE: usize = 1048;
+const PAGE_AUX_HEAD: usize = 1056;
+const PAGE_AUX_TAIL: usize = 1064;
+const PAGE_AUX_OFFSET: usize = 1072;
+const PAGE_AUX_SIZE: usize = 1080;
+
+// the cap_user_time_zero bit of the capabilities
+const CAP_USER_TIME_ZERO: u64 = 1 << 4;
+
+// struct perf_event_attr, PERF_ATTR_SIZE_VER5
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+// a bit field of the intel_pt event config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Format {
+    lo: u32,
+    hi: u32,
+}
+
+impl Format {
+    fn encode(self, val: u64) -> u64 {
+        let width = self.hi - self.lo + 1;
+        let mask = if width >= 64 { !0 } else { (1 << width) - 1 };
+        (val & mask) << self.lo
+    }
+}
+
+// parses a pmu format description like "config:14-17"
+fn parse_format(s: &str) -> Option<(u32, u32)> {
+    let bits = s.trim().strip_prefix("config:")?;
+    let mut it = bits.splitn(2, '-');
+    let lo = it.next()?.parse().ok()?;
+    let hi = match it.next() {
+        Some(hi) => hi.parse().ok()?,
+        None => lo,
+    };
+    Some((lo, hi))
+}
+
+fn pmu_format(name: &str) -> Result<Format, PtError> {
+    fs::read_to_string(format!("{}/format/{}", PMU_DIR, name))
+        .ok()
+        .and_then(|s| parse_format(&s))
+        .map(|(lo, hi)| Format { lo, hi })
+        .ok_or_else(|| {
+            PtError::new(
+                PtErrorCode::BadConfig,
+                "the intel_pt pmu does not support a requested option",
+            )
+        })
+}
+
+// checks that @value is set in the capability bitmap @cap of the pmu.
+// capabilities the pmu doesn't report are not checked.
+fn pmu_supports(cap: &str, value: u8, msg: &'static str) -> Result<(), PtError> {
+    let bits = fs::read_to_string(format!("{}/caps/{}", PMU_DIR, cap))
+        .ok()
+        .and_then(|s| u64::from_str_radix(s.trim(), 16).ok());
+    match bits {
+        Some(b) if b & (1 << value) == 0 => Err(PtError::new(PtErrorCode::BadConfig, msg)),
+        _ => Ok(()),
+    }
+}
+
+fn pmu_value(name: &str) -> Option<u64> {
+    fs::read_to_string(format!("{}/{}", PMU_DIR, name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+// the cpu we are running on and its crystal clock ratio
+fn host_cpu() -> (Option<Cpu>, u32, u32) {
+    match Cpu::current() {
+        Ok(cpu) if cpu.vendor() == CpuVendor::INTEL => {
+            let (tsc, ctc) = host_cpuid_0x15().unwrap_or((0, 0));
+            (Some(cpu), ctc, tsc)
+        }
+        _ => (None, 0, 0),
+    }
+}
+
+/// Configures Intel PT trace collection via perf_event_open(2).
+pub struct CollectorBuilder {
+    pid: i32,
+    cpu: i32,
+    data_pages: usize,
+    aux_pages: usize,
+    snapshot: bool,
+    user: bool,
+    kernel: bool,
+    tsc: bool,
+    mtc: Option<u8>,
+    cyc: Option<u8>,
+    noretcomp: bool,
+    filters: Vec<AddrRange>,
+}
+
+impl Default for CollectorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CollectorBuilder {
+    /// Traces user space of the calling process on any cpu.
+    pub fn new() -> Self {
+        CollectorBuilder {
+            pid: 0,
+            cpu: -1,
+            data_pages: 1,
+            aux_pages: 1024,
+            snapshot: false,
+            user: true,
+            kernel: false,
+            tsc: true,
+            mtc: None,
+            cyc: None,
+            noretcomp: false,
+            filters: Vec::new(),
+        }
+    }
+
+    /// The process to trace, 0 for the calling process and -1 for all processes
+    pub fn pid(&mut self, pid: i32) -> &mut Self {
+        self.pid = pid;
+        self
+    }
+
+    /// The cpu to trace on, -1 for all cpus
+    pub fn cpu(&mut self, cpu: i32) -> &mut Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// The size of the perf data ring in pages, must be a power of two
+    pub fn data_pages(&mut self, pages: usize) -> &mut Self {
+        self.data_pages = pages;
+        self
+    }
+
+    /// The size of the AUX ring the trace is written to in pages,
+    /// must be a power of two
+    pub fn aux_pages(&mut self, pages: usize) -> &mut Self {
+        self.aux_pages = pages;
+        self
+    }
+
+    /// Keep overwriting the AUX ring instead of stopping when it is full.
+    ///
+    /// Only the most recent trace can be read in snapshot mode.
+    pub fn snapshot(&mut self, snapshot: bool) -> &mut Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// Trace user space
+    pub fn user(&mut self, user: bool) -> &mut Self {
+        self.user = user;
+        self
+    }
+
+    /// Trace the kernel, usually requires privileges
+    pub fn kernel(&mut self, kernel: bool) -> &mut Self {
+        self.kernel = kernel;
+        self
+    }
+
+    /// Emit TSC packets
+    pub fn tsc(&mut self, tsc: bool) -> &mut Self {
+        self.tsc = tsc;
+        self
+    }
+
+    /// Emit MTC packets with the IA32_RTIT_CTL.MTCFreq value @freq
+    pub fn mtc(&mut self, freq: Option<u8>) -> &mut Self {
+        self.mtc = freq;
+        self
+    }
+
+    /// Emit CYC packets with the threshold @thresh
+    ///
+    /// A CYC packet is emitted at most every 2^(thresh - 1) cycles,
+    /// or with every eligible packet if @thresh is 0.
+    /// Lower thresholds give more precise cycle counts, see `IpcCounter`,
+    /// at the cost of more trace data.
+    pub fn cyc(&mut self, thresh: Option<u8>) -> &mut Self {
+        self.cyc = thresh;
+        self
+    }
+
+    /// Disable return compression
+    pub fn noretcomp(&mut self, noretcomp: bool) -> &mut Self {
+        self.noretcomp = noretcomp;
+        self
+    }
+
+    /// Only trace the kernel address range @range.
+    ///
+    /// The range is also set as address filter of the decoder config.
+    /// At most four ranges are supported.
+    pub fn filter(&mut self, range: AddrRange) -> &mut Self {
+        self.filters.push(range);
+        self
+    }
+
+    // the event config value for the requested options
+    fn event_config(&self) -> Result<u64, PtError> {
+        let mut config = 0;
+        if self.tsc {
+            config |= pmu_format("tsc")?.encode(1);
+        }
+        if let Some(freq) = self.mtc {
+            pmu_supports(
+                "mtc_periods",
+                freq,
+                "the mtc period is not supported by the processor",
+            )?;
+            config |= pmu_format("mtc")?.encode(1);
+            config |= pmu_format("mtc_period")?.encode(freq as u64);
+        }
+        if let Some(thresh) = self.cyc {
+            pmu_supports(
+                "cycle_thresholds",
+                thresh,
+                "the cyc threshold is not supported by the processor",
+            )?;
+            config |= pmu_format("cyc")?.encode(1);
+            config |= pmu_format("cyc_thresh")?.encode(thresh as u64);
+        }
+        if self.noretcomp {
+            config |= pmu_format("noretcomp")?.encode(1);
+        }
+        Ok(config)
+    }
+
+    // the perf address filter string
+    fn filter_string(&self) -> String {
+        self.filters
+            .iter()
+            .filter(|r| r.cfg() != AddrConfig::DISABLED)
+            .map(|r| {
+                let action = match r.cfg() {
+                    AddrConfig::STOP => "stop",
+                    _ => "filter",
+                };
+                format!("{} {:#x}/{:#x}", action, r.a(), r.b() - r.a() + 1)
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Opens the intel_pt event and maps its buffers.
+    ///
+    /// The event is created disabled, call `Collector::enable` to start tracing.
+    /// Returns Invalid if a buffer size is not a power of two,
+    /// if more than four filters are set
+    /// or if the mtc period or cyc threshold is bigger than 15.
+    /// Returns BadConfig if the intel_pt pmu is not available,
+    /// does not support a requested option, mtc period or cyc threshold
+    /// or the event can't be opened.
+    /// Returns Nomem if the buffers can't be mapped.
+    pub fn finish(&self) -> Result<Collector, PtError> {
+        if !self.aux_pages.is_power_of_two() || !self.data_pages.is_power_of_two() {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "buffer sizes must be a power of two",
+            ));
+        }
+        if self.filters.len() > 4 {
+            return Err(PtError::new(PtErrorCode::Invalid, "too many address filters"));
+        }
+        if self.mtc.is_some_and(|m| m > 15) {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "the mtc period must be between 0 and 15",
+            ));
+        }
+        if self.cyc.is_some_and(|c| c > 15) {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "the cyc threshold must be between 0 and 15",
+            ));
+        }
+
+        let kind = pmu_value("type").ok_or_else(|| {
+            PtError::new(PtErrorCode::BadConfig, "the intel_pt pmu is not available")
+        })?;
+        let mut attr = PerfEventAttr {
+            kind: kind as u32,
+            size: mem::size_of::<PerfEventAttr>() as u32,
+            config: self.event_config()?,
+            flags: ATTR_DISABLED | ATTR_EXCLUDE_HV,
+            ..Default::default()
+        };
+        if !self.user {
+            attr.flags |= ATTR_EXCLUDE_USER;
+        }
+        if !self.kernel {
+            attr.flags |= ATTR_EXCLUDE_KERNEL;
+        }
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                self.pid,
+                self.cpu,
+                -1,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        } as libc::c_int;
+        if fd < 0 {
+            return Err(PtError::new(
+                PtErrorCode::BadConfig,
+                "failed to open the intel_pt event",
+            ));
+        }
+
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mut collector = Collector {
+            fd,
+            base: ptr::null_mut(),
+            base_len: (self.data_pages + 1) * page,
+            aux: ptr::null_mut(),
+            aux_len: self.aux_pages * page,
+            snapshot: self.snapshot,
+            cpu: None,
+            freq: Frequency::default(),
+            filter: None,
+        };
+
+        collector.base = map(fd, collector.base_len, libc::PROT_READ | libc::PROT_WRITE, 0)?;
+        unsafe {
+            let data_offset = collector.base.add(PAGE_DATA_OFFSET) as *const u64;
+            let data_size = collector.base.add(PAGE_DATA_SIZE) as *const u64;
+            let aux_offset = ptr::read_volatile(data_offset) + ptr::read_volatile(data_size);
+            ptr::write_volatile(collector.base.add(PAGE_AUX_OFFSET) as *mut u64, aux_offset);
+            ptr::write_volatile(
+                collector.base.add(PAGE_AUX_SIZE) as *mut u64,
+                collector.aux_len as u64,
+            );
+
+            // a read only aux mapping puts the ring into overwrite mode
+            let prot = if self.snapshot {
+                libc::PROT_READ
+            } else {
+                libc::PROT_READ | libc::PROT_WRITE
+            };
+            collector.aux = map(fd, collector.aux_len, prot, aux_offset as libc::off_t)?;
+        }
+
+        if !self.filters.is_empty() {
+            let filter = std::ffi::CString::new(self.filter_string()).unwrap();
+            let res = unsafe {
+                libc::ioctl(fd, PERF_EVENT_IOC_SET_FILTER, filter.as_ptr())
+            };
+            if res < 0 {
+                return Err(PtError::new(
+                    PtErrorCode::BadConfig,
+                    "failed to set the address filters",
+                ));
+            }
+
+            let mut builder = AddrFilterBuilder::new();
+            let setters = [
+                AddrFilterBuilder::addr0,
+                AddrFilterBuilder::addr1,
+                AddrFilterBuilder::addr2,
+                AddrFilterBuilder::addr3,
+            ];
+            for (range, set) in self.filters.iter().zip(setters.iter()) {
+                set(&mut builder, *range);
+            }
+            collector.filter = Some(builder.finish());
+        }
+
+        let (cpu, ctc, tsc) = host_cpu();
+        collector.cpu = cpu;
+        collector.freq = Frequency::new(
+            self.mtc.unwrap_or(0),
+            pmu_value("max_nonturbo_ratio").unwrap_or(0) as u8,
+            ctc,
+            tsc,
+        );
+
+        Ok(collector)
+    }
+}
+
+fn map(fd: libc::c_int, len: usize, prot: libc::c_int, off: libc::off_t) -> Result<*mut u8, PtError> {
+    let p = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, off) };
+    if p == libc::MAP_FAILED {
+        return Err(PtError::new(PtErrorCode::Nomem, "failed to map the perf buffers"));
+    }
+    Ok(p as *mut u8)
+}
+
+/// An Intel PT event opened with perf_event_open(2) and its trace buffers.
+pub struct Collector {
+    fd: libc::c_int,
+    base: *mut u8,
+    base_len: usize,
+    aux: *mut u8,
+    aux_len: usize,
+    snapshot: bool,
+    cpu: Option<Cpu>,
+    freq: Frequency,
+    filter: Option<AddrFilter>,
+}
+
+impl Collector {
+    fn ioctl(&self, req: libc::c_ulong) -> Result<(), PtError> {
+        if unsafe { libc::ioctl(self.fd, req, 0) } < 0 {
+            return Err(PtError::new(PtErrorCode::BadConfig, "perf event ioctl failed"));
+        }
+        Ok(())
+    }
+
+    /// Starts tracing.
+    pub fn enable(&mut self) -> Result<(), PtError> {
+        self.ioctl(PERF_EVENT_IOC_ENABLE)
+    }
+
+    /// Stops tracing.
+    pub fn disable(&mut self) -> Result<(), PtError> {
+        self.ioctl(PERF_EVENT_IOC_DISABLE)
+    }
+
+    /// The perf event file descriptor, e.g. for poll(2).
+    pub fn fd(&self) -> libc::c_int {
+        self.fd
+    }
+
+    // the aux_head and aux_tail fields of the mmap page
+    fn counter(&self, off: usize) -> &AtomicU64 {
+        unsafe { &*(self.base.add(off) as *const AtomicU64) }
+    }
+
+    /// The total number of bytes the hardware has written to the AUX ring.
+    pub fn aux_head(&self) -> u64 {
+        let head = self.counter(PAGE_AUX_HEAD).load(Ordering::Relaxed);
+        // the data must not be read before the head
+        fence(Ordering::Acquire);
+        head
+    }
+
+    /// The total number of bytes that have been consumed from the AUX ring.
+    pub fn aux_tail(&self) -> u64 {
+        self.counter(PAGE_AUX_TAIL).load(Ordering::Relaxed)
+    }
+
+    // hands the bytes before @tail back to the hardware
+    pub(crate) fn set_aux_tail(&mut self, tail: u64) {
+        // the data must be read before the kernel may overwrite it
+        fence(Ordering::Release);
+        self.counter(PAGE_AUX_TAIL).store(tail, Ordering::Relaxed);
+    }
+
+    /// The AUX ring buffer.
+    pub fn aux(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.aux, self.aux_len) }
+    }
+
+    /// Copies the trace out of the AUX ring.
+    ///
+    /// Returns the data that was written since the last call
+    /// and hands its space back to the hardware.
+    /// In snapshot mode the most recent ring content is returned,
+    /// starting at its first synchronization point.
+    /// Tracing should be disabled while reading a snapshot.
+    pub fn read(&mut self) -> Result<Vec<u8>, PtError> {
+        let head = self.aux_head();
+        if self.snapshot {
+            return ring::linearize(self.aux(), head, 0);
+        }
+
+        let tail = self.aux_tail();
+        let data = ring::copy_out(self.aux(), head, tail)?;
+        self.set_aux_tail(head);
+        Ok(data)
+    }
+
+    /// The cpu the trace is collected on, if it is an Intel cpu.
+    pub fn cpu(&self) -> Option<Cpu> {
+        self.cpu
+    }
+
+    /// The timing packet frequencies of the trace.
+    pub fn frequency(&self) -> Frequency {
+        self.freq
+    }
+
+    /// The conversion of TSC values from the trace into perf time.
+    ///
+    /// The parameters are read from the event's mmap page.
+    /// Returns None if the kernel doesn't provide them.
+    pub fn time_converter(&self) -> Option<TimeConverter> {
+        let lock = unsafe { &*(self.base.add(PAGE_LOCK) as *const AtomicU32) };
+        loop {
+            let seq = lock.load(Ordering::Acquire);
+            let (caps, shift, mult, zero) = unsafe {
+                (
+                    ptr::read_volatile(self.base.add(PAGE_CAPABILITIES) as *const u64),
+                    ptr::read_volatile(self.base.add(PAGE_TIME_SHIFT) as *const u16),
+                    ptr::read_volatile(self.base.add(PAGE_TIME_MULT) as *const u32),
+                    ptr::read_volatile(self.base.add(PAGE_TIME_ZERO) as *const u64),
+                )
+            };
+            fence(Ordering::Acquire);
+            // retry if the kernel updated the page while we read it
+            if lock.load(Ordering::Relaxed) == seq {
+                return (caps & CAP_USER_TIME_ZERO != 0)
+                    .then(|| TimeConverter::new(shift, mult, zero));
+            }
+        }
+    }
+
+    /// Creates a decoder configuration for trace collected by this event.
+    pub fn config<'b>(&self, buf: &'b mut [u8]) -> Result<Config<'b, ()>, PtError> {
+        let mut builder = ConfigBuilder::new(buf)?;
+        if let Some(cpu) = self.cpu {
+            builder.cpu(cpu);
+        }
+        builder.freq(self.freq);
+        if let Some(filter) = self.filter {
+            builder.filter(filter);
+        }
+        Ok(builder.finish())
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.aux.is_null() {
+                libc::munmap(self.aux as *mut libc::c_void, self.aux_len);
+            }
+            if !self.base.is_null() {
+                libc::munmap(self.base as *mut libc::c_void, self.base_len);
+            }
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Decodes the trace of a `Collector` while it is being recorded.
+///
+/// New trace data is copied out of the AUX ring whenever the decoder
+/// has consumed everything it received before,
+/// which hands the space back to the hardware right away.
+/// Blocks are reported as soon as the trace for them has been written.
+///
+/// If the decoder can't keep up and the ring fills,
+/// the kernel stops tracing until space is freed,
+/// which shows up as an overflow or decode error.
+/// Synchronize the decoder again in that case.
+pub struct LiveSession<'a> {
+    collector: Collector,
+    stream: Option<BlockStream<'a>>,
+    // set before the stream exists
+    image: Option<&'a mut Image<'a>>,
+    // did the stream run out of data?
+    starved: bool,
+    closed: bool,
+}
+
+impl<'a> LiveSession<'a> {
+    /// Starts decoding the trace of @collector.
+    ///
+    /// Returns Invalid if @collector is in snapshot mode,
+    /// since its ring is overwritten without waiting for the decoder.
+    pub fn new(collector: Collector) -> Result<Self, PtError> {
+        if collector.snapshot {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "live sessions can't use snapshot mode",
+            ));
+        }
+
+        Ok(LiveSession {
+            collector,
+            stream: None,
+            image: None,
+            starved: true,
+            closed: false,
+        })
+    }
+
+    /// The collector that records the trace.
+    pub fn collector(&mut self) -> &mut Collector {
+        &mut self.collector
+    }
+
+    /// Set the traced image.
+    ///
+    /// If @img is None, the decoder's default image is used.
+    pub fn set_image(&mut self, img: Option<&'a mut Image<'a>>) -> Result<(), PtError> {
+        match self.stream.as_mut() {
+            Some(s) => s.set_image(img),
+            None => {
+                self.image = img;
+                Ok(())
+            }
+        }
+    }
+
+    /// Waits up to @timeout milliseconds for the kernel to signal new data.
+    ///
+    /// A negative @timeout waits forever.
+    /// Returns true if there is new data in the AUX ring.
+    pub fn wait(&mut self, timeout: i32) -> Result<bool, PtError> {
+        if self.collector.aux_head() == self.collector.aux_tail() {
+            let mut pfd = libc::pollfd {
+                fd: self.collector.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pfd, 1, timeout) } < 0 {
+                return Err(PtError::new(
+                    PtErrorCode::Internal,
+                    "failed to poll the perf event",
+                ));
+            }
+        }
+        Ok(self.collector.aux_head() != self.collector.aux_tail())
+    }
+
+    // copies new data out of the ring into the stream.
+    // returns false if there was nothing new
+    fn refill(&mut self) -> Result<bool, PtError> {
+        let data = self.collector.read()?;
+        if data.is_empty() {
+            return Ok(false);
+        }
+
+        match self.stream.as_mut() {
+            Some(s) => s.push(&data)?,
+            None => {
+                let mut data = data;
+                let cfg = self.collector.config(&mut data)?;
+                let mut stream = BlockStream::new(&cfg);
+                stream.set_image(self.image.take())?;
+                self.stream = Some(stream);
+            }
+        }
+        self.starved = false;
+        Ok(true)
+    }
+
+    // the stream, with new data if it ran out
+    fn stream(&mut self) -> Result<Option<&mut BlockStream<'a>>, PtError> {
+        // once closed, the stream reports the end of the data itself
+        if self.starved && !self.refill()? && !self.closed {
+            return Ok(None);
+        }
+        match self.stream.as_mut() {
+            None if self.closed => Err(PtError::new(PtErrorCode::Eos, "end of the stream")),
+            s => Ok(s),
+        }
+    }
+
+    /// Synchronize the decoder.
+    ///
+    /// See `BlockStream::sync_forward`.
+    pub fn sync_forward(&mut self) -> Result<Streamed<Status>, PtError> {
+        let res = match self.stream()? {
+            Some(s) => s.sync_forward()?,
+            None => return Ok(Streamed::NeedMoreData),
+        };
+        self.starved = matches!(res, Streamed::NeedMoreData);
+        Ok(res)
+    }
+
+    /// Determine the next block of instructions.
+    ///
+    /// See `BlockStream::next`.
+    /// Returns NeedMoreData if all recorded trace has been decoded,
+    /// use `wait` to block until there is more.
+    pub fn next(&mut self) -> Result<Streamed<(Block, Status)>, PtError> {
+        let res = match self.stream()? {
+            Some(s) => s.next()?,
+            None => return Ok(Streamed::NeedMoreData),
+        };
+        self.starved = matches!(res, Streamed::NeedMoreData);
+        Ok(res)
+    }
+
+    /// Get the next pending event.
+    ///
+    /// See `BlockStream::event`.
+    pub fn event(&mut self) -> Result<(Event, Status), PtError> {
+        match self.stream.as_mut() {
+            Some(s) => s.event(),
+            None => Err(PtError::new(PtErrorCode::BadQuery, "no pending event")),
+        }
+    }
+
+    /// Stops tracing and marks the end of the trace.
+    ///
+    /// The remaining trace can still be decoded,
+    /// after it the decoder reports Eos.
+    pub fn finish(&mut self) -> Result<(), PtError> {
+        self.collector.disable()?;
+        self.refill()?;
+        if let Some(s) = self.stream.as_mut() {
+            s.close();
+        }
+        self.closed = true;
+        Ok(())
+    }
+}
@@ -1,14 +1,22 @@
-use super::cpu::Cpu;
+use super::cpu::{host_cpuid_0x15, Cpu};
+use super::errata::Errata;
 use super::freqency::Frequency;
 use super::filter::AddrFilter;
+use super::validate::Diagnostic;
 use crate::packet::Unknown;
+use crate::ring::{find_psb, rfind_psb};
+use crate::time::TimeCal;
 use crate::error::{ PtError, PtErrorCode };
 
-use std::mem;
-use std::borrow::Cow;
-use std::marker::PhantomData;
-use std::ffi::c_void;
-use std::os::raw::c_int;
+use core::mem;
+use alloc::borrow::Cow;
+use core::marker::PhantomData;
+use alloc::sync::Arc;
+use core::ops::Range;
+use core::ffi::c_void;
+use core::ffi::c_int;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 use libipt_sys::{
     pt_config,
@@ -72,6 +80,12 @@ mod test {
             assert_eq!(c.0.flags.variant.block.keep_tcal_on_ovf(), 0);
         }
 
+        assert_eq!(c.filter().addr1(), AddrRange::new(3, 4, AddrConfig::FILTER));
+        assert_eq!(c.buffer_len(), 3);
+        assert_eq!(c.cpu().model(), 2);
+        assert_eq!(c.freq().ctc(), 3);
+        assert_eq!(c.flags::<BlockFlags>(), BlockFlags::END_ON_CALL | BlockFlags::END_ON_JUMP);
+        assert!(c.has_callback());
         assert_eq!(c.0.addr_filter.addr0_a, 1);
         assert_eq!(c.0.addr_filter.addr0_b, 2);
         assert_eq!(unsafe { c.0.addr_filter.config.ctl.addr0_cfg() },
@@ -93,7 +107,7 @@ mod test {
                    AddrConfig::STOP as u32);
 
         unsafe {
-            let mut ukn: pt_packet_unknown = std::mem::zeroed();
+            let mut ukn: pt_packet_unknown = core::mem::zeroed();
             assert_eq!(
                 c.0.decode.callback.unwrap()(&mut ukn,
                                              c.0.as_ref(), c.0.begin,
@@ -104,10 +118,21 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_config_owned() {
+        assert!(ConfigBuilder::owned(Vec::new()).is_err());
+
+        let c = ConfigBuilder::owned(vec![1, 2, 3, 4]).unwrap().finish();
+        let parts = c.split_at_sync_points(&[2]).unwrap();
+        drop(c);
+        assert_eq!(unsafe { parts[0].buffer() }, &[3, 4]);
+        assert_eq!(Arc::strong_count(parts[0].2.as_ref().unwrap()), 1);
+    }
+
     fn check_callback<T>(cfg: &mut Config<T>, expect: T, expect_sz: i32) -> bool
         where T: PartialEq {
         unsafe {
-            let mut ukn: pt_packet_unknown = std::mem::zeroed();
+            let mut ukn: pt_packet_unknown = core::mem::zeroed();
             return
                 cfg.0.decode.callback.unwrap()(&mut ukn,
                                                cfg.0.as_ref(), cfg.0.begin,
@@ -130,6 +155,28 @@ mod test {
         for _ in 0..10 { assert!(check_callback(&mut cfg, 13, 17)) }
     }
 
+    #[test]
+    fn test_config_callback_owned() {
+        let mut data = [1; 4];
+        let mut cfg = {
+            let payload = vec![7u8; 3];
+            let b = ConfigBuilder::with_callback(
+                &mut data,
+                move |_, p| (Unknown::new((payload.clone(), p[0])), 2))
+                .unwrap();
+            b.finish()
+        };
+
+        // the closure and what it captured outlive the builder
+        assert!(check_callback(&mut cfg, (vec![7; 3], 1), 2));
+        assert_eq!(Arc::strong_count(cfg.2.as_ref().unwrap()), 1);
+        // the split configs would share the closure
+        assert_eq!(
+            cfg.split_at_sync_points(&[0]).err().unwrap().code(),
+            PtErrorCode::Invalid
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_config_callback_out_of_bounds() {
@@ -142,13 +189,70 @@ mod test {
         }).unwrap().cpu(Cpu::intel(1, 2, 3)).finish();
 
         unsafe {
-            let mut ukn: pt_packet_unknown = std::mem::zeroed();
+            let mut ukn: pt_packet_unknown = core::mem::zeroed();
             cfg.0.decode.callback.unwrap()(&mut ukn,
                                            cfg.0.as_ref(), cfg.0.begin,
                                            cfg.0.decode.context);
         }
     }
 
+    #[test]
+    fn test_config_split() {
+        let mut data = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let c = ConfigBuilder::new(&mut data)
+            .unwrap()
+            .cpu(Cpu::intel(1, 2, 3))
+            .freq(Frequency::new(1, 2, 3, 4))
+            .finish();
+
+        let parts = c.split_at_sync_points(&[7, 2, 2, 42]).unwrap();
+        assert_eq!(parts.len(), 2);
+        unsafe {
+            assert_eq!(parts[0].buffer(), [2, 3, 4, 5, 6]);
+            assert_eq!(parts[1].buffer(), [7, 8, 9]);
+        }
+        for p in parts {
+            assert_eq!(p.0.cpu.model, 2);
+            assert_eq!(p.0.mtc_freq, 1);
+            assert_eq!(p.0.cpuid_0x15_eax, 4);
+        }
+
+        assert!(c.split_at_sync_points(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_builder_build() {
+        let mut data = [0; 4];
+        let mut b = ConfigBuilder::new(&mut data).unwrap();
+        b.mtc_freq(3).nom_freq(24).cpuid_0x15(2, 168);
+        let c = b.build().unwrap();
+        assert_eq!(c.0.mtc_freq, 3);
+        assert_eq!(c.0.nom_freq, 24);
+        assert_eq!(c.0.cpuid_0x15_eax, 2);
+        assert_eq!(c.0.cpuid_0x15_ebx, 168);
+
+        let cal = TimeCal { freq: c.freq(), ..TimeCal::default() };
+        let mut other = [0; 4];
+        let c = ConfigBuilder::new(&mut other).unwrap().time_cal(&cal).build().unwrap();
+        assert_eq!(c.freq(), cal.freq);
+
+        b.mtc_freq(16);
+        assert_eq!(b.build().err().unwrap().code(), PtErrorCode::BadConfig);
+        b.freq(Frequency::new(16, 24, 168, 2));
+        assert_eq!(b.build().err().unwrap().code(), PtErrorCode::BadConfig);
+        b.freq(Frequency::new(3, 24, 168, 0));
+        assert_eq!(b.build().err().unwrap().code(), PtErrorCode::BadConfig);
+        b.cpuid_0x15(0, 0)
+            .filter(AddrFilterBuilder::new()
+                .addr1(AddrRange::new(4, 3, AddrConfig::FILTER))
+                .finish());
+        assert_eq!(b.build().err().unwrap().code(), PtErrorCode::BadConfig);
+        b.filter(AddrFilterBuilder::new()
+            .addr1(AddrRange::new(4, 3, AddrConfig::DISABLED))
+            .finish());
+        assert!(b.build().is_ok());
+    }
+
     #[test]
     fn test_builder_buf_lifetimes() {
         let mut x = [10; 10];
@@ -162,37 +266,108 @@ mod test {
         }
         unsafe { assert_eq!(a.buffer(), [10; 10]) };
     }
+
+    #[test]
+    fn test_config_raw() {
+        let mut x = [10; 10];
+        let mut c = ConfigBuilder::new(&mut x).unwrap().finish();
+        assert_eq!(c.as_ptr(), c.as_mut_ptr() as *const _);
+        let raw = c.into_raw();
+        let c = unsafe { Config::<()>::from_raw(&raw) };
+        unsafe { assert_eq!(c.buffer(), [10; 10]) };
+    }
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_config_thread_safety() {
+        assert_send::<Config<()>>();
+        assert_send::<crate::event::QueryDecoder<()>>();
+        assert_sync::<crate::event::QueryDecoder<()>>();
+        assert_send::<crate::packet::PacketDecoder<()>>();
+        assert_send::<crate::packet::Encoder<()>>();
+        assert_send::<crate::image::SectionCache>();
+        assert_sync::<crate::image::SectionCache>();
+
+        let mut x = [10; 10];
+        let c = ConfigBuilder::new(&mut x).unwrap().finish();
+        std::thread::scope(|s| {
+            s.spawn(move || unsafe { assert_eq!(c.buffer(), [10; 10]) });
+        });
+    }
 }
 
 unsafe extern "C" fn decode_callback<'a, F, C>(ukn: *mut pt_packet_unknown,
                                                cfg: *const pt_config,
                                                pos: *const u8,
                                                ctx: *mut c_void) -> c_int
-    where F: FnMut(&Config<C>, &[u8]) -> (Unknown<C>, u32) {
+    where F: Fn(&Config<C>, &[u8]) -> (Unknown<C>, u32) {
 
     let sz = (*cfg).end as usize - pos as usize;
-    let pos = std::slice::from_raw_parts(pos, sz);
+    let pos = core::slice::from_raw_parts(pos, sz);
 
-    let c = ctx as *mut F;
-    let c = &mut *c;
+    // decoders sharing the config may call it concurrently, never through `&mut`
+    let c = &*(ctx as *const F);
 
     let (res, bytes) = c(&(&*cfg).into(), pos);
     (*ukn).priv_ = match res.0 {
         Some(r) => Box::into_raw(r) as *mut _,
-        None => std::ptr::null_mut()
+        None => core::ptr::null_mut()
     };
 
     bytes as i32
 }
 
 /// A helper type to create the libipt Configuration instance
-pub struct ConfigBuilder<'a, T> (pt_config, PhantomData<&'a mut T>);
+pub struct ConfigBuilder<'a, T> (pt_config, PhantomData<&'a mut T>, Option<Arc<Owned>>);
 impl<'a, T> ConfigBuilder<'a, T> {
     // when theres a bug here, there might be on in `new` too.
     /// Initializes a Config instance with a buffer and decoder callback
-    pub fn with_callback<F>(buf: &'a mut [u8], mut cb: F) -> Result<Self, PtError>
-        where F: FnMut(&Config<T>, &[u8]) -> (Unknown<T>, u32),
-              F: 'a {
+    ///
+    /// @cb is called for every unknown packet with the config
+    /// and the trace from the packet's position on.
+    /// It returns the decoded packet payload and the packet's size in bytes.
+    /// The closure is boxed and kept alive by all configs created from this builder
+    /// and by the decoders allocated with them.
+    /// These decoders may be used on different threads at the same time,
+    /// so the closure has to be `Fn`, `Send` and `Sync`.
+    /// Use a `Mutex` or atomics for state that it updates.
+    ///
+    /// A decoder can't be moved to another thread with a closure that isn't `Send`:
+    ///
+    /// ```compile_fail
+    /// use libipt::ConfigBuilder;
+    /// use libipt::packet::{PacketDecoder, Unknown};
+    /// use std::rc::Rc;
+    ///
+    /// let buf: &'static mut [u8] = Box::leak(vec![0u8; 16].into_boxed_slice());
+    /// let payload = Rc::new(0u8);
+    /// let cfg = ConfigBuilder::with_callback(buf, move |_, _| (Unknown::new(*payload), 1))
+    ///     .unwrap()
+    ///     .finish();
+    /// let mut dec = PacketDecoder::new(&cfg).unwrap();
+    /// std::thread::spawn(move || dec.sync_forward()).join().unwrap().unwrap();
+    /// ```
+    ///
+    /// It can with one that is:
+    ///
+    /// ```no_run
+    /// use libipt::ConfigBuilder;
+    /// use libipt::packet::{PacketDecoder, Unknown};
+    /// use std::sync::Arc;
+    ///
+    /// let buf: &'static mut [u8] = Box::leak(vec![0u8; 16].into_boxed_slice());
+    /// let payload = Arc::new(0u8);
+    /// let cfg = ConfigBuilder::with_callback(buf, move |_, _| (Unknown::new(*payload), 1))
+    ///     .unwrap()
+    ///     .finish();
+    /// let mut dec = PacketDecoder::new(&cfg).unwrap();
+    /// std::thread::spawn(move || dec.sync_forward()).join().unwrap().unwrap();
+    /// ```
+    pub fn with_callback<F>(buf: &'a mut [u8], cb: F) -> Result<Self, PtError>
+        where F: Fn(&Config<T>, &[u8]) -> (Unknown<T>, u32),
+              F: Send + Sync + 'a {
         // yeah.. libipt doesnt handle this -_-
         if buf.len() < 1 { return Err(
             PtError::new(PtErrorCode::Invalid, "buffer cant be empty!")
@@ -201,9 +376,11 @@ impl<'a, T> ConfigBuilder<'a, T> {
         cfg.size  = mem::size_of::<pt_config>();
         cfg.begin = buf.as_mut_ptr();
         cfg.end   = unsafe { buf.as_mut_ptr().offset(buf.len() as isize) };
+        let cb = Box::into_raw(Box::new(cb)) as *mut c_void;
         cfg.decode.callback = Some(decode_callback::<F, T>);
-        cfg.decode.context  = &mut cb as *mut _ as *mut c_void;
-        Ok(ConfigBuilder::<T>(cfg, PhantomData))
+        cfg.decode.context  = cb;
+        let owned = Owned::Callback(cb, free_callback::<F>);
+        Ok(ConfigBuilder::<T>(cfg, PhantomData, Some(Arc::new(owned))))
     }
 
     /// The cpu used for capturing the data.
@@ -216,6 +393,14 @@ impl<'a, T> ConfigBuilder<'a, T> {
         self
     }
 
+    /// The workarounds for processor errata to enable.
+    ///
+    /// This replaces the workarounds chosen by `cpu`, so call it afterwards.
+    pub fn errata(&mut self, errata: Errata) -> &mut Self {
+        self.0.errata = errata.0;
+        self
+    }
+
     /// Frequency values used for timing packets (mtc)
     pub fn freq(&mut self, freq: Frequency) -> &mut Self {
         self.0.mtc_freq = freq.mtc;
@@ -226,6 +411,48 @@ impl<'a, T> ConfigBuilder<'a, T> {
         self
     }
 
+    /// The time calibration captured from another decoder of the trace.
+    ///
+    /// Sets the frequency values of @cal, see `TimeCal`.
+    pub fn time_cal(&mut self, cal: &TimeCal) -> &mut Self {
+        self.freq(cal.freq)
+    }
+
+    /// The Mini Time Counter (MTC) frequency as defined in IA32_RTIT_CTL.MTCFreq
+    ///
+    /// Values that don't fit in the 4 bits of the MTCFreq field are rejected by `build`.
+    pub fn mtc_freq(&mut self, mtc: u8) -> &mut Self {
+        self.0.mtc_freq = mtc;
+        self
+    }
+
+    /// The nominal or max non-turbo frequency
+    pub fn nom_freq(&mut self, nom: u8) -> &mut Self {
+        self.0.nom_freq = nom;
+        self
+    }
+
+    /// The values of eax and ebx on a cpuid call for leaf 0x15
+    pub fn cpuid_0x15(&mut self, eax: u32, ebx: u32) -> &mut Self {
+        self.0.cpuid_0x15_eax = eax;
+        self.0.cpuid_0x15_ebx = ebx;
+        self
+    }
+
+    /// Sets the cpuid 0x15 values to the ones of the processor this code is running on.
+    ///
+    /// Without them, MTC packets can't be converted to time stamps.
+    /// Only use this for traces recorded on this machine.
+    /// Returns NotSupported if the values can't be determined,
+    /// neither with cpuid nor from the intel_pt PMU in sysfs.
+    pub fn host_cpuid_0x15(&mut self) -> Result<&mut Self, PtError> {
+        let (eax, ebx) = host_cpuid_0x15().ok_or(PtError::new(
+            PtErrorCode::NotSupported,
+            "the tsc to crystal clock ratio is unknown"
+        ))?;
+        Ok(self.cpuid_0x15(eax, ebx))
+    }
+
     /// Decoder specific flags
     pub fn flags(&mut self, flags: impl Into<pt_conf_flags>) -> &mut Self {
         self.0.flags = flags.into();
@@ -240,7 +467,21 @@ impl<'a, T> ConfigBuilder<'a, T> {
 
     /// turn itself into a new `Config`
     pub fn finish(&self) -> Config<'a, T> {
-        Config(Cow::Owned(self.0), self.1)
+        Config(Cow::Owned(self.0), self.1, self.2.clone())
+    }
+
+    /// Checks the settings and turns itself into a new `Config`.
+    ///
+    /// Returns BadConfig if `Config::validate` finds an error,
+    /// e.g. if the MTC frequency doesn't fit in IA32_RTIT_CTL.MTCFreq,
+    /// if only one of the cpuid 0x15 values is set
+    /// or if an enabled address range ends before it begins.
+    pub fn build(&self) -> Result<Config<'a, T>, PtError> {
+        let cfg = self.finish();
+        match cfg.validate().into_iter().find(Diagnostic::is_error) {
+            Some(d) => Err(PtError::new(PtErrorCode::BadConfig, d.message())),
+            None => Ok(cfg)
+        }
     }
 }
 
@@ -257,26 +498,263 @@ impl<'a> ConfigBuilder<'a, ()> {
         cfg.size  = mem::size_of::<pt_config>();
         cfg.begin = buf.as_mut_ptr();
         cfg.end   = unsafe { buf.as_mut_ptr().offset(buf.len() as isize) };
-        Ok(ConfigBuilder::<()>(cfg, PhantomData))
+        Ok(ConfigBuilder::<()>(cfg, PhantomData, None))
     }
 }
 
+impl ConfigBuilder<'static, ()> {
+    /// Initializes a Config instance with a buffer it takes ownership of.
+    ///
+    /// Unlike with `new`, the caller doesn't need to keep the buffer alive:
+    /// it is shared by all configs created from this builder
+    /// and by the decoders and encoders allocated with them,
+    /// and freed once the last of them is dropped.
+    /// returns `Invalid` when buf is empty
+    pub fn owned(buf: impl Into<Box<[u8]>>) -> Result<Self, PtError> {
+        let buf = buf.into();
+        if buf.is_empty() { return Err(
+            PtError::new(PtErrorCode::Invalid, "buffer cant be empty!")
+        )}
+        let len = buf.len();
+        let buf = Box::into_raw(buf);
+        Ok(Self::with_buffer(Owned::Heap(buf), buf as *mut u8, len))
+    }
+
+    // creates a builder for the @len bytes at @begin which are owned by @buf
+    pub(crate) fn with_buffer(buf: Owned, begin: *mut u8, len: usize) -> Self {
+        let mut cfg: pt_config = unsafe { mem::zeroed() };
+        cfg.size  = mem::size_of::<pt_config>();
+        cfg.begin = begin;
+        cfg.end   = unsafe { begin.add(len) };
+        ConfigBuilder::<()>(cfg, PhantomData, Some(Arc::new(buf)))
+    }
+}
+
+/// Memory owned by configs and the decoders using them:
+/// a trace buffer or a decode callback.
+///
+/// It is only ever accessed through the pointers in `pt_config`.
+pub(crate) enum Owned {
+    Heap(*mut [u8]),
+    // only kept to unmap the file when dropped
+    #[cfg(feature = "mmap")]
+    Mapped(#[allow(dead_code)] memmap2::MmapMut),
+    // a boxed closure and the function that frees it
+    Callback(*mut c_void, unsafe fn(*mut c_void)),
+}
+
+// SAFETY: the memory is never accessed through `Owned`, it is only freed.
+// Buffers can be freed from any thread and `with_callback` only accepts
+// closures that are Send and Sync, so they can be dropped on any thread too.
+unsafe impl Send for Owned {}
+unsafe impl Sync for Owned {}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        match *self {
+            Owned::Heap(b) => drop(unsafe { Box::from_raw(b) }),
+            Owned::Callback(cb, free) => unsafe { free(cb) },
+            #[cfg(feature = "mmap")]
+            Owned::Mapped(_) => (),
+        }
+    }
+}
+
+// frees a decode callback of type @F
+unsafe fn free_callback<F>(cb: *mut c_void) {
+    drop(Box::from_raw(cb as *mut F));
+}
+
 /// A libipt configuration
-pub struct Config<'a, C> (pub(crate) Cow<'a, pt_config>, PhantomData<&'a mut C>);
+///
+/// The config is Send if the payload type `C` of its decode callback is.
+/// It is not Sync, encoders write to the trace buffer through a shared config.
+pub struct Config<'a, C> (
+    pub(crate) Cow<'a, pt_config>,
+    PhantomData<&'a mut C>,
+    pub(crate) Option<Arc<Owned>>,
+);
+
+// SAFETY: the raw pointers of the config point into a trace buffer that is
+// either borrowed mutably for 'a or owned, and to a decode callback.
+// The callback's type is erased, `C` is only the type of its payloads.
+// `with_callback` requires the callback to be Send and Sync.
+unsafe impl<C: Send> Send for Config<'_, C> {}
+
 impl<'a, C> Config<'a, C> {
     /// Gets this configs buffer.
     /// This operation is unsafe because an encoder might write into the buffer
     /// at any time
-    pub unsafe fn buffer(&self) -> &'a [u8] {
-        std::slice::from_raw_parts(
-            self.0.begin,
-            self.0.end as usize - self.0.begin as usize
+    ///
+    /// The buffer is borrowed from the config, not for @'a:
+    /// an owned buffer is freed with the last config or decoder using it.
+    ///
+    /// ```compile_fail
+    /// use libipt::ConfigBuilder;
+    ///
+    /// let cfg = ConfigBuilder::<()>::owned(vec![0u8; 16]).unwrap().finish();
+    /// let buf = unsafe { cfg.buffer() };
+    /// drop(cfg);
+    /// assert_eq!(buf[0], 0);
+    /// ```
+    pub unsafe fn buffer(&self) -> &[u8] {
+        core::slice::from_raw_parts(self.0.begin, self.buffer_len())
+    }
+
+    /// The addresses of the first byte and one past the last byte of the buffer
+    pub fn buffer_range(&self) -> Range<*const u8> {
+        self.0.begin as *const u8..self.0.end as *const u8
+    }
+
+    /// The size of the buffer in bytes
+    pub fn buffer_len(&self) -> usize {
+        self.0.end as usize - self.0.begin as usize
+    }
+
+    // the offset of the first or, if @last, the last PSB packet in the buffer,
+    // where an unsynchronized decoder synchronizes.
+    // Eos if there is none, like libipt's synchronization.
+    pub(crate) fn psb_offset(&self, last: bool) -> Result<u64, PtError> {
+        // SAFETY: only called by decoders, which read the buffer anyway
+        let buf = unsafe { self.buffer() };
+        let offset = if last { rfind_psb(buf) } else { find_psb(buf) };
+        offset.map(|o| o as u64).ok_or(PtError::new(PtErrorCode::Eos, "no psb packet in the trace"))
+    }
+
+    /// Creates a Config from the raw config @cfg.
+    ///
+    /// The raw config is borrowed, not copied.
+    ///
+    /// # Safety
+    /// @cfg must point to a valid config for @'a.
+    /// Its trace buffer and decode callback must stay valid for @'a,
+    /// the callback has to be of type `C`.
+    pub unsafe fn from_raw(cfg: *const pt_config) -> Self {
+        Config::from(&*cfg)
+    }
+
+    /// A pointer to the raw config
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the config is.
+    pub fn as_ptr(&self) -> *const pt_config {
+        &*self.0
+    }
+
+    /// A mutable pointer to the raw config
+    ///
+    /// A borrowed raw config is copied first.
+    /// See `as_ptr`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_config {
+        self.0.to_mut()
+    }
+
+    /// Release the raw config.
+    ///
+    /// An owned trace buffer or decode callback is leaked,
+    /// the raw config still points to it.
+    pub fn into_raw(self) -> pt_config {
+        mem::forget(self.2);
+        self.0.into_owned()
+    }
+
+    /// The cpu used for capturing the data
+    pub fn cpu(&self) -> Cpu {
+        Cpu(self.0.cpu)
+    }
+
+    /// Frequency values used for timing packets
+    pub fn freq(&self) -> Frequency {
+        Frequency::new(
+            self.0.mtc_freq,
+            self.0.nom_freq,
+            self.0.cpuid_0x15_ebx,
+            self.0.cpuid_0x15_eax
         )
     }
+
+    /// The decoder specific flags, e.g. `cfg.flags::<BlockFlags>()`.
+    ///
+    /// The flags are stored for one kind of decoder only,
+    /// use the flags type of the decoder the config is used with.
+    pub fn flags<F: From<pt_conf_flags>>(&self) -> F {
+        F::from(self.0.flags)
+    }
+
+    /// Returns true if a decoder callback is set.
+    pub fn has_callback(&self) -> bool {
+        self.0.decode.callback.is_some()
+    }
+
+    /// The enabled workarounds for processor errata
+    pub fn errata(&self) -> Errata {
+        Errata(self.0.errata)
+    }
+
+    /// The address filter configuration used while recording
+    pub fn filter(&self) -> AddrFilter {
+        AddrFilter(self.0.addr_filter)
+    }
+
+    /// Splits this config into one config per synchronization region.
+    ///
+    /// Each of the returned configs covers the bytes from one of the
+    /// @offsets up to the next one (or the end of the buffer).
+    /// Bytes in front of the first offset are not covered.
+    /// CPU, timing, flags and filter settings are kept.
+    /// The @offsets are typically the sync offsets of a packet decoder,
+    /// they are sorted and offsets outside of the buffer are ignored.
+    /// Returns Invalid if a decoder callback is set,
+    /// since the configs would share the callback and its state
+    /// while their decoders may run on different threads.
+    pub fn split_at_sync_points(&self, offsets: &[u64]) -> Result<Vec<Config<'a, C>>, PtError> {
+        if self.has_callback() {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "a config with a decoder callback can't be split"
+            ));
+        }
+
+        let size = self.buffer_len();
+        let mut offsets: Vec<usize> = offsets
+            .iter()
+            .map(|&o| o as usize)
+            .filter(|&o| o < size)
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        Ok(offsets
+            .iter()
+            .enumerate()
+            .map(|(i, &begin)| {
+                let end = offsets.get(i + 1).copied().unwrap_or(size);
+                self.slice(begin, end)
+            })
+            .collect())
+    }
+
+    // a copy of this config which only covers the bytes from @begin up to @end
+    // of its buffer. the caller must make sure it has no decoder callback.
+    fn slice(&self, begin: usize, end: usize) -> Config<'a, C> {
+        debug_assert!(begin <= end);
+        debug_assert!(end <= self.buffer_len());
+        debug_assert!(!self.has_callback());
+        let mut cfg = *self.0;
+        cfg.begin = unsafe { self.0.begin.add(begin) };
+        cfg.end = unsafe { self.0.begin.add(end) };
+        Config(Cow::Owned(cfg), PhantomData, self.2.clone())
+    }
+}
+
+impl<'a, C> From<pt_config> for Config<'a, C> {
+    fn from(cfg: pt_config) -> Self {
+        Config(Cow::Owned(cfg), PhantomData, None)
+    }
 }
 
 impl<'a, C> From<&'a pt_config> for Config<'a, C> {
     fn from(cfg: &'a pt_config) -> Self {
-        Config(Cow::Borrowed(cfg), PhantomData)
+        Config(Cow::Borrowed(cfg), PhantomData, None)
     }
 }
@@ -6,6 +6,8 @@ use libipt_sys::{
     pt_cpu_errata,
 };
 
+use crate::error::PtError;
+
 use bitflags::bitflags;
 
 #[cfg(test)]
@@ -38,10 +40,77 @@ mod test {
         assert_eq!(e.skd007(), 1);
         assert_eq!(e.skd022(), 1);
     }
+
+    #[test]
+    fn test_cpu_signature() {
+        // a skylake desktop cpu
+        let cpu = Cpu::from_signature(CpuVendor::INTEL, 0x000506e3);
+        assert_eq!(cpu.family(), 6);
+        assert_eq!(cpu.model(), 0x5e);
+        assert_eq!(cpu.stepping(), 3);
+
+        // the extended family is only used with family 0xf
+        let cpu = Cpu::from_signature(CpuVendor::INTEL, 0x00100f41);
+        assert_eq!(cpu.family(), 0x10);
+        assert_eq!(cpu.model(), 0x04);
+        assert_eq!(cpu.stepping(), 1);
+    }
+}
+
+// the TSC to ART ratio of the intel_pt PMU
+#[cfg(feature = "std")]
+const TSC_ART_RATIO: &str = "/sys/bus/event_source/devices/intel_pt/tsc_art_ratio";
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{CpuidResult, __cpuid};
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{CpuidResult, __cpuid};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+// cpuid is only safe to call since rust 1.87
+#[allow(unused_unsafe)]
+fn cpuid(leaf: u32) -> CpuidResult {
+    unsafe { __cpuid(leaf) }
+}
+
+/// Reads the values of eax and ebx of cpuid leaf 0x15 on this machine.
+///
+/// Falls back to the TSC to ART ratio the intel_pt PMU exposes in sysfs
+/// if the leaf isn't available, e.g. in some virtual machines.
+/// Returns None if neither source provides the values.
+pub(crate) fn host_cpuid_0x15() -> Option<(u32, u32)> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if cpuid(0).eax >= 0x15 {
+        let r = cpuid(0x15);
+        if r.eax != 0 && r.ebx != 0 {
+            return Some((r.eax, r.ebx));
+        }
+    }
+
+    tsc_art_ratio()
+}
+
+#[cfg(feature = "std")]
+fn tsc_art_ratio() -> Option<(u32, u32)> {
+    // the PMU shows the ratio as ebx:eax
+    let ratio = std::fs::read_to_string(TSC_ART_RATIO).ok()?;
+    let (ebx, eax) = ratio.trim().split_once(':')?;
+    match (eax.parse().ok()?, ebx.parse().ok()?) {
+        (0, _) | (_, 0) => None,
+        r => Some(r),
+    }
+}
+
+// sysfs can't be read without std
+#[cfg(not(feature = "std"))]
+fn tsc_art_ratio() -> Option<(u32, u32)> {
+    None
 }
 
 bitflags! {
     /// i suppose this is relevant when/if amd finally gets intelpt support?
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
     pub struct CpuVendor: i32 {
         const INTEL = pt_cpu_vendor_pcv_intel;
         const UNKNOWN = pt_cpu_vendor_pcv_unknown;
@@ -50,7 +119,7 @@ bitflags! {
 
 /// A Cpu identifier
 #[derive(Clone, Copy, Debug)]
-pub struct Cpu (pub(super) pt_cpu);
+pub struct Cpu (pub(crate) pt_cpu);
 impl Cpu {
     pub fn new(vendor: CpuVendor, family: u16, model: u8, stepping: u8) -> Self {
         Cpu(pt_cpu{ vendor: vendor.bits(), family, model, stepping })
@@ -61,6 +130,55 @@ impl Cpu {
         Cpu::new(CpuVendor::INTEL, family, model, stepping)
     }
 
+    /// Identifies the processor this code is running on.
+    ///
+    /// This does what libipt's `pt_cpu_read` does, which isn't exported.
+    /// Pass the result to `ConfigBuilder::cpu` to apply the workarounds
+    /// for the errata of this processor when decoding traces recorded on this machine.
+    /// Processors of other vendors are identified with `CpuVendor::UNKNOWN`.
+    /// Returns NotSupported if this isn't an x86 processor.
+    pub fn current() -> Result<Self, PtError> {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            let leaf0 = cpuid(0);
+            let intel = leaf0.ebx == u32::from_le_bytes(*b"Genu")
+                && leaf0.edx == u32::from_le_bytes(*b"ineI")
+                && leaf0.ecx == u32::from_le_bytes(*b"ntel");
+            let vendor = if intel { CpuVendor::INTEL } else { CpuVendor::UNKNOWN };
+            Ok(Cpu::from_signature(vendor, cpuid(1).eax))
+        }
+
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        Err(PtError::new(crate::error::PtErrorCode::NotSupported, "not an x86 processor"))
+    }
+
+    // decodes the processor signature in eax of cpuid leaf 1
+    fn from_signature(vendor: CpuVendor, eax: u32) -> Self {
+        let mut family = ((eax >> 8) & 0xf) as u16;
+        if family == 0xf {
+            family += ((eax >> 20) & 0xff) as u16;
+        }
+        let mut model = ((eax >> 4) & 0xf) as u8;
+        if family == 0x6 || family >= 0xf {
+            model += (((eax >> 16) & 0xf) << 4) as u8;
+        }
+        Cpu::new(vendor, family, model, (eax & 0xf) as u8)
+    }
+
+    /// The processor vendor
+    pub fn vendor(&self) -> CpuVendor {
+        CpuVendor::from_bits_retain(self.0.vendor)
+    }
+
+    /// The cpu family
+    pub fn family(&self) -> u16 { self.0.family }
+
+    /// The cpu model
+    pub fn model(&self) -> u8 { self.0.model }
+
+    /// The stepping
+    pub fn stepping(&self) -> u8 { self.0.stepping }
+
     /// determines processor specific workarounds
     pub(super) fn determine_errata(self) -> pt_errata {
         let mut errata = pt_errata {
@@ -73,3 +191,10 @@ impl Cpu {
         errata
     }
 }
+
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Cpu {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Cpu::new(u.arbitrary()?, u.arbitrary()?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
@@ -0,0 +1,122 @@
+use super::cpu::Cpu;
+
+use core::fmt;
+use libipt_sys::pt_errata;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_errata_flags() {
+        let mut e = Errata::new();
+        assert!(!e.bdm70());
+        assert!(!e.skl168());
+
+        e.set_bdm70(true).set_skl168(true);
+        assert!(e.bdm70());
+        assert!(e.skl168());
+        assert!(!e.apl11());
+        assert_eq!(e.0.bdm70(), 1);
+
+        e.set_bdm70(false);
+        assert!(!e.bdm70());
+    }
+}
+
+macro_rules! errata {
+    ($($(#[$doc:meta])* $name:ident, $set:ident;)*) => {
+        impl Errata {
+            $(
+            $(#[$doc])*
+            #[inline]
+            pub fn $name(&self) -> bool { self.0.$name() != 0 }
+
+            $(#[$doc])*
+            #[inline]
+            pub fn $set(&mut self, on: bool) -> &mut Self {
+                self.0.$set(on as u32);
+                self
+            }
+            )*
+        }
+
+        impl fmt::Debug for Errata {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Errata")
+                    $(.field(stringify!($name), &self.$name()))*
+                    .finish()
+            }
+        }
+
+        #[cfg(feature = "fuzzing")]
+        impl<'a> arbitrary::Arbitrary<'a> for Errata {
+            fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+                let mut errata = Errata::new();
+                $(errata.$set(u.arbitrary()?);)*
+                Ok(errata)
+            }
+        }
+    };
+}
+
+/// Processor errata the decoders work around
+///
+/// `ConfigBuilder::cpu` enables the workarounds for the errata of a processor.
+/// When the processor is unknown or a trace shows symptoms of an erratum
+/// anyway, the workarounds can be chosen one by one with `ConfigBuilder::errata`.
+#[derive(Clone, Copy)]
+pub struct Errata(pub(super) pt_errata);
+
+impl Errata {
+    /// No workarounds enabled
+    pub fn new() -> Self {
+        Errata(pt_errata {
+            _bitfield_1: Default::default(),
+            reserved: Default::default()
+        })
+    }
+
+    /// The workarounds libipt enables for the errata of @cpu
+    pub fn for_cpu(cpu: Cpu) -> Self {
+        Errata(cpu.determine_errata())
+    }
+}
+
+impl Default for Errata {
+    fn default() -> Self { Errata::new() }
+}
+
+errata! {
+    /// BDM70: Intel(R) Processor Trace PSB+ Packets May Contain Unexpected Packets.
+    ///
+    /// Same as: SKD024, SKL021, KBL021.
+    bdm70, set_bdm70;
+    /// BDM64: An Incorrect LBR or Intel(R) Processor Trace Packet May Be Recorded
+    /// Following a Transactional Abort.
+    ///
+    /// Same as: SKD016, SKL016, KBL016.
+    bdm64, set_bdm64;
+    /// SKD007: Intel(R) PT Buffer Overflow May Result in Incorrect Packets.
+    ///
+    /// Same as: SKL049, KBL041.
+    skd007, set_skd007;
+    /// SKD022: VM Entry That Clears TraceEn May Generate a FUP.
+    ///
+    /// Same as: SKL024, KBL023.
+    skd022, set_skd022;
+    /// SKD010: Intel(R) PT FUP May be Dropped After OVF.
+    ///
+    /// Same as: SKD014, SKL033, KBL030.
+    skd010, set_skd010;
+    /// SKL014: Intel(R) PT TIP.PGD May Not Have Target IP Payload.
+    ///
+    /// Same as: KBL014.
+    skl014, set_skl014;
+    /// APL12: Intel(R) PT OVF May Be Followed By An Unexpected FUP Packet.
+    apl12, set_apl12;
+    /// APL11: Intel(R) PT OVF Packet May Be Followed by TIP.PGD Packet.
+    apl11, set_apl11;
+    /// SKL168: Intel(R) PT CYC Packets Can be Dropped When Immediately Preceding PSB.
+    skl168, set_skl168;
+}
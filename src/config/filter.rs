@@ -1,5 +1,7 @@
-use std::mem;
-use std::convert::TryFrom;
+use core::fmt;
+use core::mem;
+use core::convert::TryFrom;
+use alloc::vec::Vec;
 use libipt_sys::pt_conf_addr_filter;
 use num_enum::TryFromPrimitive;
 
@@ -32,14 +34,46 @@ mod test {
         assert_eq!(filter.addr3().b(), 8);
         assert_eq!(filter.addr3().cfg(), AddrConfig::DISABLED);
     }
+
+    #[test]
+    fn test_addrfilter_ranges() {
+        let mut builder = AddrFilterBuilder::new();
+        let filter = builder.finish();
+        assert!(filter.traced(0));
+        assert!(!filter.addr0().contains(0));
+
+        let filter = builder
+            .addr1(AddrRange::new(0x1000, 0x1fff, AddrConfig::FILTER))
+            .addr2(AddrRange::new(0x3000, 0x3fff, AddrConfig::STOP))
+            .finish();
+        assert_eq!(filter.range(1), Some(filter.addr1()));
+        assert_eq!(filter.range(4), None);
+        assert_eq!(filter.ranges()[2].cfg(), AddrConfig::STOP);
+        assert!(filter.traced(0x1000));
+        assert!(filter.traced(0x1fff));
+        assert!(!filter.traced(0x2000));
+        assert!(!filter.traced(0x3000));
+    }
 }
 
-#[derive(Clone, Copy, TryFromPrimitive, PartialEq, Debug)]
+/// How an address range is used while recording
+///
+/// This corresponds to the ADDRn_CFG fields in IA32_RTIT_CTL MSR
+#[derive(Clone, Copy, TryFromPrimitive, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 #[repr(u32)]
-pub enum AddrConfig {DISABLED, FILTER, STOP }
+pub enum AddrConfig {
+    /// The range is not used
+    DISABLED,
+    /// Only code inside the range is traced
+    FILTER,
+    /// Tracing stops when code inside the range is executed
+    STOP
+}
 
 /// an address range inside the address filter
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct AddrRange {
     /// This corresponds to the IA32_RTIT_ADDRn_A MSRs
     a: u64,
@@ -74,6 +108,14 @@ impl AddrRange {
     /// this corresponds to the respective fields in IA32_RTIT_CTL MSR
     #[inline]
     pub fn set_cfg(&mut self, cfg: AddrConfig) { self.cfg = cfg }
+
+    /// Returns true if the range is enabled and @ip lies inside of it.
+    ///
+    /// Both ends of the range are included.
+    #[inline]
+    pub fn contains(&self, ip: u64) -> bool {
+        self.cfg != AddrConfig::DISABLED && (self.a..=self.b).contains(&ip)
+    }
 }
 
 
@@ -112,6 +154,42 @@ impl AddrFilter {
                 AddrConfig::try_from(self.0.config.ctl.addr3_cfg()).unwrap())
         }
     }
+
+    /// The address range with index @n.
+    ///
+    /// Returns None if @n is bigger than 3.
+    pub fn range(&self, n: usize) -> Option<AddrRange> {
+        match n {
+            0 => Some(self.addr0()),
+            1 => Some(self.addr1()),
+            2 => Some(self.addr2()),
+            3 => Some(self.addr3()),
+            _ => None
+        }
+    }
+
+    /// All four address ranges in order
+    pub fn ranges(&self) -> [AddrRange; 4] {
+        [self.addr0(), self.addr1(), self.addr2(), self.addr3()]
+    }
+
+    /// Returns true if code at @ip passes the FILTER ranges.
+    ///
+    /// If no range is configured as FILTER, all code passes.
+    /// STOP ranges are not considered, they end tracing altogether.
+    pub fn traced(&self, ip: u64) -> bool {
+        let filters: Vec<AddrRange> = self.ranges()
+            .into_iter()
+            .filter(|r| r.cfg == AddrConfig::FILTER)
+            .collect();
+        filters.is_empty() || filters.iter().any(|r| r.contains(ip))
+    }
+}
+
+impl fmt::Debug for AddrFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.ranges()).finish()
+    }
 }
 
 pub struct AddrFilterBuilder (pub(super) pt_conf_addr_filter);
@@ -155,4 +233,16 @@ impl AddrFilterBuilder {
     }
 
     pub fn finish(&self) -> AddrFilter { AddrFilter(self.0) }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for AddrFilter {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(AddrFilterBuilder::new()
+            .addr0(u.arbitrary()?)
+            .addr1(u.arbitrary()?)
+            .addr2(u.arbitrary()?)
+            .addr3(u.arbitrary()?)
+            .finish())
+    }
+}
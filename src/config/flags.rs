@@ -37,6 +37,7 @@ mod test {
             assert_eq!(raw.variant.block.end_on_jump(), 1);
             assert_eq!(raw.variant.block.keep_tcal_on_ovf(), 1);
         }
+        assert_eq!(BlockFlags::from(raw), blk);
     }
 
     #[test]
@@ -56,6 +57,7 @@ mod test {
             assert_eq!(raw.variant.insn.enable_tick_events(), 1);
             assert_eq!(raw.variant.insn.keep_tcal_on_ovf(), 1);
         }
+        assert_eq!(InsnFlags::from(raw), insn);
     }
 
     #[test]
@@ -69,37 +71,58 @@ mod test {
         let raw: pt_conf_flags = query.into();
 
         unsafe { assert_eq!(raw.variant.query.keep_tcal_on_ovf(), 1); }
+        assert_eq!(QueryFlags::from(raw), query);
     }
 }
 
 bitflags! {
     /// flags for the block decoder
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
     pub struct BlockFlags: u8 {
-        /// End a block after a call instruction
+        /// End a block after a call instruction.
+        ///
+        /// Otherwise a block may continue at the target of a direct call.
+        /// Set this to see every call, e.g. to reconstruct the call stack.
         const END_ON_CALL        = 0b00000001;
         /// Enable tick events for timing updates
         const ENABLE_TICK_EVENTS = 0b00000010;
-        /// End a block after a jump instruction
+        /// End a block after a jump instruction.
+        ///
+        /// Otherwise a block may continue at the target of a direct jump.
         const END_ON_JUMP        = 0b00000100;
-        /// Preserve timing calibration on overflow
+        /// Preserve timing calibration on overflow.
+        ///
+        /// Only set this if the processor frequency didn't change
+        /// across overflows, otherwise timing after an overflow is wrong.
         const KEEP_TCAL_ON_OVF   = 0b00001000;
     }
 }
 
 bitflags! {
     /// flags for the instruction flow decoder
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
     pub struct InsnFlags: u8 {
         /// Enable tick events for timing updates
         const ENABLE_TICK_EVENTS = 0b00000001;
-        /// Preserve timing calibration on overflow
+        /// Preserve timing calibration on overflow.
+        ///
+        /// Only set this if the processor frequency didn't change
+        /// across overflows, otherwise timing after an overflow is wrong.
         const KEEP_TCAL_ON_OVF   = 0b00000010;
     }
 }
 
 bitflags! {
     /// flags for the query decoder
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
     pub struct QueryFlags: u8 {
-        /// Preserve timing calibration on overflow
+        /// Preserve timing calibration on overflow.
+        ///
+        /// Only set this if the processor frequency didn't change
+        /// across overflows, otherwise timing after an overflow is wrong.
         const KEEP_TCAL_ON_OVF = 0b00000001;
     }
 }
@@ -114,6 +137,14 @@ impl From<BlockFlags> for pt_conf_flags {
     }
 }
 
+impl From<pt_conf_flags> for BlockFlags {
+    /// Read the flags of a block decoder config
+    fn from(flags: pt_conf_flags) -> Self {
+        let bits = unsafe { flags.variant.block._bitfield_1.get(0, 8) };
+        BlockFlags::from_bits_truncate(bits as u8)
+    }
+}
+
 impl From<InsnFlags> for pt_conf_flags {
     fn from(flags: InsnFlags) -> Self {
         pt_conf_flags {
@@ -124,6 +155,14 @@ impl From<InsnFlags> for pt_conf_flags {
     }
 }
 
+impl From<pt_conf_flags> for InsnFlags {
+    /// Read the flags of an instruction flow decoder config
+    fn from(flags: pt_conf_flags) -> Self {
+        let bits = unsafe { flags.variant.insn._bitfield_1.get(0, 8) };
+        InsnFlags::from_bits_truncate(bits as u8)
+    }
+}
+
 impl From<QueryFlags> for pt_conf_flags {
     fn from(flags: QueryFlags) -> Self {
         pt_conf_flags {
@@ -132,4 +171,12 @@ impl From<QueryFlags> for pt_conf_flags {
                     _bitfield_1: __BindgenBitfieldUnit::new([flags.bits()]),
                     __bindgen_padding_0: Default::default() }}}
     }
-}
\ No newline at end of file
+}
+
+impl From<pt_conf_flags> for QueryFlags {
+    /// Read the flags of a query decoder config
+    fn from(flags: pt_conf_flags) -> Self {
+        let bits = unsafe { flags.variant.query._bitfield_1.get(0, 8) };
+        QueryFlags::from_bits_truncate(bits as u8)
+    }
+}
@@ -23,7 +23,8 @@ mod test {
 }
 
 /// Frequency values used for timing packets
-#[derive(Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct Frequency {
     /// The Mini Time Counter (MTC) frequency as defined in IA32_RTIT_CTL.MTCFreq.
     ///
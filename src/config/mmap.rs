@@ -0,0 +1,64 @@
+use super::{Config, ConfigBuilder, Owned};
+use crate::error::{PtError, PtErrorCode};
+
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::MmapOptions;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_from_file() {
+        let path = std::env::temp_dir().join(format!("libipt-mmap-{}", std::process::id()));
+        std::fs::write(&path, [1, 2, 3, 4, 5]).unwrap();
+        let c = Config::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let parts = c.split_at_sync_points(&[3]).unwrap();
+        drop(c);
+        assert_eq!(unsafe { parts[0].buffer() }, &[4, 5]);
+
+        std::fs::write(&path, []).unwrap();
+        let err = Config::from_file(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.code(), PtErrorCode::Invalid);
+    }
+}
+
+impl ConfigBuilder<'static, ()> {
+    /// Initializes a Config instance with the memory-mapped trace file at @path.
+    ///
+    /// The file is only read as the decoders get to its pages,
+    /// so traces larger than the available memory can be decoded.
+    /// The mapping is private, writes of an encoder don't reach the file.
+    /// It is kept alive like the buffer of `owned`.
+    /// The file must not be truncated while it is mapped.
+    /// Returns BadFile if the file can't be opened or mapped.
+    /// Returns Invalid if the file is empty.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PtError> {
+        let file = File::open(path)
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not open the trace file"))?;
+        let mut map = unsafe { MmapOptions::new().map_copy(&file) }
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not map the trace file"))?;
+        if map.is_empty() {
+            return Err(PtError::new(PtErrorCode::Invalid, "buffer cant be empty!"));
+        }
+
+        let begin = map.as_mut_ptr();
+        let len = map.len();
+        Ok(Self::with_buffer(Owned::Mapped(map), begin, len))
+    }
+}
+
+impl Config<'static, ()> {
+    /// Creates a Config for the memory-mapped trace file at @path
+    /// with default settings.
+    ///
+    /// See `ConfigBuilder::from_file`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PtError> {
+        ConfigBuilder::from_file(path).map(|b| b.finish())
+    }
+}
@@ -1,12 +1,18 @@
 mod flags;
 mod cpu;
+mod errata;
 mod freqency;
 mod filter;
+mod validate;
 
 mod config;
+#[cfg(feature = "mmap")]
+mod mmap;
 
 pub use config::*;
 pub use cpu::*;
+pub use errata::*;
 pub use freqency::*;
 pub use flags::*;
-pub use filter::*;
\ No newline at end of file
+pub use filter::*;
+pub use validate::*;
\ No newline at end of file
@@ -0,0 +1,124 @@
+use super::{AddrConfig, Config, CpuVendor};
+
+use core::fmt;
+use core::mem;
+use alloc::vec::Vec;
+use libipt_sys::pt_config;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::*;
+
+    #[test]
+    fn test_validate() {
+        let mut data = [0; 4];
+        let mut b = ConfigBuilder::new(&mut data).unwrap();
+        assert!(b.finish().validate().is_empty());
+
+        b.freq(Frequency::new(16, 0, 0, 2))
+            .filter(AddrFilterBuilder::new()
+                .addr2(AddrRange::new(2, 1, AddrConfig::STOP))
+                .finish());
+        let diags = b.finish().validate();
+        assert_eq!(diags, [
+            Diagnostic::MtcFreqRange,
+            Diagnostic::PartialCpuid0x15,
+            Diagnostic::UnknownCpu,
+            Diagnostic::BadAddrRange(2),
+        ]);
+        assert!(!diags[2].is_error());
+        assert_eq!(diags[3].to_string(), "address range 2 ends before it begins");
+
+        b.freq(Frequency::new(3, 0, 2, 1)).cpu(Cpu::intel(6, 0x5e, 3));
+        b.filter(AddrFilterBuilder::new().finish());
+        assert!(b.finish().validate().is_empty());
+    }
+}
+
+/// A problem with a `Config` found by `Config::validate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The trace buffer is empty or ends before it begins
+    EmptyBuffer,
+    /// The size of the raw config doesn't match the libipt version
+    BadSize,
+    /// The MTC frequency doesn't fit in IA32_RTIT_CTL.MTCFreq
+    MtcFreqRange,
+    /// Only one of the cpuid 0x15 values is set
+    PartialCpuid0x15,
+    /// Timing settings are given but the processor is not,
+    /// so no workarounds for its errata are enabled
+    UnknownCpu,
+    /// The enabled address range with this index ends before it begins
+    BadAddrRange(usize),
+}
+
+impl Diagnostic {
+    /// Returns true if decoding with the config will fail or give wrong results.
+    ///
+    /// The other diagnostics are warnings about settings that may be missing.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Diagnostic::UnknownCpu)
+    }
+
+    /// A description of the problem
+    pub fn message(&self) -> &'static str {
+        match self {
+            Diagnostic::EmptyBuffer => "the trace buffer is empty",
+            Diagnostic::BadSize => "the config size doesn't match the libipt version",
+            Diagnostic::MtcFreqRange => "the mtc frequency must be between 0 and 15",
+            Diagnostic::PartialCpuid0x15 => "both cpuid 0x15 values must be set",
+            Diagnostic::UnknownCpu => "timing is configured without a cpu, errata are not handled",
+            Diagnostic::BadAddrRange(_) => "an address range ends before it begins",
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::BadAddrRange(n) => write!(f, "address range {} ends before it begins", n),
+            d => f.write_str(d.message()),
+        }
+    }
+}
+
+impl<'a, C> Config<'a, C> {
+    /// Checks the config for common misconfigurations.
+    ///
+    /// Returns the problems found, an empty vector if there are none.
+    /// The trace buffer itself is not read.
+    /// See `Diagnostic::is_error` for which problems are fatal.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let cfg = &*self.0;
+        let mut diags = Vec::new();
+        if cfg.begin.is_null() || cfg.end <= cfg.begin {
+            diags.push(Diagnostic::EmptyBuffer);
+        }
+        if cfg.size != mem::size_of::<pt_config>() {
+            diags.push(Diagnostic::BadSize);
+        }
+        if cfg.mtc_freq > 15 {
+            diags.push(Diagnostic::MtcFreqRange);
+        }
+        if (cfg.cpuid_0x15_eax == 0) != (cfg.cpuid_0x15_ebx == 0) {
+            diags.push(Diagnostic::PartialCpuid0x15);
+        }
+
+        let timing = cfg.mtc_freq != 0 || cfg.nom_freq != 0
+            || cfg.cpuid_0x15_eax != 0 || cfg.cpuid_0x15_ebx != 0;
+        let cpu = CpuVendor::from_bits_retain(cfg.cpu.vendor) != CpuVendor::UNKNOWN
+            || cfg.cpu.family != 0;
+        if timing && !cpu {
+            diags.push(Diagnostic::UnknownCpu);
+        }
+
+        for (n, r) in self.filter().ranges().iter().enumerate() {
+            if r.cfg() != AddrConfig::DISABLED && r.a() > r.b() {
+                diags.push(Diagnostic::BadAddrRange(n));
+            }
+        }
+        diags
+    }
+}
@@ -1,7 +1,7 @@
-use num_enum::TryFromPrimitive;
-use std::convert::TryFrom;
-use std::ffi::CStr;
-use std::fmt::{Display, Formatter};
+use num_enum::{FromPrimitive, IntoPrimitive};
+use core::ffi::CStr;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
 use std::error::Error;
 
 use libipt_sys::pt_errstr;
@@ -36,8 +36,76 @@ use libipt_sys::{
     pt_error_code_pte_bad_cpu
 };
 
-#[derive(Clone, Copy, Debug, TryFromPrimitive, PartialEq)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let e = PtError::new(PtErrorCode::NoInfo, "no further information");
+        assert_eq!(e.to_string(), "no further information");
+        assert_eq!(e.kind(), ErrorKind::Libipt);
+
+        let e = PtError::stopped(ErrorKind::BudgetExhausted, "out of blocks");
+        assert_eq!(e.to_string(), "out of blocks: the decode budget has been used up");
+        assert_eq!(e.code(), PtErrorCode::NoInfo);
+
+        #[cfg(feature = "std")]
+        {
+            let e: Box<dyn Error + Send + Sync> = e.into();
+            assert!(e.downcast_ref::<PtError>().is_some());
+        }
+    }
+
+    #[test]
+    fn test_decode_error_position() {
+        let err = PtError::stopped(ErrorKind::BudgetExhausted, "out of blocks");
+        let e = DecodeError::new(err, Some(0x40), Some(0x10));
+        assert_eq!(e.kind(), ErrorKind::BudgetExhausted);
+        assert_eq!(e.offset(), Some(0x40));
+        assert_eq!(e.sync_offset(), Some(0x10));
+        assert_eq!(e.to_string(),
+                   "out of blocks: the decode budget has been used up \
+                    at offset 0x40 (last sync point 0x10)");
+
+        let e = DecodeError::new(err, None, None);
+        assert_eq!(e.to_string(), err.to_string());
+        assert_eq!(PtError::from(e).msg(), "out of blocks");
+    }
+
+    #[test]
+    fn test_error_code_unknown() {
+        assert_eq!(PtErrorCode::from(pt_error_code_pte_eos), PtErrorCode::Eos);
+        assert_eq!(PtErrorCode::from(-2), PtErrorCode::Unknown(-2));
+        assert_eq!(PtErrorCode::from(1000), PtErrorCode::Unknown(1000));
+        assert_eq!(i32::from(PtErrorCode::Unknown(1000)), 1000);
+        assert_eq!(i32::from(PtErrorCode::Nosync), pt_error_code_pte_nosync);
+    }
+
+    #[test]
+    fn test_until_eos() {
+        let eos = PtError::new(PtErrorCode::Eos, "end of the stream");
+        assert!(eos.is_eos());
+        assert_eq!(Ok::<_, PtError>(1).until_eos().unwrap(), Some(1));
+        assert_eq!(Err::<u32, _>(eos).until_eos().unwrap(), None);
+
+        let err = PtError::new(PtErrorCode::BadPacket, "bad packet");
+        assert!(Err::<u32, _>(err).until_eos().is_err());
+        let err = DecodeError::new(eos, Some(4), Some(0));
+        assert_eq!(Err::<u32, _>(err).until_eos().unwrap(), None);
+    }
+}
+
+/// The error codes of libipt
+///
+/// The bindings use NoInfo for errors libipt gives no code for,
+/// budget and cancellation are reported through `ErrorKind`.
+///
+/// Newer libipt versions may add error codes,
+/// so matches on this enum need a wildcard arm.
+#[derive(Clone, Copy, Debug, FromPrimitive, IntoPrimitive, PartialEq, Eq)]
 #[repr(i32)]
+#[non_exhaustive]
 pub enum PtErrorCode {
     /// No error. Everything is OK
     Ok = pt_error_code_pte_ok,
@@ -97,11 +165,64 @@ pub enum PtErrorCode {
     BadCpu = pt_error_code_pte_bad_cpu,
 
     /// No Error Information available
-    NoInfo = -1
+    NoInfo = -1,
+
+    /// An error code this version of the bindings doesn't know
+    // the discriminant only has to differ from the others,
+    // the code is the field
+    #[num_enum(catch_all)]
+    Unknown(i32) = -2
+}
+
+impl PtErrorCode {
+    /// A human readable description of the error code
+    ///
+    /// The descriptions of the libipt error codes come from pt_errstr.
+    pub fn msg(self) -> &'static str {
+        match self {
+            PtErrorCode::NoInfo => "no further information",
+            // libipt knows the codes it added after the bindings
+            code => unsafe {
+                CStr::from_ptr(pt_errstr(code.into())).to_str().unwrap()
+            }
+        }
+    }
+}
+
+impl Display for PtErrorCode {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        f.write_str(self.msg())
+    }
+}
+
+/// The kind of a `PtError`
+///
+/// The bindings stop decoders for reasons libipt doesn't know about,
+/// these errors have their own kind and the code NoInfo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// An error described by its `PtErrorCode`
+    Libipt,
+    /// The decode budget has been used up, see `Budget`
+    BudgetExhausted,
+    /// The decode has been cancelled, see `CancelToken`
+    Cancelled,
+}
+
+impl ErrorKind {
+    /// A human readable description of the kind
+    pub fn msg(self) -> &'static str {
+        match self {
+            ErrorKind::Libipt => "error from libipt",
+            ErrorKind::BudgetExhausted => "the decode budget has been used up",
+            ErrorKind::Cancelled => "the decode has been cancelled",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct PtError {
+     kind: ErrorKind,
      code: PtErrorCode,
      msg:  &'static str
 }
@@ -109,22 +230,29 @@ pub struct PtError {
 impl PtError {
     #[inline]
     pub(crate) fn new(code: PtErrorCode, msg: &'static str) -> Self {
-        PtError { code, msg }
+        PtError { kind: ErrorKind::Libipt, code, msg }
+    }
+
+    // an error of the bindings that has no libipt code
+    #[inline]
+    pub(crate) fn stopped(kind: ErrorKind, msg: &'static str) -> Self {
+        PtError { kind, code: PtErrorCode::NoInfo, msg }
     }
 
     /// Creates a PTError instance based on the error code
     /// The code should be provided in the way its returned from the pt function
     /// pt functions always return negative error codes
-    /// *error codes not included in the pt_error enum will panic!*
+    /// error codes not included in the pt_error enum become Unknown
     #[inline]
     pub(crate) fn from_code(code: i32) -> Self {
-        // panicing here is fine since this should only be called
-        // for return values of libipt functions
-        // so invalid returns = bug in libipt or the bindings
-        PtError::new(
-            PtErrorCode::try_from(-code).unwrap(),
-            unsafe { CStr::from_ptr(pt_errstr(-code)).to_str().unwrap() }
-        )
+        let code = PtErrorCode::from(-code);
+        PtError::new(code, code.msg())
+    }
+
+    /// get the kind of the error
+    #[inline]
+    pub fn kind(self) -> ErrorKind {
+        self.kind
     }
 
     /// get the pt error code
@@ -138,19 +266,166 @@ impl PtError {
     pub fn msg(self) -> &'static str {
         self.msg
     }
+
+    /// Returns true if the error marks the end of the trace
+    #[inline]
+    pub fn is_eos(self) -> bool {
+        self.code == PtErrorCode::Eos
+    }
 }
 
 impl Display for PtError {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(f, "error from libipt: {}", self.msg)
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        // errors created by the bindings carry their own message,
+        // the description of the code is added for context
+        let desc = match self.kind {
+            ErrorKind::Libipt => self.code.msg(),
+            kind => kind.msg(),
+        };
+        if self.msg == desc {
+            f.write_str(self.msg)
+        } else {
+            write!(f, "{}: {}", self.msg, desc)
+        }
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for PtError {
     // sadly we have no idea what the source is
     fn source(&self) -> Option<&(dyn Error + 'static)> { None }
 }
 
+/// An error of a decoder together with the position in the trace where it occurred
+///
+/// Returned by the `next` and `event` functions of the decoders,
+/// so a failing trace can be inspected at the right place in the raw bytes.
+/// Use `error` or `into` to get the plain PtError.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeError {
+    error: PtError,
+    offset: Option<u64>,
+    sync_offset: Option<u64>
+}
+
+impl DecodeError {
+    #[inline]
+    pub(crate) fn new(error: PtError, offset: Option<u64>, sync_offset: Option<u64>) -> Self {
+        DecodeError { error, offset, sync_offset }
+    }
+
+    /// The error without its position
+    #[inline]
+    pub fn error(self) -> PtError {
+        self.error
+    }
+
+    /// get the kind of the error
+    #[inline]
+    pub fn kind(self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    /// get the pt error code
+    #[inline]
+    pub fn code(self) -> PtErrorCode {
+        self.error.code()
+    }
+
+    /// get a human readable error message
+    #[inline]
+    pub fn msg(self) -> &'static str {
+        self.error.msg()
+    }
+
+    /// Returns true if the error marks the end of the trace
+    #[inline]
+    pub fn is_eos(self) -> bool {
+        self.error.is_eos()
+    }
+
+    /// The decoder position after the error.
+    ///
+    /// None if the decoder was out of sync.
+    #[inline]
+    pub fn offset(self) -> Option<u64> {
+        self.offset
+    }
+
+    /// The position of the last synchronization point before the error.
+    ///
+    /// None if the decoder was out of sync.
+    #[inline]
+    pub fn sync_offset(self) -> Option<u64> {
+        self.sync_offset
+    }
+}
+
+impl From<DecodeError> for PtError {
+    #[inline]
+    fn from(e: DecodeError) -> Self {
+        e.error
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(off) = self.offset {
+            write!(f, " at offset {:#x}", off)?;
+        }
+        if let Some(sync) = self.sync_offset {
+            write!(f, " (last sync point {:#x})", sync)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for DecodeError {
+    // the position is part of the message, there is no underlying error
+    fn source(&self) -> Option<&(dyn Error + 'static)> { None }
+}
+
+/// Separates the end of the trace from real decode errors.
+///
+/// The decoders report the end of the trace as an Eos error.
+/// Their iterators stop there, for the other functions
+/// `until_eos` turns the result into one where Eos is no error:
+///
+/// ```ignore
+/// while let Some((evt, _)) = decoder.event().until_eos()? {
+///     // ...
+/// }
+/// ```
+pub trait UntilEos<T, E> {
+    /// Returns Ok(None) instead of an Eos error.
+    /// Other errors are kept.
+    fn until_eos(self) -> Result<Option<T>, E>;
+}
+
+impl<T> UntilEos<T, PtError> for Result<T, PtError> {
+    #[inline]
+    fn until_eos(self) -> Result<Option<T>, PtError> {
+        match self {
+            Ok(x) => Ok(Some(x)),
+            Err(e) if e.is_eos() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<T> UntilEos<T, DecodeError> for Result<T, DecodeError> {
+    #[inline]
+    fn until_eos(self) -> Result<Option<T>, DecodeError> {
+        match self {
+            Ok(x) => Ok(Some(x)),
+            Err(e) if e.is_eos() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
 /// Dereferences a pointer returned by one of the libipt functions.
 /// Checks the pointer for NULL.
 /// Negative values will be translated into the appropriate error value.
@@ -158,23 +433,34 @@ impl Error for PtError {
 pub(crate) fn deref_ptresult<T>(res: *const T) -> Result<&'static T, PtError> {
     match res as isize {
         // null reference, no error info
-        0 => Err(PtError::new(PtErrorCode::NoInfo, "No further information")),
+        0 => Err(PtError::new(PtErrorCode::NoInfo, "no further information")),
         x if x < 0 => Err(PtError::from_code(x as i32)),
         _ => Ok(unsafe { res.as_ref().unwrap() })
     }
 }
 
-/// Dereferences a pointer returned by one of the libipt functions.
-/// Checks the pointer for NULL.
+/// Checks a pointer returned by one of the libipt allocation functions.
+/// Checks the pointer for NULL, the pointer itself is returned so the
+/// wrapper can hold on to the allocation.
 /// Negative values will be translated into the appropriate error value.
 #[inline]
-pub(crate) fn deref_ptresult_mut<T>(res: *mut T) -> Result<&'static mut T, PtError> {
+pub(crate) fn ensure_ptr<T>(res: *mut T) -> Result<*mut T, PtError> {
     match res as isize {
         // null reference, no error info
-        0 => Err(PtError::new(PtErrorCode::NoInfo, "No further information")),
+        0 => Err(PtError::new(PtErrorCode::NoInfo, "no further information")),
         x if x < 0 => Err(PtError::from_code(x as i32)),
-        _ => Ok(unsafe { res.as_mut().unwrap() })
+        _ => Ok(res)
+    }
+}
+
+// a decoder that was reset in place is out of sync until it is synchronized
+// again, libipt still holds the state from before the reset
+#[inline]
+pub(crate) fn ensure_synced(rewound: bool) -> Result<(), PtError> {
+    if rewound {
+        return Err(PtError::new(PtErrorCode::Nosync, "decoder was reset"));
     }
+    Ok(())
 }
 
 // Translates a pt error code into a result enum.
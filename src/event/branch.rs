@@ -1,11 +1,15 @@
+use super::{Event, Payload};
 use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_4;
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
-    use libipt_sys::{ pt_event, pt_event_type_ptev_async_branch };
+    use core::mem;
+    use libipt_sys::{
+        pt_event,
+        pt_event_type_ptev_async_branch,
+        pt_event_type_ptev_overflow
+    };
 
     #[test]
     fn test_branch_async_payload() {
@@ -25,6 +29,26 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_async_transfer() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_async_branch;
+        evt._bitfield_1 = pt_event::new_bitfield_1(1, 0, 1);
+        evt.tsc = 7;
+        evt.variant.async_branch = pt_event__bindgen_ty_1__bindgen_ty_4 {
+           from: 1,
+           to: 2
+        };
+
+        assert_eq!(Event(evt).async_transfer(), Some(AsyncTransfer {
+            from: 1,
+            to: None,
+            tsc: Some(7)
+        }));
+        evt.type_ = pt_event_type_ptev_overflow;
+        assert_eq!(Event(evt).async_transfer(), None);
+    }
 }
 
 /// An asynchronous branch, e.g. interrupt
@@ -36,4 +60,41 @@ impl AsyncBranch {
     /// The branch destination address.
     /// This field is not valid if @ip_suppressed is set.
     pub fn to(self) -> u64 { self.0.to }
-}
\ No newline at end of file
+}
+
+event_getters!(AsyncBranch { from, to });
+
+/// An asynchronous transfer of control, e.g. an interrupt or a fault
+///
+/// Unlike the branches of the decoded instructions,
+/// these transfers are not part of the architectural control flow
+/// of the traced code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AsyncTransfer {
+    /// The address of the instruction that was interrupted
+    pub from: u64,
+    /// The address control was transferred to.
+    ///
+    /// None if the destination has been suppressed,
+    /// e.g. because it lies outside the traced area.
+    pub to: Option<u64>,
+    /// The time stamp count of the transfer, if known
+    pub tsc: Option<u64>
+}
+
+impl Event {
+    /// The asynchronous transfer of an async branch event.
+    ///
+    /// Returns None for all other events.
+    pub fn async_transfer(self) -> Option<AsyncTransfer> {
+        match self.payload() {
+            Payload::AsyncBranch(b) => Some(AsyncTransfer {
+                from: b.from(),
+                to: (!self.ip_suppressed()).then(|| b.to()),
+                tsc: self.timestamp()
+            }),
+            _ => None
+        }
+    }
+}
@@ -1,11 +1,12 @@
+use super::{Event, Payload};
 use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_18;
+use alloc::vec::Vec;
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
-    use libipt_sys::{ pt_event, pt_event_type_ptev_cbr };
+    use core::mem;
+    use libipt_sys::{ pt_event, pt_event_type_ptev_cbr, pt_event_type_ptev_stop };
 
     #[test]
     fn test_cbr_payload() {
@@ -23,6 +24,34 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_cbr_timeline() {
+        let cbr = |ratio, tsc: Option<u64>| {
+            let mut evt: pt_event = unsafe { mem::zeroed() };
+            evt.type_ = pt_event_type_ptev_cbr;
+            evt._bitfield_1 = pt_event::new_bitfield_1(0, 0, tsc.is_some() as u32);
+            evt.tsc = tsc.unwrap_or(0);
+            evt.variant.cbr = pt_event__bindgen_ty_1__bindgen_ty_18 { ratio };
+            Event(evt)
+        };
+        let mut stop: pt_event = unsafe { mem::zeroed() };
+        stop.type_ = pt_event_type_ptev_stop;
+
+        let timeline = cbr_timeline(vec![
+            cbr(8, None),
+            cbr(8, Some(10)),
+            Event(stop),
+            cbr(24, Some(20)),
+            cbr(8, Some(30)),
+        ]);
+        assert_eq!(timeline, vec![
+            CbrChange { ratio: 8, tsc: None },
+            CbrChange { ratio: 24, tsc: Some(20) },
+            CbrChange { ratio: 8, tsc: Some(30) }
+        ]);
+        assert_eq!(timeline[1].mhz(100), 2400);
+    }
 }
 
 /// A core:bus ratio event
@@ -31,4 +60,42 @@ pub struct Cbr(pub(super) pt_event__bindgen_ty_1__bindgen_ty_18);
 impl Cbr {
     /// The core:bus ratio.
     pub fn ratio(self) -> u16 { self.0.ratio }
-}
\ No newline at end of file
+}
+
+event_getters!(Cbr { ratio });
+
+/// A change of the core:bus ratio, i.e. of the core frequency
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CbrChange {
+    /// The new core:bus ratio
+    pub ratio: u16,
+    /// The time stamp count of the change, if known
+    pub tsc: Option<u64>
+}
+
+impl CbrChange {
+    /// The core frequency in MHz for a bus clock of @bus_mhz MHz.
+    ///
+    /// The bus clock is 100 MHz on most recent processors.
+    pub fn mhz(self, bus_mhz: u32) -> u64 {
+        self.ratio as u64 * bus_mhz as u64
+    }
+}
+
+/// The core:bus ratio changes in @events, i.e. the core
+/// frequency over time.
+///
+/// Repeated reports of an unchanged ratio are dropped.
+/// Other events are ignored.
+pub fn cbr_timeline<I: IntoIterator<Item = Event>>(events: I) -> Vec<CbrChange> {
+    let mut timeline: Vec<CbrChange> = Vec::new();
+    for evt in events {
+        if let Payload::Cbr(c) = evt.payload() {
+            if timeline.last().map(|l| l.ratio) != Some(c.ratio()) {
+                timeline.push(CbrChange { ratio: c.ratio(), tsc: evt.timestamp() });
+            }
+        }
+    }
+    timeline
+}
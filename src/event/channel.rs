@@ -0,0 +1,290 @@
+use super::PtwriteValue;
+use crate::error::{PtError, PtErrorCode};
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(value: u64) -> PtwriteValue {
+        PtwriteValue {
+            value,
+            size: 8,
+            ip: None,
+            tsc: None,
+        }
+    }
+
+    #[test]
+    fn test_channel_records() {
+        let mut ch = Channel::new(8).unwrap();
+        ch.register(
+            1,
+            Layout::new("alloc")
+                .field("size", 32)
+                .field("align", 16)
+                .field("ptr", 64),
+        )
+        .unwrap();
+        ch.register(2, Layout::new("mark")).unwrap();
+        assert!(ch.register(0x100, Layout::new("too big")).is_err());
+        assert!(ch.register(3, Layout::new("bad").field("x", 0)).is_err());
+
+        let writes = [
+            (1 << 56) | 7,
+            (0x10 << 32) | 0x2000,
+            0x7f00_0000_1000,
+            (2 << 56) | 42,
+            9 << 56,
+        ];
+        let records: Vec<_> = writes.iter().filter_map(|w| ch.push(write(*w))).collect();
+        assert_eq!(records.len(), 3);
+
+        let alloc = records[0].as_ref().unwrap();
+        assert_eq!(alloc.tag, 1);
+        assert_eq!(alloc.inline, 7);
+        assert_eq!(alloc.name(), "alloc");
+        assert_eq!(alloc.get("size"), Some(0x2000));
+        assert_eq!(alloc.get("align"), Some(0x10));
+        assert_eq!(alloc.get("ptr"), Some(0x7f00_0000_1000));
+        assert_eq!(alloc.get("nope"), None);
+
+        let mark = records[1].as_ref().unwrap();
+        assert_eq!((mark.name(), mark.inline), ("mark", 42));
+        assert!(mark.fields().next().is_none());
+
+        assert!(records[2].is_err());
+    }
+}
+
+/// The layout of the records with one tag
+///
+/// Fields are packed into 64-bit payload words starting at the least
+/// significant bit.
+/// A field that does not fit into the rest of a word starts the next word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    name: String,
+    fields: Vec<(String, u32)>,
+}
+
+impl Layout {
+    /// A layout named @name without fields.
+    pub fn new(name: &str) -> Self {
+        Layout {
+            name: name.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Append a field named @name that is @bits wide.
+    ///
+    /// Widths from 1 to 64 bits are allowed.
+    pub fn field(mut self, name: &str, bits: u32) -> Self {
+        self.fields.push((name.to_string(), bits));
+        self
+    }
+
+    /// The name of the layout.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // the word index and bit offset of each field
+    fn placements(&self) -> impl Iterator<Item = (usize, u32, u32)> + '_ {
+        let mut word = 0;
+        let mut used = 0;
+        self.fields.iter().map(move |&(_, bits)| {
+            if used + bits > 64 {
+                word += 1;
+                used = 0;
+            }
+            let at = (word, used, bits);
+            used += bits;
+            at
+        })
+    }
+
+    // the number of payload words
+    fn words(&self) -> usize {
+        self.placements().last().map_or(0, |(word, _, _)| word + 1)
+    }
+}
+
+/// A record decoded by a `Channel`
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// The tag of the record
+    pub tag: u64,
+    /// The bits of the header below the tag
+    pub inline: u64,
+    /// The address of the ptwrite instruction that wrote the header, if known
+    pub ip: Option<u64>,
+    /// The time stamp count of the header, if known
+    pub tsc: Option<u64>,
+    layout: Arc<Layout>,
+    values: Vec<u64>,
+}
+
+impl Record {
+    /// The name of the record's layout.
+    pub fn name(&self) -> &str {
+        self.layout.name()
+    }
+
+    /// The value of the field @name.
+    ///
+    /// Returns None if the layout has no such field.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.fields().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// The names and values of all fields in layout order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.layout
+            .fields
+            .iter()
+            .zip(&self.values)
+            .map(|((n, _), v)| (n.as_str(), *v))
+    }
+}
+
+// a record waiting for its payload words
+struct Partial {
+    header: PtwriteValue,
+    tag: u64,
+    layout: Arc<Layout>,
+    words: Vec<u64>,
+}
+
+/// A decoder for structured records logged with PTWRITE
+///
+/// Each record starts with a header write that carries the record's tag
+/// in its upper bits.
+/// The tag selects the `Layout` of the payload words that follow it.
+/// The bits of the header below the tag are free for the writer,
+/// e.g. to log a small value without payload words.
+pub struct Channel {
+    tag_bits: u32,
+    layouts: BTreeMap<u64, Arc<Layout>>,
+    partial: Option<Partial>,
+}
+
+impl Channel {
+    /// A channel whose record tags are @tag_bits wide.
+    ///
+    /// Returns Invalid if @tag_bits is not between 1 and 32.
+    pub fn new(tag_bits: u32) -> Result<Self, PtError> {
+        if !(1..=32).contains(&tag_bits) {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "tags must be between 1 and 32 bits wide",
+            ));
+        }
+
+        Ok(Channel {
+            tag_bits,
+            layouts: BTreeMap::new(),
+            partial: None,
+        })
+    }
+
+    /// Use @layout for records tagged with @tag.
+    ///
+    /// Replaces the layout registered for @tag before.
+    /// Returns Invalid if @tag doesn't fit in the tag bits
+    /// or a field of @layout has an invalid width.
+    pub fn register(&mut self, tag: u64, layout: Layout) -> Result<(), PtError> {
+        if tag >> self.tag_bits != 0 {
+            return Err(PtError::new(PtErrorCode::Invalid, "the tag is too wide"));
+        }
+        if layout
+            .fields
+            .iter()
+            .any(|&(_, bits)| !(1..=64).contains(&bits))
+        {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "fields must be between 1 and 64 bits wide",
+            ));
+        }
+
+        self.layouts.insert(tag, Arc::new(layout));
+        Ok(())
+    }
+
+    // the position of the tag in a header of @size bytes
+    #[inline]
+    fn shift(&self, size: u8) -> u32 {
+        (size as u32 * 8).clamp(self.tag_bits, 64) - self.tag_bits
+    }
+
+    /// Feed the next ptwrite value @write to the channel.
+    ///
+    /// Returns the record @write completes, if any.
+    /// Returns Invalid if @write is a header with an unregistered tag.
+    pub fn push(&mut self, write: PtwriteValue) -> Option<Result<Record, PtError>> {
+        let partial = match self.partial.take() {
+            Some(mut p) => {
+                p.words.push(write.value);
+                p
+            }
+            None => {
+                let tag = write.value >> self.shift(write.size);
+                let layout = match self.layouts.get(&tag) {
+                    Some(l) => l.clone(),
+                    None => {
+                        return Some(Err(PtError::new(
+                            PtErrorCode::Invalid,
+                            "unknown ptwrite record tag",
+                        )))
+                    }
+                };
+                Partial {
+                    header: write,
+                    tag,
+                    layout,
+                    words: Vec::new(),
+                }
+            }
+        };
+
+        if partial.words.len() < partial.layout.words() {
+            self.partial = Some(partial);
+            return None;
+        }
+
+        let values = partial
+            .layout
+            .placements()
+            .map(|(word, at, bits)| {
+                let v = partial.words[word] >> at;
+                if bits == 64 {
+                    v
+                } else {
+                    v & ((1 << bits) - 1)
+                }
+            })
+            .collect();
+        let shift = self.shift(partial.header.size);
+        Some(Ok(Record {
+            tag: partial.tag,
+            inline: partial.header.value & ((1u64 << shift) - 1),
+            ip: partial.header.ip,
+            tsc: partial.header.tsc,
+            layout: partial.layout,
+            values,
+        }))
+    }
+
+    /// Drop a partially received record, e.g. after a gap in the trace.
+    ///
+    /// The next write is treated as a header again.
+    pub fn reset(&mut self) {
+        self.partial = None;
+    }
+}
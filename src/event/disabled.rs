@@ -7,7 +7,7 @@ use libipt_sys::{
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{
         pt_event,
         pt_event_type_ptev_disabled,
@@ -42,7 +42,7 @@ mod test {
 
         let payload: Payload = evt.into();
         match payload {
-            Payload::AsnycDisabled(e) => {
+            Payload::AsyncDisabled(e) => {
                 assert_eq!(e.ip(), 11);
                 assert_eq!(e.at(), 1);
             },
@@ -62,6 +62,8 @@ impl Disabled {
     pub fn ip(self) -> u64 { self.0.ip }
 }
 
+event_getters!(Disabled { ip });
+
 /// Tracing has been disabled asynchronously
 #[derive(Clone, Copy, Debug)]
 pub struct AsyncDisabled(pub(super) pt_event__bindgen_ty_1__bindgen_ty_3);
@@ -72,4 +74,6 @@ impl AsyncDisabled {
     /// This field is not valid if @ip_suppressed is set.
     pub fn ip(self) -> u64 { self.0.ip }
 
-}
\ No newline at end of file
+}
+
+event_getters!(AsyncDisabled { at, ip });
@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_1;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_enabled };
 
     #[test]
@@ -38,4 +38,6 @@ impl Enabled {
     /// A flag indicating that tracing resumes from the IP
     /// at which tracing had been disabled before.
     pub fn resumed(self) -> bool { self.0.resumed() > 0 }
-}
\ No newline at end of file
+}
+
+event_getters!(Enabled { ip, resumed });
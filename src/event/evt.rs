@@ -0,0 +1,257 @@
+use crate::error::{
+    PtError, DecodeError, deref_ptresult,
+    ensure_ptok, extract_pterr,
+    ensure_ptr, ensure_synced, UntilEos
+};
+use crate::config::{Config, Owned};
+use crate::Status;
+use crate::progress::Progress;
+use crate::event::Event;
+
+use core::marker::PhantomData;
+use alloc::sync::Arc;
+use core::mem;
+
+use libipt_sys::{pt_config, pt_event};
+
+// the event decoder was added in libipt 2.1, libipt-sys has no bindings for it.
+// the functions are only linked if libipt-sys links libipt 2.1 or newer,
+// see the `evt` feature in Cargo.toml.
+#[allow(non_camel_case_types)]
+#[repr(C)]
+struct pt_event_decoder {
+    _private: [u8; 0]
+}
+
+extern "C" {
+    fn pt_evt_alloc_decoder(config: *const pt_config) -> *mut pt_event_decoder;
+    fn pt_evt_free_decoder(decoder: *mut pt_event_decoder);
+    fn pt_evt_sync_forward(decoder: *mut pt_event_decoder) -> i32;
+    fn pt_evt_sync_backward(decoder: *mut pt_event_decoder) -> i32;
+    fn pt_evt_sync_set(decoder: *mut pt_event_decoder, offset: u64) -> i32;
+    fn pt_evt_get_offset(decoder: *const pt_event_decoder, offset: *mut u64) -> i32;
+    fn pt_evt_get_sync_offset(decoder: *const pt_event_decoder, offset: *mut u64) -> i32;
+    fn pt_evt_get_config(decoder: *const pt_event_decoder) -> *const pt_config;
+    fn pt_evt_next(decoder: *mut pt_event_decoder, event: *mut pt_event, size: usize) -> i32;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn test_evtdec_alloc() {
+        let kek = &mut [2; 1];
+        EventDecoder::new(
+            &ConfigBuilder::new(kek).unwrap().finish()
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_evtdec_props() {
+        let kek = &mut [2; 3];
+        // this just checks memory safety for property access
+        let mut b = EventDecoder::new(
+            &ConfigBuilder::new(kek).unwrap().finish()
+        ).unwrap();
+
+        assert!(b.event().is_err());
+        assert!(b.config().is_ok());
+        assert!(b.offset().is_err());
+        assert!(b.position().is_err());
+        assert!(b.sync_offset().is_err());
+        assert!(b.sync_backward().is_err());
+        assert!(b.sync_forward().is_err());
+        assert!(b.sync_set(0).is_err());
+        assert!(b.reset(None).is_ok());
+    }
+}
+
+/// An Intel PT event decoder
+///
+/// The event decoder of libipt 2.1 and newer turns the trace into events
+/// without reconstructing the control flow, e.g. for timing and power analysis.
+/// Unlike the query decoder, it doesn't need to be asked for branches,
+/// every packet combination that describes an event is returned by `event`.
+/// Only available with the `evt` feature, which requires linking against libipt 2.1.
+/// Event types that libipt 2.0 doesn't have are returned as `Payload::Unknown`.
+///
+/// The decoder will work on the buffer defined in the config,
+/// it shall contain raw trace data and remain valid for the lifetime of the decoder.
+/// The decoder needs to be synchronized before it can be used.
+///
+/// The decoder is Send and Sync if the payload type `T` of the decode callback is.
+// keeps an owned trace buffer or decode callback of the config alive,
+// and whether the decoder was reset in place and not synchronized since
+pub struct EventDecoder<'a, T>(
+    *mut pt_event_decoder,
+    PhantomData<&'a mut T>,
+    Option<Arc<Owned>>,
+    bool,
+);
+
+// SAFETY: see `QueryDecoder`.
+unsafe impl<T: Send> Send for EventDecoder<'_, T> {}
+unsafe impl<T: Sync> Sync for EventDecoder<'_, T> {}
+
+impl<'a, T> EventDecoder<'a, T> {
+    /// Allocate an Intel PT event decoder.
+    ///
+    /// The decoder will work on the buffer defined in @config,
+    /// it shall contain raw trace data and remain valid for the lifetime of the decoder.
+    /// The trace buffer of @config is borrowed for @'a, the decoder can't outlive it.
+    /// The decoder needs to be synchronized before it can be used.
+    pub fn new(cfg: &Config<'a, T>) -> Result<Self, PtError> {
+        ensure_ptr(unsafe { pt_evt_alloc_decoder(cfg.0.as_ref()) })
+            .map(|d| EventDecoder::<T>(d, PhantomData, cfg.2.clone(), false))
+    }
+
+    /// Get the next event.
+    ///
+    /// On success, provides the next event along with its status and updates the decoder.
+    /// Returns BadOpc if an unknown packet is encountered.
+    /// Returns BadPacket if an unknown packet payload is encountered.
+    /// Returns Eos if decoding reached the end of the Intel PT buffer.
+    /// Returns Nosync if decoder is out of sync.
+    /// Errors carry the decoder position, see `DecodeError`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), err))]
+    pub fn event(&mut self) -> Result<(Event, Status), DecodeError> {
+        // `pt_event` is from the libipt 2.0 bindings, libipt only fills
+        // the fields that fit in the size passed to it
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        ensure_synced(self.3).and_then(|_| extract_pterr(unsafe {
+            pt_evt_next(self.0,
+                        &mut evt,
+                        mem::size_of::<pt_event>())
+        })).map(|s| (Event(evt), Status::from_raw(s)))
+            .map_err(|e| DecodeError::new(e, self.offset().ok(), self.sync_offset().ok()))
+    }
+
+    pub fn config(&self) -> Result<Config<T>, PtError> {
+        deref_ptresult(unsafe { pt_evt_get_config(self.0) })
+            .map(Config::from)
+    }
+
+    /// Get the current decoder position.
+    ///
+    /// Returns Nosync if decoder is out of sync.
+    pub fn offset(&self) -> Result<u64, PtError> {
+        ensure_synced(self.3)?;
+        let mut off: u64 = 0;
+        ensure_ptok(unsafe { pt_evt_get_offset(self.0, &mut off) })
+            .map(|_| off)
+    }
+
+    /// Get the current decoder position within its trace buffer.
+    ///
+    /// Includes the size of the trace buffer and the number of bytes left,
+    /// see `Progress`.
+    /// Through a `&mut` reference `Iterator::position` takes precedence,
+    /// call this as `EventDecoder::position(decoder)` there.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn position(&self) -> Result<Progress, PtError> {
+        let size = self.config()?.buffer_len() as u64;
+        self.offset().map(|offset| Progress { offset, size })
+    }
+
+    /// Get the position of the last synchronization point.
+    ///
+    /// This is useful for splitting a trace stream for parallel decoding.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn sync_offset(&self) -> Result<u64, PtError> {
+        ensure_synced(self.3)?;
+        let mut off: u64 = 0;
+        ensure_ptok(unsafe { pt_evt_get_sync_offset(self.0, &mut off) })
+            .map(|_| off)
+    }
+
+    /// Synchronize an Intel PT event decoder.
+    ///
+    /// Search for the next synchronization point in backward direction.
+    /// If decoder has not been synchronized, yet, the search is started
+    /// at the end of the trace buffer.
+    /// Returns a non-negative Status on success.
+    /// Returns BadOpc if an unknown packet is encountered.
+    /// Returns BadPacket if an unknown packet payload is encountered.
+    /// Returns Eos if no further synchronization point is found.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
+    pub fn sync_backward(&mut self) -> Result<Status, PtError> {
+        if self.3 {
+            let offset = self.config()?.psb_offset(true)?;
+            return self.sync_set(offset);
+        }
+        extract_pterr(unsafe { pt_evt_sync_backward(self.0) })
+            .map(Status::from_raw)
+    }
+
+    /// Synchronize an Intel PT event decoder.
+    ///
+    /// Search for the next synchronization point in forward direction.
+    /// If decoder has not been synchronized, yet, the search is started
+    /// at the beginning of the trace buffer.
+    /// Returns a non-negative Status on success.
+    /// Returns BadOpc if an unknown packet is encountered.
+    /// Returns BadPacket if an unknown packet payload is encountered.
+    /// Returns Eos if no further synchronization point is found.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
+    pub fn sync_forward(&mut self) -> Result<Status, PtError> {
+        if self.3 {
+            let offset = self.config()?.psb_offset(false)?;
+            return self.sync_set(offset);
+        }
+        extract_pterr(unsafe { pt_evt_sync_forward(self.0) })
+            .map(Status::from_raw)
+    }
+
+    /// Manually synchronize an Intel PT event decoder.
+    ///
+    /// Synchronize decoder on the syncpoint at @offset.
+    /// There must be a PSB packet at @offset.
+    /// Returns BadOpc if an unknown packet is encountered.
+    /// Returns BadPacket if an unknown packet payload is encountered.
+    /// Returns Eos if @offset lies outside of decoder's trace buffer.
+    /// Returns Eos if decoder reaches the end of its trace buffer.
+    /// Returns Nosync if there is no syncpoint at @offset.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
+    pub fn sync_set(&mut self, offset: u64) -> Result<Status, PtError> {
+        let status = extract_pterr(unsafe { pt_evt_sync_set(self.0, offset) })
+            .map(Status::from_raw)?;
+        self.3 = false;
+        Ok(status)
+    }
+
+    /// Return the decoder to the unsynchronized state of a new decoder.
+    ///
+    /// Without @cfg, the decoder is reset in place without reallocating it.
+    /// Like a new decoder, it has to be synchronized before it can be used,
+    /// `sync_forward` starts at the beginning of the trace buffer
+    /// and `sync_backward` at its end. Until then, `event`, `offset`
+    /// and `sync_offset` return Nosync.
+    /// With @cfg, the underlying decoder is allocated anew for it,
+    /// the old one is only freed if that succeeded,
+    /// since libipt decoders can't switch to the trace buffer of another config.
+    pub fn reset(&mut self, cfg: Option<&Config<'a, T>>) -> Result<(), PtError> {
+        if let Some(cfg) = cfg {
+            let inner = ensure_ptr(unsafe { pt_evt_alloc_decoder(cfg.0.as_ref()) })?;
+            unsafe { pt_evt_free_decoder(self.0) };
+            self.0 = inner;
+            self.2 = cfg.2.clone();
+        }
+        self.3 = cfg.is_none();
+        Ok(())
+    }
+}
+
+impl<'a, T> Iterator for EventDecoder<'a, T> {
+    type Item = Result<(Event, Status), DecodeError>;
+
+    fn next(&mut self) -> Option<Result<(Event, Status), DecodeError>> {
+        // eos to stop iterating
+        self.event().until_eos().transpose()
+    }
+}
+
+impl<'a, T> Drop for EventDecoder<'a, T> {
+    fn drop(&mut self) { unsafe { pt_evt_free_decoder(self.0) }}
+}
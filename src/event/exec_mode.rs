@@ -1,4 +1,4 @@
-use std::convert::TryFrom;
+use core::convert::TryFrom;
 use libipt_sys::{
     pt_event__bindgen_ty_1__bindgen_ty_8,
     pt_exec_mode_ptem_16bit,
@@ -9,12 +9,13 @@ use libipt_sys::{
 
 use num_enum::TryFromPrimitive;
 
+use super::{Event, Payload};
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
-    use libipt_sys::{ pt_event, pt_event_type_ptev_exec_mode };
+    use core::mem;
+    use libipt_sys::{ pt_event, pt_event_type_ptev_exec_mode, pt_event_type_ptev_tick };
 
     #[test]
     fn test_exec_mode_payload() {
@@ -34,17 +35,57 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_mode_switch() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_exec_mode;
+        evt._bitfield_1 = pt_event::new_bitfield_1(0, 0, 1);
+        evt.tsc = 3;
+        evt.variant.exec_mode = pt_event__bindgen_ty_1__bindgen_ty_8 {
+            ip: 11,
+            mode: pt_exec_mode_ptem_16bit
+        };
+
+        let switch = Event(evt).mode_switch().unwrap();
+        assert_eq!(switch, ModeSwitch { ip: Some(11), mode: ExecModeType::Bit16, tsc: Some(3) });
+        assert_eq!(switch.mode.bits(), Some(16));
+        assert_eq!(ExecModeType::Unknown.bits(), None);
+
+        evt.type_ = pt_event_type_ptev_tick;
+        assert_eq!(Event(evt).mode_switch(), None);
+    }
 }
 
-#[derive(Clone, Copy, TryFromPrimitive, Debug, PartialEq)]
+/// An execution mode
+#[derive(Clone, Copy, TryFromPrimitive, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i32)]
 pub enum ExecModeType {
+    /// 16-bit mode
     Bit16 = pt_exec_mode_ptem_16bit,
+    /// 32-bit mode, including 32-bit compatibility mode
     Bit32 = pt_exec_mode_ptem_32bit,
+    /// 64-bit mode
     Bit64 = pt_exec_mode_ptem_64bit,
+    /// The mode is not known
     Unknown = pt_exec_mode_ptem_unknown
 }
 
+impl ExecModeType {
+    /// The operand size a disassembler should use for this mode.
+    ///
+    /// Returns None if the mode is not known.
+    pub fn bits(self) -> Option<u32> {
+        match self {
+            ExecModeType::Bit16 => Some(16),
+            ExecModeType::Bit32 => Some(32),
+            ExecModeType::Bit64 => Some(64),
+            ExecModeType::Unknown => None
+        }
+    }
+}
+
 /// An execution mode change
 #[derive(Clone, Copy, Debug)]
 pub struct ExecMode(pub(super) pt_event__bindgen_ty_1__bindgen_ty_8);
@@ -53,4 +94,36 @@ impl ExecMode {
     pub fn ip(self) -> u64 { self.0.ip }
     /// The execution mode
     pub fn mode(self) -> ExecModeType { ExecModeType::try_from(self.0.mode).unwrap() }
-}
\ No newline at end of file
+}
+
+event_getters!(ExecMode { ip, mode });
+
+/// A change of the execution mode, e.g. into 32-bit compatibility code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModeSwitch {
+    /// The address at which the new mode is effective.
+    ///
+    /// None if the address has been suppressed.
+    pub ip: Option<u64>,
+    /// The new execution mode
+    pub mode: ExecModeType,
+    /// The time stamp count of the change, if known
+    pub tsc: Option<u64>
+}
+
+impl Event {
+    /// The mode change of an exec mode event.
+    ///
+    /// Returns None for all other events.
+    pub fn mode_switch(self) -> Option<ModeSwitch> {
+        match self.payload() {
+            Payload::ExecMode(m) => Some(ModeSwitch {
+                ip: (!self.ip_suppressed()).then(|| m.ip()),
+                mode: m.mode(),
+                tsc: self.timestamp()
+            }),
+            _ => None
+        }
+    }
+}
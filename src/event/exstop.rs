@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_12;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_exstop };
 
     #[test]
@@ -33,4 +33,6 @@ impl Exstop {
     ///
     /// This field is not valid, if @ip_suppressed is set.
     pub fn ip(self) -> u64 { self.0.ip }
-}
\ No newline at end of file
+}
+
+event_getters!(Exstop { ip });
@@ -0,0 +1,146 @@
+use super::{Event, Payload};
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libipt_sys::{
+        pt_event, pt_event__bindgen_ty_1__bindgen_ty_1, pt_event__bindgen_ty_1__bindgen_ty_2,
+        pt_event__bindgen_ty_1__bindgen_ty_3, pt_event_type, pt_event_type_ptev_async_disabled,
+        pt_event_type_ptev_disabled, pt_event_type_ptev_enabled, pt_event_type_ptev_tick,
+    };
+    use core::mem;
+
+    fn event(type_: pt_event_type, tsc: Option<u64>) -> pt_event {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = type_;
+        evt._bitfield_1 = pt_event::new_bitfield_1(0, 0, tsc.is_some() as u32);
+        evt.tsc = tsc.unwrap_or(0);
+        evt
+    }
+
+    #[test]
+    fn test_intervals() {
+        let mut enabled = event(pt_event_type_ptev_enabled, Some(10));
+        enabled.variant.enabled = pt_event__bindgen_ty_1__bindgen_ty_1 {
+            ip: 0x1000,
+            _bitfield_1: pt_event__bindgen_ty_1__bindgen_ty_1::new_bitfield_1(0),
+            __bindgen_padding_0: Default::default(),
+        };
+        let mut disabled = event(pt_event_type_ptev_disabled, Some(20));
+        disabled.variant.disabled = pt_event__bindgen_ty_1__bindgen_ty_2 { ip: 0x2000 };
+        let mut resumed = event(pt_event_type_ptev_enabled, None);
+        resumed.variant.enabled = pt_event__bindgen_ty_1__bindgen_ty_1 {
+            ip: 0x2000,
+            _bitfield_1: pt_event__bindgen_ty_1__bindgen_ty_1::new_bitfield_1(1),
+            __bindgen_padding_0: Default::default(),
+        };
+        let mut async_disabled = event(pt_event_type_ptev_async_disabled, Some(40));
+        async_disabled.variant.async_disabled = pt_event__bindgen_ty_1__bindgen_ty_3 {
+            at: 0x2010,
+            ip: 0x3000,
+        };
+
+        let events = [
+            enabled,
+            event(pt_event_type_ptev_tick, Some(15)),
+            disabled,
+            resumed,
+            async_disabled,
+            enabled,
+        ];
+        let found = intervals(events.iter().map(|e| Event(*e)));
+        assert_eq!(
+            found,
+            vec![
+                Interval {
+                    enabled_from: Some(0x1000),
+                    disabled_at: Some(0x2000),
+                    start_tsc: Some(10),
+                    end_tsc: Some(20),
+                    resumed: false
+                },
+                Interval {
+                    enabled_from: Some(0x2000),
+                    disabled_at: Some(0x2010),
+                    start_tsc: None,
+                    end_tsc: Some(40),
+                    resumed: true
+                },
+                Interval {
+                    enabled_from: Some(0x1000),
+                    disabled_at: None,
+                    start_tsc: Some(10),
+                    end_tsc: None,
+                    resumed: false
+                },
+            ]
+        );
+    }
+}
+
+/// A stretch of the trace during which tracing was enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interval {
+    /// The address at which tracing was enabled.
+    ///
+    /// None if the address was suppressed.
+    pub enabled_from: Option<u64>,
+    /// The address at which tracing was disabled.
+    ///
+    /// For asynchronous disables this is the source of the branch
+    /// that disabled tracing, otherwise the destination of the first
+    /// branch into a filtered area.
+    /// None if the address was suppressed or the trace ended first.
+    pub disabled_at: Option<u64>,
+    /// The time stamp count at which tracing was enabled, if known.
+    pub start_tsc: Option<u64>,
+    /// The time stamp count at which tracing was disabled, if known.
+    pub end_tsc: Option<u64>,
+    /// A flag indicating that tracing resumed where it had been disabled before.
+    pub resumed: bool,
+}
+
+#[inline]
+fn ip(evt: Event, ip: u64) -> Option<u64> {
+    (!evt.ip_suppressed()).then_some(ip)
+}
+
+/// Folds the enable and disable events in @events into the intervals
+/// during which tracing was enabled.
+///
+/// Other events are ignored.
+/// The gaps between the returned intervals are the gaps in the trace.
+/// If tracing is still enabled after the last event,
+/// the last interval has no end.
+pub fn intervals<I: IntoIterator<Item = Event>>(events: I) -> Vec<Interval> {
+    let mut done = Vec::new();
+    let mut open: Option<Interval> = None;
+    for evt in events {
+        let disabled_at = match evt.payload() {
+            Payload::Enabled(e) => {
+                done.extend(open.take());
+                open = Some(Interval {
+                    enabled_from: ip(evt, e.ip()),
+                    disabled_at: None,
+                    start_tsc: evt.timestamp(),
+                    end_tsc: None,
+                    resumed: e.resumed(),
+                });
+                continue;
+            }
+            Payload::Disabled(d) => ip(evt, d.ip()),
+            Payload::AsyncDisabled(d) => Some(d.at()),
+            _ => continue,
+        };
+
+        if let Some(mut i) = open.take() {
+            i.disabled_at = disabled_at;
+            i.end_tsc = evt.timestamp();
+            done.push(i);
+        }
+    }
+    done.extend(open);
+    done
+}
@@ -1,11 +1,11 @@
+use super::{Event, Payload};
 use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_19;
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
-    use libipt_sys::{ pt_event, pt_event_type_ptev_mnt };
+    use core::mem;
+    use libipt_sys::{ pt_event, pt_event_type_ptev_mnt, pt_event_type_ptev_stop };
 
     #[test]
     fn test_mnt_payload() {
@@ -23,12 +23,44 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_maintenance() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_mnt;
+        evt.variant.mnt = pt_event__bindgen_ty_1__bindgen_ty_19 {
+            payload: 0x1234
+        };
+        assert_eq!(Event(evt).maintenance(), Some(0x1234));
+
+        evt.type_ = pt_event_type_ptev_stop;
+        assert_eq!(Event(evt).maintenance(), None);
+    }
 }
 
 /// A maintenance event.
+///
+/// Maintenance packets are model specific,
+/// their payload is passed on without interpretation.
 #[derive(Clone, Copy, Debug)]
 pub struct Mnt(pub(super) pt_event__bindgen_ty_1__bindgen_ty_19);
 impl Mnt {
     /// The raw payload.
     pub fn payload(self) -> u64 { self.0.payload }
-}
\ No newline at end of file
+}
+
+event_getters!(Mnt { payload });
+
+impl Event {
+    /// The raw payload of a maintenance event.
+    ///
+    /// Returns None for all other events.
+    /// Use this to log maintenance packets of newer processors,
+    /// which libipt can't interpret.
+    pub fn maintenance(self) -> Option<u64> {
+        match self.payload() {
+            Payload::Mnt(m) => Some(m.payload()),
+            _ => None
+        }
+    }
+}
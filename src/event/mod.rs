@@ -22,6 +22,36 @@ use libipt_sys::{
     pt_event_type_ptev_vmcs as PT_EVENT_TYPE_PTEV_VMCS
 };
 
+// compares, hashes and serializes the event data @w by the values of its getters.
+// events are only created by decoders, so they are not deserialized.
+macro_rules! event_getters {
+    ($w:ident { $($field:ident),* }) => {
+        impl PartialEq for $w {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field() == other.$field())&&*
+            }
+        }
+
+        impl Eq for $w {}
+
+        impl core::hash::Hash for $w {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                $(core::hash::Hash::hash(&self.$field(), state);)*
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $w {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+                let mut st = s.serialize_struct(stringify!($w), [$(stringify!($field)),*].len())?;
+                $(st.serialize_field(stringify!($field), &self.$field())?;)*
+                st.end()
+            }
+        }
+    };
+}
+
 mod enabled;
 pub use enabled::*;
 mod disabled;
@@ -57,12 +87,30 @@ pub use cbr::*;
 
 mod qry;
 pub use qry::*;
+#[cfg(feature = "evt")]
+mod evt;
+#[cfg(feature = "evt")]
+pub use evt::*;
+
+mod interval;
+pub use interval::*;
+mod power;
+pub use power::*;
+mod channel;
+pub use channel::*;
+
+use core::fmt;
+use core::hash::{Hash, Hasher};
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::mem;
-    use libipt_sys::pt_event_type_ptev_stop;
+    use core::mem;
+    use libipt_sys::{
+        pt_event__bindgen_ty_1__bindgen_ty_17,
+        pt_event_type_ptev_stop,
+        pt_event_type_ptev_tick
+    };
 
     #[test]
     fn test_create_event() {
@@ -79,9 +127,11 @@ mod test {
         let evt = Event(evt);
         assert!(evt.ip_suppressed());
         assert!(!evt.status_update());
+        assert_eq!(evt.ip(), None);
         assert!(evt.has_tsc());
 
         assert_eq!(evt.tsc(), 1);
+        assert_eq!(evt.timestamp(), Some(1));
         assert_eq!(evt.lost_mtc(), 2);
         assert_eq!(evt.lost_cyc(), 3);
 
@@ -90,30 +140,94 @@ mod test {
             _ => unreachable!()
         }
     }
+
+    #[test]
+    fn test_event_ip() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_tick;
+        evt.variant.tick = pt_event__bindgen_ty_1__bindgen_ty_17 { ip: 0x1000 };
+        assert_eq!(Event(evt).ip(), Some(0x1000));
+
+        evt._bitfield_1 = pt_event::new_bitfield_1(1, 0, 0);
+        assert_eq!(Event(evt).ip(), None);
+    }
+
+    #[test]
+    fn test_event_debug() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_stop;
+        let evt = Event(evt);
+
+        assert_eq!(evt.ip(), None);
+
+        let copy = evt;
+        assert!(matches!(copy.payload(), Payload::Stop));
+        let s = format!("{:?}", evt);
+        assert!(s.starts_with("Event { payload: Stop,"), "{}", s);
+        assert!(s.contains("tsc: None"), "{}", s);
+    }
+
+    #[test]
+    fn test_event_unknown_type() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = 0x1000;
+        let evt = Event(evt);
+        assert_eq!(evt.payload(), Payload::Unknown(0x1000));
+        assert_eq!(evt.ip(), None);
+    }
 }
 
-#[derive(Debug)]
+/// The type of an event together with its type specific data
+///
+/// This is a safe view of the event union of libipt,
+/// match on it instead of accessing the raw event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Payload {
+    /// Tracing has been enabled
     Enabled(Enabled),
+    /// Tracing has been disabled
     Disabled(Disabled),
-    AsnycDisabled(AsyncDisabled),
+    /// Tracing has been disabled asynchronously
+    AsyncDisabled(AsyncDisabled),
+    /// An asynchronous branch, e.g. interrupt
     AsyncBranch(AsyncBranch),
+    /// A synchronous paging event
     Paging(Paging),
+    /// An asynchronous paging event
     AsyncPaging(AsyncPaging),
+    /// Trace overflow
     Overflow(Overflow),
+    /// An execution mode change
     ExecMode(ExecMode),
+    /// A transactional execution state change
     Tsx(Tsx),
+    /// A synchronous vmcs event
     Vmcs(Vmcs),
+    /// An asynchronous vmcs event
     AsyncVmcs(AsyncVmcs),
+    /// Execution has stopped
     Exstop(Exstop),
+    /// An MWAIT operation completed
     Mwait(Mwait),
+    /// A power state was entered
     Pwre(Pwre),
+    /// A power state was exited
     Pwrx(Pwrx),
+    /// A PTWRITE event
     Ptwrite(Ptwrite),
+    /// A timing event
     Tick(Tick),
+    /// A maintenance event
     Mnt(Mnt),
+    /// A core:bus ratio event
     Cbr(Cbr),
-    Stop
+    /// Tracing has been stopped
+    Stop,
+    /// An event of a type this crate doesn't know, e.g. from a newer libipt.
+    ///
+    /// Holds the raw event type.
+    Unknown(u32)
 }
 
 impl From<pt_event> for Payload {
@@ -121,7 +235,7 @@ impl From<pt_event> for Payload {
         unsafe {
             match evt.type_ {
                 PT_EVENT_TYPE_PTEV_ASYNC_BRANCH => Payload::AsyncBranch(AsyncBranch(evt.variant.async_branch)),
-                PT_EVENT_TYPE_PTEV_ASYNC_DISABLED => Payload::AsnycDisabled(AsyncDisabled(evt.variant.async_disabled)),
+                PT_EVENT_TYPE_PTEV_ASYNC_DISABLED => Payload::AsyncDisabled(AsyncDisabled(evt.variant.async_disabled)),
                 PT_EVENT_TYPE_PTEV_ASYNC_PAGING => Payload::AsyncPaging(AsyncPaging(evt.variant.async_paging)),
                 PT_EVENT_TYPE_PTEV_ASYNC_VMCS => Payload::AsyncVmcs(AsyncVmcs(evt.variant.async_vmcs)),
                 PT_EVENT_TYPE_PTEV_CBR => Payload::Cbr(Cbr(evt.variant.cbr)),
@@ -140,24 +254,63 @@ impl From<pt_event> for Payload {
                 PT_EVENT_TYPE_PTEV_TSX => Payload::Tsx(Tsx(evt.variant.tsx)),
                 PT_EVENT_TYPE_PTEV_VMCS => Payload::Vmcs(Vmcs(evt.variant.vmcs)),
                 PT_EVENT_TYPE_PTEV_STOP => Payload::Stop,
-                _ => unreachable!()
+                t => Payload::Unknown(t as u32)
             }
         }
     }
 }
 
+/// An event along with its timing information
+///
+/// Use `Event::payload` to get the type of the event and its data.
+/// With the `serde` feature events can be serialized, but not deserialized
+/// since only decoders create them.
 #[derive(Clone, Copy)]
 pub struct Event(pub(crate) pt_event);
 impl Event {
     /// A flag indicating that the event IP has been suppressed.
+    ///
+    /// The address fields of the payload are not valid in this case,
+    /// see `Event::ip`.
     pub fn ip_suppressed(self) -> bool { self.0.ip_suppressed() > 0 }
     /// A flag indicating that the event is for status update.
+    ///
+    /// Status updates repeat the state the decoder found at a
+    /// synchronization point, e.g. the execution mode or paging state.
+    /// They don't indicate a change, consumers that track changes
+    /// should ignore them.
     pub fn status_update(self) -> bool { self.0.status_update() > 0 }
+    /// The address the event refers to.
+    ///
+    /// Returns None if the event has no address or it has been suppressed.
+    /// For asynchronous branches this is the branch destination.
+    pub fn ip(self) -> Option<u64> {
+        let ip = match self.payload() {
+            Payload::Enabled(e) => e.ip(),
+            Payload::Disabled(e) => e.ip(),
+            Payload::AsyncDisabled(e) => e.ip(),
+            Payload::AsyncBranch(e) => e.to(),
+            Payload::AsyncPaging(e) => e.ip(),
+            Payload::Overflow(e) => e.ip(),
+            Payload::ExecMode(e) => e.ip(),
+            Payload::Tsx(e) => e.ip(),
+            Payload::AsyncVmcs(e) => e.ip(),
+            Payload::Exstop(e) => e.ip(),
+            Payload::Mwait(e) => e.ip(),
+            Payload::Ptwrite(e) => e.ip(),
+            Payload::Tick(e) => e.ip(),
+            Payload::Paging(_) | Payload::Vmcs(_) | Payload::Pwre(_) | Payload::Pwrx(_) |
+            Payload::Mnt(_) | Payload::Cbr(_) | Payload::Stop | Payload::Unknown(_) => return None
+        };
+        (!self.ip_suppressed()).then_some(ip)
+    }
     /// A flag indicating that the event has timing information.
     pub fn has_tsc(self) -> bool { self.0.has_tsc() > 0 }
     /// The time stamp count of the event.
     /// This field is only valid if \@has_tsc is set.
     pub fn tsc(self) -> u64 { self.0.tsc }
+    /// The time stamp count of the event if it has timing information.
+    pub fn timestamp(self) -> Option<u64> { self.has_tsc().then(|| self.tsc()) }
     /// The number of lost mtc packets.
     ///
     /// This gives an idea about the quality of the \@tsc.
@@ -170,4 +323,57 @@ impl Event {
     pub fn lost_cyc(self) -> u32 { self.0.lost_cyc }
     /// Event specific data.
     pub fn payload(self) -> Payload { self.0.into() }
-}
\ No newline at end of file
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Event")
+            .field("payload", &self.payload())
+            .field("ip_suppressed", &self.ip_suppressed())
+            .field("status_update", &self.status_update())
+            .field("tsc", &self.timestamp())
+            .field("lost_mtc", &self.lost_mtc())
+            .field("lost_cyc", &self.lost_cyc())
+            .finish()
+    }
+}
+
+// events are equal if all their getters are, like in `Debug`
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.payload() == other.payload()
+            && self.ip_suppressed() == other.ip_suppressed()
+            && self.status_update() == other.status_update()
+            && self.timestamp() == other.timestamp()
+            && self.lost_mtc() == other.lost_mtc()
+            && self.lost_cyc() == other.lost_cyc()
+    }
+}
+
+impl Eq for Event {}
+
+impl Hash for Event {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.payload().hash(state);
+        self.ip_suppressed().hash(state);
+        self.status_update().hash(state);
+        self.timestamp().hash(state);
+        self.lost_mtc().hash(state);
+        self.lost_cyc().hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Event {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut st = s.serialize_struct("Event", 6)?;
+        st.serialize_field("payload", &self.payload())?;
+        st.serialize_field("ip_suppressed", &self.ip_suppressed())?;
+        st.serialize_field("status_update", &self.status_update())?;
+        st.serialize_field("tsc", &self.timestamp())?;
+        st.serialize_field("lost_mtc", &self.lost_mtc())?;
+        st.serialize_field("lost_cyc", &self.lost_cyc())?;
+        st.end()
+    }
+}
@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_13;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_mwait };
 
     #[test]
@@ -47,4 +47,6 @@ impl Mwait {
     ///
     /// Reserved bits are undefined.
     pub fn ext(self) -> u32 { self.0.ext }
-}
\ No newline at end of file
+}
+
+event_getters!(Mwait { ip, hints, ext });
@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_7;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_overflow };
 
     #[test]
@@ -34,4 +34,6 @@ impl Overflow {
     /// This field is not valid, if ip_suppressed is set.
     /// In this case, the overflow resolved while tracing was disabled.
     pub fn ip(self) -> u64 { self.0.ip }
-}
\ No newline at end of file
+}
+
+event_getters!(Overflow { ip });
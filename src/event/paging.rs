@@ -1,3 +1,4 @@
+use super::{Event, Payload};
 use libipt_sys::{
     pt_event__bindgen_ty_1__bindgen_ty_5,
     pt_event__bindgen_ty_1__bindgen_ty_6
@@ -6,12 +7,12 @@ use libipt_sys::{
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{
         pt_event,
         pt_event_type_ptev_paging,
-        pt_event_type_ptev_async_paging
+        pt_event_type_ptev_async_paging,
+        pt_event_type_ptev_tick
     };
 
     #[test]
@@ -54,6 +55,26 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_address_space_switch() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_async_paging;
+        evt.variant.async_paging = pt_event__bindgen_ty_1__bindgen_ty_6 {
+            cr3: 11,
+            ip: 12,
+            _bitfield_1: pt_event__bindgen_ty_1__bindgen_ty_6::new_bitfield_1(0)
+        };
+        assert_eq!(Event(evt).address_space(), Some(AddressSpace {
+            cr3: 11,
+            non_root: false,
+            ip: Some(12),
+            tsc: None
+        }));
+
+        evt.type_ = pt_event_type_ptev_tick;
+        assert_eq!(Event(evt).address_space(), None);
+    }
 }
 
 /// A synchronous paging event
@@ -70,6 +91,8 @@ impl Paging {
     pub fn non_root(self) -> bool { self.0.non_root() > 0 }
 }
 
+event_getters!(Paging { cr3, non_root });
+
 /// An asynchronous paging event
 #[derive(Clone, Copy, Debug)]
 pub struct AsyncPaging(pub(super) pt_event__bindgen_ty_1__bindgen_ty_6);
@@ -85,4 +108,47 @@ impl AsyncPaging {
     pub fn non_root(self) -> bool { self.0.non_root() > 0 }
     /// The address at which the event is effective
     pub fn ip(self) -> u64 { self.0.ip }
-}
\ No newline at end of file
+}
+
+event_getters!(AsyncPaging { cr3, non_root, ip });
+
+/// A switch of the address space, taken from a paging event
+///
+/// Use this to follow the traced processes directly from the event stream
+/// or to correlate the trace with sideband data about them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressSpace {
+    /// The new CR3 value
+    pub cr3: u64,
+    /// A flag indicating whether the cpu is operating in
+    /// vmx non-root (guest) mode.
+    pub non_root: bool,
+    /// The address at which an asynchronous switch is effective.
+    ///
+    /// None for synchronous switches, they are effective
+    /// at the next branch.
+    pub ip: Option<u64>,
+    /// The time stamp count of the switch, if known
+    pub tsc: Option<u64>
+}
+
+impl Event {
+    /// The address space switch of a paging or async paging event.
+    ///
+    /// Returns None for all other events.
+    pub fn address_space(self) -> Option<AddressSpace> {
+        let (cr3, non_root, ip) = match self.payload() {
+            Payload::Paging(p) => (p.cr3(), p.non_root(), None),
+            Payload::AsyncPaging(p) => (p.cr3(), p.non_root(), Some(p.ip())),
+            _ => return None
+        };
+
+        Some(AddressSpace {
+            cr3,
+            non_root,
+            ip,
+            tsc: self.timestamp()
+        })
+    }
+}
@@ -0,0 +1,122 @@
+use super::{Event, Exstop, Mwait, Payload, Pwre, Pwrx};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libipt_sys::{
+        pt_event, pt_event__bindgen_ty_1__bindgen_ty_13, pt_event__bindgen_ty_1__bindgen_ty_15,
+        pt_event_type_ptev_mwait, pt_event_type_ptev_pwrx, pt_event_type_ptev_tick,
+    };
+    use core::mem;
+
+    #[test]
+    fn test_power_events() {
+        let mut mwait: pt_event = unsafe { mem::zeroed() };
+        mwait.type_ = pt_event_type_ptev_mwait;
+        mwait.variant.mwait = pt_event__bindgen_ty_1__bindgen_ty_13 {
+            ip: 0x1000,
+            hints: 0x21,
+            ext: 1,
+        };
+        let mut tick: pt_event = unsafe { mem::zeroed() };
+        tick.type_ = pt_event_type_ptev_tick;
+        let mut pwrx: pt_event = unsafe { mem::zeroed() };
+        pwrx.type_ = pt_event_type_ptev_pwrx;
+        pwrx._bitfield_1 = pt_event::new_bitfield_1(1, 0, 1);
+        pwrx.tsc = 99;
+        pwrx.variant.pwrx = pt_event__bindgen_ty_1__bindgen_ty_15 {
+            last: 1,
+            deepest: 6,
+            _bitfield_1: pt_event__bindgen_ty_1__bindgen_ty_15::new_bitfield_1(1, 0, 0),
+            __bindgen_padding_0: Default::default(),
+        };
+
+        let found: Vec<_> = power_events([mwait, tick, pwrx].iter().map(|e| Event(*e))).collect();
+        assert_eq!(found.len(), 2);
+
+        assert_eq!(found[0].ip, Some(0x1000));
+        assert_eq!(found[0].tsc, None);
+        match found[0].kind {
+            PowerKind::Mwait(m) => assert_eq!(m.cstate(), (3, 1)),
+            _ => unreachable!(),
+        }
+
+        assert_eq!(found[1].ip, None);
+        assert_eq!(found[1].tsc, Some(99));
+        match found[1].kind {
+            PowerKind::Pwrx(x) => {
+                assert_eq!(x.deepest(), 6);
+                assert!(x.interrupt());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The kind of a power event and its data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum PowerKind {
+    /// Execution has stopped
+    Exstop(Exstop),
+    /// An MWAIT operation completed
+    Mwait(Mwait),
+    /// A power state was entered
+    Pwre(Pwre),
+    /// A power state was exited
+    Pwrx(Pwrx),
+}
+
+/// A power management event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PowerEvent {
+    /// The kind of event
+    pub kind: PowerKind,
+    /// The address of the instruction the event refers to.
+    ///
+    /// Only execution stops and MWAITs have an address.
+    /// None if the address has been suppressed.
+    pub ip: Option<u64>,
+    /// The time stamp count of the event, if known
+    pub tsc: Option<u64>,
+}
+
+impl Mwait {
+    /// The target C-state and sub C-state of the mwait hints.
+    ///
+    /// The C-state is given as in `Pwre::state`, e.g. 1 for C1.
+    pub fn cstate(self) -> (u8, u8) {
+        let hints = self.hints();
+        ((((hints >> 4) + 1) & 0xf) as u8, (hints & 0xf) as u8)
+    }
+}
+
+impl Event {
+    /// The power management event of an EXSTOP, MWAIT,
+    /// PWRE or PWRX event.
+    ///
+    /// Returns None for all other events.
+    pub fn power(self) -> Option<PowerEvent> {
+        let (kind, ip) = match self.payload() {
+            Payload::Exstop(e) => (PowerKind::Exstop(e), Some(e.ip())),
+            Payload::Mwait(m) => (PowerKind::Mwait(m), Some(m.ip())),
+            Payload::Pwre(e) => (PowerKind::Pwre(e), None),
+            Payload::Pwrx(x) => (PowerKind::Pwrx(x), None),
+            _ => return None,
+        };
+
+        Some(PowerEvent {
+            kind,
+            ip: ip.filter(|_| !self.ip_suppressed()),
+            tsc: self.timestamp(),
+        })
+    }
+}
+
+/// The power management events in @events.
+///
+/// Other events are skipped.
+pub fn power_events<I: IntoIterator<Item = Event>>(events: I) -> impl Iterator<Item = PowerEvent> {
+    events.into_iter().filter_map(Event::power)
+}
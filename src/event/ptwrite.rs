@@ -1,11 +1,11 @@
+use super::{Event, Payload};
 use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_16;
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
-    use libipt_sys::{ pt_event, pt_event_type_ptev_ptwrite };
+    use core::mem;
+    use libipt_sys::{ pt_event, pt_event_type_ptev_ptwrite, pt_event_type_ptev_tick };
 
     #[test]
     fn test_ptwrite_payload() {
@@ -27,6 +27,29 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_ptwrites() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_ptwrite;
+        evt._bitfield_1 = pt_event::new_bitfield_1(1, 0, 1);
+        evt.tsc = 5;
+        evt.variant.ptwrite = pt_event__bindgen_ty_1__bindgen_ty_16 {
+            ip: 11,
+            size: 4,
+            payload: 0xdead_beef
+        };
+        let mut tick: pt_event = unsafe { mem::zeroed() };
+        tick.type_ = pt_event_type_ptev_tick;
+
+        let values: Vec<_> = ptwrites([tick, evt].iter().map(|e| Event(*e))).collect();
+        assert_eq!(values, vec![PtwriteValue {
+            value: 0xdead_beef,
+            size: 4,
+            ip: None,
+            tsc: Some(5)
+        }]);
+    }
 }
 
 /// A PTWRITE event.
@@ -42,4 +65,48 @@ impl Ptwrite {
     pub fn size(self) -> u8{ self.0.size }
     /// The ptwrite payload.
     pub fn payload(self) -> u64 { self.0.payload }
-}
\ No newline at end of file
+}
+
+event_getters!(Ptwrite { ip, size, payload });
+
+/// A value written with PTWRITE
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PtwriteValue {
+    /// The written value
+    pub value: u64,
+    /// The size of the written value in bytes, 4 or 8
+    pub size: u8,
+    /// The address of the ptwrite instruction.
+    ///
+    /// None if the address has been suppressed.
+    pub ip: Option<u64>,
+    /// The time stamp count of the write, if known
+    pub tsc: Option<u64>
+}
+
+impl Event {
+    /// The value of a ptwrite event.
+    ///
+    /// Returns None for all other events.
+    pub fn ptwrite_value(self) -> Option<PtwriteValue> {
+        match self.payload() {
+            Payload::Ptwrite(p) => Some(PtwriteValue {
+                value: p.payload(),
+                size: p.size(),
+                ip: (!self.ip_suppressed()).then(|| p.ip()),
+                tsc: self.timestamp()
+            }),
+            _ => None
+        }
+    }
+}
+
+/// The values written with PTWRITE in @events.
+///
+/// Other events are skipped.
+/// Use this to read back values that software logged with PTWRITE,
+/// e.g. from the events of a query decoder.
+pub fn ptwrites<I: IntoIterator<Item = Event>>(events: I) -> impl Iterator<Item = PtwriteValue> {
+    events.into_iter().filter_map(Event::ptwrite_value)
+}
@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_14;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_pwre };
 
     #[test]
@@ -41,4 +41,6 @@ impl Pwre {
     /// A flag indicating whether the C-state entry was
     /// initiated by h/w.
     pub fn hw(self) -> bool { self.0.hw() > 0 }
-}
\ No newline at end of file
+}
+
+event_getters!(Pwre { state, sub_state, hw });
@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_15;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_pwrx };
 
     #[test]
@@ -52,4 +52,6 @@ impl Pwrx {
     ///
     /// - due to h/w autonomous condition such as HDC.
     pub fn autonomous(self) -> bool { self.0.autonomous() > 0 }
-}
\ No newline at end of file
+}
+
+event_getters!(Pwrx { last, deepest, interrupt, store, autonomous });
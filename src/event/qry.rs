@@ -1,15 +1,20 @@
 use crate::error::{
-    PtError, deref_ptresult,
+    PtError, DecodeError, deref_ptresult,
     ensure_ptok, extract_pterr,
-    deref_ptresult_mut, PtErrorCode
+    ensure_ptr, ensure_synced, UntilEos
 };
-use crate::config::Config;
+use crate::config::{Config, Owned, QueryFlags};
 use crate::Status;
+use crate::progress::Progress;
+use crate::budget::{Budget, ScanLimits};
+use crate::cancel::CancelToken;
+use crate::time::{TimeCal, TimeInfo};
 use crate::event::Event;
 
-use std::convert::TryFrom;
-use std::marker::PhantomData;
-use std::mem;
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use alloc::sync::Arc;
+use core::mem;
 
 use num_enum::TryFromPrimitive;
 use libipt_sys::{
@@ -34,6 +39,7 @@ use libipt_sys::{
 mod test {
     use super::*;
     use crate::config::ConfigBuilder;
+    use crate::error::ErrorKind;
 
     #[test]
     fn test_qrydec_alloc() {
@@ -59,14 +65,36 @@ mod test {
         assert!(b.event().is_err());
         assert!(b.config().is_ok());
         assert!(b.offset().is_err());
+        assert!(b.position().is_err());
         assert!(b.sync_offset().is_err());
         assert!(b.sync_backward().is_err());
         assert!(b.sync_forward().is_err());
-        assert!(b.time().is_err());
+        assert!(!b.time().unwrap().has_tsc);
+        assert!(b.reset(None).is_ok());
+    }
+
+    #[test]
+    fn test_qrydec_cancel() {
+        let kek = &mut [2; 3];
+        let mut b = QueryDecoder::new(
+            &ConfigBuilder::new(kek).unwrap().finish()
+        ).unwrap();
+
+        let cancel = CancelToken::new();
+        b.set_cancel(Some(cancel.clone()));
+        cancel.cancel();
+        assert_eq!(b.sync_forward().unwrap_err().kind(), ErrorKind::Cancelled);
+        assert_eq!(b.sync_set(0).unwrap_err().kind(), ErrorKind::Cancelled);
+        b.set_cancel(None);
+
+        b.set_budget(Some(Budget::new().bytes(0)));
+        assert_eq!(b.sync_backward().unwrap_err().kind(), ErrorKind::BudgetExhausted);
+        b.set_budget(None);
+        assert_ne!(b.sync_backward().unwrap_err().kind(), ErrorKind::BudgetExhausted);
     }
 }
 
-#[derive(Clone, Copy, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, TryFromPrimitive)]
 #[repr(i32)]
 pub enum CondBranch {
     Taken = 1,
@@ -76,16 +104,86 @@ pub enum CondBranch {
 /// The decoder will work on the buffer defined in the config,
 /// it shall contain raw trace data and remain valid for the lifetime of the decoder.
 /// The decoder needs to be synchronized before it can be used.
-pub struct QueryDecoder<'a, T>(&'a mut pt_query_decoder, PhantomData<T>);
+///
+/// The decoder is Send and Sync if the payload type `T` of the decode callback is.
+// keeps an owned trace buffer or decode callback of the config alive,
+// limits the synchronization scans,
+// and whether the decoder was reset in place and not synchronized since
+pub struct QueryDecoder<'a, T>(
+    *mut pt_query_decoder,
+    PhantomData<&'a mut T>,
+    Option<Arc<Owned>>,
+    ScanLimits,
+    bool,
+);
+
+// SAFETY: the decoder only reads the trace buffer and calls the decode
+// callback through a shared reference. `with_callback` requires the callback
+// to be Send and Sync, its type is erased and not reflected in `T`,
+// which is only the type of its payloads. Methods taking `&self` only
+// read the decoder state and never call the callback.
+unsafe impl<T: Send> Send for QueryDecoder<'_, T> {}
+unsafe impl<T: Sync> Sync for QueryDecoder<'_, T> {}
+
 impl<'a, T> QueryDecoder<'a, T> {
     /// Allocate an Intel PT query decoder.
     ///
     /// The decoder will work on the buffer defined in @config,
     /// it shall contain raw trace data and remain valid for the lifetime of the decoder.
+    /// The trace buffer of @config is borrowed for @'a, the decoder can't outlive it.
     /// The decoder needs to be synchronized before it can be used.
-    pub fn new(cfg: &Config<T>) -> Result<Self, PtError> {
-        deref_ptresult_mut(unsafe { pt_qry_alloc_decoder(cfg.0.as_ref()) })
-            .map(|d| QueryDecoder::<T>(d, PhantomData))
+    ///
+    /// ```compile_fail
+    /// use libipt::ConfigBuilder;
+    /// use libipt::event::QueryDecoder;
+    ///
+    /// let decoder = {
+    ///     let mut buf = [0u8; 16];
+    ///     let cfg = ConfigBuilder::new(&mut buf).unwrap().finish();
+    ///     QueryDecoder::new(&cfg).unwrap()
+    /// };
+    /// drop(decoder);
+    /// ```
+    pub fn new(cfg: &Config<'a, T>) -> Result<Self, PtError> {
+        ensure_ptr(unsafe { pt_qry_alloc_decoder(cfg.0.as_ref()) })
+            .map(|d| QueryDecoder::<T>(d, PhantomData, cfg.2.clone(), ScanLimits::default(), false))
+    }
+
+    /// Take ownership of the raw query decoder @decoder.
+    ///
+    /// # Safety
+    /// @decoder must have been allocated with pt_qry_alloc_decoder and must not be
+    /// used or freed by anyone else afterwards.
+    /// Its trace buffer and decode callback must stay valid for @'a,
+    /// the callback has to be of type `T`.
+    pub unsafe fn from_raw(decoder: *mut pt_query_decoder) -> Self {
+        QueryDecoder(decoder, PhantomData, None, ScanLimits::default(), false)
+    }
+
+    /// A pointer to the raw query decoder
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the decoder is.
+    pub fn as_ptr(&self) -> *const pt_query_decoder {
+        self.0
+    }
+
+    /// A mutable pointer to the raw query decoder
+    ///
+    /// See `as_ptr`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_query_decoder {
+        self.0
+    }
+
+    /// Release the raw query decoder.
+    ///
+    /// The caller has to free it with pt_qry_free_decoder.
+    /// An owned trace buffer or decode callback of the config is leaked,
+    /// the raw decoder still uses it.
+    pub fn into_raw(self) -> *mut pt_query_decoder {
+        let decoder = self.0;
+        mem::forget(self);
+        decoder
     }
 
     /// Query whether the next unconditional branch has been taken.
@@ -98,11 +196,12 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// Returns Eos if decoding reached the end of the Intel PT buffer.
     /// Returns Nosync if decoder is out of sync.
     pub fn cond_branch(&mut self) -> Result<(CondBranch, Status), PtError> {
+        ensure_synced(self.4)?;
         let mut taken: i32 = 0;
         extract_pterr(unsafe { pt_qry_cond_branch(self.0, &mut taken) })
             .map(|s| (
                 CondBranch::try_from(taken).unwrap(),
-                Status::from_bits(s).unwrap()))
+                Status::from_raw(s)))
     }
 
     /// Return the current core bus ratio.
@@ -111,6 +210,7 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// The ratio is defined as core cycles per bus clock cycle.
     /// Returns NoCbr if there has not been a CBR packet.
     pub fn core_bus_ratio(&mut self) -> Result<u32, PtError> {
+        ensure_synced(self.4)?;
         let mut cbr: u32 = 0;
         ensure_ptok(unsafe { pt_qry_core_bus_ratio(self.0, &mut cbr) })
             .map(|_| cbr)
@@ -124,13 +224,16 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// Returns BadQuery if no event is found.
     /// Returns Eos if decoding reached the end of the Intel PT buffer.
     /// Returns Nosync if decoder is out of sync.
-    pub fn event(&mut self) -> Result<(Event, Status), PtError> {
+    /// Errors carry the decoder position, see `DecodeError`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), err))]
+    pub fn event(&mut self) -> Result<(Event, Status), DecodeError> {
         let mut evt: pt_event = unsafe { mem::zeroed() };
-        extract_pterr(unsafe {
+        ensure_synced(self.4).and_then(|_| extract_pterr(unsafe {
             pt_qry_event(self.0,
                          &mut evt,
                          mem::size_of::<pt_event>())
-        }).map(|s| (Event(evt), Status::from_bits(s).unwrap()))
+        })).map(|s| (Event(evt), Status::from_raw(s)))
+            .map_err(|e| DecodeError::new(e, self.offset().ok(), self.sync_offset().ok()))
     }
 
     pub fn config(&self) -> Result<Config<T>, PtError> {
@@ -138,20 +241,41 @@ impl<'a, T> QueryDecoder<'a, T> {
             .map(Config::from)
     }
 
+    /// The flags the decoder was configured with.
+    ///
+    /// See `ConfigBuilder::flags`.
+    pub fn flags(&self) -> Result<QueryFlags, PtError> {
+        self.config().map(|c| QueryFlags::from(c.0.flags))
+    }
+
     /// Get the current decoder position.
     ///
     /// Returns Nosync if decoder is out of sync.
     pub fn offset(&self) -> Result<u64, PtError> {
+        ensure_synced(self.4)?;
         let mut off: u64 = 0;
         ensure_ptok(unsafe { pt_qry_get_offset(self.0, &mut off) })
             .map(|_| off)
     }
 
+    /// Get the current decoder position within its trace buffer.
+    ///
+    /// Includes the size of the trace buffer and the number of bytes left,
+    /// see `Progress`.
+    /// Through a `&mut` reference `Iterator::position` takes precedence,
+    /// call this as `QueryDecoder::position(decoder)` there.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn position(&self) -> Result<Progress, PtError> {
+        let size = self.config()?.buffer_len() as u64;
+        self.offset().map(|offset| Progress { offset, size })
+    }
+
     /// Get the position of the last synchronization point.
     ///
     /// This is useful for splitting a trace stream for parallel decoding.
     /// Returns Nosync if decoder is out of sync.
     pub fn sync_offset(&self) -> Result<u64, PtError> {
+        ensure_synced(self.4)?;
         let mut off: u64 = 0;
         ensure_ptok(unsafe { pt_qry_get_sync_offset(self.0, &mut off) })
             .map(|_| off)
@@ -168,9 +292,10 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// Returns Eos if decoding reached the end of the Intel PT buffer.
     /// Returns Nosync if decoder is out of sync.
     pub fn indirect_branch(&mut self) -> Result<(u64, Status), PtError> {
+        ensure_synced(self.4)?;
         let mut ip: u64 = 0;
         extract_pterr(unsafe { pt_qry_indirect_branch(self.0, &mut ip) })
-            .map(|s| (ip, Status::from_bits(s).unwrap()))
+            .map(|s| (ip, Status::from_raw(s)))
     }
 
     /// Synchronize an Intel PT query decoder.
@@ -183,10 +308,18 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// Returns BadOpc if an unknown packet is encountered.
     /// Returns BadPacket if an unknown packet payload is encountered.
     /// Returns Eos if no further synchronization point is found.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
     pub fn sync_backward(&mut self) -> Result<(u64, Status), PtError> {
+        if self.4 {
+            let offset = self.config()?.psb_offset(true)?;
+            return self.sync_set(offset);
+        }
+        let start = self.3.start(|| self.offset().unwrap_or(0))?;
         let mut ip: u64 = 0;
-        extract_pterr(unsafe { pt_qry_sync_backward(self.0, &mut ip)})
-            .map(|s| (ip, Status::from_bits(s).unwrap()))
+        let res = extract_pterr(unsafe { pt_qry_sync_backward(self.0, &mut ip)})
+            .map(|s| (ip, Status::from_raw(s)));
+        self.track_sync(&res, start);
+        res
     }
 
     /// Synchronize an Intel PT query decoder.
@@ -199,10 +332,18 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// Returns BadOpc if an unknown packet is encountered.
     /// Returns BadPacket if an unknown packet payload is encountered.
     /// Returns Eos if no further synchronization point is found.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
     pub fn sync_forward(&mut self) -> Result<(u64, Status), PtError> {
+        if self.4 {
+            let offset = self.config()?.psb_offset(false)?;
+            return self.sync_set(offset);
+        }
+        let start = self.3.start(|| self.offset().unwrap_or(0))?;
         let mut ip: u64 = 0;
-        extract_pterr(unsafe { pt_qry_sync_forward(self.0, &mut ip) })
-            .map(|s| (ip, Status::from_bits(s).unwrap()))
+        let res = extract_pterr(unsafe { pt_qry_sync_forward(self.0, &mut ip) })
+            .map(|s| (ip, Status::from_raw(s)));
+        self.track_sync(&res, start);
+        res
     }
 
     /// Manually synchronize an Intel PT query decoder.
@@ -215,46 +356,106 @@ impl<'a, T> QueryDecoder<'a, T> {
     /// Returns Eos if @offset lies outside of decoder's trace buffer.
     /// Returns Eos if decoder reaches the end of its trace buffer.
     /// Returns Nosync if there is no syncpoint at @offset.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
     pub fn sync_set(&mut self, offset: u64) -> Result<(u64, Status), PtError> {
+        let start = self.3.start(|| offset)?;
         let mut ip: u64 = 0;
-        extract_pterr(unsafe { pt_qry_sync_set(self.0, &mut ip, offset)})
-            .map(|s| (ip, Status::from_bits(s).unwrap()))
+        let res = extract_pterr(unsafe { pt_qry_sync_set(self.0, &mut ip, offset)})
+            .map(|s| (ip, Status::from_raw(s)));
+        self.track_sync(&res, start);
+        res
     }
 
     /// Query the current time.
     ///
     /// On success, provides the time at the last query.
+    ///
     /// The time is similar to what a rdtsc instruction would return.
     /// Depending on the configuration, the time may not be fully accurate.
     /// If TSC is not enabled, the time is relative to the last synchronization
-    /// and can't be used to correlate with other TSC-based time sources.
-    /// In this case, NoTime is returned and the relative time is provided.
+    /// and can't be used to correlate with other TSC-based time sources,
+    /// `has_tsc` is false in this case.
     /// Some timing-related packets may need to be dropped (mostly due to missing calibration or incomplete configuration).
-    /// To get an idea about the quality of the estimated time, we record the number of dropped MTC and CYC packets.
-    /// Returns time, number of lost mtc packets and number of lost cyc packets.
-    /// Returns NoTime if there has not been a TSC packet.
-    pub fn time(&mut self) -> Result<(u64, u32, u32), PtError> {
-        let mut time: u64 = 0;
-        let mut mtc: u32 = 0;
-        let mut cyc: u32 = 0;
-        ensure_ptok(unsafe {
-            pt_qry_time(self.0,
-                        &mut time,
-                        &mut mtc,
-                        &mut cyc)
-        }).map(|_| (time, mtc, cyc))
+    /// To get an idea about the quality of the estimated time, the number of dropped MTC and CYC packets is recorded,
+    /// see `TimeInfo::quality`.
+    pub fn time(&mut self) -> Result<TimeInfo, PtError> {
+        ensure_synced(self.4)?;
+        TimeInfo::query(|time, lost_mtc, lost_cyc| unsafe {
+            pt_qry_time(self.0, time, lost_mtc, lost_cyc)
+        })
+    }
+
+    /// Capture the time calibration of the decoder.
+    ///
+    /// Use this after decoding a warm-up region to seed decoders
+    /// that start at later synchronization points, see `TimeCal`.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn time_cal(&mut self) -> Result<TimeCal, PtError> {
+        let freq = self.config()?.freq();
+        let cbr = self.core_bus_ratio();
+        TimeCal::capture(freq, cbr, self.time())
+    }
+
+    /// Limit the work done by the decoder.
+    ///
+    /// Replaces the current budget and resets the work done so far.
+    /// Only the bytes scanned by the synchronization functions are counted,
+    /// once the budget is used up, `sync_forward`, `sync_backward`
+    /// and `sync_set` return BudgetExhausted.
+    /// If @budget is None, the decoder is not limited.
+    pub fn set_budget(&mut self, budget: Option<Budget>) {
+        self.3.set_budget(budget);
+    }
+
+    /// Make the decoder cancellable.
+    ///
+    /// Once @cancel is cancelled, the synchronization functions
+    /// `sync_forward`, `sync_backward` and `sync_set` return Cancelled.
+    /// If @cancel is None, the decoder can't be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        self.3.set_cancel(cancel);
+    }
+
+    // after a successful synchronization, the decoder is no longer reset
+    // and the bytes it moved from @start are charged
+    fn track_sync<R>(&mut self, res: &Result<R, PtError>, start: Option<u64>) {
+        if res.is_ok() {
+            self.4 = false;
+            let end = self.offset().ok();
+            self.3.charge(start, end);
+        }
+    }
+
+    /// Return the decoder to the unsynchronized state of a new decoder.
+    ///
+    /// Without @cfg, the decoder is reset in place without reallocating it.
+    /// Like a new decoder, it has to be synchronized before it can be used,
+    /// `sync_forward` starts at the beginning of the trace buffer
+    /// and `sync_backward` at its end. Until then, the functions that report
+    /// decoder state or query the trace, e.g. `offset` or `time`, return Nosync.
+    /// With @cfg, the underlying decoder is allocated anew for it,
+    /// the old one is only freed if that succeeded,
+    /// since libipt decoders can't switch to the trace buffer of another config.
+    /// The budget and the cancel token are kept, the work done under the budget is reset.
+    pub fn reset(&mut self, cfg: Option<&Config<'a, T>>) -> Result<(), PtError> {
+        if let Some(cfg) = cfg {
+            let inner = ensure_ptr(unsafe { pt_qry_alloc_decoder(cfg.0.as_ref()) })?;
+            unsafe { pt_qry_free_decoder(self.0) };
+            self.0 = inner;
+            self.2 = cfg.2.clone();
+        }
+        self.3.restart();
+        self.4 = cfg.is_none();
+        Ok(())
     }
 }
 
 impl<'a, T> Iterator for QueryDecoder<'a, T> {
-    type Item = Result<(Event, Status), PtError>;
+    type Item = Result<(Event, Status), DecodeError>;
 
-    fn next(&mut self) -> Option<Result<(Event, Status), PtError>> {
-        match self.event() {
-            // eos to stop iterating
-            Err(x) if x.code() == PtErrorCode::Eos => None,
-            x => Some(x)
-        }
+    fn next(&mut self) -> Option<Result<(Event, Status), DecodeError>> {
+        // eos to stop iterating
+        self.event().until_eos().transpose()
     }
 }
 
@@ -1,11 +1,11 @@
+use super::{Event, Payload};
 use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_17;
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
-    use libipt_sys::{ pt_event, pt_event_type_ptev_tick};
+    use core::mem;
+    use libipt_sys::{ pt_event, pt_event_type_ptev_tick, pt_event_type_ptev_stop };
 
     #[test]
     fn test_tick_payload() {
@@ -23,9 +23,33 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_time_update() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_tick;
+        evt._bitfield_1 = pt_event::new_bitfield_1(0, 0, 1);
+        evt.tsc = 100;
+        evt.lost_mtc = 2;
+        evt.variant.tick = pt_event__bindgen_ty_1__bindgen_ty_17 {
+            ip: 11,
+        };
+        assert_eq!(Event(evt).time_update(), Some(TimeUpdate {
+            tsc: 100,
+            ip: Some(11),
+            lost_mtc: 2,
+            lost_cyc: 0
+        }));
+
+        evt.type_ = pt_event_type_ptev_stop;
+        assert_eq!(Event(evt).time_update(), None);
+    }
 }
 
 /// A timing event
+///
+/// Tick events are only reported if they are enabled in the decoder flags,
+/// see `BlockFlags::ENABLE_TICK_EVENTS` and `InsnFlags::ENABLE_TICK_EVENTS`.
 #[derive(Clone, Copy, Debug)]
 pub struct Tick(pub(super) pt_event__bindgen_ty_1__bindgen_ty_17);
 impl Tick {
@@ -37,4 +61,39 @@ impl Tick {
     ///
     /// This field is not valid, if \@ip_suppressed is set.
     pub fn ip(self) -> u64 { self.0.ip }
-}
\ No newline at end of file
+}
+
+event_getters!(Tick { ip });
+
+/// A timing update from a tick event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeUpdate {
+    /// The time stamp count
+    pub tsc: u64,
+    /// The instruction address near which the tick occured.
+    ///
+    /// None if the address has been suppressed.
+    pub ip: Option<u64>,
+    /// The number of lost mtc packets
+    pub lost_mtc: u32,
+    /// The number of lost cyc packets
+    pub lost_cyc: u32
+}
+
+impl Event {
+    /// The timing update of a tick event.
+    ///
+    /// Returns None for all other events and for ticks without timing information.
+    pub fn time_update(self) -> Option<TimeUpdate> {
+        match self.payload() {
+            Payload::Tick(_) => Some(TimeUpdate {
+                tsc: self.timestamp()?,
+                ip: self.ip(),
+                lost_mtc: self.lost_mtc(),
+                lost_cyc: self.lost_cyc()
+            }),
+            _ => None
+        }
+    }
+}
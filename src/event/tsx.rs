@@ -4,7 +4,7 @@ use libipt_sys::pt_event__bindgen_ty_1__bindgen_ty_9;
 mod test {
     use super::*;
     use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{ pt_event, pt_event_type_ptev_tsx };
 
     #[test]
@@ -43,4 +43,6 @@ impl Tsx {
 
     /// A flag indicating speculative execution aborts
     pub fn aborted(self) -> bool { self.0.aborted() > 0 }
-}
\ No newline at end of file
+}
+
+event_getters!(Tsx { ip, speculative, aborted });
@@ -1,3 +1,4 @@
+use super::{Event, Payload};
 use libipt_sys::{
     pt_event__bindgen_ty_1__bindgen_ty_10,
     pt_event__bindgen_ty_1__bindgen_ty_11
@@ -6,12 +7,12 @@ use libipt_sys::{
 #[cfg(test)]
 mod test {
     use super::*;
-    use super::super::Payload;
-    use std::mem;
+    use core::mem;
     use libipt_sys::{
         pt_event,
         pt_event_type_ptev_vmcs,
-        pt_event_type_ptev_async_vmcs
+        pt_event_type_ptev_async_vmcs,
+        pt_event_type_ptev_stop
     };
 
     #[test]
@@ -49,6 +50,26 @@ mod test {
             _ => unreachable!("oof")
         }
     }
+
+    #[test]
+    fn test_vmcs_switch() {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_async_vmcs;
+        evt._bitfield_1 = pt_event::new_bitfield_1(0, 0, 1);
+        evt.tsc = 8;
+        evt.variant.async_vmcs = pt_event__bindgen_ty_1__bindgen_ty_11 {
+            base: 0x5000,
+            ip: 12
+        };
+        assert_eq!(Event(evt).vmcs(), Some(VmcsSwitch {
+            base: 0x5000,
+            ip: Some(12),
+            tsc: Some(8)
+        }));
+
+        evt.type_ = pt_event_type_ptev_stop;
+        assert_eq!(Event(evt).vmcs(), None);
+    }
 }
 
 /// A synchronous vmcs event
@@ -61,6 +82,8 @@ impl Vmcs {
     pub fn base(self) -> u64 { self.0.base }
 }
 
+event_getters!(Vmcs { base });
+
 /// An asynchronous vmcs event
 #[derive(Clone, Copy, Debug)]
 pub struct AsyncVmcs(pub(super) pt_event__bindgen_ty_1__bindgen_ty_11);
@@ -72,4 +95,42 @@ impl AsyncVmcs {
 
     /// The address at which the event is effective.
     pub fn ip(self) -> u64 { self.0.ip }
-}
\ No newline at end of file
+}
+
+event_getters!(AsyncVmcs { base, ip });
+
+/// A switch to another VMCS, i.e. to another virtual machine
+///
+/// The VMCS base address identifies the virtual machine,
+/// use it to correlate the trace with hypervisor sideband data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmcsSwitch {
+    /// The VMCS base address
+    pub base: u64,
+    /// The address at which an asynchronous switch is effective.
+    ///
+    /// None for synchronous switches.
+    pub ip: Option<u64>,
+    /// The time stamp count of the switch, if known
+    pub tsc: Option<u64>
+}
+
+impl Event {
+    /// The VMCS switch of a vmcs or async vmcs event.
+    ///
+    /// Returns None for all other events.
+    pub fn vmcs(self) -> Option<VmcsSwitch> {
+        let (base, ip) = match self.payload() {
+            Payload::Vmcs(v) => (v.base(), None),
+            Payload::AsyncVmcs(v) => (v.base(), Some(v.ip())),
+            _ => return None
+        };
+
+        Some(VmcsSwitch {
+            base,
+            ip,
+            tsc: self.timestamp()
+        })
+    }
+}
@@ -5,8 +5,25 @@ use libipt_sys::{
 };
 use bitflags::bitflags;
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_helpers() {
+        let s = Status::EVENT_PENDING | Status::IP_SUPRESSED;
+        assert!(s.event_pending());
+        assert!(s.ip_suppressed());
+        assert!(!s.eos());
+        assert!(Status::from_raw(pt_status_flag_pts_eos as u32).eos());
+        assert_eq!(Status::from_raw(1 << 31), Status::empty());
+    }
+}
+
 bitflags! {
     /// Status flags for various IntelPT actions
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Status: u32 {
         /// There is no more trace data available.
         const EOS = pt_status_flag_pts_eos as u32;
@@ -18,10 +35,20 @@ bitflags! {
 }
 
 impl Status {
+    /// The status flags of a libipt return value.
+    ///
+    /// Unknown bits are dropped.
+    pub fn from_raw(status: u32) -> Self { Status::from_bits_truncate(status) }
     /// There is no more trace data available.
     pub fn eos(self) -> bool { self.contains(Status::EOS) }
     /// There is an event pending.
+    ///
+    /// The events have to be drained with `event`
+    /// before the decoder continues with `next`.
     pub fn event_pending(self) -> bool { self.contains(Status::EVENT_PENDING) }
     /// The address has been suppressed.
-    pub fn ip_supressed(self) -> bool { self.contains(Status::IP_SUPRESSED) }
-}
\ No newline at end of file
+    pub fn ip_suppressed(self) -> bool { self.contains(Status::IP_SUPRESSED) }
+    /// The address has been suppressed.
+    #[deprecated(note = "use ip_suppressed")]
+    pub fn ip_supressed(self) -> bool { self.ip_suppressed() }
+}
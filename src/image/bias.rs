@@ -0,0 +1,102 @@
+use super::elf::Elf;
+use super::Image;
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+
+#[cfg(test)]
+mod test {
+    use super::super::elf::{Segment, PF_X, PT_LOAD};
+    use super::*;
+
+    #[test]
+    fn test_bias_for() {
+        let segs = [
+            Segment {
+                kind: PT_LOAD,
+                flags: 0,
+                offset: 0,
+                vaddr: 0,
+                filesz: 0x1000,
+                memsz: 0x1000,
+            },
+            Segment {
+                kind: PT_LOAD,
+                flags: PF_X,
+                offset: 0x1000,
+                vaddr: 0x1000,
+                filesz: 0x3000,
+                memsz: 0x3000,
+            },
+        ];
+        let elf = Elf {
+            kind: 3,
+            segments: segs.to_vec(),
+        };
+
+        // the text segment of a PIE mapped by the loader, as seen in an mmap record
+        assert_eq!(
+            bias_for(&elf, 0x5555_5555_6000, 0x1000),
+            Some(0x5555_5555_5000)
+        );
+        assert_eq!(
+            bias_for(&elf, 0x5555_5555_7000, 0x2000),
+            Some(0x5555_5555_5000)
+        );
+        assert_eq!(bias_for(&elf, 0x5555_5555_9000, 0x8000), None);
+    }
+}
+
+// the bias of @elf if its file offset @pgoff is mapped at @addr
+fn bias_for(elf: &Elf, addr: u64, pgoff: u64) -> Option<u64> {
+    let seg = elf
+        .loads()
+        .find(|s| (s.offset..s.offset + s.filesz).contains(&pgoff))?;
+    Some(addr.wrapping_sub(seg.vaddr + (pgoff - seg.offset)))
+}
+
+impl<'a> Image<'a> {
+    /// Add the executable segments of the ELF file @filename,
+    /// loaded with the load bias @bias.
+    ///
+    /// The bias is the difference between the addresses the segments are
+    /// loaded at and the addresses in the file, e.g. `l_addr` of the
+    /// dynamic loader's link map. It is zero for non-PIE executables.
+    /// See `Image::load_bias` for computing it from a single mapping.
+    /// Returns the number of added sections.
+    /// Returns BadFile if @filename is not an ELF64 file.
+    pub fn add_elf(
+        &mut self,
+        filename: &str,
+        bias: u64,
+        asid: Option<Asid>,
+    ) -> Result<u32, PtError> {
+        let elf = Elf::read(filename)?;
+        let mut added = 0;
+        for seg in elf.loads().filter(|s| s.executable() && s.filesz > 0) {
+            self.add_file(
+                filename,
+                seg.offset,
+                seg.filesz,
+                asid,
+                seg.vaddr.wrapping_add(bias),
+            )?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Computes the load bias of the ELF file @filename from one of its mappings.
+    ///
+    /// @addr and @pgoff are the start address and file offset of the mapping,
+    /// as reported by /proc/<pid>/maps or a perf mmap record.
+    /// The result can be passed to `Image::add_elf`.
+    /// Returns BadFile if @filename is not an ELF64 file.
+    /// Returns Nomap if @pgoff is not part of a loadable segment.
+    pub fn load_bias(filename: &str, addr: u64, pgoff: u64) -> Result<u64, PtError> {
+        let elf = Elf::read(filename)?;
+        bias_for(&elf, addr, pgoff).ok_or(PtError::new(
+            PtErrorCode::Nomap,
+            "the offset is not part of a loadable segment",
+        ))
+    }
+}
@@ -0,0 +1,191 @@
+use super::elf::{u32_at, u64_at, Elf, ET_CORE, PT_NOTE};
+use super::Image;
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note(kind: u32, name: &[u8], desc: &[u8]) -> Vec<u8> {
+        let mut n = Vec::new();
+        n.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        n.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        n.extend_from_slice(&kind.to_le_bytes());
+        n.extend_from_slice(name);
+        n.resize((n.len() + 3) & !3, 0);
+        n.extend_from_slice(desc);
+        n.resize((n.len() + 3) & !3, 0);
+        n
+    }
+
+    #[test]
+    fn test_nt_file() {
+        let mut desc = Vec::new();
+        for v in [2u64, 0x1000, 0x400000, 0x401000, 0, 0x600000, 0x602000, 3] {
+            desc.extend_from_slice(&v.to_le_bytes());
+        }
+        desc.extend_from_slice(b"/bin/a\0/lib/b.so\0");
+
+        let mut notes = note(1, b"CORE\0", &[0; 12]);
+        notes.extend(note(NT_FILE, b"CORE\0", &desc));
+
+        let files = file_mappings(&notes).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[1],
+            FileMapping {
+                start: 0x600000,
+                end: 0x602000,
+                offset: 0x3000,
+                path: "/lib/b.so".to_string(),
+            }
+        );
+        assert_eq!(files[0].path, "/bin/a");
+    }
+
+    #[test]
+    fn test_nt_file_truncated() {
+        let mut desc = Vec::new();
+        for v in [5u64, 0x1000] {
+            desc.extend_from_slice(&v.to_le_bytes());
+        }
+        let notes = note(NT_FILE, b"CORE\0", &desc);
+        assert_eq!(
+            file_mappings(&notes).unwrap_err().code(),
+            PtErrorCode::BadFile
+        );
+    }
+}
+
+// the note listing the files mapped into the crashed process
+const NT_FILE: u32 = 0x4649_4c45;
+
+#[inline]
+fn bad_core() -> PtError {
+    PtError::new(PtErrorCode::BadFile, "invalid core file notes")
+}
+
+#[inline]
+fn align4(v: usize) -> usize {
+    (v + 3) & !3
+}
+
+// a file mapping from the NT_FILE note
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileMapping {
+    start: u64,
+    end: u64,
+    offset: u64,
+    path: String,
+}
+
+// finds the NT_FILE note in @notes and parses it
+fn file_mappings(notes: &[u8]) -> Result<Vec<FileMapping>, PtError> {
+    let mut off = 0;
+    while off + 12 <= notes.len() {
+        let namesz = u32_at(notes, off) as usize;
+        let descsz = u32_at(notes, off + 4) as usize;
+        let kind = u32_at(notes, off + 8);
+        let desc_off = off + 12 + align4(namesz);
+        let desc = notes
+            .get(desc_off..desc_off + descsz)
+            .ok_or_else(bad_core)?;
+        off = desc_off + align4(descsz);
+        if kind != NT_FILE {
+            continue;
+        }
+
+        if desc.len() < 16 {
+            return Err(bad_core());
+        }
+        let count = u64_at(desc, 0) as usize;
+        let page_size = u64_at(desc, 8);
+        let names_off = count
+            .checked_mul(24)
+            .and_then(|n| n.checked_add(16))
+            .filter(|&n| n <= desc.len())
+            .ok_or_else(bad_core)?;
+        let mut names = desc[names_off..].split(|&b| b == 0);
+
+        return (0..count)
+            .map(|i| {
+                let e = 16 + i * 24;
+                Ok(FileMapping {
+                    start: u64_at(desc, e),
+                    end: u64_at(desc, e + 8),
+                    offset: u64_at(desc, e + 16) * page_size,
+                    path: String::from_utf8_lossy(names.next().ok_or_else(bad_core)?).into_owned(),
+                })
+            })
+            .collect();
+    }
+
+    Ok(Vec::new())
+}
+
+impl<'a> Image<'a> {
+    /// Add the executable memory of a crashed process from its ELF core file.
+    ///
+    /// Code that the core file does not contain is taken from the binaries
+    /// listed in the core's NT_FILE note.
+    /// If @sysroot is given, it is prepended to the paths of those binaries,
+    /// e.g. to decode on another machine.
+    /// Memory contained in @core takes precedence over the binaries.
+    /// Binaries that can't be read are skipped.
+    /// Returns the number of skipped binaries.
+    /// Returns BadFile if @core is not an ELF64 core file.
+    pub fn add_core(
+        &mut self,
+        core: &str,
+        sysroot: Option<&str>,
+        asid: Option<Asid>,
+    ) -> Result<u32, PtError> {
+        let elf = Elf::read(core)?;
+        if elf.kind != ET_CORE {
+            return Err(PtError::new(PtErrorCode::BadFile, "not a core file"));
+        }
+
+        let mut file = File::open(core)
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not open the core file"))?;
+        let mut mappings = Vec::new();
+        for seg in elf.segments.iter().filter(|s| s.kind == PT_NOTE) {
+            let mut notes = vec![0; seg.filesz as usize];
+            file.seek(SeekFrom::Start(seg.offset))
+                .and_then(|_| file.read_exact(&mut notes))
+                .map_err(|_| bad_core())?;
+            mappings.extend(file_mappings(&notes)?);
+        }
+
+        let text: Vec<_> = elf.loads().filter(|s| s.executable()).collect();
+        let mut ignored = 0;
+        for m in &mappings {
+            let path = format!("{}{}", sysroot.unwrap_or(""), m.path);
+            for seg in &text {
+                let lo = m.start.max(seg.vaddr);
+                let hi = m.end.min(seg.vaddr.saturating_add(seg.memsz));
+                if lo >= hi {
+                    continue;
+                }
+                match self.add_file(&path, m.offset + (lo - m.start), hi - lo, asid, lo) {
+                    Ok(()) => (),
+                    Err(e) if e.code() == PtErrorCode::BadFile => {
+                        ignored += 1;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        // the dumped memory overrides the binaries
+        for seg in text.iter().filter(|s| s.filesz > 0) {
+            self.add_file(core, seg.offset, seg.filesz, asid, seg.vaddr)?;
+        }
+
+        Ok(ignored)
+    }
+}
@@ -0,0 +1,163 @@
+use crate::error::{PtError, PtErrorCode};
+
+use core::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    const ET_EXEC: u16 = 2;
+
+    /// Builds the headers of a little endian ELF64 file
+    pub(crate) fn elf(kind: u16, segments: &[Segment]) -> Vec<u8> {
+        let mut raw = vec![0u8; EHDR_SIZE];
+        raw[..4].copy_from_slice(b"\x7fELF");
+        raw[4] = 2;
+        raw[5] = 1;
+        raw[16..18].copy_from_slice(&kind.to_le_bytes());
+        raw[32..40].copy_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+        raw[54..56].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        raw[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+        for s in segments {
+            raw.extend_from_slice(&s.kind.to_le_bytes());
+            raw.extend_from_slice(&s.flags.to_le_bytes());
+            for v in [s.offset, s.vaddr, s.vaddr, s.filesz, s.memsz, 0x1000] {
+                raw.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        raw
+    }
+
+    #[test]
+    fn test_elf_segments() {
+        let segs = [
+            Segment { kind: PT_LOAD, flags: PF_X, offset: 0, vaddr: 0x1000, filesz: 0x10, memsz: 0x20 },
+            Segment { kind: PT_NOTE, flags: 0, offset: 0x10, vaddr: 0, filesz: 4, memsz: 0 },
+        ];
+        let raw = elf(ET_EXEC, &segs);
+        let e = Elf::parse(&mut std::io::Cursor::new(raw)).unwrap();
+        assert_eq!(e.kind, ET_EXEC);
+        assert_eq!(e.segments, segs);
+        assert_eq!(e.loads().count(), 1);
+        assert!(e.segments[0].executable());
+    }
+
+    #[test]
+    fn test_elf_bad_magic() {
+        let mut raw = elf(ET_EXEC, &[]);
+        raw[0] = 0;
+        let err = Elf::parse(&mut std::io::Cursor::new(raw)).unwrap_err();
+        assert_eq!(err.code(), PtErrorCode::BadFile);
+    }
+}
+
+pub(crate) const ET_CORE: u16 = 4;
+
+pub(crate) const PT_LOAD: u32 = 1;
+pub(crate) const PT_NOTE: u32 = 4;
+
+pub(crate) const PF_X: u32 = 1;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+#[inline]
+fn bad_elf() -> PtError {
+    PtError::new(PtErrorCode::BadFile, "not a little endian ELF64 file")
+}
+
+#[inline]
+pub(crate) fn u16_at(b: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(b[off..off + 2].try_into().unwrap())
+}
+
+#[inline]
+pub(crate) fn u32_at(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(b[off..off + 4].try_into().unwrap())
+}
+
+#[inline]
+pub(crate) fn u64_at(b: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(b[off..off + 8].try_into().unwrap())
+}
+
+/// An ELF program header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Segment {
+    pub(crate) kind: u32,
+    pub(crate) flags: u32,
+    pub(crate) offset: u64,
+    pub(crate) vaddr: u64,
+    pub(crate) filesz: u64,
+    pub(crate) memsz: u64,
+}
+
+impl Segment {
+    #[inline]
+    pub(crate) fn executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+}
+
+/// The parts of an ELF64 file needed to place it in an image
+#[derive(Debug, Clone)]
+pub(crate) struct Elf {
+    pub(crate) kind: u16,
+    pub(crate) segments: Vec<Segment>,
+}
+
+impl Elf {
+    /// Reads the ELF and program headers of the file at @path.
+    ///
+    /// Only the headers are read, so this works on huge files like /proc/kcore.
+    pub(crate) fn read(path: impl AsRef<Path>) -> Result<Self, PtError> {
+        let mut file = File::open(path)
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not open the ELF file"))?;
+        Self::parse(&mut file)
+    }
+
+    pub(crate) fn parse<R: Read + Seek>(r: &mut R) -> Result<Self, PtError> {
+        let mut ehdr = [0u8; EHDR_SIZE];
+        r.read_exact(&mut ehdr).map_err(|_| bad_elf())?;
+        if &ehdr[..4] != b"\x7fELF" || ehdr[4] != 2 || ehdr[5] != 1 {
+            return Err(bad_elf());
+        }
+
+        let phoff = u64_at(&ehdr, 32);
+        let phentsize = u16_at(&ehdr, 54) as usize;
+        let phnum = u16_at(&ehdr, 56) as usize;
+        if phnum > 0 && phentsize < PHDR_SIZE {
+            return Err(bad_elf());
+        }
+
+        let mut phdrs = vec![0u8; phentsize * phnum];
+        r.seek(SeekFrom::Start(phoff))
+            .and_then(|_| r.read_exact(&mut phdrs))
+            .map_err(|_| bad_elf())?;
+
+        let segments = phdrs
+            .chunks_exact(phentsize.max(1))
+            .map(|p| Segment {
+                kind: u32_at(p, 0),
+                flags: u32_at(p, 4),
+                offset: u64_at(p, 8),
+                vaddr: u64_at(p, 16),
+                filesz: u64_at(p, 32),
+                memsz: u64_at(p, 40),
+            })
+            .collect();
+
+        Ok(Elf {
+            kind: u16_at(&ehdr, 16),
+            segments,
+        })
+    }
+
+    /// The loadable segments
+    pub(crate) fn loads(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|s| s.kind == PT_LOAD)
+    }
+}
@@ -0,0 +1,138 @@
+use super::{Image, SectionCache};
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+
+use alloc::string::{String, ToString};
+use std::sync::{Arc, Mutex};
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_factory_instances() {
+        assert_send_sync::<ImageFactory>();
+
+        let file: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testfiles", "garbage.txt"]
+            .iter()
+            .collect();
+        let file = file.to_str().unwrap();
+        let factory = ImageFactory::new(Some("base")).unwrap();
+        factory.add_file(file, 3, 10, None, 0x123).unwrap();
+
+        let mut a = factory.instance().unwrap();
+        assert_eq!(a.name(), Some("base"));
+        assert_eq!(a.sections().len(), 1);
+        assert_eq!(a.remove_by_asid(Asid::default()).unwrap(), 1);
+
+        // instances are independent of the factory and of each other
+        factory.add_file(file, 0, 3, None, 0x100).unwrap();
+        assert_eq!(a.files.len(), 0);
+        assert_eq!(factory.instance().unwrap().files.len(), 2);
+        assert_eq!(factory.clone().instance().unwrap().files.len(), 2);
+    }
+
+    #[test]
+    fn test_factory_from_image() {
+        let mut img = Image::named("proc").unwrap();
+        img.set_callback(Some(|_: &mut [u8], _, _| 0)).unwrap();
+        assert_eq!(
+            ImageFactory::from_image(&img).err().unwrap().code(),
+            PtErrorCode::Invalid
+        );
+    }
+}
+
+// the sections of the images a factory creates
+struct Template {
+    name: Option<String>,
+    iscache: SectionCache<'static>,
+    // the sections in the order they were added
+    sections: Vec<(u32, Asid)>,
+}
+
+/// A factory for images of decoders on different threads
+///
+/// libipt images are not thread-safe, not even for decoders that only read
+/// from them, so every decoder needs an image of its own.
+/// A factory records a list of file sections and creates a new image with
+/// these sections for every decoder. The sections are kept in a `SectionCache`
+/// that all images of the factory share, so every file is only mapped once.
+/// libipt synchronizes access to the cache.
+///
+/// The factory can be cloned and sent to other threads, the clones share
+/// the same sections.
+/// Images created by a factory have no read memory callback,
+/// since it could not be called from several threads at once.
+#[derive(Clone)]
+pub struct ImageFactory(Arc<Mutex<Template>>);
+
+impl ImageFactory {
+    /// A factory for images with the optional name @name and no sections.
+    ///
+    /// Returns Invalid if @name contains null bytes.
+    pub fn new(name: Option<&str>) -> Result<Self, PtError> {
+        let iscache = SectionCache::new(name)?;
+        Ok(ImageFactory(Arc::new(Mutex::new(Template {
+            name: name.map(|n| n.to_string()),
+            iscache,
+            sections: Vec::new(),
+        }))))
+    }
+
+    /// A factory for images with the name and the sections of @image.
+    ///
+    /// Only sections listed by `Image::sections` are recorded.
+    /// Returns Invalid if @image has a read memory callback.
+    pub fn from_image(image: &Image) -> Result<Self, PtError> {
+        if image.has_callback() {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "a factory image must not have a read memory callback",
+            ));
+        }
+
+        let factory = ImageFactory::new(image.name())?;
+        for f in &image.files {
+            factory.add_file(&f.filename, f.offset, f.size, Some(f.asid), f.vaddr)?;
+        }
+        Ok(factory)
+    }
+
+    /// Add a file section to the images created from now on.
+    ///
+    /// See `Image::add_file`.
+    /// Images that have already been created are not affected.
+    pub fn add_file(
+        &self,
+        filename: &str,
+        offset: u64,
+        size: u64,
+        asid: Option<Asid>,
+        vaddr: u64,
+    ) -> Result<(), PtError> {
+        let mut template = self.0.lock().unwrap();
+        let isid = template.iscache.add_file(filename, offset, size, vaddr)?;
+        template.sections.push((isid, asid.unwrap_or_default()));
+        Ok(())
+    }
+
+    /// Create an image for use by a single decoder.
+    ///
+    /// The image contains the sections the factory has now,
+    /// sections added later are not reflected.
+    /// The image can't outlive the factory, whose section cache it reads from.
+    pub fn instance(&self) -> Result<Image<'_>, PtError> {
+        let mut template = self.0.lock().unwrap();
+        let template = &mut *template;
+        let mut image = Image::new(template.name.as_deref())?;
+        for &(isid, asid) in &template.sections {
+            image.add_cached(&mut template.iscache, isid, asid)?;
+        }
+        Ok(image)
+    }
+}
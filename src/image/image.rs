@@ -1,15 +1,25 @@
-use super::SectionCache;
+#[cfg(feature = "std")]
+use super::JitRegions;
+use super::{MappedSection, SectionCache};
 use crate::asid::Asid;
 use crate::error::{
-    deref_ptresult, deref_ptresult_mut, ensure_ptok, extract_pterr, PtError, PtErrorCode,
+    deref_ptresult, ensure_ptok, ensure_ptr, extract_pterr, PtError, PtErrorCode,
 };
 use libipt_sys::{
-    pt_asid, pt_image, pt_image_add_cached, pt_image_add_file, pt_image_alloc, pt_image_copy,
-    pt_image_free, pt_image_name, pt_image_remove_by_asid, pt_image_remove_by_filename,
-    pt_image_set_callback,
+    pt_asid, pt_image, pt_image_add_cached, pt_image_alloc, pt_image_copy, pt_image_free,
+    pt_image_name, pt_image_remove_by_asid, pt_image_set_callback,
 };
-use std::ffi::{c_void, CStr, CString};
-use std::ptr;
+#[cfg(feature = "std")]
+use libipt_sys::{pt_image_add_file, pt_image_remove_by_filename};
+use alloc::ffi::CString;
+use core::ffi::{c_void, CStr};
+#[cfg(feature = "std")]
+use std::path::Path;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 #[cfg(test)]
 mod test {
@@ -69,18 +79,42 @@ mod test {
         assert_eq!(ret, 42);
     }
 
+    #[test]
+    fn test_box_borrowing_closure_and_call() {
+        let code = vec![0x90u8, 0xc3];
+        let boxed = BoxedCallback::box_callback(|buf: &mut [u8], ip, _| {
+            buf[..2].copy_from_slice(&code);
+            ip as i32
+        });
+        let mut buf = vec![0u8; 4];
+        let ret = unsafe { BoxedCallback::call(boxed.0, &mut buf, 2, Asid::new(None, None)) };
+        assert_eq!(ret, 2);
+        assert_eq!(&buf[..2], &code[..]);
+    }
+
     #[test]
     fn test_img_alloc() {
         Image::new(None).unwrap();
         Image::new(Some("yeet")).unwrap();
     }
 
+    #[test]
+    fn test_img_raw() {
+        let i = Image::named("raw").unwrap();
+        let raw = i.into_raw();
+        let i = unsafe { Image::from_raw(raw) };
+        assert_eq!(i.name(), Some("raw"));
+        assert_eq!(i.as_ptr(), raw as *const _);
+    }
+
     #[test]
     fn test_img_name() {
         let i = Image::new(Some("yeet")).unwrap();
         assert_eq!(i.name().unwrap(), "yeet");
         let i = Image::new(None).unwrap();
         assert!(i.name().is_none());
+        let i = Image::named("proc-1234").unwrap();
+        assert_eq!(i.name(), Some("proc-1234"));
     }
 
     fn img_with_file<'a>() -> Image<'a> {
@@ -100,6 +134,22 @@ mod test {
         img_with_file();
     }
 
+    #[test]
+    fn test_img_file_path() {
+        let file: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testfiles", "garbage.txt"]
+            .iter()
+            .collect();
+        let mut i = Image::new(None).unwrap();
+        i.add_file(&file, 3, 10, None, 0x123).unwrap();
+        assert_eq!(i.files[0].filename, file.to_str().unwrap());
+        assert_eq!(
+            i.add_file("nul\0file", 0, 10, None, 0x123)
+                .unwrap_err()
+                .code(),
+            PtErrorCode::Invalid
+        );
+    }
+
     #[test]
     fn test_img_remove_filename() {
         let file: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testfiles", "garbage.txt"]
@@ -112,6 +162,10 @@ mod test {
                 .unwrap(),
             1
         );
+
+        let mut i = img_with_file();
+        assert_eq!(i.remove_by_filename(&file, Asid::new(Some(1), Some(2))).unwrap(), 1);
+        assert!(i.files.is_empty());
     }
 
     #[test]
@@ -129,6 +183,14 @@ mod test {
         assert_eq!(img_with_file().copy(&img_with_file()).unwrap(), 0)
     }
 
+    #[test]
+    fn test_img_duplicate() {
+        let (mut i, ignored) = img_with_file().duplicate().unwrap();
+        assert_eq!(ignored, 0);
+        assert_eq!(i.files.len(), 1);
+        assert_eq!(i.remove_by_asid(Asid::new(Some(1), Some(2))).unwrap(), 1);
+    }
+
     #[test]
     fn test_img_add_cached() {
         let file: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testfiles", "garbage.txt"]
@@ -141,6 +203,8 @@ mod test {
         let mut i = img_with_file();
         i.add_cached(&mut c, isid, Asid::new(Some(3), Some(4)))
             .unwrap();
+        assert_eq!(i.files.len(), 2);
+        assert_eq!(i.files[1].vaddr, 0x1337);
         assert_eq!(i.remove_by_asid(Asid::new(Some(3), Some(4))).unwrap(), 1);
     }
 }
@@ -152,11 +216,36 @@ unsafe extern "C" fn read_callback(
     ip: u64,
     context: *mut c_void,
 ) -> i32 {
-    let buffer = std::slice::from_raw_parts_mut(buffer, size);
+    let buffer = core::slice::from_raw_parts_mut(buffer, size);
     let asid = Asid(*asid);
     BoxedCallback::call(context, buffer, ip, asid)
 }
 
+// converts a path for passing it to libipt
+#[cfg(feature = "std")]
+fn path_cstring(path: &Path) -> Result<CString, PtError> {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes()
+    };
+    #[cfg(not(unix))]
+    let bytes = path
+        .to_str()
+        .ok_or(PtError::new(
+            PtErrorCode::Invalid,
+            "Error converting filename to Cstring as it is not valid unicode",
+        ))?
+        .as_bytes();
+
+    CString::new(bytes).map_err(|_| {
+        PtError::new(
+            PtErrorCode::Invalid,
+            "Error converting filename to Cstring as it contains null bytes",
+        )
+    })
+}
+
 /// Represent a boxed Rust function that can be passed to and from C code.
 ///
 /// # Internals
@@ -186,13 +275,17 @@ struct BoxedCallback(*mut c_void);
 
 impl BoxedCallback {
     /// Box the given Rust closure into a `BoxedCallback`.
-    fn box_callback<F>(callback: F) -> Self
+    ///
+    /// The callback may borrow data for @'f.
+    /// The caller has to drop the `BoxedCallback` before @'f ends.
+    fn box_callback<'f, F>(callback: F) -> Self
     where
-        F: FnMut(&mut [u8], u64, Asid) -> i32,
+        F: FnMut(&mut [u8], u64, Asid) -> i32 + 'f,
     {
         // The callback can be an arbitrary Rust closure. So move it onto the heap
         // (the allocation only takes place when the closure captures).
-        let boxed_dyn_callback: Box<dyn FnMut(&mut [u8], u64, Asid) -> i32> = Box::new(callback);
+        let boxed_dyn_callback: Box<dyn FnMut(&mut [u8], u64, Asid) -> i32 + 'f> =
+            Box::new(callback);
 
         // `boxed_dyn_callback` is itself a fat pointer and we cannot just return it.
         // Instead, move `boxed_dyn_callback` onto the heap and returns the pointer
@@ -227,13 +320,25 @@ impl Drop for BoxedCallback {
 
 /// An Image defines the memory image that was traced as a collection
 /// of file sections and the virtual addresses at which those sections were loaded.
+///
+/// Images are neither Send nor Sync, libipt images are not thread-safe
+/// and the read memory callback may not be safe to call from another thread.
+/// Use an `ImageFactory` to decode with the same sections on several threads.
 pub struct Image<'a> {
-    // the wrapped inst
-    pub(crate) inner: &'a mut pt_image,
+    // the allocation returned by libipt or the image of a decoder
+    pub(crate) inner: *mut pt_image,
     // do we need to free this instance on drop?
     dealloc: bool,
     // Any read data callback set by this `Image` instance.
     callback: Option<BoxedCallback>,
+    // The anonymous regions read by the callback, if it was set by `Image::regions`.
+    #[cfg(feature = "std")]
+    pub(super) regions: Option<JitRegions>,
+    // The file sections in the order they were added, see `Image::sections`.
+    // libipt does not tell us which sections an image contains.
+    pub(crate) files: Vec<MappedSection>,
+    // a borrowed image must not outlive its owner
+    phantom: PhantomData<&'a mut pt_image>,
 }
 
 impl<'a> Image<'a> {
@@ -241,7 +346,7 @@ impl<'a> Image<'a> {
     /// An optional @name may be given to the image.
     /// The name string is copied.
     pub fn new(name: Option<&str>) -> Result<Self, PtError> {
-        deref_ptresult_mut(unsafe {
+        ensure_ptr(unsafe {
             match name {
                 None => pt_image_alloc(ptr::null()),
                 Some(n) => pt_image_alloc(
@@ -256,13 +361,76 @@ impl<'a> Image<'a> {
                 ),
             }
         })
-        .map(|i| Image {
-            inner: i,
-            dealloc: true,
-            callback: None,
+        .map(|i| {
+            let mut img = Image::borrowed(i);
+            img.dealloc = true;
+            img
         })
     }
 
+    /// Allocate a traced memory image with the name @name.
+    ///
+    /// The name identifies the image in diagnostics, e.g. `proc-1234`.
+    /// Returns Invalid if @name contains null bytes.
+    pub fn named(name: &str) -> Result<Self, PtError> {
+        Self::new(Some(name))
+    }
+
+    /// Take ownership of the raw image @image.
+    ///
+    /// Sections and a read memory callback already in @image are kept,
+    /// but are not listed by `sections`.
+    ///
+    /// # Safety
+    /// @image must have been allocated with pt_image_alloc and must not be
+    /// used or freed by anyone else afterwards.
+    /// Its read memory callback must stay valid for @'a.
+    pub unsafe fn from_raw(image: *mut pt_image) -> Self {
+        let mut img = Image::borrowed(image);
+        img.dealloc = true;
+        img
+    }
+
+    /// A pointer to the raw image
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the image is.
+    pub fn as_ptr(&self) -> *const pt_image {
+        self.inner
+    }
+
+    /// A mutable pointer to the raw image
+    ///
+    /// See `as_ptr`.
+    /// Sections added through the pointer are not listed by `sections`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_image {
+        self.inner
+    }
+
+    /// Release the raw image.
+    ///
+    /// The caller has to free it with pt_image_free,
+    /// unless the image is borrowed from a decoder.
+    /// The read memory callback is leaked, the raw image still uses it.
+    pub fn into_raw(mut self) -> *mut pt_image {
+        mem::forget(self.callback.take());
+        self.dealloc = false;
+        self.inner
+    }
+
+    // wraps @inner without taking ownership, it is not freed on drop
+    pub(crate) fn borrowed(inner: *mut pt_image) -> Self {
+        Image {
+            inner,
+            dealloc: false,
+            callback: None,
+            #[cfg(feature = "std")]
+            regions: None,
+            files: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
     /// Get the image name.
     /// The name is optional.
     pub fn name(&self) -> Option<&str> {
@@ -280,27 +448,40 @@ impl<'a> Image<'a> {
     ///
     /// Removes all sections loaded into @asid.
     /// Specify the same @asid that was used for adding sections.
+    /// Use this to drop the sections of a process that exited.
     /// Returns the number of removed sections on success.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), fields(image = ?self.name()), ret, err))]
     pub fn remove_by_asid(&mut self, asid: Asid) -> Result<u32, PtError> {
-        extract_pterr(unsafe { pt_image_remove_by_asid(self.inner, &asid.0) })
+        let removed = extract_pterr(unsafe { pt_image_remove_by_asid(self.inner, &asid.0) })?;
+        self.files.retain(|f| !f.asid.matches(asid));
+        Ok(removed)
     }
 
     /// Remove all sections loaded from a file.
     ///
     /// Removes all sections loaded from @filename from the address space @asid.
     /// Specify the same @asid that was used for adding sections from @filename.
+    /// Use this to unmap a library that was unloaded.
     /// Returns the number of removed sections on success
-    pub fn remove_by_filename(&mut self, filename: &str, asid: Asid) -> Result<u32, PtError> {
-        let cfilename = CString::new(filename).map_err(|_| {
-            PtError::new(
-                PtErrorCode::Invalid,
-                "Error converting filename to Cstring as it contains null bytes",
-            )
-        })?;
+    /// Returns Invalid if @filename contains null bytes.
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self, filename),
+        fields(image = ?self.name(), filename = %filename.as_ref().display()), ret, err))]
+    pub fn remove_by_filename(
+        &mut self,
+        filename: impl AsRef<Path>,
+        asid: Asid,
+    ) -> Result<u32, PtError> {
+        let filename = filename.as_ref();
+        let cfilename = path_cstring(filename)?;
 
-        extract_pterr(unsafe {
+        let removed = extract_pterr(unsafe {
             pt_image_remove_by_filename(self.inner, cfilename.as_ptr(), &asid.0)
-        })
+        })?;
+        let filename = filename.to_string_lossy();
+        self.files
+            .retain(|f| f.filename != filename || !f.asid.matches(asid));
+        Ok(removed)
     }
 
     /// Set the memory callback for the traced memory image.
@@ -309,10 +490,16 @@ impl<'a> Image<'a> {
     /// There can only be one callback at any time.
     /// A subsequent call will replace the previous callback.
     /// If @callback is None, the callback is removed.
+    /// @callback may borrow data that outlives the image.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(image = ?self.name(), set = callback.is_some()), err))]
     pub fn set_callback<F>(&mut self, callback: Option<F>) -> Result<(), PtError>
     where
-        F: FnMut(&mut [u8], u64, Asid) -> i32,
+        F: FnMut(&mut [u8], u64, Asid) -> i32 + 'a,
     {
+        #[cfg(feature = "std")]
+        {
+            self.regions = None;
+        }
         self.callback = callback.map(BoxedCallback::box_callback);
         ensure_ptok(unsafe {
             match &self.callback {
@@ -322,27 +509,62 @@ impl<'a> Image<'a> {
         })
     }
 
+    #[inline]
+    #[cfg(feature = "std")]
+    pub(super) fn has_callback(&self) -> bool {
+        self.callback.is_some()
+    }
+
     /// Copy an image.
     ///
     /// Adds all sections from @src.
     /// Sections that could not be added will be ignored.
     /// Returns the number of ignored sections on success.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(image = ?self.name(), src = ?src.name()), ret, err))]
     pub fn copy(&mut self, src: &Image) -> Result<u32, PtError> {
-        extract_pterr(unsafe { pt_image_copy(self.inner, src.inner) })
+        let ignored = extract_pterr(unsafe { pt_image_copy(self.inner, src.inner) })?;
+        self.files.extend_from_slice(&src.files);
+        Ok(ignored)
+    }
+
+    /// Create a new image with the name and sections of this image.
+    ///
+    /// Use this to specialize a base image, e.g. of common libraries, per process.
+    /// The read memory callback is not copied.
+    /// Returns the new image and the number of sections that could not be copied.
+    pub fn duplicate<'b>(&self) -> Result<(Image<'b>, u32), PtError> {
+        let mut image = Image::new(self.name())?;
+        let ignored = image.copy(self)?;
+        Ok((image, ignored))
     }
 
     /// Add a section from an image section cache.
     ///
     /// Add the section from @iscache identified by @isid in address space @asid.
+    /// The section is shared with @iscache and all other images it was added to,
+    /// so the file is only read once.
+    /// @iscache must not be dropped while the image is in use.
     /// Existing sections that would overlap with the new section will be shrunk or split.
     /// Returns BadImage if @iscache does not contain @isid.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self, iscache), fields(image = ?self.name()), err))]
     pub fn add_cached(
         &mut self,
         iscache: &mut SectionCache,
         isid: u32,
         asid: Asid,
     ) -> Result<(), PtError> {
-        ensure_ptok(unsafe { pt_image_add_cached(self.inner, iscache.0, isid as i32, &asid.0) })
+        ensure_ptok(unsafe { pt_image_add_cached(self.inner, iscache.0, isid as i32, &asid.0) })?;
+
+        if let Some(section) = iscache.section(isid) {
+            self.files.push(MappedSection {
+                filename: section.filename.clone(),
+                offset: section.offset,
+                size: section.size,
+                asid,
+                vaddr: section.vaddr,
+            });
+        }
+        Ok(())
     }
 
     /// Add a new file section to the traced memory image.
@@ -356,20 +578,20 @@ impl<'a> Image<'a> {
     /// Existing sections that would overlap with the new section will be shrunk or split.
     /// Returns Invalid if @offset is too big.
     /// Returns Invalid if @filename contains null bytes
+    /// or, on platforms other than unix, is not valid unicode.
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self, filename),
+        fields(image = ?self.name(), filename = %filename.as_ref().display()), err))]
     pub fn add_file(
         &mut self,
-        filename: &str,
+        filename: impl AsRef<Path>,
         offset: u64,
         size: u64,
         asid: Option<Asid>,
         vaddr: u64,
     ) -> Result<(), PtError> {
-        let cfilename = CString::new(filename).map_err(|_| {
-            PtError::new(
-                PtErrorCode::Invalid,
-                "Error converting filename to Cstring as it contains null bytes",
-            )
-        })?;
+        let filename = filename.as_ref();
+        let cfilename = path_cstring(filename)?;
 
         ensure_ptok(unsafe {
             pt_image_add_file(
@@ -383,17 +605,22 @@ impl<'a> Image<'a> {
                 },
                 vaddr,
             )
-        })
+        })?;
+
+        self.files.push(MappedSection {
+            filename: filename.to_string_lossy().into_owned(),
+            offset,
+            size,
+            asid: asid.unwrap_or_default(),
+            vaddr,
+        });
+        Ok(())
     }
 }
 
 impl<'a> From<&'a mut pt_image> for Image<'a> {
     fn from(img: &'a mut pt_image) -> Self {
-        Image {
-            inner: img,
-            dealloc: false,
-            callback: None,
-        }
+        Image::borrowed(img)
     }
 }
 
@@ -0,0 +1,73 @@
+use super::{Image, SectionCache};
+use crate::asid::Asid;
+use crate::error::PtError;
+
+use libipt_sys::pt_image;
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+/// The image a decoder reads memory from, borrowed from the decoder
+///
+/// Sections may be added and removed as long as no decoder using the image is running.
+/// A read memory callback can't be set, it would not outlive the borrow.
+/// Sections added before the image was borrowed are not listed by it.
+pub struct ImageRef<'d>(Image<'d>);
+
+impl<'d> ImageRef<'d> {
+    // @inner has to be the image of the decoder borrowed for @'d
+    pub(crate) fn new(inner: *mut pt_image) -> Self {
+        ImageRef(Image::borrowed(inner))
+    }
+
+    /// See `Image::add_file`.
+    #[cfg(feature = "std")]
+    pub fn add_file(
+        &mut self,
+        filename: impl AsRef<Path>,
+        offset: u64,
+        size: u64,
+        asid: Option<Asid>,
+        vaddr: u64,
+    ) -> Result<(), PtError> {
+        self.0.add_file(filename, offset, size, asid, vaddr)
+    }
+
+    /// See `Image::add_cached`.
+    pub fn add_cached(
+        &mut self,
+        iscache: &mut SectionCache,
+        isid: u32,
+        asid: Asid,
+    ) -> Result<(), PtError> {
+        self.0.add_cached(iscache, isid, asid)
+    }
+
+    /// See `Image::copy`.
+    pub fn copy(&mut self, src: &Image) -> Result<u32, PtError> {
+        self.0.copy(src)
+    }
+
+    /// See `Image::remove_by_filename`.
+    #[cfg(feature = "std")]
+    pub fn remove_by_filename(
+        &mut self,
+        filename: impl AsRef<Path>,
+        asid: Asid,
+    ) -> Result<u32, PtError> {
+        self.0.remove_by_filename(filename, asid)
+    }
+
+    /// See `Image::remove_by_asid`.
+    pub fn remove_by_asid(&mut self, asid: Asid) -> Result<u32, PtError> {
+        self.0.remove_by_asid(asid)
+    }
+}
+
+impl<'d> Deref for ImageRef<'d> {
+    type Target = Image<'d>;
+
+    fn deref(&self) -> &Image<'d> {
+        &self.0
+    }
+}
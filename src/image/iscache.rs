@@ -2,13 +2,18 @@ use crate::error::{
     PtError,
     PtErrorCode,
     deref_ptresult,
-    deref_ptresult_mut,
+    ensure_ptr,
     ensure_ptok,
     extract_pterr
 };
 
-use std::ffi::{CString, CStr};
-use std::ptr;
+use alloc::collections::BTreeMap;
+use alloc::ffi::CString;
+use core::ffi::CStr;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use alloc::string::{String, ToString};
 
 use libipt_sys::{
     pt_image_section_cache,
@@ -51,6 +56,24 @@ mod test {
             .add_file(file.to_str().unwrap(), 5, 15, 0x1337).unwrap();
     }
 
+    #[test]
+    fn test_isc_filename() {
+        let file: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"), "testfiles", "garbage.txt"
+        ].iter().collect();
+
+        let mut isc = SectionCache::new(None).unwrap();
+        let isid = isc.add_file(file.to_str().unwrap(), 5, 15, 0x1337).unwrap();
+        assert_eq!(isc.filename(isid), file.to_str());
+        assert_eq!(isc.section(isid), Some(&CachedSection {
+            filename: file.to_str().unwrap().to_string(),
+            offset: 5,
+            size: 15,
+            vaddr: 0x1337
+        }));
+        assert!(isc.filename(isid + 1).is_none());
+    }
+
     #[test]
     fn test_isc_memsection() {
         let file: PathBuf =
@@ -76,12 +99,37 @@ mod test {
         let mut isc = SectionCache::new(None).unwrap();
         isc.set_limit(111).unwrap();
         isc.set_limit(0).unwrap();
-        isc.set_limit(std::u64::MAX).unwrap();
+        isc.set_limit(u64::MAX).unwrap();
     }
 }
 
+/// A file section in a `SectionCache`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedSection {
+    pub filename: String,
+    /// The offset of the section in @filename
+    pub offset: u64,
+    pub size: u64,
+    /// The virtual address the section is loaded at
+    pub vaddr: u64,
+}
+
 /// A cache of traced image sections.
-pub struct SectionCache<'a>(pub(crate) &'a mut pt_image_section_cache);
+///
+/// The cache is Send and Sync, libipt synchronizes access to it.
+/// It can be shared by the images of decoders on different threads.
+pub struct SectionCache<'a>(
+    pub(crate) *mut pt_image_section_cache,
+    // the sections added with `SectionCache::add_file` by isid
+    BTreeMap<u32, CachedSection>,
+    PhantomData<&'a mut pt_image_section_cache>
+);
+
+// SAFETY: libipt locks the cache in all functions working on it,
+// methods taking `&self` only read the cache name and the section map.
+unsafe impl Send for SectionCache<'_> {}
+unsafe impl Sync for SectionCache<'_> {}
+
 impl<'a> SectionCache<'a> {
     /// Allocate a traced memory image section cache.
     ///
@@ -89,14 +137,51 @@ impl<'a> SectionCache<'a> {
     /// The name string is copied.
     /// Returns a new traced memory image section cache on success
     pub fn new(name: Option<&str>) -> Result<Self, PtError> {
-        deref_ptresult_mut(unsafe { match name {
+        ensure_ptr(unsafe { match name {
             None => pt_iscache_alloc(ptr::null()),
             Some(n) => pt_iscache_alloc(
                 CString::new(n).map_err(|_| PtError::new(
                     PtErrorCode::Invalid,
                     "invalid @name string: contains null bytes")
                 )?.as_ptr())
-        }}).map(|s| SectionCache(s))
+        }}).map(|s| SectionCache(s, BTreeMap::new(), PhantomData))
+    }
+
+    /// Take ownership of the raw section cache @iscache.
+    ///
+    /// Sections already in @iscache are not listed by `section`.
+    ///
+    /// # Safety
+    /// @iscache must have been allocated with pt_iscache_alloc and must not
+    /// be freed by anyone else afterwards.
+    pub unsafe fn from_raw(iscache: *mut pt_image_section_cache) -> Self {
+        SectionCache(iscache, BTreeMap::new(), PhantomData)
+    }
+
+    /// A pointer to the raw section cache
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the cache is.
+    pub fn as_ptr(&self) -> *const pt_image_section_cache {
+        self.0
+    }
+
+    /// A mutable pointer to the raw section cache
+    ///
+    /// See `as_ptr`.
+    /// Sections added through the pointer are not listed by `section`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_image_section_cache {
+        self.0
+    }
+
+    /// Release the raw section cache.
+    ///
+    /// The caller has to free it with pt_iscache_free.
+    pub fn into_raw(mut self) -> *mut pt_image_section_cache {
+        self.1.clear();
+        let iscache = self.0;
+        mem::forget(self);
+        iscache
     }
 
     /// Get the image section cache name.
@@ -127,13 +212,35 @@ impl<'a> SectionCache<'a> {
                 "Error converting filename to Cstring as it contains null bytes")
             )?;
 
-        extract_pterr(unsafe {
+        let isid = extract_pterr(unsafe {
             pt_iscache_add_file(self.0,
                                 cfilename.as_ptr(),
                                 offset,
                                 size,
                                 vaddr)
-        })
+        })?;
+
+        self.1.entry(isid).or_insert_with(|| CachedSection {
+            filename: filename.to_string(),
+            offset,
+            size,
+            vaddr
+        });
+        Ok(isid)
+    }
+
+    /// Get the section identified by @isid.
+    ///
+    /// Returns None if @isid was not added with `SectionCache::add_file`.
+    pub fn section(&self, isid: u32) -> Option<&CachedSection> {
+        self.1.get(&isid)
+    }
+
+    /// Get the name of the file of the section identified by @isid.
+    ///
+    /// Returns None if @isid was not added with `SectionCache::add_file`.
+    pub fn filename(&self, isid: u32) -> Option<&str> {
+        self.section(isid).map(|s| s.filename.as_str())
     }
 
     /// Read memory from a cached file section
@@ -0,0 +1,172 @@
+use super::{Image, MemoryReader};
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_regions_read() {
+        let r = JitRegions::default();
+        r.add(1, 0x1000, vec![1, 2, 3, 4], None);
+        r.add(2, 0x1002, vec![9], Some(Asid::new(Some(0x42), None)));
+
+        let mut r = r;
+        let mut buf = [0u8; 8];
+        let mut read = |ip, cr3| {
+            r.read(ip, &mut buf, Asid::new(cr3, None))
+                .map(|n| buf[..n].to_vec())
+                .map_err(|e| e.code())
+        };
+        assert_eq!(read(0x1001, Some(0x42)), Ok(vec![2]));
+        assert_eq!(read(0x1001, Some(0x43)), Ok(vec![2, 3, 4]));
+        // the later region takes precedence
+        assert_eq!(read(0x1002, Some(0x42)), Ok(vec![9]));
+        assert_eq!(read(0x1002, Some(0x43)), Ok(vec![3, 4]));
+        assert_eq!(read(0x1004, None), Err(PtErrorCode::Nomap));
+
+        assert!(r.remove(2));
+        assert!(!r.remove(2));
+        assert_eq!(
+            r.read(0x1001, &mut buf, Asid::new(Some(0x42), None))
+                .unwrap(),
+            3
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Region {
+    cookie: u64,
+    addr: u64,
+    code: Vec<u8>,
+    asid: Option<Asid>,
+}
+
+impl Region {
+    #[inline]
+    fn visible(&self, asid: Asid) -> bool {
+        !matches!(self.asid, Some(r) if !r.matches(asid))
+    }
+
+    #[inline]
+    fn contains(&self, ip: u64) -> bool {
+        ip.wrapping_sub(self.addr) < self.code.len() as u64
+    }
+}
+
+/// Anonymous executable memory regions, e.g. the code of a JIT compiler
+///
+/// This is a handle that can be cloned and shared between threads,
+/// all clones refer to the same regions.
+/// See `Image::regions`.
+#[derive(Debug, Clone, Default)]
+pub struct JitRegions(Arc<Mutex<Vec<Region>>>);
+
+impl JitRegions {
+    /// Add the code @code located at @addr in the address space @asid.
+    ///
+    /// The region is identified by @cookie for removing it.
+    /// It takes precedence over regions that were added before.
+    /// If @asid is None, the region is visible in all address spaces.
+    pub fn add(&self, cookie: u64, addr: u64, code: Vec<u8>, asid: Option<Asid>) {
+        self.0.lock().unwrap().push(Region {
+            cookie,
+            addr,
+            code,
+            asid,
+        });
+    }
+
+    /// Remove all regions added with @cookie.
+    ///
+    /// Returns true if there were any.
+    pub fn remove(&self, cookie: u64) -> bool {
+        let mut regions = self.0.lock().unwrap();
+        let len = regions.len();
+        regions.retain(|r| r.cookie != cookie);
+        regions.len() != len
+    }
+}
+
+impl MemoryReader for JitRegions {
+    fn read(&mut self, addr: u64, buf: &mut [u8], asid: Asid) -> Result<usize, PtError> {
+        let regions = self.0.lock().unwrap();
+        let i = regions
+            .iter()
+            .rposition(|r| r.visible(asid) && r.contains(addr))
+            .ok_or(PtError::new(PtErrorCode::Nomap, "no region at the address"))?;
+
+        let r = &regions[i];
+        let code = &r.code[(addr - r.addr) as usize..];
+        // stop where a later region starts
+        let end = regions[i + 1..]
+            .iter()
+            .filter(|l| l.visible(asid) && l.addr > addr)
+            .map(|l| (l.addr - addr).min(usize::MAX as u64) as usize)
+            .min()
+            .unwrap_or(usize::MAX);
+        let len = buf.len().min(code.len()).min(end);
+        buf[..len].copy_from_slice(&code[..len]);
+        Ok(len)
+    }
+}
+
+impl<'a> Image<'a> {
+    /// Get the anonymous executable regions of the image.
+    ///
+    /// The first call installs the image's read memory callback,
+    /// replacing any callback or reader set before.
+    /// Regions are only consulted for addresses without file sections.
+    /// The returned handle can be used from other threads to keep the
+    /// regions up to date, e.g. while a decoder using this image is paused.
+    pub fn regions(&mut self) -> Result<JitRegions, PtError> {
+        if let Some(regions) = &self.regions {
+            return Ok(regions.clone());
+        }
+
+        let regions = JitRegions::default();
+        self.set_reader(regions.clone())?;
+        self.regions = Some(regions.clone());
+        Ok(regions)
+    }
+
+    /// Add an anonymous executable region to the image.
+    ///
+    /// See `JitRegions::add` and `Image::regions`.
+    pub fn add_region(
+        &mut self,
+        cookie: u64,
+        addr: u64,
+        code: Vec<u8>,
+        asid: Option<Asid>,
+    ) -> Result<(), PtError> {
+        self.regions()?.add(cookie, addr, code, asid);
+        Ok(())
+    }
+
+    /// Add the bytes @bytes as executable memory at @vaddr in the address space @asid.
+    ///
+    /// The bytes are copied, no backing file is needed.
+    /// This adds a region with @vaddr as its cookie,
+    /// so it can be removed with `Image::remove_region(vaddr)`.
+    /// See `Image::add_region` for how regions interact with file sections.
+    pub fn add_bytes(
+        &mut self,
+        bytes: &[u8],
+        vaddr: u64,
+        asid: Option<Asid>,
+    ) -> Result<(), PtError> {
+        self.add_region(vaddr, vaddr, bytes.to_vec(), asid)
+    }
+
+    /// Remove the anonymous executable regions added with @cookie.
+    ///
+    /// Returns true if there were any.
+    pub fn remove_region(&mut self, cookie: u64) -> bool {
+        self.regions.as_ref().is_some_and(|r| r.remove(cookie))
+    }
+}
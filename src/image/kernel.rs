@@ -0,0 +1,152 @@
+use super::elf::Elf;
+use super::Image;
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+
+use std::fs;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_modules() {
+        let mods = parse_modules(concat!(
+            "nf_tables 249856 0 - Live 0xffffffffc0a00000\n",
+            "hidden 4096 0 - Live 0x0000000000000000\n",
+            "kvm_intel 372736 0 - Live 0xffffffffc0900000 (E)\n",
+        ));
+        assert_eq!(mods.len(), 3);
+        assert_eq!(mods[0].name, "nf_tables");
+        assert_eq!(mods[0].size, 249856);
+        assert_eq!(mods[0].addr, 0xffff_ffff_c0a0_0000);
+        assert_eq!(mods[1].addr, 0);
+        assert_eq!(mods[2].addr, 0xffff_ffff_c090_0000);
+    }
+
+    #[test]
+    fn test_parse_text_range() {
+        let syms = concat!(
+            "ffffffff81000000 T startup_64\n",
+            "ffffffff81000000 T _stext\n",
+            "ffffffff82000000 T _etext\n",
+        );
+        assert_eq!(
+            parse_text_range(syms),
+            Some((0xffff_ffff_8100_0000, 0xffff_ffff_8200_0000))
+        );
+        assert_eq!(parse_text_range("0000000000000000 T _stext\n"), None);
+    }
+}
+
+/// A kernel module as listed in /proc/modules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelModule {
+    pub name: String,
+    /// The size of the module in memory
+    pub size: u64,
+    /// The load address, zero if the kernel hides it
+    pub addr: u64,
+}
+
+fn parse_modules(text: &str) -> Vec<KernelModule> {
+    text.lines()
+        .filter_map(|l| {
+            let f: Vec<&str> = l.split_whitespace().collect();
+            Some(KernelModule {
+                name: f.first()?.to_string(),
+                size: f.get(1)?.parse().ok()?,
+                addr: u64::from_str_radix(f.get(5)?.trim_start_matches("0x"), 16).ok()?,
+            })
+        })
+        .collect()
+}
+
+// the kernel text range from /proc/kallsyms
+fn parse_text_range(kallsyms: &str) -> Option<(u64, u64)> {
+    let sym = |name: &str| {
+        kallsyms.lines().find_map(|l| {
+            let mut f = l.split_whitespace();
+            let addr = f.next()?;
+            f.next()?;
+            (f.next()? == name).then(|| u64::from_str_radix(addr, 16).ok())?
+        })
+    };
+    let start = sym("_stext")?;
+    let end = sym("_etext")?;
+    (start != 0 && start < end).then_some((start, end))
+}
+
+/// Lists the loaded kernel modules from /proc/modules.
+///
+/// Module addresses are zero unless the caller may see kernel addresses.
+pub fn kernel_modules() -> Result<Vec<KernelModule>, PtError> {
+    fs::read_to_string("/proc/modules")
+        .map(|t| parse_modules(&t))
+        .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not read /proc/modules"))
+}
+
+impl<'a> Image<'a> {
+    /// Add the kernel's executable segments from a vmlinux file.
+    ///
+    /// The segments are loaded at the addresses given in the file,
+    /// which is only correct if the kernel was not relocated (KASLR).
+    /// Module code is not part of vmlinux, see `Image::add_kcore_ranges`.
+    /// Returns the number of added sections.
+    /// Returns BadFile if @vmlinux is not an ELF64 file.
+    pub fn add_vmlinux(&mut self, vmlinux: &str, asid: Option<Asid>) -> Result<u32, PtError> {
+        self.add_elf(vmlinux, 0, asid)
+    }
+
+    /// Add the parts of kernel memory in @ranges from a kcore file.
+    ///
+    /// @ranges are pairs of start and end (exclusive) virtual addresses.
+    /// Parts of a range that are not backed by @kcore are skipped.
+    /// Returns the number of added sections.
+    /// Returns BadFile if @kcore is not an ELF64 file.
+    pub fn add_kcore_ranges(
+        &mut self,
+        kcore: &str,
+        ranges: &[(u64, u64)],
+        asid: Option<Asid>,
+    ) -> Result<u32, PtError> {
+        let elf = Elf::read(kcore)?;
+        let mut added = 0;
+        for &(start, end) in ranges {
+            for seg in elf.loads() {
+                let lo = start.max(seg.vaddr);
+                let hi = end.min(seg.vaddr.saturating_add(seg.filesz));
+                if lo < hi {
+                    self.add_file(kcore, seg.offset + (lo - seg.vaddr), hi - lo, asid, lo)?;
+                    added += 1;
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// Add the running kernel's text and all loaded modules from /proc/kcore.
+    ///
+    /// This reflects the kernel as it is now, including KASLR and
+    /// code patching, and usually requires root privileges.
+    /// Returns the number of added sections.
+    /// Returns BadFile if the kernel text can't be located,
+    /// e.g. because kernel addresses are hidden by kptr_restrict.
+    pub fn add_kcore(&mut self, asid: Option<Asid>) -> Result<u32, PtError> {
+        let kallsyms = fs::read_to_string("/proc/kallsyms")
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not read /proc/kallsyms"))?;
+        let text = parse_text_range(&kallsyms).ok_or(PtError::new(
+            PtErrorCode::BadFile,
+            "kernel addresses are not visible",
+        ))?;
+
+        let mut ranges = vec![text];
+        ranges.extend(
+            kernel_modules()?
+                .iter()
+                .filter(|m| m.addr != 0)
+                .map(|m| (m.addr, m.addr + m.size)),
+        );
+        self.add_kcore_ranges("/proc/kcore", &ranges, asid)
+    }
+}
@@ -1,5 +1,39 @@
 mod image;
+mod imageref;
 mod iscache;
+#[cfg(feature = "std")]
+mod elf;
+#[cfg(feature = "std")]
+mod bias;
+#[cfg(feature = "std")]
+mod coredump;
+#[cfg(feature = "std")]
+mod jit;
+#[cfg(feature = "std")]
+mod kernel;
+#[cfg(feature = "object")]
+mod object;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod procfs;
+#[cfg(feature = "std")]
+mod reader;
+mod section;
+#[cfg(feature = "std")]
+mod factory;
+#[cfg(feature = "std")]
+mod validate;
 
 pub use image::*;
-pub use iscache::*;
\ No newline at end of file
+pub use imageref::*;
+pub use iscache::*;
+#[cfg(feature = "std")]
+pub use jit::*;
+#[cfg(feature = "std")]
+pub use kernel::*;
+#[cfg(feature = "std")]
+pub use reader::*;
+pub use section::*;
+#[cfg(feature = "std")]
+pub use factory::*;
+#[cfg(feature = "std")]
+pub use validate::*;
@@ -0,0 +1,82 @@
+use super::Image;
+use crate::asid::Asid;
+use crate::error::PtError;
+
+use object::{Object, ObjectSection, SectionKind};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_placements() {
+        let sections = [
+            (SectionKind::Text, 0x1040, Some((0x1040, 0x200))),
+            (SectionKind::Data, 0x3000, Some((0x2000, 0x100))),
+            // e.g. .bss like text without file content
+            (SectionKind::Text, 0x4000, None),
+            (SectionKind::Text, 0x5000, Some((0x3000, 0))),
+        ];
+        assert_eq!(
+            placements(sections.into_iter(), 0, 0x5555_0000_0000),
+            vec![(0x1040, 0x200, 0x5555_0000_1040)]
+        );
+
+        // PE sections are relative to the preferred image base
+        let pe = [(SectionKind::Text, 0x1_4000_1000, Some((0x400, 0x800)))];
+        assert_eq!(
+            placements(pe.into_iter(), 0x1_4000_0000, 0x7ff6_0000_0000),
+            vec![(0x400, 0x800, 0x7ff6_0000_1000)]
+        );
+    }
+}
+
+// the file offset, size and load address of the executable sections
+fn placements(
+    sections: impl Iterator<Item = (SectionKind, u64, Option<(u64, u64)>)>,
+    preferred: u64,
+    base: u64,
+) -> Vec<(u64, u64, u64)> {
+    sections
+        .filter(|(kind, _, _)| *kind == SectionKind::Text)
+        .filter_map(|(_, addr, range)| {
+            let (offset, size) = range.filter(|&(_, size)| size > 0)?;
+            Some((
+                offset,
+                size,
+                base.wrapping_add(addr.wrapping_sub(preferred)),
+            ))
+        })
+        .collect()
+}
+
+impl<'a> Image<'a> {
+    /// Add the executable sections of an ELF, PE or Mach-O file.
+    ///
+    /// @obj is the parsed content of @filename.
+    /// @base is the address the file is loaded at,
+    /// the sections are placed relative to it just like the loader would,
+    /// so e.g. the base of a PIE executable or shared library is its load bias.
+    /// Sections without file content are skipped.
+    /// Returns the number of added sections.
+    ///
+    /// Only available with the `object` feature.
+    pub fn add_object(
+        &mut self,
+        filename: &str,
+        obj: &object::File,
+        base: u64,
+        asid: Option<Asid>,
+    ) -> Result<u32, PtError> {
+        let sections = obj
+            .sections()
+            .map(|s| (s.kind(), s.address(), s.file_range()));
+
+        let mut added = 0;
+        for (offset, size, vaddr) in placements(sections, obj.relative_address_base(), base) {
+            self.add_file(filename, offset, size, asid, vaddr)?;
+            added += 1;
+        }
+        Ok(added)
+    }
+}
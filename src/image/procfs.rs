@@ -0,0 +1,124 @@
+use super::Image;
+use crate::error::{PtError, PtErrorCode};
+
+use std::fs::{self, File};
+use std::os::unix::fs::FileExt;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_maps() {
+        let maps = parse_maps(concat!(
+            "00400000-00452000 r-xp 00000000 08:02 173521 /usr/bin/dbus-daemon\n",
+            "00651000-00652000 rw-p 00051000 08:02 173521 /usr/bin/dbus-daemon\n",
+            "7f0000000000-7f0000001000 rwxp 00000000 00:00 0 \n",
+            "7ffd5b5fe000-7ffd5b600000 r-xp 00000000 00:00 0 [vdso]\n",
+            "ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0 [vsyscall]\n",
+            "7f0000002000-7f0000003000 r-xp 00001000 08:02 12 /tmp/gone (deleted)\n",
+        ));
+
+        assert_eq!(maps.len(), 5);
+        assert_eq!(
+            maps[0],
+            MapsEntry {
+                start: 0x400000,
+                end: 0x452000,
+                offset: 0,
+                path: Some("/usr/bin/dbus-daemon".to_string()),
+            }
+        );
+        assert_eq!(maps[1].path, None);
+        assert_eq!(maps[1].start, 0x7f00_0000_0000);
+        assert_eq!(maps[2].path, None);
+        assert_eq!(maps[3].start, 0xffff_ffff_ff60_0000);
+        // the file is gone, read the memory instead
+        assert_eq!(maps[4].path, None);
+        assert_eq!(maps[4].offset, 0x1000);
+    }
+}
+
+// an executable mapping from /proc/<pid>/maps
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MapsEntry {
+    start: u64,
+    end: u64,
+    offset: u64,
+    // the backing file, None for anonymous memory
+    path: Option<String>,
+}
+
+// parses the executable mappings of a maps file
+fn parse_maps(text: &str) -> Vec<MapsEntry> {
+    text.lines()
+        .filter_map(|l| {
+            let mut f = l.splitn(6, ' ');
+            let (start, end) = f.next()?.split_once('-')?;
+            let perms = f.next()?;
+            let offset = f.next()?;
+            f.next()?;
+            f.next()?;
+            let path = f.next().unwrap_or("").trim();
+            if !perms.contains('x') {
+                return None;
+            }
+
+            Some(MapsEntry {
+                start: u64::from_str_radix(start, 16).ok()?,
+                end: u64::from_str_radix(end, 16).ok()?,
+                offset: u64::from_str_radix(offset, 16).ok()?,
+                path: (path.starts_with('/') && !path.ends_with(" (deleted)"))
+                    .then(|| path.to_string()),
+            })
+        })
+        .collect()
+}
+
+impl<'a> Image<'a> {
+    /// Creates an image of the executable memory of the running process @pid.
+    ///
+    /// Executable file mappings from `/proc/<pid>/maps` are added as file sections.
+    /// Anonymous executable memory, e.g. JIT compiled code or the vdso,
+    /// is read from `/proc/<pid>/mem` on demand through the image's read callback,
+    /// so it reflects the process as it is at the time of decoding.
+    /// Reading another process' memory requires ptrace access to it.
+    /// Returns BadFile if the mappings of @pid can't be read.
+    pub fn from_pid(pid: u32) -> Result<Self, PtError> {
+        let maps = fs::read_to_string(format!("/proc/{}/maps", pid))
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not read the process maps"))?;
+
+        let mut image = Image::new(Some(&format!("pid {}", pid)))?;
+        let mut anon = Vec::new();
+        for m in parse_maps(&maps) {
+            let added = match &m.path {
+                Some(path) => image
+                    .add_file(path, m.offset, m.end - m.start, None, m.start)
+                    .is_ok(),
+                None => false,
+            };
+            // fall back to the process memory if the file can't be used
+            if !added {
+                anon.push((m.start, m.end));
+            }
+        }
+
+        if !anon.is_empty() {
+            if let Ok(mem) = File::open(format!("/proc/{}/mem", pid)) {
+                image.set_callback(Some(move |buf: &mut [u8], ip: u64, _| {
+                    let end = match anon.iter().find(|(s, e)| (*s..*e).contains(&ip)) {
+                        Some(&(_, end)) => end,
+                        None => return -i32::from(PtErrorCode::Nomap),
+                    };
+                    let len = buf.len().min((end - ip) as usize);
+                    match mem.read_at(&mut buf[..len], ip) {
+                        Ok(n) if n > 0 => n as i32,
+                        _ => -i32::from(PtErrorCode::Nomap),
+                    }
+                }))?;
+            }
+        }
+
+        Ok(image)
+    }
+}
@@ -0,0 +1,183 @@
+use super::Image;
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::FileExt;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_bytes_reader() {
+        let mut r = BytesReader::new(0x1000, vec![1, 2, 3]);
+        let mut buf = [0u8; 8];
+        assert_eq!(r.read(0x1001, &mut buf, Asid::default()).unwrap(), 2);
+        assert_eq!(&buf[..2], &[2, 3]);
+        assert_eq!(
+            r.read(0x1003, &mut buf, Asid::default())
+                .unwrap_err()
+                .code(),
+            PtErrorCode::Nomap
+        );
+        assert_eq!(callback(&mut r, &mut buf, 0x1000, Asid::default()), 3);
+        assert_eq!(
+            callback(&mut r, &mut buf, 0xfff, Asid::default()),
+            -i32::from(PtErrorCode::Nomap)
+        );
+    }
+
+    #[test]
+    fn test_file_reader() {
+        let file: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testfiles", "garbage.txt"]
+            .iter()
+            .collect();
+        let mut r = FileReader::new(File::open(file).unwrap(), 6, 5, 0x400000);
+        let mut buf = [0u8; 8];
+        assert_eq!(r.read(0x400000, &mut buf, Asid::default()).unwrap(), 5);
+        assert_eq!(&buf[..5], b"ipsum");
+        assert!(r.read(0x400005, &mut buf, Asid::default()).is_err());
+    }
+}
+
+/// A source of the memory of the traced process
+///
+/// See `Image::set_reader`.
+pub trait MemoryReader {
+    /// Read the memory at @addr in the address space @asid into @buf.
+    ///
+    /// Reads at most `buf.len()` bytes, but may read fewer.
+    /// Returns the number of bytes read.
+    /// Returns Nomap if there is no memory at @addr.
+    fn read(&mut self, addr: u64, buf: &mut [u8], asid: Asid) -> Result<usize, PtError>;
+}
+
+#[inline]
+fn nomap() -> PtError {
+    PtError::new(PtErrorCode::Nomap, "no memory at the address")
+}
+
+// serves the image's read memory callback from @reader
+fn callback<R: MemoryReader + ?Sized>(reader: &mut R, buf: &mut [u8], ip: u64, asid: Asid) -> i32 {
+    let len = buf.len().min(i32::MAX as usize);
+    match reader.read(ip, &mut buf[..len], asid) {
+        Ok(0) => -i32::from(PtErrorCode::Nomap),
+        Ok(n) => n.min(len) as i32,
+        Err(e) => -i32::from(e.code()),
+    }
+}
+
+/// Memory from bytes, located at a fixed address in all address spaces
+#[derive(Debug, Clone)]
+pub struct BytesReader {
+    addr: u64,
+    bytes: Vec<u8>,
+}
+
+impl BytesReader {
+    /// Memory with the content @bytes at @addr.
+    pub fn new(addr: u64, bytes: Vec<u8>) -> Self {
+        BytesReader { addr, bytes }
+    }
+}
+
+impl MemoryReader for BytesReader {
+    fn read(&mut self, addr: u64, buf: &mut [u8], _: Asid) -> Result<usize, PtError> {
+        let off = addr.wrapping_sub(self.addr);
+        if off >= self.bytes.len() as u64 {
+            return Err(nomap());
+        }
+        let src = &self.bytes[off as usize..];
+        let len = buf.len().min(src.len());
+        buf[..len].copy_from_slice(&src[..len]);
+        Ok(len)
+    }
+}
+
+/// Memory from @size bytes at @offset in a file, located at @addr in all address spaces
+#[derive(Debug)]
+pub struct FileReader {
+    file: File,
+    offset: u64,
+    size: u64,
+    addr: u64,
+}
+
+impl FileReader {
+    pub fn new(file: File, offset: u64, size: u64, addr: u64) -> Self {
+        FileReader {
+            file,
+            offset,
+            size,
+            addr,
+        }
+    }
+}
+
+impl MemoryReader for FileReader {
+    fn read(&mut self, addr: u64, buf: &mut [u8], _: Asid) -> Result<usize, PtError> {
+        let off = addr.wrapping_sub(self.addr);
+        if off >= self.size {
+            return Err(nomap());
+        }
+        let len = buf
+            .len()
+            .min((self.size - off).min(usize::MAX as u64) as usize);
+        let res = self
+            .file
+            .seek(SeekFrom::Start(self.offset + off))
+            .and_then(|_| self.file.read(&mut buf[..len]));
+        match res {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(nomap()),
+        }
+    }
+}
+
+/// The memory of a running process, read from /proc/<pid>/mem
+///
+/// Reading another process' memory requires ptrace access to it.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct ProcessReader {
+    mem: File,
+}
+
+#[cfg(target_os = "linux")]
+impl ProcessReader {
+    /// Open the memory of process @pid.
+    ///
+    /// Returns BadFile if the memory can't be opened.
+    pub fn new(pid: u32) -> Result<Self, PtError> {
+        File::open(format!("/proc/{}/mem", pid))
+            .map(|mem| ProcessReader { mem })
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not open the process memory"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl MemoryReader for ProcessReader {
+    fn read(&mut self, addr: u64, buf: &mut [u8], _: Asid) -> Result<usize, PtError> {
+        match self.mem.read_at(buf, addr) {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(nomap()),
+        }
+    }
+}
+
+impl<'a> Image<'a> {
+    /// Read memory that is not covered by file sections from @reader.
+    ///
+    /// This uses the image's read memory callback and
+    /// replaces any callback set with `Image::set_callback`.
+    /// @reader may borrow data that outlives the image.
+    pub fn set_reader<R: MemoryReader + 'a>(&mut self, mut reader: R) -> Result<(), PtError> {
+        self.set_callback(Some(move |buf: &mut [u8], ip, asid| {
+            callback(&mut reader, buf, ip, asid)
+        }))
+    }
+}
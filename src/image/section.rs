@@ -0,0 +1,130 @@
+use super::Image;
+use crate::asid::Asid;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn section(filename: &str, vaddr: u64, size: u64, cr3: Option<u64>) -> MappedSection {
+        MappedSection {
+            filename: filename.to_string(),
+            offset: 0x100,
+            size,
+            asid: Asid::new(cr3, None),
+            vaddr,
+        }
+    }
+
+    #[test]
+    fn test_overlay_split() {
+        let files = [
+            section("/a", 0x1000, 0x1000, Some(1)),
+            section("/b", 0x1400, 0x100, Some(1)),
+            // other address spaces are not affected
+            section("/c", 0x1000, 0x1000, Some(2)),
+        ];
+        let s = overlay(&files);
+        assert_eq!(s.len(), 4);
+        assert_eq!(s[0], section("/a", 0x1000, 0x400, Some(1)));
+        assert_eq!(s[1].vaddr, 0x1500);
+        assert_eq!(s[1].size, 0xb00);
+        assert_eq!(s[1].offset, 0x600);
+        assert_eq!(s[2].filename, "/b");
+        assert_eq!(s[3].filename, "/c");
+        assert!(s[3].contains(0x1fff));
+        assert!(!s[3].contains(0x2000));
+    }
+
+    #[test]
+    fn test_overlay_replace() {
+        let files = [
+            section("/a", 0x1000, 0x1000, Some(1)),
+            section("/b", 0x800, 0x2000, None),
+        ];
+        let s = overlay(&files);
+        assert_eq!(s, vec![files[1].clone()]);
+    }
+}
+
+/// A file section mapped into an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedSection {
+    /// The file the section is read from
+    pub filename: String,
+    /// The offset of the section in @filename
+    pub offset: u64,
+    pub size: u64,
+    /// The address space the section is loaded into
+    pub asid: Asid,
+    /// The virtual address the section is loaded at
+    pub vaddr: u64,
+}
+
+impl MappedSection {
+    /// The end (exclusive) of the section in memory
+    #[inline]
+    pub fn end(&self) -> u64 {
+        self.vaddr.saturating_add(self.size)
+    }
+
+    /// Check if @addr is part of the section.
+    #[inline]
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.vaddr..self.end()).contains(&addr)
+    }
+
+    #[inline]
+    pub(super) fn overlaps(&self, other: &MappedSection) -> bool {
+        self.vaddr < other.end() && other.vaddr < self.end() && self.asid.matches(other.asid)
+    }
+}
+
+// applies the sections in @files in order like libipt does:
+// existing sections that overlap a new section are shrunk or split
+pub(super) fn overlay(files: &[MappedSection]) -> Vec<MappedSection> {
+    let mut sections: Vec<MappedSection> = Vec::new();
+    for f in files {
+        let mut next = Vec::with_capacity(sections.len() + 2);
+        for s in sections.drain(..) {
+            if !s.overlaps(f) {
+                next.push(s);
+                continue;
+            }
+            if s.vaddr < f.vaddr {
+                next.push(MappedSection {
+                    size: f.vaddr - s.vaddr,
+                    ..s.clone()
+                });
+            }
+            if s.end() > f.end() {
+                let cut = f.end() - s.vaddr;
+                next.push(MappedSection {
+                    offset: s.offset + cut,
+                    size: s.size - cut,
+                    vaddr: f.end(),
+                    ..s
+                });
+            }
+        }
+        next.push(f.clone());
+        sections = next;
+    }
+    sections
+}
+
+impl<'a> Image<'a> {
+    /// Get the file sections mapped into the image, oldest first.
+    ///
+    /// This accounts for sections that were shrunk or split by
+    /// overlapping sections added later.
+    /// Only sections added through this `Image` are known,
+    /// so this is empty for the image of a decoder (see `ImageRef`)
+    /// until sections are added to it.
+    /// Sections that libipt truncated to the size of their file are
+    /// listed with the size they were added with.
+    pub fn sections(&self) -> Vec<MappedSection> {
+        overlay(&self.files)
+    }
+}
@@ -0,0 +1,185 @@
+use super::{overlay, Image, MappedSection};
+use crate::asid::Asid;
+
+use std::fs;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn section(filename: &str, vaddr: u64, size: u64, cr3: Option<u64>) -> MappedSection {
+        MappedSection {
+            filename: filename.to_string(),
+            offset: 0,
+            size,
+            asid: Asid::new(cr3, None),
+            vaddr,
+        }
+    }
+
+    #[test]
+    fn test_check_sections() {
+        let files = [
+            section("/a", 0x1000, 0x1000, Some(1)),
+            section("/b", 0x1800, 0x1000, Some(1)),
+            section("/c", 0x8000, 0x1000, Some(2)),
+            section("/gone", 0x9000, 0x1000, None),
+        ];
+        let len = |f: &str| match f {
+            "/gone" => None,
+            "/b" => Some(0x800),
+            _ => Some(0x1000),
+        };
+
+        let issues = check(&files, None, len);
+        assert_eq!(
+            issues,
+            vec![
+                ImageIssue::Overlap {
+                    earlier: files[0].clone(),
+                    later: files[1].clone(),
+                },
+                ImageIssue::Truncated {
+                    section: files[1].clone(),
+                    file_size: Some(0x800),
+                },
+                ImageIssue::Truncated {
+                    section: files[3].clone(),
+                    file_size: None,
+                },
+            ]
+        );
+
+        let asid = Asid::new(Some(1), None);
+        let issues = check(&files[..3], Some((0x8010, asid)), len);
+        assert_eq!(
+            issues.last(),
+            Some(&ImageIssue::AsidMismatch {
+                ip: 0x8010,
+                asid,
+                sections: vec![files[2].clone()],
+            })
+        );
+
+        let issues = check(&files[..3], Some((0x3000, asid)), len);
+        match issues.last() {
+            Some(ImageIssue::Unmapped { ip, below, above }) => {
+                assert_eq!(*ip, 0x3000);
+                assert_eq!(below.as_ref().unwrap().filename, "/b");
+                assert!(above.is_none());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+/// A problem with the sections of an image, see `Image::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageIssue {
+    /// The section @later was added over a part of @earlier
+    /// in the same address space, which hides that part.
+    Overlap {
+        earlier: MappedSection,
+        later: MappedSection,
+    },
+    /// The file of @section is smaller than the section,
+    /// the rest of the section is not mapped.
+    /// @file_size is None if the file can't be read at all.
+    Truncated {
+        section: MappedSection,
+        file_size: Option<u64>,
+    },
+    /// @ip is not mapped in address space @asid,
+    /// but in other address spaces by @sections.
+    AsidMismatch {
+        ip: u64,
+        asid: Asid,
+        sections: Vec<MappedSection>,
+    },
+    /// @ip is not mapped at all.
+    /// @below and @above are the closest sections of the address space.
+    Unmapped {
+        ip: u64,
+        below: Option<MappedSection>,
+        above: Option<MappedSection>,
+    },
+}
+
+// checks @files with @file_len providing the size of a file
+fn check(
+    files: &[MappedSection],
+    ip: Option<(u64, Asid)>,
+    file_len: impl Fn(&str) -> Option<u64>,
+) -> Vec<ImageIssue> {
+    let mut issues = Vec::new();
+    for (i, earlier) in files.iter().enumerate() {
+        for later in files[i + 1..].iter().filter(|l| l.overlaps(earlier)) {
+            issues.push(ImageIssue::Overlap {
+                earlier: earlier.clone(),
+                later: later.clone(),
+            });
+        }
+    }
+
+    for f in files {
+        let file_size = file_len(&f.filename);
+        if file_size
+            .filter(|&len| len >= f.offset.saturating_add(f.size))
+            .is_none()
+        {
+            issues.push(ImageIssue::Truncated {
+                section: f.clone(),
+                file_size,
+            });
+        }
+    }
+
+    let (ip, asid) = match ip {
+        Some(ip) => ip,
+        None => return issues,
+    };
+    let sections = overlay(files);
+    let (mine, others): (Vec<_>, Vec<_>) = sections.into_iter().partition(|s| s.asid.matches(asid));
+    if mine.iter().any(|s| s.contains(ip)) {
+        return issues;
+    }
+
+    let elsewhere: Vec<_> = others.into_iter().filter(|s| s.contains(ip)).collect();
+    if !elsewhere.is_empty() {
+        issues.push(ImageIssue::AsidMismatch {
+            ip,
+            asid,
+            sections: elsewhere,
+        });
+    } else {
+        issues.push(ImageIssue::Unmapped {
+            ip,
+            below: mine
+                .iter()
+                .filter(|s| s.end() <= ip)
+                .max_by_key(|s| s.end())
+                .cloned(),
+            above: mine
+                .iter()
+                .filter(|s| s.vaddr > ip)
+                .min_by_key(|s| s.vaddr)
+                .cloned(),
+        });
+    }
+    issues
+}
+
+impl<'a> Image<'a> {
+    /// Check the image for common causes of Nomap and BadInsn errors.
+    ///
+    /// Reports overlapping sections in the same address space and
+    /// sections whose file is missing or too small.
+    /// If @ip is given, e.g. the address of a failed decode,
+    /// also reports why @ip is not mapped in the address space @asid.
+    /// Only the sections listed by `Image::sections` are checked.
+    /// Returns an empty list if no problems were found.
+    pub fn validate(&self, ip: Option<u64>, asid: Option<Asid>) -> Vec<ImageIssue> {
+        let ip = ip.map(|ip| (ip, asid.unwrap_or_default()));
+        check(&self.files, ip, |f| fs::metadata(f).ok().map(|m| m.len()))
+    }
+}
@@ -16,7 +16,8 @@ use libipt_sys::{
 ///
 /// We provide only a very coarse classification suitable for reconstructing
 /// the execution flow.
-#[derive(Clone, Copy, Debug, TryFromPrimitive, PartialEq)]
+#[derive(Clone, Copy, Debug, TryFromPrimitive, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i32)]
 pub enum Class {
     /// The instruction is a near (function) call.
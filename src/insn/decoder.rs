@@ -1,18 +1,26 @@
 use crate::error::{
-    PtError, deref_ptresult,
-    deref_ptresult_mut, PtErrorCode,
+    PtError, DecodeError, deref_ptresult,
+    ensure_ptr, UntilEos,
     ensure_ptok, extract_pterr
 };
-use crate::config::Config;
+use crate::config::{Config, InsnFlags, Owned};
 use crate::Asid;
+use crate::budget::Budget;
+use crate::cancel::CancelToken;
+use crate::progress::{Progress, Reporter};
+use crate::checkpoint::Checkpoint;
+use crate::tracker::{self, RawDecoder, SyncTo, Tracker};
 use crate::event::Event;
 use crate::Status;
-use crate::Image;
+use crate::time::{TimeCal, TimeInfo};
+use crate::{Image, ImageRef};
 use super::Insn;
 
-use std::mem;
-use std::ptr;
-use std::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use alloc::vec::Vec;
 
 use libipt_sys::{
     pt_insn_decoder,
@@ -33,13 +41,15 @@ use libipt_sys::{
     pt_insn_sync_backward,
     pt_insn_sync_forward,
     pt_insn_sync_set,
-    pt_insn_time
+    pt_insn_time,
+    pt_image
 };
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::config::ConfigBuilder;
+    use crate::error::ErrorKind;
 
     #[test]
     fn test_insndec_alloc() {
@@ -67,34 +77,121 @@ mod test {
         assert!(b.config().is_ok());
         assert!(b.image().unwrap().name().is_none());
         assert!(b.offset().is_err());
+        assert!(b.position().is_err());
         assert!(b.sync_offset().is_err());
         assert!(b.next().is_err());
         assert!(b.sync_backward().is_err());
         assert!(b.sync_forward().is_err());
-        assert!(b.time().is_err());
+        assert!(!b.time().unwrap().has_tsc);
+        assert!(b.checkpoint().is_err());
+        assert!(b.step_back(1).is_err());
+        b.set_budget(Some(Budget::new().insns(0)));
+        assert_eq!(b.next().err().unwrap().kind(), ErrorKind::BudgetExhausted);
+        assert_eq!(b.sync_backward().unwrap_err().kind(), ErrorKind::BudgetExhausted);
+        assert_eq!(b.sync_set(0).unwrap_err().kind(), ErrorKind::BudgetExhausted);
+        b.set_budget(None);
+        let cancel = CancelToken::new();
+        b.set_cancel(Some(cancel.clone()));
+        cancel.cancel();
+        assert_eq!(b.next().err().unwrap().kind(), ErrorKind::Cancelled);
+        assert_eq!(b.sync_forward().unwrap_err().kind(), ErrorKind::Cancelled);
+        assert_eq!(b.sync_backward().unwrap_err().kind(), ErrorKind::Cancelled);
+        assert_eq!(b.sync_set(0).unwrap_err().kind(), ErrorKind::Cancelled);
+        b.set_cancel(None);
+        assert!(b.reset(None).is_ok());
+        assert!(b.offset().is_err());
     }
 }
 
 /// The decoder will work on the buffer defined in the Config,
 /// it shall contain raw trace data and remain valid for the lifetime of the decoder.
 /// The decoder needs to be synchronized before it can be used.
-pub struct InsnDecoder<'a, T>(&'a mut pt_insn_decoder, PhantomData<T>);
+///
+/// Like the `BlockDecoder`, the decoder is neither Send nor Sync.
+pub struct InsnDecoder<'a, T> {
+    // the allocation returned by libipt, freed on drop
+    inner: *mut pt_insn_decoder,
+    // the image set by the user, null for the decoder's default image.
+    // the raw pointer also keeps the decoder from being Send and Sync.
+    image: *mut pt_image,
+    // checkpoints, budget, cancellation and progress, see `tracker`
+    tracker: Tracker<'a>,
+    // keeps an owned trace buffer or decode callback alive
+    owned: Option<Arc<Owned>>,
+    phantom: PhantomData<T>
+}
+
 impl<'a, T> InsnDecoder<'a, T> {
     /// Allocate an Intel PT instruction flow decoder.
     ///
     /// The decoder will work on the buffer defined in @config,
     /// it shall contain raw trace data and remain valid for the lifetime of the decoder.
+    /// The trace buffer of @config is borrowed for @'a, the decoder can't outlive it.
     /// The decoder needs to be synchronized before it can be used.
-    pub fn new(cfg: &Config<T>) -> Result<Self, PtError> {
-        deref_ptresult_mut(unsafe { pt_insn_alloc_decoder(cfg.0.as_ref()) })
-            .map(|d| InsnDecoder::<T>(d, PhantomData))
+    pub fn new(cfg: &Config<'a, T>) -> Result<Self, PtError> {
+        ensure_ptr(unsafe { pt_insn_alloc_decoder(cfg.0.as_ref()) })
+            .map(|d| InsnDecoder::<T> {
+                inner: d,
+                image: ptr::null_mut(),
+                tracker: Tracker::new(),
+                owned: cfg.2.clone(),
+                phantom: PhantomData
+            })
+    }
+
+    /// Take ownership of the raw instruction flow decoder @decoder.
+    ///
+    /// An image set on @decoder with pt_insn_set_image is kept,
+    /// but is not set again by `restore` and `step_back`.
+    ///
+    /// # Safety
+    /// @decoder must have been allocated with pt_insn_alloc_decoder and must not be
+    /// used or freed by anyone else afterwards.
+    /// Its trace buffer, image and decode callback must stay valid for @'a,
+    /// the callback has to be of type `T`.
+    pub unsafe fn from_raw(decoder: *mut pt_insn_decoder) -> Self {
+        InsnDecoder {
+            inner: decoder,
+            image: ptr::null_mut(),
+            tracker: Tracker::new(),
+            owned: None,
+            phantom: PhantomData
+        }
+    }
+
+    /// A pointer to the raw instruction flow decoder
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the decoder is.
+    pub fn as_ptr(&self) -> *const pt_insn_decoder {
+        self.inner
+    }
+
+    /// A mutable pointer to the raw instruction flow decoder
+    ///
+    /// See `as_ptr`.
+    /// Synchronizing the raw decoder is not seen by `checkpoint`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_insn_decoder {
+        self.inner
+    }
+
+    /// Release the raw instruction flow decoder.
+    ///
+    /// The caller has to free it with pt_insn_free_decoder.
+    /// An owned trace buffer or decode callback of the config is leaked,
+    /// the raw decoder still uses it.
+    pub fn into_raw(self) -> *mut pt_insn_decoder {
+        let decoder = self.inner;
+        mem::forget(self);
+        decoder
     }
 
     /// Return the current address space identifier.
     pub fn asid(&self) -> Result<Asid, PtError> {
+        self.tracker.synced()?;
         let mut asid: pt_asid = unsafe { mem::zeroed() };
         ensure_ptok(unsafe {
-            pt_insn_asid(self.0,
+            pt_insn_asid(self.inner,
                          &mut asid,
                          mem::size_of::<pt_asid>())
         }).map(|_| Asid(asid))
@@ -106,8 +203,9 @@ impl<'a, T> InsnDecoder<'a, T> {
     /// The ratio is defined as core cycles per bus clock cycle.
     /// Returns NoCbr if there has not been a CBR packet.
     pub fn core_bus_ratio(&mut self) -> Result<u32, PtError> {
+        self.tracker.synced()?;
         let mut cbr: u32 = 0;
-        ensure_ptok(unsafe { pt_insn_core_bus_ratio(self.0, &mut cbr) })
+        ensure_ptok(unsafe { pt_insn_core_bus_ratio(self.inner, &mut cbr) })
             .map(|_| cbr)
     }
 
@@ -115,44 +213,75 @@ impl<'a, T> InsnDecoder<'a, T> {
     ///
     /// On success, provides the next event with StatusFlag and updates the decoder.
     /// Returns BadQuery if there is no event.
-    pub fn event(&mut self) -> Result<(Event, Status), PtError> {
-        let mut evt: pt_event = unsafe { mem::zeroed() };
-        extract_pterr(unsafe {
-            pt_insn_event(self.0,
-                          &mut evt,
-                          mem::size_of::<pt_event>())
-        }).map(|s| (Event(evt), Status::from_bits(s).unwrap()))
+    /// Errors carry the decoder position, see `DecodeError`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), err))]
+    pub fn event(&mut self) -> Result<(Event, Status), DecodeError> {
+        tracker::event(self)
+    }
+
+    /// Get all pending events.
+    ///
+    /// Calls `event` as long as the status has event_pending set,
+    /// e.g. right after synchronizing, so `next` can be called afterwards.
+    /// Returns the events in the order they occurred.
+    pub fn drain_events(&mut self) -> Result<Vec<Event>, DecodeError> {
+        let mut events = Vec::new();
+        while self.tracker.status().event_pending() {
+            events.push(self.event()?.0);
+        }
+        Ok(events)
     }
 
     pub fn config(&self) -> Result<Config<T>, PtError> {
-        deref_ptresult(unsafe { pt_insn_get_config(self.0) })
+        deref_ptresult(unsafe { pt_insn_get_config(self.inner) })
             .map(Config::from)
     }
 
+    /// The flags the decoder was configured with.
+    ///
+    /// See `ConfigBuilder::flags`.
+    pub fn flags(&self) -> Result<InsnFlags, PtError> {
+        self.config().map(|c| InsnFlags::from(c.0.flags))
+    }
+
     /// Get the traced image.
     ///
     /// The returned image may be modified as long as no decoder that uses this image is running.
+    /// It is borrowed from the decoder, so the decoder can't run while it is in use.
     /// Returns the traced image the decoder uses for reading memory.
-    pub fn image(&mut self) -> Result<Image, PtError> {
-        deref_ptresult_mut(unsafe { pt_insn_get_image(self.0) })
-            .map(Image::from)
+    pub fn image(&mut self) -> Result<ImageRef<'_>, PtError> {
+        ensure_ptr(unsafe { pt_insn_get_image(self.inner) }).map(ImageRef::new)
     }
 
     /// Get the current decoder position.
     ///
     /// Returns Nosync if decoder is out of sync.
     pub fn offset(&self) -> Result<u64, PtError> {
+        self.tracker.synced()?;
         let mut off: u64 = 0;
-        ensure_ptok(unsafe { pt_insn_get_offset(self.0, &mut off) })
+        ensure_ptok(unsafe { pt_insn_get_offset(self.inner, &mut off) })
             .map(|_| off)
     }
 
+    /// Get the current decoder position within its trace buffer.
+    ///
+    /// Includes the size of the trace buffer and the number of bytes left,
+    /// see `Progress`.
+    /// Through a `&mut` reference `Iterator::position` takes precedence,
+    /// call this as `InsnDecoder::position(decoder)` there.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn position(&self) -> Result<Progress, PtError> {
+        let size = self.config()?.buffer_len() as u64;
+        self.offset().map(|offset| Progress { offset, size })
+    }
+
     /// Get the position of the last synchronization point.
     ///
     /// Returns Nosync if @decoder is out of sync.
     pub fn sync_offset(&self) -> Result<u64, PtError> {
+        self.tracker.synced()?;
         let mut off = 0;
-        ensure_ptok(unsafe { pt_insn_get_sync_offset(self.0, &mut off) })
+        ensure_ptok(unsafe { pt_insn_get_sync_offset(self.inner, &mut off) })
             .map(|_| off)
     }
 
@@ -168,13 +297,10 @@ impl<'a, T> InsnDecoder<'a, T> {
     /// Returns Eos if decoding reached the end of the Intel PT buffer.
     /// Returns Nomap if the memory at the instruction address can't be read.
     /// Returns Nosync if decoder is out of sync.
-    pub fn next(&mut self) -> Result<(Insn, Status), PtError> {
-        let mut insn: pt_insn = unsafe { mem::zeroed() };
-        extract_pterr(unsafe {
-            pt_insn_next(self.0,
-                         &mut insn,
-                         mem::size_of::<pt_insn>())
-        }).map(|s| (Insn(insn), Status::from_bits(s).unwrap()))
+    /// Errors carry the decoder position, see `DecodeError`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), err))]
+    pub fn next(&mut self) -> Result<(Insn, Status), DecodeError> {
+        tracker::next(self)
     }
 
     /// Set the traced image.
@@ -182,19 +308,22 @@ impl<'a, T> InsnDecoder<'a, T> {
     /// Sets the image that the decoder uses for reading memory to @image.
     /// If @image is None, sets the image to decoder's default image.
     /// Only one image can be active at any time.
-    pub fn set_image(&mut self, img: Option<&mut Image>) -> Result<(), PtError> {
-        ensure_ptok(unsafe {
-            pt_insn_set_image(self.0,
-                             match img {
-                                 None => ptr::null_mut(),
-                                 Some(i) => i.inner
-                             })
-        })
+    /// The image is borrowed for @'a, the decoder can't outlive it.
+    pub fn set_image(&mut self, img: Option<&'a mut Image<'_>>) -> Result<(), PtError> {
+        let img = match img {
+            None => ptr::null_mut(),
+            Some(i) => i.inner
+        };
+        ensure_ptok(unsafe { pt_insn_set_image(self.inner, img) })
+            .map(|_| self.image = img)
     }
 
+    /// Synchronize an Intel PT instruction flow decoder backwards.
+    ///
+    /// See `sync_forward`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
     pub fn sync_backward(&mut self) -> Result<Status, PtError> {
-        extract_pterr(unsafe { pt_insn_sync_backward(self.0) })
-            .map(|s| Status::from_bits(s).unwrap())
+        tracker::sync(self, SyncTo::Backward)
     }
 
     /// Synchronize an Intel PT instruction flow decoder.
@@ -204,67 +333,249 @@ impl<'a, T> InsnDecoder<'a, T> {
     /// the search is started at the beginning of the trace buffer
     /// in case of forward synchronization and at the end of the trace buffer
     /// in case of backward synchronization.
+    /// On success, provides the status after synchronizing.
+    /// If it has event_pending set, the events have to be drained
+    /// with `event` before the next call to `next`.
     /// Returns BadOpc if an unknown packet is encountered.
     /// Returns BadPacket if an unknown packet payload is encountered.
     /// Returns Eos if no further synchronization point is found.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
     pub fn sync_forward(&mut self) -> Result<Status, PtError> {
-        extract_pterr(unsafe { pt_insn_sync_forward(self.0) })
-            .map(|s| Status::from_bits(s).unwrap())
+        tracker::sync(self, SyncTo::Forward)
     }
 
     /// Manually synchronize an Intel PT instruction flow decoder.
     ///
     /// Synchronize @decoder on the syncpoint at @offset.
     /// There must be a PSB packet at @offset.
+    /// On success, provides the status after synchronizing, see `sync_forward`.
     /// Returns BadOpc if an unknown packet is encountered.
     /// Returns BadPacket if an unknown packet payload is encountered.
     /// Returns Eos if @offset lies outside of decoder's trace buffer.
     /// Returns Eos if decoder reaches the end of its trace buffer.
     /// Returns Nosync if there is no syncpoint at @offset.
-    pub fn sync_set(&mut self, offset: u64) -> Result<(), PtError> {
-        ensure_ptok(unsafe { pt_insn_sync_set(self.0, offset) })
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), ret, err))]
+    pub fn sync_set(&mut self, offset: u64) -> Result<Status, PtError> {
+        tracker::sync(self, SyncTo::Offset(offset))
     }
 
     /// Return the current time.
     ///
-    /// On success, provides the time at the last preceding timing packet,
-    /// The number of lost mtc packets and
-    /// The number of lost cyc packets.
+    /// On success, provides the time at the last preceding timing packet.
     ///
     /// The time is similar to what a rdtsc instruction would return.
     /// Depending on the configuration, the time may not be fully accurate.
-    /// If TSC is not enabled, the time is relative to the last synchronization and can't be used to correlate with other TSC-based time sources.
-    /// In this case, NoTime is returned and the relative time is provided in @time.
+    /// If TSC is not enabled, the time is relative to the last synchronization
+    /// and can't be used to correlate with other TSC-based time sources,
+    /// `has_tsc` is false in this case.
     /// Some timing-related packets may need to be dropped (mostly due to missing calibration or incomplete configuration).
-    /// To get an idea about the quality of the estimated time, we record the number of dropped MTC and CYC packets.
-    /// Returns NoTime if there has not been a TSC packet.
-    pub fn time(&mut self) -> Result<(u64, u32, u32), PtError> {
-        let mut time: u64 = 0;
-        let mut lost_mtc: u32 = 0;
-        let mut lost_cyc: u32 = 0;
-        ensure_ptok(
-            unsafe {
-                pt_insn_time(self.0,
-                             &mut time,
-                             &mut lost_mtc,
-                             &mut lost_cyc)
+    /// To get an idea about the quality of the estimated time, the number of dropped MTC and CYC packets is recorded,
+    /// see `TimeInfo::quality`.
+    pub fn time(&mut self) -> Result<TimeInfo, PtError> {
+        self.tracker.synced()?;
+        TimeInfo::query(|time, lost_mtc, lost_cyc| unsafe {
+            pt_insn_time(self.inner, time, lost_mtc, lost_cyc)
+        })
+    }
+
+    /// Capture the time calibration of the decoder.
+    ///
+    /// Use this after decoding a warm-up region to seed decoders
+    /// that start at later synchronization points, see `TimeCal`.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn time_cal(&mut self) -> Result<TimeCal, PtError> {
+        let freq = self.config()?.freq();
+        let cbr = self.core_bus_ratio();
+        TimeCal::capture(freq, cbr, self.time())
+    }
+
+    /// Take a snapshot of the current decoder position.
+    ///
+    /// The checkpoint can be restored on this or any other instruction flow
+    /// decoder working on the same trace.
+    /// Returns Nosync if the decoder is out of sync.
+    /// Returns BadContext if a `next` or `event` call failed since the last
+    /// synchronization, since that state can't be reproduced.
+    pub fn checkpoint(&self) -> Result<Checkpoint, PtError> {
+        tracker::checkpoint(self)
+    }
+
+    /// Restore a decoder position.
+    ///
+    /// Synchronizes the decoder at the checkpoint's sync offset and replays
+    /// the decoding steps up to the checkpoint.
+    /// Pending events are consumed during the replay just like regular usage would.
+    /// The decoder needs to use the same image as the one that took the checkpoint.
+    /// Returns the status of the last replayed step.
+    /// Returns BadContext if the replay does not end at the checkpoint's offset.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<Status, PtError> {
+        tracker::restore(self, checkpoint)
+    }
+
+    /// Step back by @n instructions.
+    ///
+    /// Re-decodes the trace from the nearest checkpoint before the target,
+    /// see `set_checkpoint_interval`, or else from the last synchronization point
+    /// the same way a checkpoint is restored, so that the following calls to `next`
+    /// provide the previous @n instructions again.
+    /// Events in between those instructions will be reported again.
+    /// Returns the status of the last replayed step.
+    /// Returns Invalid if @n goes beyond the last synchronization point.
+    /// Returns Nosync if the decoder is out of sync.
+    pub fn step_back(&mut self, n: u64) -> Result<Status, PtError> {
+        tracker::step_back(self, n)
+    }
+
+    /// Record a checkpoint for `step_back` every @n instructions.
+    ///
+    /// See `BlockDecoder::set_checkpoint_interval`.
+    pub fn set_checkpoint_interval(&mut self, n: u64) {
+        self.tracker.set_checkpoint_interval(n);
+    }
+
+    /// Limit the work done by the decoder.
+    ///
+    /// Replaces the current budget and resets the work done so far.
+    /// Once the budget is used up, `next` and the synchronization functions
+    /// `sync_forward`, `sync_backward` and `sync_set` return BudgetExhausted.
+    /// If @budget is None, the decoder is not limited.
+    pub fn set_budget(&mut self, budget: Option<Budget>) {
+        self.tracker.set_budget(budget);
+    }
+
+    /// Make the decoder cancellable.
+    ///
+    /// Once @cancel is cancelled, `next` and the synchronization functions
+    /// `sync_forward`, `sync_backward` and `sync_set` return Cancelled,
+    /// which also stops the iterators working on the decoder.
+    /// If @cancel is None, the decoder can't be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        self.tracker.set_cancel(cancel);
+    }
+
+    /// Report the decoder's progress through its trace buffer.
+    ///
+    /// @callback is called with the current position after `next` and after synchronizing
+    /// whenever the decoder moved by at least @interval bytes since the last report.
+    /// The first position after setting the callback is always reported.
+    /// If @callback is None, progress is no longer reported.
+    pub fn set_progress<F>(&mut self, interval: u64, callback: Option<F>)
+    where
+        F: FnMut(Progress) + 'a,
+    {
+        self.tracker.set_progress(callback.map(|c| Reporter::new(interval, c)));
+    }
+
+    /// Return the decoder to the unsynchronized state of a new decoder.
+    ///
+    /// Without @cfg, the decoder is reset in place without reallocating it.
+    /// Like a new decoder, it has to be synchronized before it can be used,
+    /// `sync_forward` starts at the beginning of the trace buffer
+    /// and `sync_backward` at its end. Until then, the functions that report
+    /// decoder state, e.g. `offset` or `time`, return Nosync.
+    /// With @cfg, the underlying decoder is allocated anew for it,
+    /// the old one is only freed if that succeeded,
+    /// since libipt decoders can't switch to the trace buffer of another config.
+    /// The image set with `set_image`, the budget, the cancel token
+    /// and the progress callback are kept,
+    /// the work done under the budget is reset.
+    pub fn reset(&mut self, cfg: Option<&Config<'a, T>>) -> Result<(), PtError> {
+        if let Some(cfg) = cfg {
+            self.reallocate(cfg)?;
+        }
+        self.tracker.reset(cfg.is_none());
+        Ok(())
+    }
+
+    // replaces the libipt decoder with one for @cfg
+    fn reallocate(&mut self, cfg: &Config<'a, T>) -> Result<(), PtError> {
+        let inner = ensure_ptr(unsafe { pt_insn_alloc_decoder(cfg.0.as_ref()) })?;
+        if !self.image.is_null() {
+            if let Err(e) = ensure_ptok(unsafe { pt_insn_set_image(inner, self.image) }) {
+                unsafe { pt_insn_free_decoder(inner) };
+                return Err(e);
+            }
+        }
+
+        unsafe { pt_insn_free_decoder(self.inner) };
+        self.inner = inner;
+        self.owned = cfg.2.clone();
+        Ok(())
+    }
+}
+
+impl<'a, T> RawDecoder<'a> for InsnDecoder<'a, T> {
+    type Item = Insn;
+
+    fn tracker(&self) -> &Tracker<'a> {
+        &self.tracker
+    }
+
+    fn tracker_mut(&mut self) -> &mut Tracker<'a> {
+        &mut self.tracker
+    }
+
+    fn raw_offset(&self) -> Result<u64, PtError> {
+        self.offset()
+    }
+
+    fn raw_sync_offset(&self) -> Result<u64, PtError> {
+        self.sync_offset()
+    }
+
+    fn raw_position(&self) -> Result<Progress, PtError> {
+        // `Iterator::position` would shadow ours on a `&mut self`
+        Self::position(self)
+    }
+
+    fn raw_psb(&self, last: bool) -> Result<u64, PtError> {
+        self.config()?.psb_offset(last)
+    }
+
+    fn raw_next(&mut self) -> (Insn, Result<Status, PtError>) {
+        let mut insn: pt_insn = unsafe { mem::zeroed() };
+        let res = extract_pterr(unsafe {
+            pt_insn_next(self.inner,
+                         &mut insn,
+                         mem::size_of::<pt_insn>())
+        }).map(Status::from_raw);
+        (Insn(insn), res)
+    }
+
+    fn insns(_: &Insn) -> u64 {
+        1
+    }
+
+    fn raw_event(&mut self) -> (Event, Result<Status, PtError>) {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        let res = extract_pterr(unsafe {
+            pt_insn_event(self.inner,
+                          &mut evt,
+                          mem::size_of::<pt_event>())
+        }).map(Status::from_raw);
+        (Event(evt), res)
+    }
+
+    fn raw_sync(&mut self, to: SyncTo) -> Result<Status, PtError> {
+        extract_pterr(unsafe {
+            match to {
+                SyncTo::Forward => pt_insn_sync_forward(self.inner),
+                SyncTo::Backward => pt_insn_sync_backward(self.inner),
+                SyncTo::Offset(offset) => pt_insn_sync_set(self.inner, offset)
             }
-        ).map(|_| (time, lost_mtc, lost_cyc))
+        }).map(Status::from_raw)
     }
 }
 
 impl<'a, T> Iterator for InsnDecoder<'a, T> {
-    type Item = Result<(Insn, Status), PtError>;
+    type Item = Result<(Insn, Status), DecodeError>;
 
-    fn next(&mut self) -> Option<Result<(Insn, Status), PtError>> {
-        match self.next() {
-            // eos to stop iterating
-            Err(x) if x.code() == PtErrorCode::Eos => None,
-            x => Some(x)
-        }
+    fn next(&mut self) -> Option<Result<(Insn, Status), DecodeError>> {
+        // eos to stop iterating
+        self.next().until_eos().transpose()
     }
 }
 
 impl<'a, T> Drop for InsnDecoder<'a, T> {
-    fn drop(&mut self) { unsafe { pt_insn_free_decoder(self.0) } }
+    fn drop(&mut self) { unsafe { pt_insn_free_decoder(self.inner) } }
 }
\ No newline at end of file
@@ -1,7 +1,9 @@
 use crate::event::ExecModeType;
 use super::Class;
 
-use std::convert::TryFrom;
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 
 use libipt_sys::pt_insn;
 
@@ -78,4 +80,45 @@ impl Insn {
     /// It starts in the image section identified by \@isid and continues
     /// in one or more other sections.
     pub fn truncated(self) -> bool { self.0.truncated() > 0 }
-}
\ No newline at end of file
+}
+
+impl fmt::Debug for Insn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Insn")
+            .field("ip", &self.ip())
+            .field("isid", &self.isid())
+            .field("mode", &self.mode())
+            .field("class", &self.class())
+            .field("raw", &self.raw())
+            .field("speculative", &self.speculative())
+            .field("truncated", &self.truncated())
+            .finish()
+    }
+}
+
+// instructions are equal if all their getters are, like in `Debug`
+impl PartialEq for Insn {
+    fn eq(&self, other: &Self) -> bool {
+        self.ip() == other.ip()
+            && self.isid() == other.isid()
+            && self.mode() == other.mode()
+            && self.class() == other.class()
+            && self.raw() == other.raw()
+            && self.speculative() == other.speculative()
+            && self.truncated() == other.truncated()
+    }
+}
+
+impl Eq for Insn {}
+
+impl Hash for Insn {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ip().hash(state);
+        self.isid().hash(state);
+        self.mode().hash(state);
+        self.class().hash(state);
+        self.raw().hash(state);
+        self.speculative().hash(state);
+        self.truncated().hash(state);
+    }
+}
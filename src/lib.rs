@@ -1,3 +1,23 @@
+//! Rust bindings for libipt, Intel's reference implementation for decoding Intel PT.
+//!
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`.
+//! Reading traces and images from files, memory mapping and
+//! multithreaded decoding are only available with `std`.
+//!
+//! The `serde` feature implements `Serialize` and `Deserialize` for blocks,
+//! packets, address spaces and status flags, and `Serialize` for events,
+//! e.g. to store decode results for offline analysis.
+//! The `fuzzing` feature implements `arbitrary::Arbitrary` for packets and
+//! the settings of a `ConfigBuilder`, for structured fuzzing of the encoder and decoders.
+//! The `tracing` feature emits `tracing` spans for synchronizing, decoding and
+//! image updates, recording offsets, status flags and errors.
+//! The `evt` feature adds `EventDecoder` for the event decoder of libipt 2.1.
+//! It does not change which libipt is linked: libipt-sys has to be built
+//! against libipt 2.1 or newer, otherwise linking fails.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 /// The pt_config structure defines an Intel Processor Trace (Intel PT) encoder or decoder configuration.
 ///
 /// It is required for allocating a trace packet encoder (see pt_alloc_encoder(3)),
@@ -14,6 +34,9 @@ pub use config::*;
 pub mod error;
 pub use error::PtError;
 pub use error::PtErrorCode;
+pub use error::ErrorKind;
+pub use error::DecodeError;
+pub use error::UntilEos;
 
 /// This layer deals with Intel PT packet encoding and decoding.
 ///
@@ -35,11 +58,69 @@ pub mod block;
 /// The instruction flow layer provides a simple API for iterating over instructions in execution order.
 pub mod insn;
 
+/// Helpers for trace data recorded into a ring buffer.
+pub mod ring;
+
+/// Decoding of trace data that is still being recorded.
+pub mod stream;
+
+/// Decoding of traces that are split into several buffers.
+pub mod segmented;
+
+/// Traces captured with the Windows Intel PT driver (ipt.sys).
+pub mod winipt;
+
+/// Decoding of traces that span VM entries and exits.
+pub mod virt;
+
+/// Sideband files written by the simple-pt tracer.
+///
+/// Only available with the `std` feature.
+#[cfg(feature = "std")]
+pub mod simplept;
+
+/// Decoding of whole-system traces with an image per process.
+pub mod process;
+
+/// Symbolization of decoded addresses.
+///
+/// The DWARF based symbolizer is only available with the `addr2line` feature.
+pub mod symbolize;
+
+/// Decodes a trace on multiple threads by splitting it at its synchronization points.
+///
+/// Only available with the `parallel` feature.
+#[cfg(feature = "parallel")]
+pub mod parallel;
+
+/// Reading Intel PT traces and their setup from perf.data files.
+///
+/// Only available with the `perf` feature.
+#[cfg(feature = "perf")]
+pub mod perf;
+
+/// Collects Intel PT traces with the Linux perf_event_open(2) interface.
+///
+/// Only available with the `collect` feature on Linux.
+#[cfg(all(feature = "collect", target_os = "linux"))]
+pub mod collect;
+
 mod version;
-pub use version::Version;
+pub use version::{Capabilities, Version};
 mod image;
 pub use image::*;
 mod asid;
-pub use asid::Asid;
+pub use asid::{Asid, AsidBuilder};
+mod checkpoint;
+pub use checkpoint::{Checkpoint, CHECKPOINT_INTERVAL};
+mod budget;
+pub use budget::Budget;
+mod cancel;
+pub use cancel::CancelToken;
+mod progress;
+pub use progress::Progress;
+mod tracker;
+mod time;
+pub use time::{TimeCal, TimeConverter, TimeInfo, TimeQuality};
 mod flags;
 pub use flags::Status;
\ No newline at end of file
@@ -19,3 +19,4 @@ impl Cbr {
 
 wrap2raw!(Cbr, pt_packet_type_ppt_cbr, cbr);
 raw2wrap!(Cbr, Cbr, pt_packet_cbr);
+packet_fields!(Cbr { ratio: u8 });
@@ -5,7 +5,7 @@ macro_rules! wrap2raw {
             fn from(origin: $target) -> Self {
                 libipt_sys::pt_packet {
                     type_: $type,
-                    size: std::mem::size_of::<libipt_sys::pt_packet>() as u8,
+                    size: core::mem::size_of::<libipt_sys::pt_packet>() as u8,
                     payload: libipt_sys::pt_packet__bindgen_ty_1 { $payload: origin.0 }
                 }
             }
@@ -20,4 +20,54 @@ macro_rules! raw2wrap {
             fn into(self) -> $target { $target_fac(self) }
         }
     };
-}
\ No newline at end of file
+}
+// implements comparisons, hashing and the optional traits of the packet @target
+// from its fields. they are compared and serialized by the values of their getters
+// and created with the constructor of @target, which takes the fields in order.
+// a field can name its own getter if the packet's one doesn't fit.
+macro_rules! packet_fields {
+    ($target:ident { $($field:ident : $ty:ty = $get:expr),* }) => {
+        impl PartialEq for $target {
+            fn eq(&self, other: &Self) -> bool {
+                $(($get)(*self) == ($get)(*other))&&*
+            }
+        }
+
+        impl Eq for $target {}
+
+        impl core::hash::Hash for $target {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                $(core::hash::Hash::hash(&($get)(*self), state);)*
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        const _: () = {
+            #[derive(serde::Serialize, serde::Deserialize)]
+            struct Repr { $($field: $ty),* }
+
+            impl serde::Serialize for $target {
+                fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                    serde::Serialize::serialize(&Repr { $($field: ($get)(*self)),* }, s)
+                }
+            }
+
+            impl<'de> serde::Deserialize<'de> for $target {
+                fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                    let r = <Repr as serde::Deserialize>::deserialize(d)?;
+                    Ok($target::new($(r.$field),*))
+                }
+            }
+        };
+
+        #[cfg(feature = "fuzzing")]
+        impl<'a> arbitrary::Arbitrary<'a> for $target {
+            fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+                Ok($target::new($(u.arbitrary::<$ty>()?),*))
+            }
+        }
+    };
+    ($target:ident { $($field:ident : $ty:ty),* }) => {
+        packet_fields!($target { $($field: $ty = $target::$field),* });
+    };
+}
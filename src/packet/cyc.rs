@@ -19,3 +19,4 @@ impl Cyc {
 
 wrap2raw!(Cyc, pt_packet_type_ppt_cyc, cyc);
 raw2wrap!(Cyc, Cyc, pt_packet_cyc);
+packet_fields!(Cyc { value: u64 });
@@ -1,13 +1,17 @@
 use crate::error::{
-    PtError, PtErrorCode,
-    deref_ptresult, deref_ptresult_mut,
-    ensure_ptok
+    PtError, DecodeError, UntilEos,
+    deref_ptresult, ensure_ptr,
+    ensure_ptok, ensure_synced
 };
 use super::Packet;
-use crate::config::Config;
+use crate::config::{Config, Owned};
+use crate::progress::Progress;
+use crate::budget::{Budget, ScanLimits};
+use crate::cancel::CancelToken;
 
-use std::mem;
-use std::marker::PhantomData;
+use core::mem;
+use core::marker::PhantomData;
+use alloc::sync::Arc;
 
 use libipt_sys::{
     pt_packet_decoder,
@@ -27,6 +31,8 @@ use libipt_sys::{
 mod test {
     use super::*;
     use crate::config::ConfigBuilder;
+    use crate::error::{ErrorKind, PtErrorCode};
+    use crate::packet::{Encoder, Psb, Psbend};
 
     #[test]
     fn test_pktdec_alloc() {
@@ -47,23 +53,134 @@ mod test {
         ).unwrap();
         assert!(p.config().is_ok());
         assert!(p.offset().is_err());
+        assert!(p.position().is_err());
         assert!(p.sync_offset().is_err());
         assert!(p.next().is_err());
         assert!(p.sync_backward().is_err());
         assert!(p.sync_forward().is_err());
+        assert!(p.reset(None).is_ok());
+    }
+
+    #[test]
+    fn test_pktdec_cancel() {
+        let daturu = &mut [11; 11];
+        let mut p = PacketDecoder::new(
+            &ConfigBuilder::new(daturu).unwrap().finish()
+        ).unwrap();
+
+        let cancel = CancelToken::new();
+        p.set_cancel(Some(cancel.clone()));
+        cancel.cancel();
+        assert_eq!(p.sync_forward().unwrap_err().kind(), ErrorKind::Cancelled);
+        assert_eq!(p.sync_set(0).unwrap_err().kind(), ErrorKind::Cancelled);
+        p.set_cancel(None);
+
+        p.set_budget(Some(Budget::new().bytes(0)));
+        assert_eq!(p.sync_backward().unwrap_err().kind(), ErrorKind::BudgetExhausted);
+        p.set_budget(None);
+        assert_ne!(p.sync_backward().unwrap_err().kind(), ErrorKind::BudgetExhausted);
+    }
+
+    #[test]
+    fn test_pktdec_reset() {
+        let buf = &mut [0; 64];
+        let mut psbs = Vec::new();
+        let size = {
+            let mut cfg = ConfigBuilder::new(buf).unwrap().finish();
+            let mut enc = Encoder::new(&mut cfg).unwrap();
+            let mut size = 0;
+            for _ in 0..2 {
+                psbs.push(size as u64);
+                size += enc.next(Psb::new()).unwrap();
+                size += enc.next(Psbend::new()).unwrap();
+            }
+            size as usize
+        };
+
+        let cfg = ConfigBuilder::new(&mut buf[..size]).unwrap().finish();
+        let mut p = PacketDecoder::new(&cfg).unwrap();
+        p.sync_forward().unwrap();
+        assert!(matches!(p.next(), Ok(Packet::Psb(_))));
+
+        p.reset(None).unwrap();
+        assert_eq!(p.offset().unwrap_err().code(), PtErrorCode::Nosync);
+        assert_eq!(p.next().err().unwrap().code(), PtErrorCode::Nosync);
+        // synchronizes at the PSB at offset 0 instead of skipping it
+        p.sync_forward().unwrap();
+        assert_eq!(p.sync_offset().unwrap(), psbs[0]);
+        assert!(matches!(p.next(), Ok(Packet::Psb(_))));
+
+        p.reset(None).unwrap();
+        p.sync_backward().unwrap();
+        assert_eq!(p.sync_offset().unwrap(), psbs[1]);
     }
 }
 
-pub struct PacketDecoder<'a, T>(&'a mut pt_packet_decoder, PhantomData<T>);
+/// An Intel PT packet decoder
+///
+/// The decoder is Send and Sync if the payload type `T` of the decode callback is.
+// keeps an owned trace buffer or decode callback of the config alive,
+// limits the synchronization scans,
+// and whether the decoder was reset in place and not synchronized since
+pub struct PacketDecoder<'a, T>(
+    *mut pt_packet_decoder,
+    PhantomData<&'a mut T>,
+    Option<Arc<Owned>>,
+    ScanLimits,
+    bool,
+);
+
+// SAFETY: see `QueryDecoder`, the packet decoder only reads the trace buffer.
+unsafe impl<T: Send> Send for PacketDecoder<'_, T> {}
+unsafe impl<T: Sync> Sync for PacketDecoder<'_, T> {}
+
 impl<'a, T> PacketDecoder<'a, T> {
     /// Allocate an Intel PT packet decoder.
     ///
     /// The decoder will work on the buffer defined in @config,
     /// it shall contain raw trace data and remain valid for the lifetime of the decoder.
+    /// The trace buffer of @config is borrowed for @'a, the decoder can't outlive it.
     /// The decoder needs to be synchronized before it can be used.
-    pub fn new(cfg: &Config<T>) -> Result<Self, PtError> {
-        deref_ptresult_mut(unsafe { pt_pkt_alloc_decoder(cfg.0.as_ref()) })
-            .map(|d| PacketDecoder::<T>(d, PhantomData))
+    pub fn new(cfg: &Config<'a, T>) -> Result<Self, PtError> {
+        ensure_ptr(unsafe { pt_pkt_alloc_decoder(cfg.0.as_ref()) })
+            .map(|d| PacketDecoder::<T>(d, PhantomData, cfg.2.clone(), ScanLimits::default(), false))
+    }
+
+    /// Take ownership of the raw packet decoder @decoder.
+    ///
+    /// # Safety
+    /// @decoder must have been allocated with pt_pkt_alloc_decoder and must not be
+    /// used or freed by anyone else afterwards.
+    /// Its trace buffer and decode callback must stay valid for @'a,
+    /// the callback has to be of type `T`.
+    pub unsafe fn from_raw(decoder: *mut pt_packet_decoder) -> Self {
+        PacketDecoder(decoder, PhantomData, None, ScanLimits::default(), false)
+    }
+
+    /// A pointer to the raw packet decoder
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the decoder is.
+    pub fn as_ptr(&self) -> *const pt_packet_decoder {
+        self.0
+    }
+
+    /// A mutable pointer to the raw packet decoder
+    ///
+    /// See `as_ptr`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_packet_decoder {
+        self.0
+    }
+
+    /// Release the raw packet decoder.
+    ///
+    /// The caller has to free it with pt_pkt_free_decoder.
+    /// An owned trace buffer or decode callback of the config is leaked,
+    /// the raw decoder still uses it.
+    pub fn into_raw(self) -> *mut pt_packet_decoder {
+        let decoder = self.0;
+        mem::forget(self);
+        decoder
     }
 
     pub fn config(&self) -> Result<Config<T>, PtError> {
@@ -75,16 +192,30 @@ impl<'a, T> PacketDecoder<'a, T> {
     ///
     /// Returns Nosync if decoder is out of sync.
     pub fn offset(&self) -> Result<u64, PtError> {
+        ensure_synced(self.4)?;
         let mut off: u64 = 0;
         ensure_ptok(unsafe { pt_pkt_get_offset(self.0, &mut off) })
             .map(|_| off)
     }
 
+    /// Get the current decoder position within its trace buffer.
+    ///
+    /// Includes the size of the trace buffer and the number of bytes left,
+    /// see `Progress`.
+    /// Through a `&mut` reference `Iterator::position` takes precedence,
+    /// call this as `PacketDecoder::position(decoder)` there.
+    /// Returns Nosync if decoder is out of sync.
+    pub fn position(&self) -> Result<Progress, PtError> {
+        let size = self.config()?.buffer_len() as u64;
+        self.offset().map(|offset| Progress { offset, size })
+    }
+
     /// Get the position of the last synchronization point.
     ///
     /// This is useful when splitting a trace stream for parallel decoding.
     /// Returns Nosync if decoder is out of sync.
     pub fn sync_offset(&self) -> Result<u64, PtError> {
+        ensure_synced(self.4)?;
         let mut off: u64 = 0;
         ensure_ptok(unsafe { pt_pkt_get_sync_offset(self.0, &mut off) })
             .map(|_| off)
@@ -98,17 +229,28 @@ impl<'a, T> PacketDecoder<'a, T> {
     /// Returns BadPacket if an unknown packet payload is encountered.
     /// Returns Eos if decoder reached the end of the Intel PT buffer.
     /// Returns Nosync if decoder is out of sync.
-    pub fn next(&mut self) -> Result<Packet<T>, PtError> {
+    /// Errors carry the decoder position, see `DecodeError`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), err))]
+    pub fn next(&mut self) -> Result<Packet<T>, DecodeError> {
         let mut pkt: pt_packet = unsafe { mem::zeroed() };
-        ensure_ptok(unsafe {
+        ensure_synced(self.4).and_then(|_| ensure_ptok(unsafe {
             pt_pkt_next(self.0,
                         &mut pkt,
                         mem::size_of::<pt_packet>())
-        }).map(|_| pkt.into())
+        })).map(|_| pkt.into())
+            .map_err(|e| DecodeError::new(e, self.offset().ok(), self.sync_offset().ok()))
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), err))]
     pub fn sync_backward(&mut self) -> Result<(), PtError> {
-        ensure_ptok(unsafe { pt_pkt_sync_backward(self.0) })
+        if self.4 {
+            let offset = self.config()?.psb_offset(true)?;
+            return self.sync_set(offset);
+        }
+        let start = self.3.start(|| self.offset().unwrap_or(0))?;
+        let res = ensure_ptok(unsafe { pt_pkt_sync_backward(self.0) });
+        self.track_sync(&res, start);
+        res
     }
 
     /// Synchronize an Intel PT packet decoder.
@@ -118,28 +260,90 @@ impl<'a, T> PacketDecoder<'a, T> {
     /// at the beginning of the trace buffer in case of forward synchronization
     /// and at the end of the trace buffer in case of backward synchronization.
     /// Returns Eos if no further synchronization point is found.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), err))]
     pub fn sync_forward(&mut self) -> Result<(), PtError> {
-        ensure_ptok(unsafe { pt_pkt_sync_forward(self.0) })
+        if self.4 {
+            let offset = self.config()?.psb_offset(false)?;
+            return self.sync_set(offset);
+        }
+        let start = self.3.start(|| self.offset().unwrap_or(0))?;
+        let res = ensure_ptok(unsafe { pt_pkt_sync_forward(self.0) });
+        self.track_sync(&res, start);
+        res
     }
 
     /// Hard set synchronization point of an Intel PT decoder.
     ///
     /// Synchronize decoder to @offset within the trace buffer.
     /// Returns Eos if the given offset is behind the end of the trace buffer.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), err))]
     pub fn sync_set(&mut self, offset: u64) -> Result<(), PtError> {
-        ensure_ptok(unsafe { pt_pkt_sync_set(self.0, offset) })
+        let start = self.3.start(|| offset)?;
+        let res = ensure_ptok(unsafe { pt_pkt_sync_set(self.0, offset) });
+        self.track_sync(&res, start);
+        res
+    }
+
+    /// Limit the work done by the decoder.
+    ///
+    /// Replaces the current budget and resets the work done so far.
+    /// Only the bytes scanned by the synchronization functions are counted,
+    /// once the budget is used up, `sync_forward`, `sync_backward`
+    /// and `sync_set` return BudgetExhausted.
+    /// If @budget is None, the decoder is not limited.
+    pub fn set_budget(&mut self, budget: Option<Budget>) {
+        self.3.set_budget(budget);
+    }
+
+    /// Make the decoder cancellable.
+    ///
+    /// Once @cancel is cancelled, the synchronization functions
+    /// `sync_forward`, `sync_backward` and `sync_set` return Cancelled.
+    /// If @cancel is None, the decoder can't be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        self.3.set_cancel(cancel);
+    }
+
+    // after a successful synchronization, the decoder is no longer reset
+    // and the bytes it moved from @start are charged
+    fn track_sync(&mut self, res: &Result<(), PtError>, start: Option<u64>) {
+        if res.is_ok() {
+            self.4 = false;
+            let end = self.offset().ok();
+            self.3.charge(start, end);
+        }
+    }
+
+    /// Return the decoder to the unsynchronized state of a new decoder.
+    ///
+    /// Without @cfg, the decoder is reset in place without reallocating it.
+    /// Like a new decoder, it has to be synchronized before it can be used,
+    /// `sync_forward` starts at the beginning of the trace buffer
+    /// and `sync_backward` at its end. Until then, `next`, `offset`
+    /// and `sync_offset` return Nosync.
+    /// With @cfg, the underlying decoder is allocated anew for it,
+    /// the old one is only freed if that succeeded,
+    /// since libipt decoders can't switch to the trace buffer of another config.
+    /// The budget and the cancel token are kept, the work done under the budget is reset.
+    pub fn reset(&mut self, cfg: Option<&Config<'a, T>>) -> Result<(), PtError> {
+        if let Some(cfg) = cfg {
+            let inner = ensure_ptr(unsafe { pt_pkt_alloc_decoder(cfg.0.as_ref()) })?;
+            unsafe { pt_pkt_free_decoder(self.0) };
+            self.0 = inner;
+            self.2 = cfg.2.clone();
+        }
+        self.3.restart();
+        self.4 = cfg.is_none();
+        Ok(())
     }
 }
 
 impl<'a, T> Iterator for PacketDecoder<'a, T> {
-    type Item = Result<Packet<T>, PtError>;
+    type Item = Result<Packet<T>, DecodeError>;
 
-    fn next(&mut self) -> Option<Result<Packet<T>, PtError>> {
-        match self.next() {
-            // eos to stop iterating
-            Err(x) if x.code() == PtErrorCode::Eos => None,
-            x => Some(x)
-        }
+    fn next(&mut self) -> Option<Result<Packet<T>, DecodeError>> {
+        // eos to stop iterating
+        self.next().until_eos().transpose()
     }
 }
 
@@ -1,11 +1,12 @@
 use crate::error::{
     PtError, ensure_ptok,
     extract_pterr, deref_ptresult,
-    deref_ptresult_mut
+    ensure_ptr
 };
 use crate::config::Config;
 
-use std::marker::PhantomData;
+use core::marker::PhantomData;
+use core::mem;
 
 use libipt_sys::{
     pt_packet,
@@ -36,9 +37,8 @@ mod tests {
         let kek = &mut [1; 2];
         // this just checks memory safety for property access
         // usage can be found in the integration tests
-        let mut p = Encoder::new(
-            &mut ConfigBuilder::new(kek).unwrap().finish()
-        ).unwrap();
+        let mut cfg = ConfigBuilder::new(kek).unwrap().finish();
+        let mut p = Encoder::new(&mut cfg).unwrap();
 
         assert!(p.config().is_ok());
         assert_eq!(p.offset().unwrap(), 0);
@@ -47,17 +47,66 @@ mod tests {
     }
 }
 
-pub struct Encoder<'a, T>(&'a mut pt_encoder, PhantomData<T>);
+/// An Intel PT packet encoder
+///
+/// The encoder is Send and Sync if the payload type `T` of the decode callback is.
+pub struct Encoder<'a, T>(*mut pt_encoder, PhantomData<&'a mut T>);
+
+// SAFETY: the trace buffer is only written by methods taking `&mut self`,
+// methods taking `&self` only read the encoder state.
+// The encoder never calls the decode callback of its config.
+unsafe impl<T: Send> Send for Encoder<'_, T> {}
+unsafe impl<T: Sync> Sync for Encoder<'_, T> {}
+
 impl<'a, T> Encoder<'a, T> {
     /// Allocate an Intel PT packet encoder.
     ///
     /// The encoder will work on the buffer defined in @config, it shall contain raw trace data and remain valid for the lifetime of the encoder.
+    /// @config and its trace buffer are borrowed for @'a, the encoder can't outlive them.
+    /// This also keeps an owned trace buffer alive.
     /// The encoder starts at the beginning of the trace buffer.
-    pub fn new(cfg: &mut Config<T>) -> Result<Self, PtError> {
-        deref_ptresult_mut(unsafe { pt_alloc_encoder(cfg.0.to_mut()) })
+    pub fn new(cfg: &'a mut Config<'_, T>) -> Result<Self, PtError> {
+        ensure_ptr(unsafe { pt_alloc_encoder(cfg.0.to_mut()) })
             .map(|x| Encoder::<T>(x, PhantomData))
     }
 
+    /// Take ownership of the raw packet encoder @encoder.
+    ///
+    /// # Safety
+    /// @encoder must have been allocated with pt_alloc_encoder and must not be
+    /// used or freed by anyone else afterwards.
+    /// Its trace buffer and decode callback must stay valid for @'a,
+    /// the callback has to be of type `T`.
+    pub unsafe fn from_raw(encoder: *mut pt_encoder) -> Self {
+        Encoder(encoder, PhantomData)
+    }
+
+    /// A pointer to the raw packet encoder
+    ///
+    /// Use it to call libipt functions that are not wrapped by this crate.
+    /// The pointer is valid as long as the encoder is.
+    pub fn as_ptr(&self) -> *const pt_encoder {
+        self.0
+    }
+
+    /// A mutable pointer to the raw packet encoder
+    ///
+    /// See `as_ptr`.
+    pub fn as_mut_ptr(&mut self) -> *mut pt_encoder {
+        self.0
+    }
+
+    /// Release the raw packet encoder.
+    ///
+    /// The caller has to free it with pt_free_encoder.
+    /// The raw encoder still uses the trace buffer of the config,
+    /// it must not be used after the config is dropped.
+    pub fn into_raw(self) -> *mut pt_encoder {
+        let encoder = self.0;
+        mem::forget(self);
+        encoder
+    }
+
     pub fn config(&self) -> Result<Config<T>, PtError> {
         deref_ptresult(unsafe{pt_enc_get_config(self.0)})
             .map(Config::from)
@@ -33,4 +33,5 @@ impl Exstop {
 }
 
 wrap2raw!(Exstop, pt_packet_type_ppt_exstop, exstop);
-raw2wrap!(Exstop, Exstop, pt_packet_exstop);
\ No newline at end of file
+raw2wrap!(Exstop, Exstop, pt_packet_exstop);
+packet_fields!(Exstop { ip: bool });
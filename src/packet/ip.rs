@@ -1,4 +1,4 @@
-use std::convert::TryFrom;
+use core::convert::TryFrom;
 use num_enum::{TryFromPrimitive, IntoPrimitive};
 use libipt_sys::{
     pt_packet_ip,
@@ -15,7 +15,9 @@ use libipt_sys::{
 };
 
 /// The IP compression
-#[derive(Clone, Copy, Debug, TryFromPrimitive, IntoPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 #[repr(i32)]
 pub enum Compression {
     /// No payload. The IP has been suppressed
@@ -167,12 +169,16 @@ impl TipPgd {
 
 wrap2raw!(Fup, pt_packet_type_ppt_fup, ip);
 raw2wrap!(Fup, Fup, pt_packet_ip);
+packet_fields!(Fup { fup: u64, compression: Compression });
 
 wrap2raw!(Tip, pt_packet_type_ppt_tip, ip);
 raw2wrap!(Tip, Tip, pt_packet_ip);
+packet_fields!(Tip { tip: u64, compression: Compression });
 
 wrap2raw!(TipPge, pt_packet_type_ppt_tip_pge, ip);
 raw2wrap!(TipPge, TipPge, pt_packet_ip);
+packet_fields!(TipPge { tippge: u64, compression: Compression });
 
 wrap2raw!(TipPgd, pt_packet_type_ppt_tip_pgd, ip);
 raw2wrap!(TipPgd, TipPgd, pt_packet_ip);
+packet_fields!(TipPgd { tippgd: u64, compression: Compression });
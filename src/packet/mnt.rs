@@ -19,3 +19,4 @@ impl Mnt {
 
 wrap2raw!(Mnt, pt_packet_type_ppt_mnt, mnt);
 raw2wrap!(Mnt, Mnt, pt_packet_mnt);
+packet_fields!(Mnt { payload: u64 });
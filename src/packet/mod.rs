@@ -1,4 +1,4 @@
-use std::fmt::{Debug, Formatter};
+use core::fmt::{Debug, Formatter};
 
 use libipt_sys::{
     pt_packet,
@@ -99,7 +99,7 @@ mod test {
         let p1 = pt_packet_mnt { payload: 666 };
         let p2 = pt_packet {
             type_: PT_PACKET_TYPE_PPT_MNT,
-            size: std::mem::size_of::<pt_packet_mnt>() as u8,
+            size: core::mem::size_of::<pt_packet_mnt>() as u8,
             payload: pt_packet__bindgen_ty_1 { mnt: p1 }
         };
         let p3: Packet::<()> = p2.into();
@@ -108,8 +108,56 @@ mod test {
             _ => unreachable!()
         };
     }
+
+    #[test]
+    fn test_pkt_eq() {
+        let tip = Packet::<()>::Tip(Tip::new(0x1000, Compression::Full));
+        assert_eq!(tip, Packet::Tip(Tip::new(0x1000, Compression::Full)));
+        assert_ne!(tip, Packet::Tip(Tip::new(0x1000, Compression::Sext48)));
+        assert_ne!(tip, Packet::Fup(Fup::new(0x1000, Compression::Full)));
+
+        let pkts = [tip.clone(), tip, Packet::Pad(Pad::new())];
+        assert_eq!(pkts.into_iter().collect::<std::collections::HashSet<_>>().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "fuzzing")]
+    fn test_pkt_arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let data = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 3];
+        let a = Tip::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        let b = Tip::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        assert_eq!(a.tip(), b.tip());
+        assert_eq!(i32::from(a.compression()), i32::from(b.compression()));
+
+        // running out of data still gives a packet
+        assert!(Pwrx::arbitrary(&mut Unstructured::new(&[])).is_ok());
+        assert!(Mode::arbitrary(&mut Unstructured::new(&[])).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_pkt_serde() {
+        let json = serde_json::to_string(&Packet::<()>::Tnt64(Tnt64::new(0x1234, 13))).unwrap();
+        assert_eq!(json, r#"{"Tnt64":{"payload":4660,"bitsize":13}}"#);
+        match serde_json::from_str::<Packet<()>>(&json).unwrap() {
+            Packet::Tnt64(t) => assert_eq!(t.bitsize(), 13),
+            _ => unreachable!()
+        };
+
+        let json = serde_json::to_string(&Packet::<()>::Tip(Tip::new(0x1000, Compression::Full))).unwrap();
+        assert_eq!(json, r#"{"Tip":{"tip":4096,"compression":"Full"}}"#);
+        assert!(serde_json::to_string(&Packet::Unknown(Unknown::new(1))).is_err());
+    }
 }
 
+/// A decoded Intel PT packet
+///
+/// With the `serde` feature, packets can be serialized and deserialized
+/// except for `Unknown` packets, whose data is private to the decode callback.
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Packet<T> {
     Invalid(invalid::Invalid),
     Psbend(psbend::Psbend),
@@ -117,6 +165,7 @@ pub enum Packet<T> {
     Pad(pad::Pad),
     Psb(psb::Psb),
     Ovf(ovf::Ovf),
+    #[cfg_attr(feature = "serde", serde(skip))]
     Unknown(unknown::Unknown<T>),
 
     Fup(ip::Fup),
@@ -142,7 +191,7 @@ pub enum Packet<T> {
 }
 
 impl<T> Debug for Packet<T> {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
         match self {
             Self::Invalid(pack) => f.write_fmt(format_args!("Invalid({:?})", pack)),
             Self::Psbend(pack) => f.write_fmt(format_args!("Psbend({:?})", pack)),
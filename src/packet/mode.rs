@@ -4,11 +4,13 @@ use libipt_sys::{
     pt_mode_leaf_pt_mol_tsx as PT_MODE_LEAF_PT_MOL_TSX, pt_packet_mode,
     pt_packet_mode__bindgen_ty_1, pt_packet_mode_exec, pt_packet_mode_tsx, pt_packet_type_ppt_mode,
 };
-use std::fmt::{Debug, Formatter};
+use core::fmt::{Debug, Formatter};
 
 bitflags! {
     /// A mode.exec packet
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
     pub struct Exec : u32 {
         /// The mode.exec csl bit
         const CSL = 0b00000001;
@@ -19,7 +21,9 @@ bitflags! {
 
 bitflags! {
     /// A mode.tsx packet
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
     pub struct Tsx : u32 {
         /// The mode.tsx intx bit
         const INTX = 0b00000001;
@@ -50,7 +54,9 @@ impl Into<pt_packet_mode__bindgen_ty_1> for Tsx {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub enum Payload {
     /// A mode.exec packet.
     Exec(Exec),
@@ -106,10 +112,11 @@ impl Mode {
 }
 
 impl Debug for Mode {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
         f.write_fmt(format_args!("Mode({{ leaf: {:?} }})", self.0.leaf))
     }
 }
 
 wrap2raw!(Mode, pt_packet_type_ppt_mode, mode);
 raw2wrap!(Mode, Mode, pt_packet_mode);
+packet_fields!(Mode { payload: Payload });
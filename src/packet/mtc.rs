@@ -19,3 +19,4 @@ impl Mtc {
 
 wrap2raw!(Mtc, pt_packet_type_ppt_mtc, mtc);
 raw2wrap!(Mtc, Mtc, pt_packet_mtc);
+packet_fields!(Mtc { ctc: u8 });
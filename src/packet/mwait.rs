@@ -28,4 +28,5 @@ impl Mwait {
 }
 
 wrap2raw!(Mwait, pt_packet_type_ppt_mwait, mwait);
-raw2wrap!(Mwait, Mwait, pt_packet_mwait);
\ No newline at end of file
+raw2wrap!(Mwait, Mwait, pt_packet_mwait);
+packet_fields!(Mwait { ext: u32, hints: u32 });
@@ -35,3 +35,4 @@ impl Pip {
 
 wrap2raw!(Pip, pt_packet_type_ppt_pip, pip);
 raw2wrap!(Pip, Pip, pt_packet_pip);
+packet_fields!(Pip { cr3: u64, nr: bool });
@@ -1,7 +1,9 @@
-use std::mem;
+use core::mem;
 use libipt_sys::{pt_packet, pt_packet_type_ppt_psbend};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct Psbend {}
 
 impl Psbend {
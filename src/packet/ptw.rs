@@ -47,3 +47,4 @@ impl Ptw {
 
 wrap2raw!(Ptw, pt_packet_type_ppt_ptw, ptw);
 raw2wrap!(Ptw, Ptw, pt_packet_ptw);
+packet_fields!(Ptw { payload: u64, plc: u8, ip: bool });
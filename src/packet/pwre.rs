@@ -44,4 +44,5 @@ impl Pwre {
 }
 
 wrap2raw!(Pwre, pt_packet_type_ppt_pwre, pwre);
-raw2wrap!(Pwre, Pwre, pt_packet_pwre);
\ No newline at end of file
+raw2wrap!(Pwre, Pwre, pt_packet_pwre);
+packet_fields!(Pwre { state: u8, substate: u8, hw: bool });
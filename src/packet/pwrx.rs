@@ -77,3 +77,4 @@ impl Pwrx {
 
 wrap2raw!(Pwrx, pt_packet_type_ppt_pwrx, pwrx);
 raw2wrap!(Pwrx, Pwrx, pt_packet_pwrx);
+packet_fields!(Pwrx { last: u8, deepest: u8, interrupt: bool, store: bool, autonomous: bool });
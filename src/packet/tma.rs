@@ -27,3 +27,4 @@ impl Tma {
 
 wrap2raw!(Tma, pt_packet_type_ppt_tma, tma);
 raw2wrap!(Tma, Tma, pt_packet_tma);
+packet_fields!(Tma { ctc: u16, fc: u16 });
@@ -58,6 +58,9 @@ impl Tnt64 {
 
 wrap2raw!(Tnt8, pt_packet_type_ppt_tnt_8, tnt);
 raw2wrap!(Tnt8, Tnt8, pt_packet_tnt);
+packet_fields!(Tnt8 { payload: u8, bitsize: u8 });
 
 wrap2raw!(Tnt64, pt_packet_type_ppt_tnt_64, tnt);
 raw2wrap!(Tnt64, Tnt64, pt_packet_tnt);
+// `Tnt64::payload` only returns the low byte
+packet_fields!(Tnt64 { payload: u64 = |p: Tnt64| p.0.payload, bitsize: u8 = Tnt64::bitsize });
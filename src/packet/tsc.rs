@@ -19,3 +19,4 @@ impl Tsc {
 
 wrap2raw!(Tsc, pt_packet_type_ppt_tsc, tsc);
 raw2wrap!(Tsc, Tsc, pt_packet_tsc);
+packet_fields!(Tsc { tsc: u64 });
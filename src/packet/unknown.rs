@@ -1,7 +1,9 @@
 use libipt_sys::pt_packet_unknown;
+use alloc::boxed::Box;
 
 /// An unknown packet decodable by the optional decoder callback.
 /// Packet: unknown
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Unknown<T> (pub(crate) Option<Box<T>>);
 impl<T> Unknown<T> {
     // Create new instance of Unknown, putting `data` in a box
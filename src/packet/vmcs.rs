@@ -18,4 +18,5 @@ impl Vmcs {
 }
 
 wrap2raw!(Vmcs, pt_packet_type_ppt_vmcs, vmcs);
-raw2wrap!(Vmcs, Vmcs, pt_packet_vmcs);
\ No newline at end of file
+raw2wrap!(Vmcs, Vmcs, pt_packet_vmcs);
+packet_fields!(Vmcs { base: u64 });
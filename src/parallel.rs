@@ -0,0 +1,274 @@
+use crate::block::BlockDecoder;
+use crate::cancel::CancelToken;
+use crate::config::Config;
+use crate::error::{PtError, PtErrorCode};
+use crate::image::ImageFactory;
+use crate::packet::PacketDecoder;
+
+use rayon::prelude::*;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigBuilder;
+    use crate::packet::{self, Compression, Encoder, Exec, Fup, Mode, Psb, Psbend, Tnt64};
+
+    #[test]
+    fn test_segments_from_offsets() {
+        let segs = segments_from(&[0, 10, 25], 30);
+        assert_eq!(
+            segs,
+            vec![
+                Segment { offset: 0, len: 10 },
+                Segment { offset: 10, len: 15 },
+                Segment { offset: 25, len: 5 },
+            ]
+        );
+        assert!(segments_from(&[], 30).is_empty());
+    }
+
+    #[test]
+    fn test_pardec_no_sync() {
+        let kek = &mut [0; 16];
+        let cfg = ConfigBuilder::new(kek).unwrap().finish();
+        let dec = ParallelDecoder::new(&cfg, None).unwrap();
+        assert!(dec.segments().is_empty());
+        assert!(dec.run(|_, _| ()).unwrap().is_empty());
+    }
+
+    // decodes the blocks of @dec, with the offset after each one
+    fn blocks(dec: &mut BlockDecoder<()>) -> Vec<(u64, u64)> {
+        let mut blocks = Vec::new();
+        dec.sync_forward().unwrap();
+        loop {
+            dec.drain_events().unwrap();
+            match dec.next() {
+                Ok((block, _)) => blocks.push((dec.offset().unwrap(), block.ip())),
+                Err(e) if e.is_eos() => return blocks,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_pardec_matches_sequential() {
+        // jz 0x1000
+        let path = std::env::temp_dir().join(format!("libipt-pardec-{}", std::process::id()));
+        std::fs::write(&path, [0x74, 0xfe]).unwrap();
+        let factory = ImageFactory::new(None).unwrap();
+        factory.add_file(path.to_str().unwrap(), 0, 2, None, 0x1000).unwrap();
+
+        // segments with a different number of loop iterations each
+        let buf = &mut [0; 256];
+        let size = {
+            let mut cfg = ConfigBuilder::new(buf).unwrap().finish();
+            let mut enc = Encoder::new(&mut cfg).unwrap();
+            let mut size = 0;
+            for bits in [10, 20, 30] {
+                size += enc.next(Psb::new()).unwrap();
+                size += enc.next(Mode::new(packet::Payload::Exec(Exec::CSL))).unwrap();
+                size += enc.next(Fup::new(0x1000, Compression::Sext48)).unwrap();
+                size += enc.next(Psbend::new()).unwrap();
+                size += enc.next(Tnt64::new((1 << bits) - 1, bits)).unwrap();
+            }
+            size as usize
+        };
+        let cfg = ConfigBuilder::new(&mut buf[..size]).unwrap().finish();
+
+        let par = ParallelDecoder::new(&cfg, Some(&factory)).unwrap();
+        assert_eq!(par.segments().len(), 3);
+        let parallel = par
+            .run(|seg, dec| {
+                blocks(dec)
+                    .into_iter()
+                    .map(|(off, ip)| (seg.offset + off, ip))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap()
+            .concat();
+
+        let mut img = factory.instance().unwrap();
+        let mut dec = BlockDecoder::new(&cfg).unwrap();
+        dec.set_image(Some(&mut img)).unwrap();
+        let sequential = blocks(&mut dec);
+        std::fs::remove_file(&path).unwrap();
+
+        // the segment of each block, by the offset after it
+        let segment = |off: u64| par.segments().iter().rposition(|s| s.offset < off).unwrap();
+        let by_segment = |blocks: &[(u64, u64)]| {
+            blocks.iter().map(|&(off, ip)| (segment(off), ip)).collect::<Vec<_>>()
+        };
+        assert_eq!(by_segment(&parallel), by_segment(&sequential));
+        let per_segment = (0..3)
+            .map(|i| parallel.iter().filter(|&&(off, _)| segment(off) == i).count())
+            .collect::<Vec<_>>();
+        assert_eq!(per_segment, [10, 20, 30]);
+    }
+}
+
+/// A part of the trace buffer which starts at a synchronization point
+/// and ends right before the next one (or at the end of the buffer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// The offset of the segment's PSB packet in the original trace buffer
+    pub offset: u64,
+    /// The size of the segment in bytes
+    pub len: u64,
+}
+
+/// Finds the offsets of all synchronization points in @cfg's trace buffer.
+///
+/// Returns an empty vector if the trace does not contain any PSB packet.
+pub fn sync_points<T>(cfg: &Config<T>) -> Result<Vec<u64>, PtError> {
+    scan_sync_points(cfg, None)
+}
+
+/// Finds the offsets of all synchronization points in @cfg's trace buffer
+/// unless @cancel is cancelled.
+///
+/// See `sync_points`.
+/// Returns Cancelled if @cancel has been cancelled before the scan finished.
+pub fn sync_points_cancellable<T>(
+    cfg: &Config<T>,
+    cancel: &CancelToken,
+) -> Result<Vec<u64>, PtError> {
+    scan_sync_points(cfg, Some(cancel))
+}
+
+fn scan_sync_points<T>(cfg: &Config<T>, cancel: Option<&CancelToken>) -> Result<Vec<u64>, PtError> {
+    let mut dec = PacketDecoder::new(cfg)?;
+    let mut offsets = Vec::new();
+    loop {
+        if let Some(c) = cancel {
+            c.check()?;
+        }
+        match dec.sync_forward() {
+            Ok(()) => offsets.push(dec.sync_offset()?),
+            Err(e) if e.is_eos() => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(offsets)
+}
+
+fn segments_from(offsets: &[u64], size: u64) -> Vec<Segment> {
+    offsets
+        .iter()
+        .enumerate()
+        .map(|(i, &offset)| {
+            let end = offsets.get(i + 1).copied().unwrap_or(size);
+            Segment {
+                offset,
+                len: end - offset,
+            }
+        })
+        .collect()
+}
+
+/// Decodes a trace on multiple threads.
+///
+/// The trace is split at its synchronization points.
+/// Every segment is decoded by its own block decoder on the rayon thread pool
+/// and the per-segment results are returned in trace order.
+///
+/// Each decoder gets its own image from an `ImageFactory`,
+/// the images share the factory's sections and with them the mapped files.
+pub struct ParallelDecoder<'a, 'b> {
+    cfg: &'b Config<'a, ()>,
+    image: Option<&'b ImageFactory>,
+    segments: Vec<Segment>,
+    cancel: Option<CancelToken>,
+}
+
+impl<'a, 'b> ParallelDecoder<'a, 'b> {
+    /// Prepares a parallel decoder for the trace in @cfg.
+    ///
+    /// The decoders will read memory from images created by @image.
+    /// Returns Invalid if @cfg has a decode callback,
+    /// since it can't be shared between threads.
+    pub fn new(cfg: &'b Config<'a, ()>, image: Option<&'b ImageFactory>) -> Result<Self, PtError> {
+        Self::prepare(cfg, image, None)
+    }
+
+    /// Prepares a parallel decoder that can be cancelled with @cancel.
+    ///
+    /// See `new`.
+    /// Splitting the trace as well as `run` stop once @cancel is cancelled,
+    /// the decoders passed to the closure return Cancelled from `next` and the synchronization functions.
+    /// Returns Cancelled if @cancel has been cancelled before the trace was split.
+    pub fn with_cancel(
+        cfg: &'b Config<'a, ()>,
+        image: Option<&'b ImageFactory>,
+        cancel: CancelToken,
+    ) -> Result<Self, PtError> {
+        Self::prepare(cfg, image, Some(cancel))
+    }
+
+    fn prepare(
+        cfg: &'b Config<'a, ()>,
+        image: Option<&'b ImageFactory>,
+        cancel: Option<CancelToken>,
+    ) -> Result<Self, PtError> {
+        if cfg.0.decode.callback.is_some() {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "decode callbacks are not supported for parallel decoding",
+            ));
+        }
+
+        let size = cfg.buffer_len() as u64;
+        let segments = segments_from(&scan_sync_points(cfg, cancel.as_ref())?, size);
+        Ok(ParallelDecoder {
+            cfg,
+            image,
+            segments,
+            cancel,
+        })
+    }
+
+    /// The segments the trace has been split into, in trace order.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Decodes all segments in parallel.
+    ///
+    /// @f is called once per segment with a block decoder that only sees
+    /// the bytes of that segment. The decoder is not synchronized yet,
+    /// its buffer begins with the segment's PSB packet.
+    /// Returns the results of @f in trace order.
+    /// Returns an error if a decoder or an image can't be allocated.
+    /// Returns Cancelled if the decode has been cancelled, see `with_cancel`.
+    pub fn run<R, F>(&self, f: F) -> Result<Vec<R>, PtError>
+    where
+        F: Fn(Segment, &mut BlockDecoder<()>) -> R + Sync,
+        R: Send,
+    {
+        let offsets: Vec<u64> = self.segments.iter().map(|s| s.offset).collect();
+        let jobs: Vec<_> = self
+            .segments
+            .iter()
+            .copied()
+            .zip(self.cfg.split_at_sync_points(&offsets)?)
+            .collect();
+
+        // the config is not Sync, the workers must not capture self
+        let (image, cancel) = (self.image, &self.cancel);
+        jobs.into_par_iter()
+            .map(|(segment, cfg)| {
+                // segments that have not been started yet are skipped
+                if let Some(c) = cancel {
+                    c.check()?;
+                }
+                let mut img = image.map(ImageFactory::instance).transpose()?;
+                let mut dec = BlockDecoder::new(&cfg)?;
+                dec.set_cancel(cancel.clone());
+                if let Some(img) = img.as_mut() {
+                    dec.set_image(Some(img))?;
+                }
+                Ok(f(segment, &mut dec))
+            })
+            .collect()
+    }
+}
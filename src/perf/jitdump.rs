@@ -0,0 +1,210 @@
+use super::record::{read_str, read_u32, read_u64};
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+use crate::image::Image;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER_SIZE: usize = 40;
+
+    fn record(raw: &mut Vec<u8>, id: u32, timestamp: u64, body: &[u8]) {
+        raw.extend_from_slice(&id.to_le_bytes());
+        raw.extend_from_slice(&(body.len() as u32 + 16).to_le_bytes());
+        raw.extend_from_slice(&timestamp.to_le_bytes());
+        raw.extend_from_slice(body);
+    }
+
+    fn jitdump() -> Vec<u8> {
+        let mut raw = Vec::new();
+        for v in [MAGIC, 1, HEADER_SIZE as u32, 62, 0, 42] {
+            raw.extend_from_slice(&v.to_le_bytes());
+        }
+        raw.extend_from_slice(&[0; 16]);
+
+        let mut load = Vec::new();
+        load.extend_from_slice(&42u32.to_le_bytes());
+        load.extend_from_slice(&43u32.to_le_bytes());
+        for v in [0x7f00_0000_1000u64, 0x7f00_0000_1000, 3, 7] {
+            load.extend_from_slice(&v.to_le_bytes());
+        }
+        load.extend_from_slice(b"foo\0\x90\x90\xc3");
+        record(&mut raw, JIT_CODE_LOAD, 100, &load);
+
+        // debug info is skipped
+        record(&mut raw, 2, 101, &[0; 8]);
+
+        let mut mv = Vec::new();
+        mv.extend_from_slice(&42u32.to_le_bytes());
+        mv.extend_from_slice(&43u32.to_le_bytes());
+        for v in [
+            0x7f00_0000_2000u64,
+            0x7f00_0000_1000,
+            0x7f00_0000_2000,
+            3,
+            7,
+        ] {
+            mv.extend_from_slice(&v.to_le_bytes());
+        }
+        record(&mut raw, JIT_CODE_MOVE, 102, &mv);
+        raw
+    }
+
+    #[test]
+    fn test_jitdump_parse() {
+        let raw = jitdump();
+        let code = parse(&raw).unwrap();
+        assert_eq!(code.len(), 2);
+        assert_eq!(code[0].timestamp, 100);
+        assert_eq!(code[0].pid, 42);
+        assert_eq!(code[0].tid, 43);
+        assert_eq!(code[0].addr, 0x7f00_0000_1000);
+        assert_eq!(code[0].size, 3);
+        assert_eq!(code[0].index, 7);
+        assert_eq!(code[0].name, "foo");
+        let off = code[0].offset as usize;
+        assert_eq!(&raw[off..off + 3], b"\x90\x90\xc3");
+
+        // the moved code is still read from the load record
+        assert_eq!(code[1].timestamp, 102);
+        assert_eq!(code[1].addr, 0x7f00_0000_2000);
+        assert_eq!(code[1].offset, code[0].offset);
+        assert_eq!(code[1].name, "foo");
+    }
+
+    #[test]
+    fn test_jitdump_bad_magic() {
+        let mut raw = jitdump();
+        raw[0] = 0;
+        assert_eq!(parse(&raw).unwrap_err().code(), PtErrorCode::BadFile);
+    }
+}
+
+// 'JiTD' in little endian
+const MAGIC: u32 = 0x4a69_5444;
+
+const JIT_CODE_LOAD: u32 = 0;
+const JIT_CODE_MOVE: u32 = 1;
+const JIT_CODE_CLOSE: u32 = 3;
+
+/// A region of JIT compiled code from a jitdump file
+///
+/// The code bytes are stored in the jitdump file at @offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitCode {
+    /// The time the code was loaded or moved, in the clock of the jitdump
+    pub timestamp: u64,
+    pub pid: u32,
+    pub tid: u32,
+    /// The address the code is located at
+    pub addr: u64,
+    pub size: u64,
+    /// The unique index the runtime assigned to the code
+    pub index: u64,
+    /// The name of the compiled function
+    pub name: String,
+    /// The offset of the code bytes in the jitdump file
+    pub offset: u64,
+}
+
+/// Parses the content of a jitdump file as written by JIT runtimes for perf.
+///
+/// Returns the code load and move records in the order they were written.
+/// A moved region refers to the code bytes of its load record.
+/// Returns BadFile if @data is not a little endian jitdump file.
+pub fn parse(data: &[u8]) -> Result<Vec<JitCode>, PtError> {
+    if read_u32(data, 0)? != MAGIC {
+        return Err(PtError::new(PtErrorCode::BadFile, "not a jitdump file"));
+    }
+
+    let mut code = Vec::new();
+    let mut loads: HashMap<u64, usize> = HashMap::new();
+    let mut off = read_u32(data, 8)? as usize;
+    while off + 16 <= data.len() {
+        let id = read_u32(data, off)?;
+        let size = read_u32(data, off + 4)? as usize;
+        let timestamp = read_u64(data, off + 8)?;
+        if size < 16 {
+            return Err(PtError::new(PtErrorCode::BadFile, "invalid jitdump record"));
+        }
+        let body = off + 16;
+
+        match id {
+            JIT_CODE_LOAD => {
+                let name = read_str(data, body + 40)?;
+                let size = read_u64(data, body + 24)?;
+                let offset = (body + 40 + name.len() + 1) as u64;
+                if offset + size > data.len() as u64 {
+                    return Err(PtError::new(PtErrorCode::BadFile, "jitdump is truncated"));
+                }
+                let index = read_u64(data, body + 32)?;
+                loads.insert(index, code.len());
+                code.push(JitCode {
+                    timestamp,
+                    pid: read_u32(data, body)?,
+                    tid: read_u32(data, body + 4)?,
+                    addr: read_u64(data, body + 16)?,
+                    size,
+                    index,
+                    name: name.to_string(),
+                    offset,
+                });
+            }
+            JIT_CODE_MOVE => {
+                let index = read_u64(data, body + 40)?;
+                // without the load record there are no code bytes
+                if let Some(&load) = loads.get(&index) {
+                    let moved = JitCode {
+                        timestamp,
+                        pid: read_u32(data, body)?,
+                        tid: read_u32(data, body + 4)?,
+                        addr: read_u64(data, body + 24)?,
+                        ..code[load].clone()
+                    };
+                    code.push(moved);
+                }
+            }
+            JIT_CODE_CLOSE => break,
+            _ => (),
+        }
+        off += size;
+    }
+
+    Ok(code)
+}
+
+/// Reads and parses the jitdump file at @path.
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<JitCode>, PtError> {
+    let data =
+        fs::read(path).map_err(|_| PtError::new(PtErrorCode::BadFile, "could not read jitdump"))?;
+    parse(&data)
+}
+
+/// Adds the JIT compiled code of @code to @image.
+///
+/// The code bytes are read from the jitdump file at @path,
+/// which @code must have been parsed from.
+/// Regions are added in order, so code that replaced other code at
+/// the same address takes precedence.
+/// Only regions up to the time @until are used, if given.
+/// Returns the number of added regions.
+pub fn load(
+    path: &str,
+    code: &[JitCode],
+    image: &mut Image,
+    asid: Option<Asid>,
+    until: Option<u64>,
+) -> Result<u32, PtError> {
+    let mut added = 0;
+    let until = until.unwrap_or(u64::MAX);
+    for c in code.iter().filter(|c| c.timestamp <= until && c.size > 0) {
+        image.add_file(path, c.offset, c.size, asid, c.addr)?;
+        added += 1;
+    }
+    Ok(added)
+}
@@ -0,0 +1,703 @@
+use crate::config::{Config, ConfigBuilder, Cpu, CpuVendor, Frequency};
+use crate::error::{PtError, PtErrorCode};
+use crate::TimeConverter;
+
+use alloc::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+mod record;
+pub use record::*;
+mod sideband;
+pub use sideband::*;
+
+/// JIT compiled code from the jitdump files of JIT runtimes.
+pub mod jitdump;
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    const ATTR_SIZE: usize = 112;
+
+    /// Assembles a minimal perf.data file for tests
+    #[derive(Default)]
+    pub(crate) struct PerfWriter {
+        pub(crate) sample_type: u64,
+        pub(crate) pt_config: u64,
+        pub(crate) cpuid: Option<&'static str>,
+        pub(crate) records: Vec<u8>,
+        /// The sample ids of the intel_pt event
+        pub(crate) ids: Vec<u64>,
+        /// More software events with their sample type and ids
+        pub(crate) others: Vec<(u64, Vec<u64>)>,
+    }
+
+    impl PerfWriter {
+        pub(crate) fn record(&mut self, kind: u32, misc: u16, body: &[u8]) -> &mut Self {
+            self.records.extend_from_slice(&kind.to_le_bytes());
+            self.records.extend_from_slice(&misc.to_le_bytes());
+            self.records
+                .extend_from_slice(&(body.len() as u16 + 8).to_le_bytes());
+            self.records.extend_from_slice(body);
+            self
+        }
+
+        pub(crate) fn auxtrace(&mut self, idx: u32, cpu: u32, data: &[u8]) -> &mut Self {
+            let mut body = Vec::new();
+            body.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            body.extend_from_slice(&0u64.to_le_bytes());
+            body.extend_from_slice(&0u64.to_le_bytes());
+            body.extend_from_slice(&idx.to_le_bytes());
+            body.extend_from_slice(&u32::MAX.to_le_bytes());
+            body.extend_from_slice(&cpu.to_le_bytes());
+            body.extend_from_slice(&0u32.to_le_bytes());
+            self.record(RECORD_AUXTRACE, 0, &body);
+            self.records.extend_from_slice(data);
+            self
+        }
+
+        pub(crate) fn auxtrace_info(&mut self, private: &[u64]) -> &mut Self {
+            let mut body = Vec::new();
+            body.extend_from_slice(&1u32.to_le_bytes());
+            body.extend_from_slice(&0u32.to_le_bytes());
+            for p in private {
+                body.extend_from_slice(&p.to_le_bytes());
+            }
+            self.record(RECORD_AUXTRACE_INFO, 0, &body)
+        }
+
+        pub(crate) fn finish(&self) -> Vec<u8> {
+            let mut attrs = vec![(8u32, self.pt_config, self.sample_type, &self.ids)];
+            attrs.extend(self.others.iter().map(|(st, ids)| (1, 0, *st, ids)));
+
+            let attr_size = ATTR_SIZE + 16;
+            let attrs_off = HEADER_SIZE;
+            let ids_off = attrs_off + attrs.len() * attr_size;
+            let ids_size = attrs.iter().map(|a| a.3.len() * 8).sum::<usize>();
+            let data_off = ids_off + ids_size;
+            let data_size = self.records.len();
+
+            let mut out = Vec::new();
+            out.extend_from_slice(&PERF_MAGIC.to_le_bytes());
+            for v in [
+                HEADER_SIZE as u64,
+                attr_size as u64,
+                attrs_off as u64,
+                (attrs.len() * attr_size) as u64,
+                data_off as u64,
+                data_size as u64,
+                0,
+                0,
+            ] {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            let features = if self.cpuid.is_some() { 1u64 << HEADER_CPUID } else { 0 };
+            for v in [features, 0, 0, 0] {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+
+            // the intel_pt attr with pmu type 8 and the software ones
+            let mut off = ids_off;
+            for (kind, config, sample_type, ids) in &attrs {
+                let mut attr = vec![0u8; ATTR_SIZE];
+                attr[0..4].copy_from_slice(&kind.to_le_bytes());
+                attr[4..8].copy_from_slice(&(ATTR_SIZE as u32).to_le_bytes());
+                attr[8..16].copy_from_slice(&config.to_le_bytes());
+                attr[24..32].copy_from_slice(&sample_type.to_le_bytes());
+                attr[40..48].copy_from_slice(&ATTR_SAMPLE_ID_ALL.to_le_bytes());
+                out.extend_from_slice(&attr);
+                out.extend_from_slice(&(off as u64).to_le_bytes());
+                out.extend_from_slice(&(ids.len() as u64 * 8).to_le_bytes());
+                off += ids.len() * 8;
+            }
+            for id in attrs.iter().flat_map(|a| a.3.iter()) {
+                out.extend_from_slice(&id.to_le_bytes());
+            }
+
+            out.extend_from_slice(&self.records);
+
+            if let Some(cpuid) = self.cpuid {
+                let sec = out.len() + 16;
+                let len = (cpuid.len() + 1 + 7) & !7;
+                out.extend_from_slice(&(sec as u64).to_le_bytes());
+                out.extend_from_slice(&(len as u64 + 4).to_le_bytes());
+                out.extend_from_slice(&(len as u32).to_le_bytes());
+                out.extend_from_slice(cpuid.as_bytes());
+                out.resize(out.len() + len - cpuid.len(), 0);
+            }
+            out
+        }
+    }
+
+    #[test]
+    fn test_perf_bad_magic() {
+        let mut raw = PerfWriter::default().finish();
+        raw[0] = b'X';
+        assert_eq!(
+            PerfData::parse(raw).err().unwrap().code(),
+            PtErrorCode::BadFile
+        );
+        assert!(PerfData::parse(vec![0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_perf_bad_header() {
+        let raw = PerfWriter::default().finish();
+        let bad = |off: usize, v: u64| {
+            let mut raw = raw.clone();
+            raw[off..off + 8].copy_from_slice(&v.to_le_bytes());
+            PerfData::parse(raw).err().unwrap().code()
+        };
+        // attrs and data sections that end beyond the address space
+        assert_eq!(bad(24, u64::MAX), PtErrorCode::BadFile);
+        assert_eq!(bad(32, u64::MAX), PtErrorCode::BadFile);
+        assert_eq!(bad(40, u64::MAX), PtErrorCode::BadFile);
+        assert_eq!(bad(48, u64::MAX), PtErrorCode::BadFile);
+        // sections that end beyond the file
+        assert_eq!(bad(32, raw.len() as u64), PtErrorCode::BadFile);
+        assert_eq!(bad(48, raw.len() as u64), PtErrorCode::BadFile);
+
+        assert_eq!(
+            PerfData::parse(raw[..HEADER_SIZE - 1].to_vec()).err().unwrap().code(),
+            PtErrorCode::BadFile
+        );
+    }
+
+    #[test]
+    fn test_perf_records() {
+        let mut w = PerfWriter {
+            sample_type: SAMPLE_TID | SAMPLE_TIME,
+            ..Default::default()
+        };
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&10u32.to_le_bytes());
+        comm.extend_from_slice(&11u32.to_le_bytes());
+        comm.extend_from_slice(b"true\0\0\0\0");
+        comm.extend_from_slice(&10u32.to_le_bytes());
+        comm.extend_from_slice(&11u32.to_le_bytes());
+        comm.extend_from_slice(&99u64.to_le_bytes());
+        w.record(RECORD_COMM, MISC_COMM_EXEC, &comm);
+        w.auxtrace(0, 0, &[1, 2, 3]);
+        w.record(1234, 0, &[0; 16]);
+
+        let perf = PerfData::parse(w.finish()).unwrap();
+        let recs = perf.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(recs.len(), 3);
+        match recs[0].data {
+            RecordData::Comm { pid, tid, comm } => {
+                assert_eq!((pid, tid, comm), (10, 11, "true"))
+            }
+            _ => panic!("expected a comm record"),
+        }
+        assert_eq!(recs[0].id.time, Some(99));
+        assert_eq!(recs[0].id.tid, Some(11));
+        match recs[1].data {
+            RecordData::Auxtrace { data, .. } => assert_eq!(data, &[1, 2, 3]),
+            _ => panic!("expected an auxtrace record"),
+        }
+        assert_eq!(recs[2].kind, 1234);
+    }
+
+    // a comm record of @pid with a sample id trailer for @sample_type
+    fn comm(w: &mut PerfWriter, pid: u32, sample_type: u64, time: u64, id: u64) {
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&pid.to_le_bytes());
+        comm.extend_from_slice(&pid.to_le_bytes());
+        comm.extend_from_slice(b"true\0\0\0\0");
+        if sample_type & SAMPLE_TID != 0 {
+            comm.extend_from_slice(&pid.to_le_bytes());
+            comm.extend_from_slice(&pid.to_le_bytes());
+        }
+        comm.extend_from_slice(&time.to_le_bytes());
+        comm.extend_from_slice(&id.to_le_bytes());
+        w.record(RECORD_COMM, 0, &comm);
+    }
+
+    #[test]
+    fn test_perf_sample_identifier() {
+        let pt = SAMPLE_IDENTIFIER | SAMPLE_TID | SAMPLE_TIME;
+        let sw = SAMPLE_IDENTIFIER | SAMPLE_TIME;
+        let mut w = PerfWriter {
+            sample_type: pt,
+            ids: vec![1, 2],
+            others: vec![(sw, vec![3])],
+            ..Default::default()
+        };
+        comm(&mut w, 10, sw, 99, 3);
+        comm(&mut w, 11, pt, 100, 2);
+
+        let perf = PerfData::parse(w.finish()).unwrap();
+        assert_eq!(perf.attrs().len(), 2);
+        let recs = perf.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(recs[0].id.time, Some(99));
+        assert_eq!(recs[0].id.tid, None);
+        assert_eq!(recs[1].id.time, Some(100));
+        assert_eq!(recs[1].id.tid, Some(11));
+
+        comm(&mut w, 12, sw, 101, 4);
+        assert_eq!(
+            PerfData::parse(w.finish()).err().unwrap().code(),
+            PtErrorCode::BadFile
+        );
+    }
+
+    #[test]
+    fn test_perf_aux_streams() {
+        let mut w = PerfWriter::default();
+        w.auxtrace(1, 1, &[4, 5])
+            .auxtrace(0, 0, &[1, 2])
+            .auxtrace(1, 1, &[6])
+            .auxtrace(0, 0, &[3]);
+
+        let perf = PerfData::parse(w.finish()).unwrap();
+        let streams = perf.aux_streams().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].idx, 0);
+        assert_eq!(streams[0].data, vec![1, 2, 3]);
+        assert_eq!(streams[1].cpu, Some(1));
+        assert_eq!(streams[1].data, vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_perf_cpu_and_freq() {
+        let mut private = vec![0u64; 16];
+        private[INFO_PMU_TYPE] = 8;
+        private[INFO_TIME_SHIFT] = 10;
+        private[INFO_TIME_MULT] = 512;
+        private[INFO_MTC_FREQ_BITS] = 14;
+        private[INFO_TSC_CTC_N] = 200;
+        private[INFO_TSC_CTC_D] = 2;
+        private[INFO_MAX_NONTURBO_RATIO] = 24;
+
+        let mut w = PerfWriter {
+            cpuid: Some("GenuineIntel,6,142,10"),
+            pt_config: 3 << 14,
+            ..Default::default()
+        };
+        w.auxtrace_info(&private);
+
+        let perf = PerfData::parse(w.finish()).unwrap();
+        let cpu = perf.cpu().unwrap();
+        assert_eq!(cpu.0.family, 6);
+        assert_eq!(cpu.0.model, 142);
+        assert_eq!(cpu.0.stepping, 10);
+
+        let freq = perf.frequency().unwrap();
+        assert_eq!(freq.mtc(), 3);
+        assert_eq!(freq.nom(), 24);
+        assert_eq!(freq.ctc(), 200);
+        assert_eq!(freq.tsc(), 2);
+
+        let info = perf.intel_pt().unwrap();
+        assert_eq!(info.tsc_to_perf_time(2048), 1024);
+        assert_eq!(info.perf_time_to_tsc(1024), 2048);
+    }
+
+    #[test]
+    fn test_perf_no_info() {
+        let perf = PerfData::parse(PerfWriter::default().finish()).unwrap();
+        assert!(perf.cpu().is_none());
+        assert!(perf.intel_pt().is_none());
+        assert!(perf.frequency().is_none());
+    }
+}
+
+// "PERFILE2"
+const PERF_MAGIC: u64 = 0x3245_4c49_4652_4550;
+const HEADER_SIZE: usize = 104;
+const ATTR_SAMPLE_ID_ALL: u64 = 1 << 18;
+const HEADER_CPUID: u32 = 9;
+
+// indices into the intel-pt AUXTRACE_INFO private data
+const INFO_PMU_TYPE: usize = 0;
+const INFO_TIME_SHIFT: usize = 1;
+const INFO_TIME_MULT: usize = 2;
+const INFO_TIME_ZERO: usize = 3;
+const INFO_CAP_USER_TIME_ZERO: usize = 4;
+const INFO_HAVE_SCHED_SWITCH: usize = 7;
+const INFO_SNAPSHOT_MODE: usize = 8;
+const INFO_PER_CPU_MMAPS: usize = 9;
+const INFO_MTC_FREQ_BITS: usize = 11;
+const INFO_TSC_CTC_N: usize = 12;
+const INFO_TSC_CTC_D: usize = 13;
+const INFO_MAX_NONTURBO_RATIO: usize = 15;
+
+#[inline]
+fn bad_file(msg: &'static str) -> PtError {
+    PtError::new(PtErrorCode::BadFile, msg)
+}
+
+/// A perf_event_attr from the attrs section
+#[derive(Debug, Clone, Copy)]
+pub struct EventAttr {
+    /// The pmu type, matches `IntelPtInfo::pmu_type` for the intel_pt event
+    pub kind: u32,
+    /// The event config, for intel_pt the value of IA32_RTIT_CTL
+    pub config: u64,
+    pub sample_type: u64,
+    pub sample_id_all: bool,
+}
+
+/// The Intel PT setup recorded in the AUXTRACE_INFO record
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntelPtInfo {
+    pub pmu_type: u32,
+    pub time_shift: u16,
+    pub time_mult: u32,
+    pub time_zero: u64,
+    pub cap_user_time_zero: bool,
+    pub have_sched_switch: bool,
+    pub snapshot_mode: bool,
+    pub per_cpu_mmaps: bool,
+    pub mtc_freq_bits: u32,
+    pub tsc_ctc_n: u32,
+    pub tsc_ctc_d: u32,
+    pub max_nonturbo_ratio: u8,
+}
+
+impl IntelPtInfo {
+    fn from_private(p: &[u64]) -> Self {
+        let get = |i: usize| p.get(i).copied().unwrap_or(0);
+        IntelPtInfo {
+            pmu_type: get(INFO_PMU_TYPE) as u32,
+            time_shift: get(INFO_TIME_SHIFT) as u16,
+            time_mult: get(INFO_TIME_MULT) as u32,
+            time_zero: get(INFO_TIME_ZERO),
+            cap_user_time_zero: get(INFO_CAP_USER_TIME_ZERO) != 0,
+            have_sched_switch: get(INFO_HAVE_SCHED_SWITCH) != 0,
+            snapshot_mode: get(INFO_SNAPSHOT_MODE) != 0,
+            per_cpu_mmaps: get(INFO_PER_CPU_MMAPS) != 0,
+            mtc_freq_bits: get(INFO_MTC_FREQ_BITS) as u32,
+            tsc_ctc_n: get(INFO_TSC_CTC_N) as u32,
+            tsc_ctc_d: get(INFO_TSC_CTC_D) as u32,
+            max_nonturbo_ratio: get(INFO_MAX_NONTURBO_RATIO) as u8,
+        }
+    }
+
+    /// The conversion of TSC values from the trace into perf time.
+    pub fn time_converter(&self) -> TimeConverter {
+        TimeConverter::new(self.time_shift, self.time_mult, self.time_zero)
+    }
+
+    /// Converts a TSC value from the trace into perf time.
+    pub fn tsc_to_perf_time(&self, tsc: u64) -> u64 {
+        self.time_converter().tsc_to_ns(tsc)
+    }
+
+    /// Converts a perf timestamp into a TSC value.
+    ///
+    /// Returns 0 if the conversion parameters are unknown.
+    pub fn perf_time_to_tsc(&self, time: u64) -> u64 {
+        self.time_converter().ns_to_tsc(time)
+    }
+}
+
+/// The trace data of one aux buffer, concatenated in recording order.
+#[derive(Debug, Clone)]
+pub struct AuxStream {
+    /// The index of the aux mmap the data was recorded into
+    pub idx: u32,
+    /// The cpu the data was recorded on, for per-cpu recordings
+    pub cpu: Option<u32>,
+    /// The thread the data was recorded for, for per-thread recordings
+    pub tid: Option<u32>,
+    /// The raw Intel PT trace
+    pub data: Vec<u8>,
+}
+
+/// A parsed perf.data file as written by `perf record -e intel_pt//`.
+///
+/// Only the file format with a seekable header is supported,
+/// not the one `perf record -o -` writes to a pipe.
+pub struct PerfData {
+    raw: Vec<u8>,
+    data: (usize, usize),
+    features: [u64; 4],
+    attrs: Vec<EventAttr>,
+    // the index into @attrs of each sample id
+    ids: BTreeMap<u64, usize>,
+    info: Option<IntelPtInfo>,
+}
+
+impl PerfData {
+    /// Reads and parses the perf.data file at @path.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, PtError> {
+        let raw = fs::read(path)
+            .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not read perf data"))?;
+        Self::parse(raw)
+    }
+
+    /// Parses the contents of a perf.data file.
+    ///
+    /// Returns BadFile if @raw is not a valid perf.data file.
+    pub fn parse(raw: Vec<u8>) -> Result<Self, PtError> {
+        if read_u64(&raw, 0)? != PERF_MAGIC {
+            return Err(bad_file("not a perf.data file"));
+        }
+        let header_size = read_u64(&raw, 8)? as usize;
+        if header_size < HEADER_SIZE {
+            return Err(bad_file("unsupported perf.data header"));
+        }
+        let attr_size = read_u64(&raw, 16)? as usize;
+        let attrs_off = read_u64(&raw, 24)? as usize;
+        let attrs_size = read_u64(&raw, 32)? as usize;
+        let data_off = read_u64(&raw, 40)? as usize;
+        let data_size = read_u64(&raw, 48)? as usize;
+        let mut features = [0; 4];
+        for (i, f) in features.iter_mut().enumerate() {
+            *f = read_u64(&raw, 72 + i * 8)?;
+        }
+
+        let data_end = data_off
+            .checked_add(data_size)
+            .filter(|&e| e <= raw.len())
+            .ok_or_else(|| bad_file("perf data is truncated"))?;
+        let attrs_end = attrs_off
+            .checked_add(attrs_size)
+            .ok_or_else(|| bad_file("perf data is truncated"))?;
+
+        // every entry is an attr followed by its ids section
+        if attr_size < 64 + 16 {
+            return Err(bad_file("unsupported perf event attr size"));
+        }
+        let attrs = raw
+            .get(attrs_off..attrs_end)
+            .ok_or_else(|| bad_file("perf data is truncated"))?
+            .chunks_exact(attr_size)
+            .map(|a| {
+                Ok(EventAttr {
+                    kind: read_u32(a, 0)?,
+                    config: read_u64(a, 8)?,
+                    sample_type: read_u64(a, 24)?,
+                    sample_id_all: read_u64(a, 40)? & ATTR_SAMPLE_ID_ALL != 0,
+                })
+            })
+            .collect::<Result<Vec<_>, PtError>>()?;
+
+        // each attr is followed by the section of its sample ids
+        let mut ids = BTreeMap::new();
+        for (i, a) in raw[attrs_off..attrs_end].chunks_exact(attr_size).enumerate() {
+            let off = read_u64(a, attr_size - 16)? as usize;
+            let size = read_u64(a, attr_size - 8)? as usize;
+            let sec = off
+                .checked_add(size)
+                .and_then(|end| raw.get(off..end))
+                .ok_or_else(|| bad_file("perf data is truncated"))?;
+            for id in sec.chunks_exact(8) {
+                ids.insert(read_u64(id, 0)?, i);
+            }
+        }
+
+        let mut perf = PerfData {
+            raw,
+            data: (data_off, data_end),
+            features,
+            attrs,
+            ids,
+            info: None,
+        };
+        for rec in perf.records() {
+            if let RecordData::AuxtraceInfo { private, .. } = rec?.data {
+                perf.info = Some(IntelPtInfo::from_private(&private));
+                break;
+            }
+        }
+
+        Ok(perf)
+    }
+
+    /// The event attributes of the recording.
+    pub fn attrs(&self) -> &[EventAttr] {
+        &self.attrs
+    }
+
+    /// Iterates over the records in the data section.
+    ///
+    /// With several events, the sample id of each record is parsed
+    /// with the attr of its event, found by the `PERF_SAMPLE_IDENTIFIER`
+    /// perf records them with.
+    pub fn records(&self) -> Records<'_> {
+        Records {
+            buf: &self.raw[self.data.0..self.data.1],
+            pos: 0,
+            attrs: &self.attrs,
+            ids: &self.ids,
+        }
+    }
+
+    /// The Intel PT setup, if the file contains an AUXTRACE_INFO record.
+    pub fn intel_pt(&self) -> Option<&IntelPtInfo> {
+        self.info.as_ref()
+    }
+
+    // the intel_pt event's attr
+    fn pt_attr(&self) -> Option<&EventAttr> {
+        let info = self.info.as_ref()?;
+        self.attrs.iter().find(|a| a.kind == info.pmu_type)
+    }
+
+    // the raw contents of the feature section @feat
+    fn feature(&self, feat: u32) -> Option<&[u8]> {
+        let set = |f: u32| self.features[(f / 64) as usize] & (1 << (f % 64)) != 0;
+        if !set(feat) {
+            return None;
+        }
+        let idx = (0..feat).filter(|&f| set(f)).count();
+        let table = self.data.1 + idx * 16;
+        let off = read_u64(&self.raw, table).ok()? as usize;
+        let size = read_u64(&self.raw, table + 8).ok()? as usize;
+        self.raw.get(off..off.checked_add(size)?)
+    }
+
+    /// The cpu the trace was recorded on.
+    ///
+    /// Returns None if the file does not contain a cpuid feature section
+    /// or if it was not recorded on an Intel cpu.
+    pub fn cpu(&self) -> Option<Cpu> {
+        let sec = self.feature(HEADER_CPUID)?;
+        let len = read_u32(sec, 0).ok()? as usize;
+        let s = read_str(sec.get(..len.checked_add(4)?)?, 4).ok()?;
+
+        let mut parts = s.split(',');
+        if parts.next()? != "GenuineIntel" {
+            return None;
+        }
+        let family = parts.next()?.trim().parse().ok()?;
+        let model = parts.next()?.trim().parse().ok()?;
+        let stepping = parts.next()?.trim().parse().ok()?;
+        Some(Cpu::new(CpuVendor::INTEL, family, model, stepping))
+    }
+
+    /// The timing packet frequencies of the trace.
+    ///
+    /// Returns None if the file does not contain an AUXTRACE_INFO record
+    /// or an intel_pt event.
+    pub fn frequency(&self) -> Option<Frequency> {
+        let info = self.info.as_ref()?;
+        let attr = self.pt_attr()?;
+        let mtc = if info.mtc_freq_bits != 0 {
+            ((attr.config >> info.mtc_freq_bits) & 0xf) as u8
+        } else {
+            0
+        };
+        Some(Frequency::new(
+            mtc,
+            info.max_nonturbo_ratio,
+            info.tsc_ctc_n,
+            info.tsc_ctc_d,
+        ))
+    }
+
+    /// Collects the trace data of all aux buffers.
+    ///
+    /// The AUXTRACE chunks of every aux mmap are concatenated in file order.
+    /// Returns one stream per aux mmap, ordered by their index.
+    pub fn aux_streams(&self) -> Result<Vec<AuxStream>, PtError> {
+        let mut streams = BTreeMap::new();
+        for rec in self.records() {
+            if let RecordData::Auxtrace {
+                idx, tid, cpu, data, ..
+            } = rec?.data
+            {
+                streams
+                    .entry(idx)
+                    .or_insert_with(|| AuxStream {
+                        idx,
+                        cpu: (cpu != u32::MAX).then_some(cpu),
+                        tid: (tid != u32::MAX).then_some(tid),
+                        data: Vec::new(),
+                    })
+                    .data
+                    .extend_from_slice(data);
+            }
+        }
+        Ok(streams.into_values().collect())
+    }
+
+    /// Creates a decoder configuration for the trace in @buf.
+    ///
+    /// The cpu and timing frequencies are taken from the recording
+    /// where available.
+    pub fn config<'b>(&self, buf: &'b mut [u8]) -> Result<Config<'b, ()>, PtError> {
+        let mut builder = ConfigBuilder::new(buf)?;
+        if let Some(cpu) = self.cpu() {
+            builder.cpu(cpu);
+        }
+        if let Some(freq) = self.frequency() {
+            builder.freq(freq);
+        }
+        Ok(builder.finish())
+    }
+}
+
+/// Iterator over the records of a perf.data file
+pub struct Records<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    attrs: &'a [EventAttr],
+    ids: &'a BTreeMap<u64, usize>,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, PtError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+        let res = self.parse_next();
+        if res.is_err() {
+            // don't keep going after garbage
+            self.pos = self.buf.len();
+        }
+        Some(res)
+    }
+}
+
+impl<'a> Records<'a> {
+    fn parse_next(&mut self) -> Result<Record<'a>, PtError> {
+        let buf = self.buf;
+        let kind = read_u32(buf, self.pos)?;
+        let misc = read_u16(buf, self.pos + 4)?;
+        let size = read_u16(buf, self.pos + 6)? as usize;
+        if size < 8 {
+            return Err(bad_file("invalid perf record size"));
+        }
+        let end = self.pos + size;
+        let body = buf
+            .get(self.pos + 8..end)
+            .ok_or_else(|| bad_file("perf data is truncated"))?;
+        let extra = &buf[end..];
+
+        let (sample_type, sample_id_all) = self
+            .attr(kind, body)?
+            .map_or((0, false), |a| (a.sample_type, a.sample_id_all));
+        let rec = parse_record(kind, misc, body, extra, sample_type, sample_id_all)?;
+        self.pos = end;
+        if let RecordData::Auxtrace { data, .. } = rec.data {
+            // the trace data is not included in the record size
+            self.pos += data.len();
+        }
+        Ok(rec)
+    }
+
+    // the attr of the event that recorded a record of @kind with @body.
+    // perf records the sample identifier if the events' sample types differ,
+    // first in samples and last in the trailer of other records
+    fn attr(&self, kind: u32, body: &[u8]) -> Result<Option<&'a EventAttr>, PtError> {
+        let first = match self.attrs.first() {
+            Some(a) if self.attrs.len() > 1 && a.sample_type & SAMPLE_IDENTIFIER != 0 => a,
+            first => return Ok(first),
+        };
+        let id = match kind {
+            RECORD_SAMPLE => read_u64(body, 0)?,
+            k if k < 64 && first.sample_id_all => read_u64(body, body.len().saturating_sub(8))?,
+            _ => return Ok(Some(first)),
+        };
+        self.ids
+            .get(&id)
+            .map(|&i| Some(&self.attrs[i]))
+            .ok_or_else(|| bad_file("perf record of an unknown event"))
+    }
+}
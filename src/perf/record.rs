@@ -0,0 +1,336 @@
+use crate::error::{PtError, PtErrorCode};
+
+use core::convert::TryInto;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_helpers() {
+        let buf = [1, 0, 0, 0, 2, 0, 0, 0, b'a', b'b', 0, 0];
+        assert_eq!(read_u32(&buf, 0).unwrap(), 1);
+        assert_eq!(read_u64(&buf, 0).unwrap(), 0x2_0000_0001);
+        assert_eq!(read_str(&buf, 8).unwrap(), "ab");
+        assert!(read_u64(&buf, 8).is_err());
+        assert!(read_u32(&buf, 10).is_err());
+    }
+
+    #[test]
+    fn test_sample_id_trailer() {
+        let st = SAMPLE_TID | SAMPLE_TIME | SAMPLE_CPU;
+        let mut body = vec![0xaa; 4];
+        body.extend_from_slice(&7u32.to_le_bytes());
+        body.extend_from_slice(&8u32.to_le_bytes());
+        body.extend_from_slice(&1234u64.to_le_bytes());
+        body.extend_from_slice(&3u32.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+
+        let id = SampleId::from_trailer(&body, st).unwrap();
+        assert_eq!(id.pid, Some(7));
+        assert_eq!(id.tid, Some(8));
+        assert_eq!(id.time, Some(1234));
+        assert_eq!(id.cpu, Some(3));
+    }
+}
+
+pub(super) const RECORD_MMAP: u32 = 1;
+pub(super) const RECORD_COMM: u32 = 3;
+pub(super) const RECORD_EXIT: u32 = 4;
+pub(super) const RECORD_FORK: u32 = 7;
+pub(super) const RECORD_SAMPLE: u32 = 9;
+pub(super) const RECORD_MMAP2: u32 = 10;
+pub(super) const RECORD_AUX: u32 = 11;
+pub(super) const RECORD_ITRACE_START: u32 = 12;
+pub(super) const RECORD_SWITCH: u32 = 14;
+pub(super) const RECORD_SWITCH_CPU_WIDE: u32 = 15;
+pub(super) const RECORD_AUXTRACE_INFO: u32 = 70;
+pub(super) const RECORD_AUXTRACE: u32 = 71;
+
+pub(super) const SAMPLE_IP: u64 = 1 << 0;
+pub(super) const SAMPLE_TID: u64 = 1 << 1;
+pub(super) const SAMPLE_TIME: u64 = 1 << 2;
+pub(super) const SAMPLE_ADDR: u64 = 1 << 3;
+pub(super) const SAMPLE_ID: u64 = 1 << 6;
+pub(super) const SAMPLE_CPU: u64 = 1 << 7;
+pub(super) const SAMPLE_STREAM_ID: u64 = 1 << 9;
+pub(super) const SAMPLE_IDENTIFIER: u64 = 1 << 16;
+
+/// The cpu mode bits of `misc`
+pub const MISC_CPUMODE_MASK: u16 = 7;
+/// The record belongs to the kernel
+pub const MISC_KERNEL: u16 = 1;
+/// The record belongs to user space
+pub const MISC_USER: u16 = 2;
+/// The record belongs to a guest kernel
+pub const MISC_GUEST_KERNEL: u16 = 4;
+/// The record belongs to guest user space
+pub const MISC_GUEST_USER: u16 = 5;
+/// A COMM record caused by an exec
+pub const MISC_COMM_EXEC: u16 = 1 << 13;
+/// A context switch away from the task
+pub const MISC_SWITCH_OUT: u16 = 1 << 13;
+
+#[inline]
+fn truncated() -> PtError {
+    PtError::new(PtErrorCode::BadFile, "perf data is truncated")
+}
+
+#[inline]
+pub(super) fn read_u16(buf: &[u8], off: usize) -> Result<u16, PtError> {
+    buf.get(off..off + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+#[inline]
+pub(super) fn read_u32(buf: &[u8], off: usize) -> Result<u32, PtError> {
+    buf.get(off..off + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+#[inline]
+pub(super) fn read_u64(buf: &[u8], off: usize) -> Result<u64, PtError> {
+    buf.get(off..off + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+// reads a null terminated (and padded) string
+pub(super) fn read_str(buf: &[u8], off: usize) -> Result<&str, PtError> {
+    let s = buf.get(off..).ok_or_else(truncated)?;
+    let end = s.iter().position(|&b| b == 0).unwrap_or(s.len());
+    core::str::from_utf8(&s[..end])
+        .map_err(|_| PtError::new(PtErrorCode::BadFile, "invalid string in perf data"))
+}
+
+/// The sample identification perf attaches to records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SampleId {
+    pub pid: Option<u32>,
+    pub tid: Option<u32>,
+    /// The perf timestamp
+    pub time: Option<u64>,
+    pub cpu: Option<u32>,
+}
+
+impl SampleId {
+    // parses the `sample_id_all` fields at the end of a non-sample record
+    pub(super) fn from_trailer(body: &[u8], sample_type: u64) -> Result<Self, PtError> {
+        let fields = [
+            SAMPLE_TID,
+            SAMPLE_TIME,
+            SAMPLE_ID,
+            SAMPLE_STREAM_ID,
+            SAMPLE_CPU,
+            SAMPLE_IDENTIFIER,
+        ];
+        let len = fields.iter().filter(|&&f| sample_type & f != 0).count() * 8;
+        let mut off = body.len().checked_sub(len).ok_or_else(truncated)?;
+
+        let mut id = SampleId::default();
+        for f in fields.iter().filter(|&&f| sample_type & f != 0) {
+            match *f {
+                SAMPLE_TID => {
+                    id.pid = Some(read_u32(body, off)?);
+                    id.tid = Some(read_u32(body, off + 4)?);
+                }
+                SAMPLE_TIME => id.time = Some(read_u64(body, off)?),
+                SAMPLE_CPU => id.cpu = Some(read_u32(body, off)?),
+                _ => (),
+            }
+            off += 8;
+        }
+        Ok(id)
+    }
+
+    // parses the leading fields of a sample record
+    pub(super) fn from_sample(body: &[u8], sample_type: u64) -> Result<Self, PtError> {
+        let fields = [
+            SAMPLE_IDENTIFIER,
+            SAMPLE_IP,
+            SAMPLE_TID,
+            SAMPLE_TIME,
+            SAMPLE_ADDR,
+            SAMPLE_ID,
+            SAMPLE_STREAM_ID,
+            SAMPLE_CPU,
+        ];
+        let mut off = 0;
+        let mut id = SampleId::default();
+        for f in fields.iter().filter(|&&f| sample_type & f != 0) {
+            match *f {
+                SAMPLE_TID => {
+                    id.pid = Some(read_u32(body, off)?);
+                    id.tid = Some(read_u32(body, off + 4)?);
+                }
+                SAMPLE_TIME => id.time = Some(read_u64(body, off)?),
+                SAMPLE_CPU => id.cpu = Some(read_u32(body, off)?),
+                _ => (),
+            }
+            off += 8;
+        }
+        Ok(id)
+    }
+}
+
+/// A record from the data section of a perf.data file
+#[derive(Debug, Clone)]
+pub struct Record<'a> {
+    /// The record type
+    pub kind: u32,
+    /// The record's misc flags, see the `MISC_*` constants
+    pub misc: u16,
+    /// Sample identification, if it was recorded
+    pub id: SampleId,
+    /// The decoded payload
+    pub data: RecordData<'a>,
+}
+
+impl<'a> Record<'a> {
+    /// The cpu mode (`MISC_KERNEL`, `MISC_USER`, ...) of the record
+    #[inline]
+    pub fn cpumode(&self) -> u16 {
+        self.misc & MISC_CPUMODE_MASK
+    }
+}
+
+/// The payload of the records that are relevant for Intel PT decoding
+#[derive(Debug, Clone)]
+pub enum RecordData<'a> {
+    /// A memory mapping (PERF_RECORD_MMAP and PERF_RECORD_MMAP2)
+    Mmap {
+        pid: u32,
+        tid: u32,
+        addr: u64,
+        len: u64,
+        pgoff: u64,
+        filename: &'a str,
+    },
+    /// A process changed its name, e.g. due to an exec
+    Comm { pid: u32, tid: u32, comm: &'a str },
+    /// A new process or thread
+    Fork { pid: u32, ppid: u32, tid: u32, ptid: u32, time: u64 },
+    /// A process or thread exited
+    Exit { pid: u32, ppid: u32, tid: u32, ptid: u32, time: u64 },
+    /// A context switch, `MISC_SWITCH_OUT` tells the direction
+    Switch,
+    /// A cpu wide context switch, with the task switched to or from
+    SwitchCpuWide { next_prev_pid: u32, next_prev_tid: u32 },
+    /// Tracing started for a task
+    ItraceStart { pid: u32, tid: u32 },
+    /// New data in the aux area
+    Aux { offset: u64, size: u64, flags: u64 },
+    /// Information about the aux tracing setup
+    AuxtraceInfo { kind: u32, private: Vec<u64> },
+    /// A chunk of trace data
+    Auxtrace {
+        offset: u64,
+        reference: u64,
+        idx: u32,
+        tid: u32,
+        cpu: u32,
+        data: &'a [u8],
+    },
+    /// Any other record
+    Other,
+}
+
+// parses one record, @body excludes the 8 byte header.
+// @extra holds what follows the record, for the AUXTRACE payload
+pub(super) fn parse_record<'a>(
+    kind: u32,
+    misc: u16,
+    body: &'a [u8],
+    extra: &'a [u8],
+    sample_type: u64,
+    sample_id_all: bool,
+) -> Result<Record<'a>, PtError> {
+    let data = match kind {
+        RECORD_MMAP => RecordData::Mmap {
+            pid: read_u32(body, 0)?,
+            tid: read_u32(body, 4)?,
+            addr: read_u64(body, 8)?,
+            len: read_u64(body, 16)?,
+            pgoff: read_u64(body, 24)?,
+            filename: read_str(body, 32)?,
+        },
+        RECORD_MMAP2 => RecordData::Mmap {
+            pid: read_u32(body, 0)?,
+            tid: read_u32(body, 4)?,
+            addr: read_u64(body, 8)?,
+            len: read_u64(body, 16)?,
+            pgoff: read_u64(body, 24)?,
+            filename: read_str(body, 64)?,
+        },
+        RECORD_COMM => RecordData::Comm {
+            pid: read_u32(body, 0)?,
+            tid: read_u32(body, 4)?,
+            comm: read_str(body, 8)?,
+        },
+        RECORD_FORK => RecordData::Fork {
+            pid: read_u32(body, 0)?,
+            ppid: read_u32(body, 4)?,
+            tid: read_u32(body, 8)?,
+            ptid: read_u32(body, 12)?,
+            time: read_u64(body, 16)?,
+        },
+        RECORD_EXIT => RecordData::Exit {
+            pid: read_u32(body, 0)?,
+            ppid: read_u32(body, 4)?,
+            tid: read_u32(body, 8)?,
+            ptid: read_u32(body, 12)?,
+            time: read_u64(body, 16)?,
+        },
+        RECORD_SWITCH => RecordData::Switch,
+        RECORD_SWITCH_CPU_WIDE => RecordData::SwitchCpuWide {
+            next_prev_pid: read_u32(body, 0)?,
+            next_prev_tid: read_u32(body, 4)?,
+        },
+        RECORD_ITRACE_START => RecordData::ItraceStart {
+            pid: read_u32(body, 0)?,
+            tid: read_u32(body, 4)?,
+        },
+        RECORD_AUX => RecordData::Aux {
+            offset: read_u64(body, 0)?,
+            size: read_u64(body, 8)?,
+            flags: read_u64(body, 16)?,
+        },
+        RECORD_AUXTRACE_INFO => RecordData::AuxtraceInfo {
+            kind: read_u32(body, 0)?,
+            private: body
+                .get(8..)
+                .unwrap_or(&[])
+                .chunks_exact(8)
+                .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+        },
+        RECORD_AUXTRACE => {
+            let size = read_u64(body, 0)? as usize;
+            RecordData::Auxtrace {
+                offset: read_u64(body, 8)?,
+                reference: read_u64(body, 16)?,
+                idx: read_u32(body, 24)?,
+                tid: read_u32(body, 28)?,
+                cpu: read_u32(body, 32)?,
+                data: extra.get(..size).ok_or_else(truncated)?,
+            }
+        }
+        _ => RecordData::Other,
+    };
+
+    let id = match kind {
+        RECORD_SAMPLE => SampleId::from_sample(body, sample_type)?,
+        // records synthesized by perf itself have no sample id
+        k if k < 64 && sample_id_all => SampleId::from_trailer(body, sample_type)?,
+        _ => SampleId::default(),
+    };
+
+    Ok(Record {
+        kind,
+        misc,
+        id,
+        data,
+    })
+}
@@ -0,0 +1,373 @@
+use super::{IntelPtInfo, PerfData, RecordData, MISC_COMM_EXEC, MISC_KERNEL, MISC_SWITCH_OUT};
+use crate::block::BlockDecoder;
+use crate::error::{PtError, PtErrorCode};
+use crate::image::Image;
+use crate::asid::Asid;
+
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod test {
+    use super::super::test::PerfWriter;
+    use super::super::*;
+    use super::*;
+
+    fn mmap(pid: u32, addr: u64, name: &str, time: u64) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(&pid.to_le_bytes());
+        b.extend_from_slice(&pid.to_le_bytes());
+        b.extend_from_slice(&addr.to_le_bytes());
+        b.extend_from_slice(&0x1000u64.to_le_bytes());
+        b.extend_from_slice(&0u64.to_le_bytes());
+        b.extend_from_slice(name.as_bytes());
+        b.resize((b.len() + 8) & !7, 0);
+        trailer(&mut b, pid, time, 0);
+        b
+    }
+
+    fn trailer(b: &mut Vec<u8>, pid: u32, time: u64, cpu: u32) {
+        b.extend_from_slice(&pid.to_le_bytes());
+        b.extend_from_slice(&pid.to_le_bytes());
+        b.extend_from_slice(&time.to_le_bytes());
+        b.extend_from_slice(&cpu.to_le_bytes());
+        b.extend_from_slice(&0u32.to_le_bytes());
+    }
+
+    fn perf() -> PerfData {
+        let mut private = vec![0u64; 16];
+        private[INFO_PMU_TYPE] = 8;
+        private[INFO_TIME_MULT] = 1;
+
+        let mut w = PerfWriter {
+            sample_type: SAMPLE_TID | SAMPLE_TIME | SAMPLE_CPU,
+            ..Default::default()
+        };
+        w.auxtrace_info(&private);
+        w.record(RECORD_MMAP, MISC_KERNEL, &mmap(u32::MAX, 0xffff_0000, "[kernel]", 0));
+        w.record(RECORD_MMAP, MISC_USER, &mmap(10, 0x1000, "/bin/a", 5));
+
+        let mut fork = Vec::new();
+        for v in [11u32, 10, 11, 10] {
+            fork.extend_from_slice(&v.to_le_bytes());
+        }
+        fork.extend_from_slice(&20u64.to_le_bytes());
+        trailer(&mut fork, 11, 20, 0);
+        w.record(RECORD_FORK, MISC_USER, &fork);
+
+        let mut sw = Vec::new();
+        trailer(&mut sw, 11, 30, 0);
+        w.record(RECORD_SWITCH, 0, &sw);
+        // other cpus don't affect us
+        let mut sw = Vec::new();
+        trailer(&mut sw, 10, 35, 1);
+        w.record(RECORD_SWITCH, 0, &sw);
+
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&11u32.to_le_bytes());
+        comm.extend_from_slice(&11u32.to_le_bytes());
+        comm.extend_from_slice(b"b\0\0\0\0\0\0\0");
+        trailer(&mut comm, 11, 40, 0);
+        w.record(RECORD_COMM, MISC_COMM_EXEC, &comm);
+        w.record(RECORD_MMAP, MISC_USER, &mmap(11, 0x2000, "/bin/b", 50));
+
+        PerfData::parse(w.finish()).unwrap()
+    }
+
+    #[test]
+    fn test_sideband_tasks() {
+        let mut sb = Sideband::new(&perf(), Some(0)).unwrap();
+        assert_eq!(sb.current(), None);
+        assert!(sb.kernel_mappings().is_empty());
+
+        sb.advance(25);
+        assert_eq!(sb.current(), None);
+        assert_eq!(sb.kernel_mappings().len(), 1);
+        // the child inherits the parent's mappings
+        assert_eq!(sb.mappings(11).unwrap()[0].filename, "/bin/a");
+
+        sb.advance(35);
+        assert_eq!(sb.current(), Some(11));
+
+        sb.advance(u64::MAX);
+        assert_eq!(sb.current(), Some(11));
+        assert_eq!(sb.comm(11), Some("b"));
+        let maps = sb.mappings(11).unwrap();
+        assert_eq!(maps.len(), 1);
+        assert_eq!(maps[0].filename, "/bin/b");
+        assert_eq!(sb.mappings(10).unwrap()[0].filename, "/bin/a");
+    }
+
+    #[test]
+    fn test_sideband_all_cpus() {
+        let mut sb = Sideband::new(&perf(), None).unwrap();
+        sb.advance(35);
+        assert_eq!(sb.current(), Some(10));
+    }
+
+    #[test]
+    fn test_sideband_no_info() {
+        let perf = PerfData::parse(PerfWriter::default().finish()).unwrap();
+        assert_eq!(
+            Sideband::new(&perf, None).err().unwrap().code(),
+            PtErrorCode::BadConfig
+        );
+    }
+}
+
+/// A file mapped into a process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// The virtual address the mapping starts at
+    pub addr: u64,
+    /// The size of the mapping in bytes
+    pub len: u64,
+    /// The offset of the mapping in @filename
+    pub pgoff: u64,
+    pub filename: String,
+}
+
+// a sideband record that affects the decoder, in perf time
+#[derive(Debug, Clone)]
+enum Change {
+    Mmap { pid: u32, kernel: bool, map: Mapping },
+    Comm { pid: u32, comm: String, exec: bool },
+    Fork { pid: u32, ppid: u32 },
+    SwitchIn { pid: u32 },
+}
+
+#[derive(Default)]
+struct Task {
+    comm: String,
+    maps: Vec<Mapping>,
+    // the task's memory image, built on first use
+    image: Option<Image<'static>>,
+    // how many kernel and task mappings have been added to the image
+    kernel_loaded: usize,
+    loaded: usize,
+    // the mappings were replaced and the image needs to be rebuilt
+    stale: bool,
+}
+
+/// Applies the sideband records of a perf recording to the decoder.
+///
+/// Memory mappings, process creation, exec and context switches are
+/// replayed in perf time order, maintaining one memory image per process.
+/// Kernel mappings are added to every image.
+///
+/// perf does not record the CR3 value of a process,
+/// so sections are added for all address spaces
+/// and the image of the running process is swapped in
+/// on every context switch instead.
+pub struct Sideband {
+    changes: Vec<(u64, Change)>,
+    next: usize,
+    info: IntelPtInfo,
+    kernel: Vec<Mapping>,
+    tasks: HashMap<u32, Task>,
+    current: Option<u32>,
+    // the process whose image the decoder is using
+    active: Option<u32>,
+}
+
+impl Sideband {
+    /// Collects the sideband records of @perf.
+    ///
+    /// Context switches are only considered if they happened on @cpu,
+    /// use None for traces recorded per thread.
+    /// Returns BadConfig if @perf has no Intel PT setup, since
+    /// trace timestamps can't be related to sideband records without it.
+    pub fn new(perf: &PerfData, cpu: Option<u32>) -> Result<Self, PtError> {
+        let info = *perf.intel_pt().ok_or_else(|| {
+            PtError::new(
+                PtErrorCode::BadConfig,
+                "perf data does not contain an intel pt setup",
+            )
+        })?;
+
+        let mut changes = Vec::new();
+        for rec in perf.records() {
+            let rec = rec?;
+            let on_cpu = cpu.is_none() || rec.id.cpu == cpu;
+            let change = match rec.data {
+                RecordData::Mmap {
+                    pid,
+                    addr,
+                    len,
+                    pgoff,
+                    filename,
+                    ..
+                } => Change::Mmap {
+                    pid,
+                    kernel: pid == u32::MAX || rec.cpumode() == MISC_KERNEL,
+                    map: Mapping {
+                        addr,
+                        len,
+                        pgoff,
+                        filename: filename.to_string(),
+                    },
+                },
+                RecordData::Comm { pid, comm, .. } => Change::Comm {
+                    pid,
+                    comm: comm.to_string(),
+                    exec: rec.misc & MISC_COMM_EXEC != 0,
+                },
+                RecordData::Fork { pid, ppid, .. } => Change::Fork { pid, ppid },
+                RecordData::Switch | RecordData::SwitchCpuWide { .. }
+                    if on_cpu && rec.misc & MISC_SWITCH_OUT == 0 =>
+                {
+                    match rec.id.pid {
+                        Some(pid) => Change::SwitchIn { pid },
+                        None => continue,
+                    }
+                }
+                RecordData::ItraceStart { pid, .. } if on_cpu => Change::SwitchIn { pid },
+                _ => continue,
+            };
+            // synthesized records describe the state before tracing started
+            changes.push((rec.id.time.unwrap_or(0), change));
+        }
+        changes.sort_by_key(|(time, _)| *time);
+
+        Ok(Sideband {
+            changes,
+            next: 0,
+            info,
+            kernel: Vec::new(),
+            tasks: HashMap::new(),
+            current: None,
+            active: None,
+        })
+    }
+
+    /// The process that is running at the current point in time, if known.
+    pub fn current(&self) -> Option<u32> {
+        self.current
+    }
+
+    /// The name of process @pid, if known.
+    pub fn comm(&self, pid: u32) -> Option<&str> {
+        self.tasks.get(&pid).map(|t| t.comm.as_str())
+    }
+
+    /// The user space mappings of process @pid.
+    pub fn mappings(&self, pid: u32) -> Option<&[Mapping]> {
+        self.tasks.get(&pid).map(|t| t.maps.as_slice())
+    }
+
+    /// The kernel mappings seen so far.
+    pub fn kernel_mappings(&self) -> &[Mapping] {
+        &self.kernel
+    }
+
+    /// Applies all sideband records up to and including perf time @time.
+    pub fn advance(&mut self, time: u64) {
+        while let Some((t, change)) = self.changes.get(self.next) {
+            if *t > time {
+                break;
+            }
+            let change = change.clone();
+            self.next += 1;
+            self.apply(change);
+        }
+    }
+
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Mmap { kernel: true, map, .. } => self.kernel.push(map),
+            Change::Mmap { pid, map, .. } => self.tasks.entry(pid).or_default().maps.push(map),
+            Change::Comm { pid, comm, exec } => {
+                let task = self.tasks.entry(pid).or_default();
+                task.comm = comm;
+                if exec {
+                    task.maps.clear();
+                    task.stale = true;
+                }
+            }
+            Change::Fork { pid, ppid } if pid != ppid => {
+                let (comm, maps) = self
+                    .tasks
+                    .get(&ppid)
+                    .map(|p| (p.comm.clone(), p.maps.clone()))
+                    .unwrap_or_default();
+                let task = self.tasks.entry(pid).or_default();
+                task.comm = comm;
+                task.maps = maps;
+                task.stale = true;
+            }
+            // a new thread shares its process' address space
+            Change::Fork { .. } => (),
+            Change::SwitchIn { pid } => self.current = Some(pid),
+        }
+    }
+
+    /// The memory image of process @pid with all mappings applied so far.
+    ///
+    /// The image is kept alive by the sideband and updated in place,
+    /// so a decoder may keep using it while the sideband advances.
+    pub fn image(&mut self, pid: u32) -> Result<&mut Image<'static>, PtError> {
+        let kernel = &self.kernel;
+        let task = self.tasks.entry(pid).or_default();
+        if task.image.is_none() {
+            task.image = Some(Image::new(Some(&format!("pid {}", pid)))?);
+        }
+        let image = task.image.as_mut().unwrap();
+
+        if task.stale {
+            image.remove_by_asid(Asid::default())?;
+            task.kernel_loaded = 0;
+            task.loaded = 0;
+            task.stale = false;
+        }
+        for map in kernel[task.kernel_loaded..]
+            .iter()
+            .chain(&task.maps[task.loaded..])
+        {
+            // mappings of files we can't read are not an error
+            match image.add_file(&map.filename, map.pgoff, map.len, None, map.addr) {
+                Ok(()) => (),
+                Err(e) if e.code() == PtErrorCode::BadFile => (),
+                Err(e) => return Err(e),
+            }
+        }
+        task.kernel_loaded = kernel.len();
+        task.loaded = task.maps.len();
+
+        Ok(image)
+    }
+
+    /// Advances to the trace timestamp @tsc.
+    ///
+    /// Returns the image of the process that is running at @tsc
+    /// if it is not the one returned by the previous call.
+    pub fn update(&mut self, tsc: u64) -> Result<Option<&mut Image<'static>>, PtError> {
+        self.advance(self.info.tsc_to_perf_time(tsc));
+        let pid = match self.current {
+            Some(pid) => pid,
+            None => return Ok(None),
+        };
+
+        let switched = self.active != Some(pid);
+        self.active = Some(pid);
+        // keep the running process' image up to date even without a switch
+        let image = self.image(pid)?;
+        Ok(switched.then_some(image))
+    }
+
+    /// Brings @decoder's image in line with the sideband at its current time.
+    ///
+    /// Call this after every block and event.
+    /// Returns true if the decoder switched to another process' image.
+    /// Returns NoTime if there has not been a TSC packet.
+    ///
+    /// # Safety
+    /// The decoder reads from the images owned by the sideband,
+    /// so the sideband must outlive @decoder, or @decoder has to be given
+    /// another image with `BlockDecoder::set_image` before the sideband is dropped.
+    pub unsafe fn sync<T>(&mut self, decoder: &mut BlockDecoder<T>) -> Result<bool, PtError> {
+        let tsc = decoder.time()?.absolute()?;
+        match self.update(tsc)? {
+            Some(image) => decoder.set_image_unchecked(Some(image.inner)).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
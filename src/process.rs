@@ -0,0 +1,178 @@
+use crate::block::{Block, BlockDecoder};
+use crate::error::PtError;
+use crate::event::Event;
+use crate::flags::Status;
+use crate::image::Image;
+use crate::time::TimeInfo;
+
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::format;
+
+/// A block decoder for whole-system traces that uses a separate image per process.
+///
+/// Processes are identified by their CR3 value.
+/// The decoder follows paging events in the trace and reads memory from
+/// the image of the current process, so the right code is used for
+/// processes that map different binaries at the same address.
+/// The image of a process is created on first use as a copy of the common image,
+/// which should hold the sections all processes share, e.g. the kernel.
+///
+/// Events have to be read with `ProcessDecoder::event` so the decoder sees them.
+/// Use `ProcessDecoder::switch` to follow process switches from sideband
+/// information instead, e.g. when the trace has no PIP packets.
+pub struct ProcessDecoder<'a, T> {
+    // has to be dropped before the images it reads from
+    decoder: BlockDecoder<'a, T>,
+    common: Image<'static>,
+    processes: BTreeMap<u64, Image<'static>>,
+    cr3: Option<u64>,
+}
+
+// the image of process @cr3, copied from @common on first use
+fn process<'m>(
+    processes: &'m mut BTreeMap<u64, Image<'static>>,
+    common: &Image,
+    cr3: u64,
+) -> Result<&'m mut Image<'static>, PtError> {
+    match processes.entry(cr3) {
+        Entry::Occupied(e) => Ok(e.into_mut()),
+        Entry::Vacant(e) => {
+            let mut image = Image::new(Some(&format!("cr3 {:#x}", cr3)))?;
+            image.copy(common)?;
+            Ok(e.insert(image))
+        }
+    }
+}
+
+impl<'a, T> ProcessDecoder<'a, T> {
+    /// Wraps @decoder, which must not be synchronized yet.
+    ///
+    /// Returns an error if the common image can't be allocated.
+    pub fn new(mut decoder: BlockDecoder<'a, T>) -> Result<Self, PtError> {
+        let common = Image::new(Some("common"))?;
+        // SAFETY: the images are owned by the process decoder
+        // and dropped after the decoder
+        unsafe { decoder.set_image_unchecked(Some(common.inner))? };
+        Ok(ProcessDecoder {
+            decoder,
+            common,
+            processes: BTreeMap::new(),
+            cr3: None,
+        })
+    }
+
+    /// Call @f with the image used while the current process is not known,
+    /// which is the template for the images of new processes.
+    ///
+    /// Changes only affect processes whose image is created afterwards.
+    /// The image may be replaced, the decoder uses it from then on.
+    /// Returns the result of @f, or an error if the decoder can't use the image.
+    pub fn with_common_image<R>(&mut self, f: impl FnOnce(&mut Image<'static>) -> R) -> Result<R, PtError> {
+        self.detach()?;
+        let res = f(&mut self.common);
+        self.switch(self.cr3)?;
+        Ok(res)
+    }
+
+    /// Call @f with the image of the process with the CR3 value @cr3.
+    ///
+    /// The image is created on first use.
+    /// See `with_common_image`.
+    pub fn with_process_image<R>(
+        &mut self,
+        cr3: u64,
+        f: impl FnOnce(&mut Image<'static>) -> R,
+    ) -> Result<R, PtError> {
+        self.detach()?;
+        let res = process(&mut self.processes, &self.common, cr3).map(f);
+        self.switch(self.cr3)?;
+        res
+    }
+
+    // points the decoder at its own default image,
+    // so it doesn't refer to an image that is about to be replaced
+    fn detach(&mut self) -> Result<(), PtError> {
+        // SAFETY: the default image lives as long as the decoder
+        unsafe { self.decoder.set_image_unchecked(None) }
+    }
+
+    /// Forget the image of the process @cr3, e.g. because it exited.
+    ///
+    /// If the process runs again, it gets a fresh copy of the common image.
+    /// Returns false if the process had no image.
+    pub fn remove_process(&mut self, cr3: u64) -> Result<bool, PtError> {
+        if self.cr3 == Some(cr3) {
+            // SAFETY: see `new`
+            unsafe { self.decoder.set_image_unchecked(Some(self.common.inner))? };
+            self.cr3 = None;
+        }
+        Ok(self.processes.remove(&cr3).is_some())
+    }
+
+    /// The CR3 value of the current process, if known.
+    pub fn cr3(&self) -> Option<u64> {
+        self.cr3
+    }
+
+    /// The wrapped block decoder.
+    ///
+    /// The decoder uses the images of the process decoder,
+    /// configure it before wrapping it.
+    pub fn decoder(&self) -> &BlockDecoder<'a, T> {
+        &self.decoder
+    }
+
+    /// The time at the last timing packet, see `BlockDecoder::time`.
+    pub fn time(&mut self) -> Result<TimeInfo, PtError> {
+        self.decoder.time()
+    }
+
+    /// The current core:bus ratio, see `BlockDecoder::core_bus_ratio`.
+    pub fn core_bus_ratio(&mut self) -> Result<u32, PtError> {
+        self.decoder.core_bus_ratio()
+    }
+
+    /// Switch to the image of process @cr3.
+    ///
+    /// If @cr3 is None, the common image is used.
+    pub fn switch(&mut self, cr3: Option<u64>) -> Result<(), PtError> {
+        let image = match cr3 {
+            Some(cr3) => process(&mut self.processes, &self.common, cr3)?.inner,
+            None => self.common.inner,
+        };
+        // SAFETY: see `new`
+        unsafe { self.decoder.set_image_unchecked(Some(image))? };
+        self.cr3 = cr3;
+        Ok(())
+    }
+
+    /// Synchronize the decoder.
+    ///
+    /// Switches to the process the trace says is running at the
+    /// synchronization point, if it does.
+    pub fn sync_forward(&mut self) -> Result<Status, PtError> {
+        let status = self.decoder.sync_forward()?;
+        let cr3 = self.decoder.asid()?.cr3();
+        self.switch(cr3)?;
+        Ok(status)
+    }
+
+    /// Get the next pending event and switch images if it switches processes.
+    pub fn event(&mut self) -> Result<(Event, Status), PtError> {
+        let (evt, status) = self.decoder.event()?;
+        if let Some(space) = evt.address_space() {
+            if self.cr3 != Some(space.cr3) {
+                self.switch(Some(space.cr3))?;
+            }
+        }
+        Ok((evt, status))
+    }
+
+    /// Determine the next block of instructions and the CR3 value
+    /// of the process that executed it, if known.
+    pub fn next(&mut self) -> Result<(Block, Status, Option<u64>), PtError> {
+        let cr3 = self.cr3;
+        self.decoder.next().map(|(b, s)| (b, s, cr3)).map_err(PtError::from)
+    }
+}
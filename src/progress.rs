@@ -0,0 +1,98 @@
+use alloc::boxed::Box;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    #[test]
+    fn test_progress_fraction() {
+        assert_eq!(Progress { offset: 0, size: 0 }.fraction(), 1.0);
+        assert_eq!(Progress { offset: 16, size: 64 }.fraction(), 0.25);
+        assert_eq!(Progress { offset: 80, size: 64 }.fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_progress_remaining() {
+        let p = Progress { offset: 16, size: 64 };
+        assert_eq!(p.remaining(), 48);
+        assert_eq!(p.percent(), 25.0);
+        assert_eq!(Progress { offset: 80, size: 64 }.remaining(), 0);
+    }
+
+    #[test]
+    fn test_progress_interval() {
+        let seen = RefCell::new(Vec::new());
+        let mut r = Reporter::new(100, |p: Progress| seen.borrow_mut().push(p.offset));
+        for off in [0, 50, 99, 100, 150, 250, 20, 60, 130] {
+            r.update(off, 1000);
+        }
+        drop(r);
+        assert_eq!(seen.into_inner(), [0, 100, 250, 20, 130]);
+    }
+}
+
+/// How far a decoder got in its trace buffer.
+///
+/// Passed to the callback registered with `set_progress`
+/// and returned by the decoders' `position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The current decoder position
+    pub offset: u64,
+    /// The size of the trace buffer in bytes
+    pub size: u64,
+}
+
+impl Progress {
+    /// The part of the trace buffer that has been consumed, between 0 and 1.
+    ///
+    /// An empty trace buffer is always fully consumed.
+    pub fn fraction(&self) -> f64 {
+        if self.size == 0 {
+            return 1.0;
+        }
+        self.offset.min(self.size) as f64 / self.size as f64
+    }
+
+    /// The part of the trace buffer that has been consumed in percent.
+    pub fn percent(&self) -> f64 {
+        self.fraction() * 100.0
+    }
+
+    /// The number of bytes left in the trace buffer
+    pub fn remaining(&self) -> u64 {
+        self.size.saturating_sub(self.offset)
+    }
+}
+
+// calls the progress callback whenever the decoder moved by at least an interval
+pub(crate) struct Reporter<'a> {
+    callback: Box<dyn FnMut(Progress) + 'a>,
+    interval: u64,
+    last: Option<u64>,
+}
+
+impl<'a> Reporter<'a> {
+    pub(crate) fn new(interval: u64, callback: impl FnMut(Progress) + 'a) -> Self {
+        Reporter {
+            callback: Box::new(callback),
+            interval,
+            last: None,
+        }
+    }
+
+    // the decoder may also move backwards, e.g. after `sync_backward`
+    pub(crate) fn update(&mut self, offset: u64, size: u64) {
+        if self.last.is_some_and(|l| offset.abs_diff(l) < self.interval) {
+            return;
+        }
+        self.last = Some(offset);
+        (self.callback)(Progress { offset, size });
+    }
+
+    pub(crate) fn restart(&mut self) {
+        self.last = None;
+    }
+}
@@ -0,0 +1,129 @@
+use crate::error::{PtError, PtErrorCode};
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ring_with_psb_at(size: usize, at: usize) -> Vec<u8> {
+        let mut aux = vec![0xff; size];
+        for i in 0..PSB.len() {
+            aux[(at + i) % size] = PSB[i];
+        }
+        aux
+    }
+
+    #[test]
+    fn test_find_psb() {
+        let mut data = vec![0; 5];
+        data.extend_from_slice(&PSB);
+        assert_eq!(find_psb(&data), Some(5));
+        assert_eq!(find_psb(&data[6..]), None);
+        assert_eq!(find_psb(&[]), None);
+
+        data.extend_from_slice(&[0; 3]);
+        data.extend_from_slice(&PSB);
+        assert_eq!(find_psb(&data), Some(5));
+        assert_eq!(rfind_psb(&data), Some(24));
+        assert_eq!(rfind_psb(&data[..23]), Some(5));
+        assert_eq!(rfind_psb(&[]), None);
+    }
+
+    #[test]
+    fn test_linearize_unwrapped() {
+        let aux = ring_with_psb_at(64, 8);
+        let lin = linearize(&aux, 40, 4).unwrap();
+        assert_eq!(lin.len(), 32);
+        assert_eq!(lin[..16], PSB);
+    }
+
+    #[test]
+    fn test_linearize_wrapped() {
+        // psb straddles the end of the ring
+        let aux = ring_with_psb_at(64, 56);
+        let lin = linearize(&aux, 64 + 30, 50).unwrap();
+        assert_eq!(lin.len(), 38);
+        assert_eq!(lin[..16], PSB);
+    }
+
+    #[test]
+    fn test_linearize_overwritten() {
+        // more data than the ring holds, only the last 64 bytes survived
+        let aux = ring_with_psb_at(64, 20);
+        let lin = linearize(&aux, 3 * 64 + 10, 0).unwrap();
+        assert_eq!(lin.len(), 54);
+        assert_eq!(lin[..16], PSB);
+    }
+
+    #[test]
+    fn test_copy_out_wrapped() {
+        let aux: Vec<u8> = (0..8).collect();
+        assert_eq!(copy_out(&aux, 10, 5).unwrap(), vec![5, 6, 7, 0, 1]);
+        assert_eq!(copy_out(&aux, 3, 3).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_linearize_errors() {
+        assert_eq!(linearize(&[], 1, 0).unwrap_err().code(), PtErrorCode::Invalid);
+        assert_eq!(linearize(&[0; 8], 1, 2).unwrap_err().code(), PtErrorCode::Invalid);
+        assert_eq!(linearize(&[0; 64], 64, 0).unwrap_err().code(), PtErrorCode::Nosync);
+    }
+}
+
+/// The encoding of a PSB packet, the synchronization point of the trace
+pub const PSB: [u8; 16] = [
+    0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82,
+    0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82,
+];
+
+/// Finds the offset of the first PSB packet in @data.
+pub fn find_psb(data: &[u8]) -> Option<usize> {
+    data.windows(PSB.len()).position(|w| w == PSB)
+}
+
+/// Finds the offset of the last PSB packet in @data.
+pub fn rfind_psb(data: &[u8]) -> Option<usize> {
+    data.windows(PSB.len()).rposition(|w| w == PSB)
+}
+
+/// Copies the bytes between @tail and @head out of a wrapped AUX ring buffer.
+///
+/// @head and @tail are the free running `aux_head` and `aux_tail` counters
+/// of the perf mmap page, they are not reduced to the size of @aux.
+/// If more than the ring's size has been written (e.g. in snapshot mode),
+/// only the most recent @aux.len() bytes are copied.
+/// Returns Invalid if @aux is empty or @tail is ahead of @head.
+pub fn copy_out(aux: &[u8], head: u64, tail: u64) -> Result<Vec<u8>, PtError> {
+    if aux.is_empty() {
+        return Err(PtError::new(PtErrorCode::Invalid, "aux buffer cant be empty!"));
+    }
+    if tail > head {
+        return Err(PtError::new(PtErrorCode::Invalid, "aux tail is ahead of the head"));
+    }
+
+    let size = aux.len() as u64;
+    let len = (head - tail).min(size) as usize;
+    let start = ((head - len as u64) % size) as usize;
+
+    let mut lin = Vec::with_capacity(len);
+    let first = len.min(aux.len() - start);
+    lin.extend_from_slice(&aux[start..start + first]);
+    lin.extend_from_slice(&aux[..len - first]);
+    Ok(lin)
+}
+
+/// Copies the content of a wrapped AUX ring buffer into a linear buffer.
+///
+/// Works like `copy_out`, but the result starts at the first
+/// synchronization point so it can be decoded right away.
+/// Returns Invalid if @aux is empty or @tail is ahead of @head.
+/// Returns Nosync if there is no PSB packet in the data.
+pub fn linearize(aux: &[u8], head: u64, tail: u64) -> Result<Vec<u8>, PtError> {
+    let mut lin = copy_out(aux, head, tail)?;
+    let psb = find_psb(&lin).ok_or(PtError::new(
+        PtErrorCode::Nosync,
+        "no synchronization point in the aux data",
+    ))?;
+    lin.drain(..psb);
+    Ok(lin)
+}
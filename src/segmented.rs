@@ -0,0 +1,203 @@
+use crate::block::{BlockDecoder, Blocks, Decoded, OverflowPolicy};
+use crate::config::Config;
+use crate::cancel::CancelToken;
+use crate::error::{DecodeError, ErrorKind, PtError};
+use crate::image::Image;
+use crate::progress::{Progress, Reporter};
+
+use alloc::vec::Vec;
+use libipt_sys::pt_image;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn test_segmented_empty() {
+        let mut kek = [0; 4];
+        let mut t = SegmentedTrace::<()>::new(Vec::new(), OverflowPolicy::Gap);
+        assert!(t.next().is_none());
+        assert_eq!(t.segment(), 0);
+
+        t.push(ConfigBuilder::new(&mut kek).unwrap().finish());
+        assert_eq!(t.segments().len(), 1);
+    }
+}
+
+/// A block decoder for a trace that is split into several buffers,
+/// e.g. the chunks of a wrapping trace buffer copied out one after another.
+///
+/// The segments are decoded in order as if they were a single trace.
+/// Every segment gets its own decoder which is created and synchronized
+/// when the previous segment has been decoded,
+/// segments without a synchronization point are skipped.
+/// Since the execution between two segments is unknown,
+/// the first block of a segment doesn't continue the last block of the previous one.
+///
+/// Iterates over the blocks and events like `Blocks`.
+/// An error ends the current segment, decoding continues with the next one.
+/// Only cancelling the decode with a `CancelToken` ends the iteration.
+pub struct SegmentedTrace<'a, T> {
+    // has to be dropped before the buffers it decodes
+    decoder: Option<BlockDecoder<'a, T>>,
+    configs: Vec<Config<'a, T>>,
+    image: Option<*mut pt_image>,
+    policy: OverflowPolicy,
+    // the segment the decoder works on, or the next one to open
+    segment: usize,
+    cancel: Option<CancelToken>,
+    progress: Option<Reporter<'a>>,
+}
+
+impl<'a, T> SegmentedTrace<'a, T> {
+    /// Creates a decoder for the trace split into the buffers of @configs,
+    /// handling overflows according to @policy.
+    pub fn new(configs: Vec<Config<'a, T>>, policy: OverflowPolicy) -> Self {
+        SegmentedTrace {
+            decoder: None,
+            configs,
+            image: None,
+            policy,
+            segment: 0,
+            cancel: None,
+            progress: None,
+        }
+    }
+
+    /// Append a segment to the trace.
+    pub fn push(&mut self, cfg: Config<'a, T>) {
+        self.configs.push(cfg);
+    }
+
+    /// The configs of all segments.
+    pub fn segments(&self) -> &[Config<'a, T>] {
+        &self.configs
+    }
+
+    /// The index of the segment that is currently decoded.
+    ///
+    /// This is the segment the last item came from,
+    /// or the number of segments once decoding has finished.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// Set the traced image.
+    ///
+    /// The image is used by the decoders of all segments.
+    /// If @img is None, the decoders' default image is used.
+    pub fn set_image(&mut self, img: Option<&'a mut Image>) -> Result<(), PtError> {
+        self.image = img.map(|i| i.inner);
+        if let Some(dec) = self.decoder.as_mut() {
+            // SAFETY: the image is borrowed for @'a
+            unsafe { dec.set_image_unchecked(self.image)? };
+        }
+        Ok(())
+    }
+
+    /// Make the decode cancellable.
+    ///
+    /// @cancel is passed on to the decoders of all segments.
+    /// Once it is cancelled, the next item is a Cancelled error and the iteration ends.
+    /// If @cancel is None, the decode can't be cancelled.
+    pub fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        if let Some(dec) = self.decoder.as_mut() {
+            dec.set_cancel(cancel.clone());
+        }
+        self.cancel = cancel;
+    }
+
+    /// Report the progress through all segments.
+    ///
+    /// The segments count as one trace buffer of their combined size.
+    /// @callback is called after an item has been decoded or a segment has ended
+    /// whenever the position moved by at least @interval bytes since the last report.
+    /// If @callback is None, progress is no longer reported.
+    pub fn set_progress<F>(&mut self, interval: u64, callback: Option<F>)
+    where
+        F: FnMut(Progress) + 'a,
+    {
+        self.progress = callback.map(|c| Reporter::new(interval, c));
+    }
+
+    // passes the position in the combined buffers to the progress callback
+    fn report(&mut self) {
+        let Some(p) = self.progress.as_mut() else {
+            return;
+        };
+        let len = |cfgs: &[Config<T>]| cfgs.iter().map(|c| c.buffer_len() as u64).sum::<u64>();
+        let done = self.segment.min(self.configs.len());
+        let current = self.decoder.as_ref().and_then(|d| d.offset().ok()).unwrap_or(0);
+        p.update(len(&self.configs[..done]) + current, len(&self.configs));
+    }
+
+    // moves on to the next segment after an error,
+    // a cancelled decode doesn't continue with any of them
+    fn skip(&mut self, e: DecodeError) {
+        self.segment = match e.kind() {
+            ErrorKind::Cancelled => self.configs.len(),
+            _ => self.segment + 1,
+        };
+    }
+
+    // creates and synchronizes the decoder for the current segment.
+    // returns false if there are no segments left.
+    fn open(&mut self) -> Result<bool, PtError> {
+        while let Some(cfg) = self.configs.get(self.segment) {
+            let mut dec = BlockDecoder::new(cfg)?;
+            dec.set_cancel(self.cancel.clone());
+            // SAFETY: the image is borrowed for @'a
+            unsafe { dec.set_image_unchecked(self.image)? };
+            match dec.sync_forward() {
+                Ok(_) => {
+                    self.decoder = Some(dec);
+                    return Ok(true);
+                }
+                Err(e) if e.is_eos() => self.segment += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<'a, T> Iterator for SegmentedTrace<'a, T> {
+    type Item = Result<Decoded, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dec = match self.decoder.as_mut() {
+                Some(dec) => dec,
+                None => match self.open() {
+                    Ok(true) => continue,
+                    Ok(false) => return None,
+                    // there is no position without a synchronized decoder
+                    Err(e) => {
+                        let e = DecodeError::new(e, None, None);
+                        self.skip(e);
+                        return Some(Err(e));
+                    }
+                },
+            };
+
+            match Blocks::new(dec, self.policy).next() {
+                Some(Ok(item)) => {
+                    self.report();
+                    return Some(Ok(item));
+                }
+                res => {
+                    self.decoder = None;
+                    match &res {
+                        Some(Err(e)) => self.skip(*e),
+                        _ => self.segment += 1,
+                    }
+                    self.report();
+                    if let Some(Err(e)) = res {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::asid::Asid;
+use crate::error::{PtError, PtErrorCode};
+use crate::image::Image;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simplept_parse() {
+        let sb = parse(concat!(
+            "# a comment\n",
+            "\n",
+            "1.500000000 42 0x1d000 400000 0 /usr/bin/true\n",
+            "2.25 43 1e000 7f0000001000 2000 /lib/with space.so\n",
+        ))
+        .unwrap();
+
+        assert_eq!(sb.len(), 2);
+        assert_eq!(sb[0].time, 1.5);
+        assert_eq!(sb[0].pid, 42);
+        assert_eq!(sb[0].cr3, 0x1d000);
+        assert_eq!(sb[0].addr, 0x400000);
+        assert_eq!(sb[0].offset, 0);
+        assert_eq!(sb[0].path, "/usr/bin/true");
+        assert_eq!(sb[1].addr, 0x7f00_0000_1000);
+        assert_eq!(sb[1].offset, 0x2000);
+        assert_eq!(sb[1].path, "/lib/with space.so");
+
+        let procs = processes(&sb);
+        assert_eq!(procs[&0x1d000], 42);
+        assert_eq!(procs[&0x1e000], 43);
+    }
+
+    #[test]
+    fn test_simplept_bad_line() {
+        assert_eq!(parse("1.0 42\n").unwrap_err().code(), PtErrorCode::BadFile);
+        assert_eq!(
+            parse("1.0 x 0 0 0 /bin/a\n").unwrap_err().code(),
+            PtErrorCode::BadFile
+        );
+    }
+}
+
+/// A line of a simple-pt sideband file.
+///
+/// It tells that @path was mapped at @addr into the process @pid
+/// whose page tables are at @cr3.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidebandEntry {
+    /// The time of the mapping in seconds, as recorded by ftrace
+    pub time: f64,
+    pub pid: u32,
+    pub cr3: u64,
+    /// The virtual address the file is loaded at
+    pub addr: u64,
+    /// The offset in @path that is loaded at @addr
+    pub offset: u64,
+    pub path: String,
+}
+
+impl SidebandEntry {
+    /// The address space of the process the mapping belongs to
+    pub fn asid(&self) -> Asid {
+        Asid::new(Some(self.cr3), None)
+    }
+}
+
+#[inline]
+fn bad_line() -> PtError {
+    PtError::new(PtErrorCode::BadFile, "invalid simple-pt sideband line")
+}
+
+fn hex(s: Option<&str>) -> Result<u64, PtError> {
+    let s = s.ok_or_else(bad_line)?;
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(s, 16).map_err(|_| bad_line())
+}
+
+/// Parses the content of a sideband file written by simple-pt's sptcmd.
+///
+/// Every line has the form `timestamp pid cr3 address offset path`
+/// with hexadecimal cr3, address and offset.
+/// Empty lines and lines starting with `#` are skipped.
+/// Returns BadFile if a line does not have this form.
+pub fn parse(text: &str) -> Result<Vec<SidebandEntry>, PtError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.splitn(6, char::is_whitespace);
+            let time = fields
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(bad_line)?;
+            let pid = fields
+                .next()
+                .and_then(|p| p.parse().ok())
+                .ok_or_else(bad_line)?;
+            Ok(SidebandEntry {
+                time,
+                pid,
+                cr3: hex(fields.next())?,
+                addr: hex(fields.next())?,
+                offset: hex(fields.next())?,
+                path: fields.next().ok_or_else(bad_line)?.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads and parses the simple-pt sideband file at @path.
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<SidebandEntry>, PtError> {
+    let text = fs::read_to_string(path)
+        .map_err(|_| PtError::new(PtErrorCode::BadFile, "could not read the sideband file"))?;
+    parse(&text)
+}
+
+/// Maps the cr3 values of the sideband to process ids.
+pub fn processes(entries: &[SidebandEntry]) -> HashMap<u64, u32> {
+    entries.iter().map(|e| (e.cr3, e.pid)).collect()
+}
+
+/// Adds the mappings of @entries to @image.
+///
+/// Every file is added in the address space of its process,
+/// so the decoder picks the right one based on the traced cr3.
+/// Only entries up to the time @until are used, if given.
+/// Pseudo files like `[vdso]` and files that can't be read are skipped.
+/// Returns the number of skipped entries.
+pub fn load(
+    entries: &[SidebandEntry],
+    image: &mut Image,
+    until: Option<f64>,
+) -> Result<u32, PtError> {
+    let mut ignored = 0;
+    let until = until.unwrap_or(f64::INFINITY);
+    for e in entries.iter().filter(|e| e.time <= until) {
+        let size = match fs::metadata(&e.path) {
+            Ok(m) if !e.path.starts_with('[') && m.len() > e.offset => m.len() - e.offset,
+            _ => {
+                ignored += 1;
+                continue;
+            }
+        };
+        match image.add_file(&e.path, e.offset, size, Some(e.asid()), e.addr) {
+            Ok(()) => (),
+            Err(err) if err.code() == PtErrorCode::BadFile => ignored += 1,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(ignored)
+}
@@ -0,0 +1,312 @@
+use crate::block::{Block, BlockDecoder};
+use crate::checkpoint::Checkpoint;
+use crate::config::Config;
+use crate::error::{PtError, PtErrorCode};
+use crate::event::Event;
+use crate::flags::Status;
+use crate::image::Image;
+
+use libipt_sys::{pt_config, pt_image};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigBuilder;
+    use crate::packet::{self, Compression, Encoder, Exec, Fup, Mode, Pad, Psb, Psbend, Tnt64};
+
+    #[test]
+    fn test_stream_push_closed() {
+        let kek = &mut [1; 2];
+        let mut s = BlockStream::new(&ConfigBuilder::new(kek).unwrap().finish());
+        s.push(&[3, 4]).unwrap();
+        assert_eq!(s.buf, [1, 1, 3, 4]);
+        s.close();
+        assert_eq!(s.push(&[5]).unwrap_err().code(), PtErrorCode::Invalid);
+    }
+
+    #[test]
+    fn test_stream_need_more_data() {
+        let kek = &mut [0; 16];
+        let mut s = BlockStream::new(&ConfigBuilder::new(kek).unwrap().finish());
+        assert!(matches!(s.sync_forward(), Ok(Streamed::NeedMoreData)));
+        s.close();
+        assert_eq!(s.sync_forward().unwrap_err().code(), PtErrorCode::Eos);
+    }
+
+    #[test]
+    fn test_stream_split_psb() {
+        let mut trace = [0; 64];
+        let mut cfg = ConfigBuilder::new(&mut trace).unwrap().finish();
+        let mut enc = Encoder::new(&mut cfg).unwrap();
+        let mut size = 0;
+        for _ in 0..20 {
+            size += enc.next(Pad::new()).unwrap();
+        }
+        size += enc.next(Psb::new()).unwrap();
+        size += enc.next(Mode::new(packet::Payload::Exec(Exec::CSL))).unwrap();
+        size += enc.next(Fup::new(0x1000, Compression::Sext48)).unwrap();
+        size += enc.next(Psbend::new()).unwrap();
+        size += enc.next(Tnt64::new(1, 1)).unwrap();
+        drop(enc);
+
+        // the first chunk ends in the middle of the PSB
+        let (first, rest) = trace[..size as usize].split_at_mut(28);
+        let mut s = BlockStream::new(&ConfigBuilder::new(first).unwrap().finish());
+        assert!(matches!(s.sync_forward(), Ok(Streamed::NeedMoreData)));
+        // only what might be the beginning of a PSB is kept
+        assert_eq!(s.buf, first[13..]);
+
+        s.push(rest).unwrap();
+        assert!(matches!(s.sync_forward(), Ok(Streamed::Item(_))));
+        let dec = s.decoder.as_ref().unwrap();
+        assert_eq!(dec.sync_offset().unwrap(), 7);
+
+        // the decoder keeps its position, new data waits for the end of the old
+        s.push(&[0; 4]).unwrap();
+        assert!(s.decoder.is_some());
+        assert_eq!(s.pending, [0; 4]);
+    }
+}
+
+// the size of a PSB packet
+const PSB_SIZE: u64 = 16;
+
+/// The outcome of a streaming decoder call
+#[derive(Debug, Clone, Copy)]
+pub enum Streamed<T> {
+    /// The call succeeded
+    Item(T),
+    /// The decoder reached the end of the data received so far.
+    /// Push more data and repeat the call.
+    NeedMoreData,
+    /// The decoder could not continue with the new data where it stopped
+    /// and synchronized at the next PSB packet instead.
+    /// The trace in between is lost, pending events have to be drained
+    /// with `event` before the call is repeated.
+    Resynced(Status),
+}
+
+/// A block decoder for trace data that is still being recorded.
+///
+/// The stream owns a copy of the trace that has not been decoded yet.
+/// Instead of Eos the decoder returns `Streamed::NeedMoreData` while the stream is open.
+/// Since libipt decoders can't change the end of their buffer,
+/// new data is held back until the decoder reached the end of the data it has.
+/// Only then the underlying decoder is rebuilt with the new data
+/// and restores its position with a `Checkpoint`.
+/// Data in front of that checkpoint's synchronization point is discarded.
+/// If the position can't be restored, e.g. after a decode error,
+/// the new decoder synchronizes at the next PSB packet, see `Streamed::Resynced`.
+/// Once the stream is closed, the end of the data is reported as Eos.
+pub struct BlockStream<'a> {
+    // has to be dropped before the buffer it decodes
+    decoder: Option<BlockDecoder<'static, ()>>,
+    buf: Vec<u8>,
+    // data pushed while the decoder still works on @buf
+    pending: Vec<u8>,
+    // the decoder settings, begin and end are set when the decoder is built
+    cfg: pt_config,
+    image: Option<*mut pt_image>,
+    // where the next decoder will continue
+    resume: Option<Checkpoint>,
+    // the next decoder has to sync forward since there is no checkpoint
+    resync: bool,
+    // the status of an implicit synchronization the user has not seen yet
+    resynced: Option<Status>,
+    // the decoder ran out of data and no new data has been pushed since
+    stalled: bool,
+    closed: bool,
+    // the image set with `set_image` is borrowed for @'a
+    phantom: PhantomData<&'a mut Image<'a>>,
+}
+
+impl<'a> BlockStream<'a> {
+    /// Creates a streaming block decoder.
+    ///
+    /// The settings of @cfg are used for decoding
+    /// and its buffer is copied as the first chunk of trace data.
+    pub fn new(cfg: &Config<()>) -> Self {
+        BlockStream {
+            decoder: None,
+            buf: unsafe { cfg.buffer() }.to_vec(),
+            pending: Vec::new(),
+            cfg: *cfg.0,
+            image: None,
+            resume: None,
+            resync: false,
+            resynced: None,
+            stalled: false,
+            closed: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Append trace data to the stream.
+    ///
+    /// The decoder continues with @data once it reached the end of the earlier data.
+    /// Returns Invalid if the stream has been closed.
+    pub fn push(&mut self, data: &[u8]) -> Result<(), PtError> {
+        if self.closed {
+            return Err(PtError::new(
+                PtErrorCode::Invalid,
+                "the stream has been closed",
+            ));
+        }
+
+        // the decoder still uses the buffer
+        match self.decoder {
+            Some(_) => self.pending.extend_from_slice(data),
+            None => self.buf.extend_from_slice(data),
+        }
+        self.stalled = false;
+        Ok(())
+    }
+
+    /// Mark the end of the trace data.
+    ///
+    /// From now on the end of the data is reported as Eos.
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.stalled = false;
+    }
+
+    /// Set the traced image.
+    ///
+    /// The image is kept across decoder rebuilds.
+    /// If @img is None, the decoder's default image is used.
+    pub fn set_image(&mut self, img: Option<&'a mut Image>) -> Result<(), PtError> {
+        self.image = img.map(|i| i.inner);
+        if let Some(dec) = self.decoder.as_mut() {
+            // SAFETY: the image is borrowed for @'a
+            unsafe { dec.set_image_unchecked(self.image)? };
+        }
+        Ok(())
+    }
+
+    /// Synchronize the decoder.
+    ///
+    /// See `BlockDecoder::sync_forward`.
+    /// Returns NeedMoreData if no synchronization point has been received yet.
+    /// Returns the status of an implicit synchronization as an item.
+    pub fn sync_forward(&mut self) -> Result<Streamed<Status>, PtError> {
+        let closed = self.closed;
+        if self.decoder()?.is_none() {
+            return Ok(Streamed::NeedMoreData);
+        }
+        if let Some(s) = self.resynced.take() {
+            return Ok(Streamed::Item(s));
+        }
+        let dec = self.decoder.as_mut().unwrap();
+
+        match dec.sync_forward() {
+            Err(e) if e.is_eos() && !closed => {
+                let resume = dec.checkpoint().ok();
+                self.park(resume);
+                Ok(Streamed::NeedMoreData)
+            }
+            res => res.map(Streamed::Item),
+        }
+    }
+
+    /// Determine the next block of instructions.
+    ///
+    /// See `BlockDecoder::next`.
+    /// A block that reaches the end of the received data may be incomplete,
+    /// so instead of such a block, NeedMoreData is returned.
+    /// Returns Resynced if the decoder had to synchronize again.
+    pub fn next(&mut self) -> Result<Streamed<(Block, Status)>, PtError> {
+        let closed = self.closed;
+        if self.decoder()?.is_none() {
+            return Ok(Streamed::NeedMoreData);
+        }
+        if let Some(s) = self.resynced.take() {
+            return Ok(Streamed::Resynced(s));
+        }
+        let dec = self.decoder.as_mut().unwrap();
+
+        let before = dec.checkpoint().ok();
+        match dec.next() {
+            Ok((_, s)) if s.eos() && !closed => {
+                self.park(before);
+                Ok(Streamed::NeedMoreData)
+            }
+            Err(e) if e.is_eos() && !closed => {
+                self.park(before);
+                Ok(Streamed::NeedMoreData)
+            }
+            res => res.map(Streamed::Item).map_err(PtError::from),
+        }
+    }
+
+    /// Get the next pending event.
+    ///
+    /// See `BlockDecoder::event`.
+    pub fn event(&mut self) -> Result<(Event, Status), PtError> {
+        match self.decoder()? {
+            Some(dec) => dec.event().map_err(PtError::from),
+            None => Err(PtError::new(PtErrorCode::BadQuery, "no pending event")),
+        }
+    }
+
+    // returns the current decoder, building it if needed.
+    // returns None if there is no data to decode yet.
+    fn decoder(&mut self) -> Result<Option<&mut BlockDecoder<'static, ()>>, PtError> {
+        if self.decoder.is_none() {
+            if self.buf.is_empty() || self.stalled {
+                if self.closed {
+                    return Err(PtError::new(PtErrorCode::Eos, "end of the stream"));
+                }
+                return Ok(None);
+            }
+
+            let mut cfg = self.cfg;
+            cfg.begin = self.buf.as_mut_ptr();
+            cfg.end = unsafe { cfg.begin.add(self.buf.len()) };
+            let mut dec = BlockDecoder::new(&Config::from(cfg))?;
+            // SAFETY: the image is borrowed for @'a
+            unsafe { dec.set_image_unchecked(self.image)? };
+            if let Some(cp) = self.resume {
+                dec.restore(&cp)?;
+            } else if self.resync {
+                match dec.sync_forward() {
+                    Ok(s) => self.resynced = Some(s),
+                    Err(e) if e.is_eos() && !self.closed => {
+                        self.decoder = Some(dec);
+                        self.park(None);
+                        return Ok(None);
+                    }
+                    Err(e) => return Err(e),
+                }
+                self.resync = false;
+            }
+            self.decoder = Some(dec);
+        }
+
+        Ok(self.decoder.as_mut())
+    }
+
+    // drops the decoder that reached the end of its data,
+    // the next one continues at @resume with the pending data.
+    // everything before that point is not needed anymore.
+    fn park(&mut self, resume: Option<Checkpoint>) {
+        let consumed = match (resume, self.decoder.take()) {
+            (Some(cp), _) => cp.sync_offset(),
+            (None, dec) => match dec.map(|d| d.offset()) {
+                // we can't go back to where we were, sync forward from there
+                Some(Ok(offset)) => {
+                    self.resync = true;
+                    offset
+                }
+                // not synchronized, the end might hold the beginning of a PSB
+                _ => (self.buf.len() as u64).saturating_sub(PSB_SIZE - 1),
+            },
+        };
+
+        self.resume = resume.map(|cp| Checkpoint::new(0, cp.offset() - consumed, cp.steps()));
+        self.buf.drain(..consumed as usize);
+        self.stalled = self.pending.is_empty();
+        self.buf.append(&mut self.pending);
+    }
+}
@@ -0,0 +1,180 @@
+use super::Frame;
+use crate::asid::Asid;
+use crate::image::{Image, MappedSection};
+
+use addr2line::gimli::{EndianRcSlice, RunTimeEndian};
+use addr2line::Context;
+use std::collections::HashMap;
+use std::fs;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn section(filename: &str, vaddr: u64, size: u64) -> Mapping {
+        Mapping {
+            section: MappedSection {
+                filename: filename.to_string(),
+                offset: 0,
+                size,
+                asid: Asid::default(),
+                vaddr,
+            },
+            bias: None,
+        }
+    }
+
+    #[test]
+    fn test_dwarf_lookup() {
+        let mut s = DwarfSymbolizer {
+            mappings: vec![section("/a", 0x1000, 0x1000), section("/b", 0x1800, 0x100)],
+            binaries: HashMap::new(),
+            cache: HashMap::new(),
+        };
+
+        let frames = s.symbolize_all(&[0x1000, 0x1880, 0x1900, 0x3000]);
+        assert_eq!(frames[0].binary.as_deref(), Some("/a"));
+        // the newer section takes precedence
+        assert_eq!(frames[1].binary.as_deref(), Some("/b"));
+        assert_eq!(frames[2].binary.as_deref(), Some("/a"));
+        assert_eq!(
+            frames[3],
+            Frame {
+                ip: 0x3000,
+                ..Default::default()
+            }
+        );
+        // binaries with an unknown load bias are not read
+        assert!(s.binaries.is_empty());
+    }
+}
+
+// a file section with the load bias of its file
+#[derive(Debug, Clone)]
+struct Mapping {
+    section: MappedSection,
+    bias: Option<u64>,
+}
+
+/// Symbolizes addresses with the DWARF debug information of the binaries
+/// that were added to an image.
+///
+/// Only ELF files with a known load bias are symbolized.
+/// The binaries are read when they are first needed.
+///
+/// Only available with the `addr2line` feature.
+pub struct DwarfSymbolizer {
+    // oldest first, like in the image
+    mappings: Vec<Mapping>,
+    // None if the binary could not be read
+    binaries: HashMap<String, Option<Context<EndianRcSlice<RunTimeEndian>>>>,
+    cache: HashMap<u64, Frame>,
+}
+
+impl DwarfSymbolizer {
+    /// Create a symbolizer for the file sections of @image.
+    ///
+    /// Later changes to @image are not reflected.
+    pub fn new(image: &Image) -> Self {
+        Self::from_sections(image.sections().into_iter())
+    }
+
+    /// Create a symbolizer for the file sections of @image in address space @asid.
+    pub fn with_asid(image: &Image, asid: Asid) -> Self {
+        Self::from_sections(
+            image
+                .sections()
+                .into_iter()
+                .filter(|s| s.asid.matches(asid)),
+        )
+    }
+
+    fn from_sections(sections: impl Iterator<Item = MappedSection>) -> Self {
+        let mappings = sections
+            .map(|s| Mapping {
+                bias: Image::load_bias(&s.filename, s.vaddr, s.offset).ok(),
+                section: s,
+            })
+            .collect();
+
+        DwarfSymbolizer {
+            mappings,
+            binaries: HashMap::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Symbolize the address @ip.
+    pub fn symbolize(&mut self, ip: u64) -> Frame {
+        if let Some(frame) = self.cache.get(&ip) {
+            return frame.clone();
+        }
+
+        let frame = self.lookup(ip);
+        self.cache.insert(ip, frame.clone());
+        frame
+    }
+
+    /// Symbolize all addresses in @ips.
+    ///
+    /// Addresses are only looked up once, so this is cheap for
+    /// the repetitive addresses of a trace.
+    pub fn symbolize_all(&mut self, ips: &[u64]) -> Vec<Frame> {
+        ips.iter().map(|&ip| self.symbolize(ip)).collect()
+    }
+
+    fn lookup(&mut self, ip: u64) -> Frame {
+        let mut frame = Frame {
+            ip,
+            ..Default::default()
+        };
+        let mapping = match self
+            .mappings
+            .iter()
+            .rev()
+            .find(|m| ip.wrapping_sub(m.section.vaddr) < m.section.size)
+        {
+            Some(m) => m,
+            None => return frame,
+        };
+        let filename = &mapping.section.filename;
+        frame.binary = Some(filename.clone());
+        let bias = match mapping.bias {
+            Some(bias) => bias,
+            None => return frame,
+        };
+
+        let ctx = self
+            .binaries
+            .entry(filename.clone())
+            .or_insert_with(|| load(filename));
+        let ctx = match ctx {
+            Some(ctx) => ctx,
+            None => return frame,
+        };
+
+        let probe = ip.wrapping_sub(bias);
+        if let Some(Ok(Some(f))) = ctx
+            .find_frames(probe)
+            .skip_all_loads()
+            .ok()
+            .map(|mut frames| frames.next())
+        {
+            frame.function = f
+                .function
+                .and_then(|n| n.demangle().ok().map(|n| n.into_owned()));
+            if let Some(loc) = f.location {
+                frame.file = loc.file.map(str::to_string);
+                frame.line = loc.line;
+            }
+        }
+        frame
+    }
+}
+
+// reads the debug information of @filename
+fn load(filename: &str) -> Option<Context<EndianRcSlice<RunTimeEndian>>> {
+    let data = fs::read(filename).ok()?;
+    let obj = addr2line::object::File::parse(&*data).ok()?;
+    Context::new(&obj).ok()
+}
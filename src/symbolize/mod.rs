@@ -0,0 +1,143 @@
+use crate::block::{Block, BlockDecoder};
+use crate::flags::Status;
+use alloc::string::String;
+
+#[cfg(feature = "addr2line")]
+mod dwarf;
+#[cfg(feature = "addr2line")]
+pub use dwarf::*;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::{PtError, PtErrorCode};
+    use crate::block::test::block;
+    use libipt_sys::pt_insn_class_ptic_other;
+
+    #[test]
+    fn test_symbolized() {
+        let blocks = vec![
+            Ok((block(0x1000, 0x1000, pt_insn_class_ptic_other, 1), Status::empty())),
+            Err(PtError::new(PtErrorCode::Nomap, "")),
+            Ok((block(0x2000, 0x2000, pt_insn_class_ptic_other, 1), Status::EVENT_PENDING)),
+        ];
+        let names = |ip: u64| Frame {
+            ip,
+            function: (ip == 0x1000).then(|| "main".to_string()),
+            ..Default::default()
+        };
+
+        let out: Vec<_> = Symbolized::new(blocks.into_iter(), names).collect();
+        assert_eq!(out.len(), 3);
+        let (b, _, f) = out[0].as_ref().unwrap();
+        assert_eq!(b.ip(), 0x1000);
+        assert_eq!(f.function.as_deref(), Some("main"));
+        assert!(out[1].is_err());
+        let (_, s, f) = out[2].as_ref().unwrap();
+        assert!(s.event_pending());
+        assert_eq!(f.ip, 0x2000);
+        assert!(f.function.is_none());
+    }
+}
+
+/// The symbol information of an address
+///
+/// Fields are None if they are not known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// The symbolized address
+    pub ip: u64,
+    /// The binary the address belongs to
+    pub binary: Option<String>,
+    /// The (demangled) name of the function containing the address.
+    ///
+    /// For inlined code this is the inlined function.
+    pub function: Option<String>,
+    /// The source file of the address
+    pub file: Option<String>,
+    /// The source line of the address
+    pub line: Option<u32>,
+}
+
+/// A source of symbol information
+///
+/// Implement this to annotate decoded blocks with symbols from custom
+/// sources, e.g. a symbol server or PDB files.
+/// It is implemented for closures taking an address and returning a `Frame`.
+pub trait Symbolizer {
+    /// Symbolize the address @ip.
+    fn symbolize(&mut self, ip: u64) -> Frame;
+}
+
+impl<F> Symbolizer for F
+where
+    F: FnMut(u64) -> Frame,
+{
+    fn symbolize(&mut self, ip: u64) -> Frame {
+        self(ip)
+    }
+}
+
+#[cfg(feature = "addr2line")]
+impl Symbolizer for DwarfSymbolizer {
+    fn symbolize(&mut self, ip: u64) -> Frame {
+        DwarfSymbolizer::symbolize(self, ip)
+    }
+}
+
+/// An iterator over decoded blocks that symbolizes the first
+/// instruction of every block.
+///
+/// Errors are passed through unchanged.
+pub struct Symbolized<I, S> {
+    blocks: I,
+    symbolizer: S,
+}
+
+impl<I, S, E> Symbolized<I, S>
+where
+    I: Iterator<Item = Result<(Block, Status), E>>,
+    S: Symbolizer,
+{
+    /// Symbolize the blocks of @blocks with @symbolizer.
+    pub fn new(blocks: I, symbolizer: S) -> Self {
+        Symbolized { blocks, symbolizer }
+    }
+
+    /// The symbolizer, e.g. to reuse its caches for another trace.
+    pub fn symbolizer(&mut self) -> &mut S {
+        &mut self.symbolizer
+    }
+
+    /// The underlying block iterator, e.g. to drain pending events.
+    pub fn blocks(&mut self) -> &mut I {
+        &mut self.blocks
+    }
+}
+
+impl<I, S, E> Iterator for Symbolized<I, S>
+where
+    I: Iterator<Item = Result<(Block, Status), E>>,
+    S: Symbolizer,
+{
+    type Item = Result<(Block, Status, Frame), E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.blocks.next()?;
+        Some(res.map(|(block, status)| {
+            let frame = self.symbolizer.symbolize(block.ip());
+            (block, status, frame)
+        }))
+    }
+}
+
+impl<'a, T> BlockDecoder<'a, T> {
+    /// Iterate over the decoded blocks together with the symbol
+    /// information @symbolizer provides for them.
+    ///
+    /// The decoder is borrowed, so it can still be used for
+    /// events and resynchronization, see `Symbolized::blocks`.
+    pub fn symbolized<S: Symbolizer>(&mut self, symbolizer: S) -> Symbolized<&mut Self, S> {
+        Symbolized::new(self, symbolizer)
+    }
+}
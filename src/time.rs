@@ -0,0 +1,279 @@
+use crate::config::Frequency;
+use crate::error::{ensure_ptok, PtError, PtErrorCode};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libipt_sys::{pt_error_code_pte_invalid, pt_error_code_pte_no_time};
+
+    #[test]
+    fn test_perf_params() {
+        let t = TimeConverter::new(10, 3 << 9, 1000);
+        // 1.5 ns per tick
+        assert_eq!(t.tsc_to_ns(0), 1000);
+        assert_eq!(t.tsc_to_ns(2048), 1000 + 3072);
+        assert_eq!(t.ns_to_tsc(1000 + 3072), 2048);
+        assert_eq!(TimeConverter::new(0, 0, 0).ns_to_tsc(5), 0);
+    }
+
+    #[test]
+    fn test_large_shift() {
+        let t = TimeConverter::new(u16::MAX, u32::MAX, 7);
+        assert_eq!(t.shift(), 63);
+        assert_eq!(t.tsc_to_ns(0), 7);
+        assert_eq!(t.tsc_to_ns(1 << 63), 7 + u32::MAX as u64);
+        t.tsc_to_ns(u64::MAX);
+        t.ns_to_tsc(u64::MAX);
+
+        let t = TimeConverter::new(40, 1 << 8, 0);
+        assert_eq!(t.tsc_to_ns((1 << 40) - 1), 255);
+        assert_eq!(t.ns_to_tsc(255), 255 << 32);
+    }
+
+    #[test]
+    fn test_frequency() {
+        let t = TimeConverter::from_frequency(2_000_000_000, 100, 50);
+        assert_eq!(t.tsc_to_ns(100), 50);
+        assert_eq!(t.tsc_to_ns(100 + 2_000_000_000), 1_000_000_050);
+        assert_eq!(t.ns_to_tsc(1_000_000_050), 100 + 2_000_000_000);
+
+        let slow = TimeConverter::from_frequency(25_000_000, 0, 0);
+        assert_eq!(slow.tsc_to_ns(25_000_000), 1_000_000_000);
+    }
+
+    #[test]
+    fn test_time_info() {
+        let info = TimeInfo::query(|t, m, c| {
+            (*t, *m, *c) = (42, 0, 3);
+            -pt_error_code_pte_no_time
+        })
+        .unwrap();
+        assert_eq!(info.tsc, 42);
+        assert!(!info.has_tsc);
+        assert_eq!(info.quality(), TimeQuality::Relative);
+
+        let info = TimeInfo::query(|_, _, c| {
+            *c = 1;
+            0
+        })
+        .unwrap();
+        assert!(info.has_tsc);
+        assert_eq!(info.quality(), TimeQuality::Lossy);
+        assert!(TimeInfo::query(|_, _, _| -pt_error_code_pte_invalid).is_err());
+    }
+
+    #[test]
+    fn test_time_cal() {
+        let freq = Frequency::new(3, 24, 168, 2);
+        let time = TimeInfo { tsc: 1000, has_tsc: true, ..TimeInfo::default() };
+        let cal = TimeCal::capture(freq, Ok(36), Ok(time)).unwrap();
+        assert_eq!(cal.freq, freq);
+        assert_eq!(cal.cbr, Some(36));
+        assert_eq!(cal.time, time);
+
+        let no_cbr = Err(PtError::new(PtErrorCode::NoCbr, "no cbr"));
+        let cal = TimeCal::capture(freq, no_cbr, Ok(time)).unwrap();
+        assert_eq!(cal.cbr, None);
+
+        let bad = Err(PtError::new(PtErrorCode::Invalid, "invalid"));
+        assert!(TimeCal::capture(freq, bad, Ok(time)).is_err());
+        let bad = Err(PtError::new(PtErrorCode::Invalid, "invalid"));
+        assert!(TimeCal::capture(freq, Ok(36), bad).is_err());
+        assert!(TimeCal::capture(freq, Ok(1 << 16), Ok(time)).is_err());
+    }
+}
+
+/// Converts time stamp counts from the trace into nanoseconds
+///
+/// This uses the linear conversion of the perf_event_mmap_page:
+/// `ns = zero + (tsc * mult) >> shift`.
+/// With the parameters perf reports, the result is in perf's clock,
+/// the clock of the timestamps of all other perf events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeConverter {
+    shift: u16,
+    mult: u32,
+    zero: u64,
+}
+
+impl TimeConverter {
+    /// A converter with perf's time_shift @shift, time_mult @mult
+    /// and time_zero @zero.
+    ///
+    /// @shift is clamped to 63, larger shifts would shift out every bit.
+    pub fn new(shift: u16, mult: u32, zero: u64) -> Self {
+        TimeConverter { shift: shift.min(63), mult, zero }
+    }
+
+    /// A converter for a TSC running at @hz,
+    /// where the time stamp count @tsc corresponds to @ns nanoseconds.
+    ///
+    /// Use this to convert into any clock that was sampled
+    /// together with the TSC, e.g. CLOCK_MONOTONIC.
+    pub fn from_frequency(hz: u64, tsc: u64, ns: u64) -> Self {
+        let hz = hz.max(1) as u128;
+        // the most precise multiplier that fits
+        let shift = (0..=32u16)
+            .rev()
+            .find(|s| (1_000_000_000u128 << s) / hz <= u32::MAX as u128)
+            .unwrap_or(0);
+        let mult = ((1_000_000_000u128 << shift) / hz) as u32;
+        let mut conv = TimeConverter::new(shift, mult, 0);
+        conv.zero = ns.wrapping_sub(conv.tsc_to_ns(tsc));
+        conv
+    }
+
+    /// perf's time_shift
+    pub fn shift(&self) -> u16 {
+        self.shift
+    }
+
+    /// perf's time_mult
+    pub fn mult(&self) -> u32 {
+        self.mult
+    }
+
+    /// perf's time_zero
+    pub fn zero(&self) -> u64 {
+        self.zero
+    }
+
+    /// Converts the time stamp count @tsc into nanoseconds.
+    pub fn tsc_to_ns(&self, tsc: u64) -> u64 {
+        let shift = self.shift as u32;
+        let mult = self.mult as u64;
+        let quot = tsc >> shift;
+        let rem = tsc & ((1u64 << shift) - 1);
+        // the remainder times the multiplier exceeds 64 bits for shifts above 32
+        let frac = ((rem as u128 * mult as u128) >> shift) as u64;
+        self.zero
+            .wrapping_add(quot.wrapping_mul(mult))
+            .wrapping_add(frac)
+    }
+
+    /// Converts the time @ns in nanoseconds into a time stamp count.
+    ///
+    /// Returns 0 if the multiplier is zero.
+    pub fn ns_to_tsc(&self, ns: u64) -> u64 {
+        let mult = self.mult as u64;
+        if mult == 0 {
+            return 0;
+        }
+        let shift = self.shift as u32;
+        let t = ns.wrapping_sub(self.zero);
+        let quot = t / mult;
+        let rem = t % mult;
+        let frac = (((rem as u128) << shift) / mult as u128) as u64;
+        (quot << shift).wrapping_add(frac)
+    }
+}
+
+/// How far the time reported by a decoder can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeQuality {
+    /// There has not been a TSC packet,
+    /// the time is relative to the last synchronization
+    Relative,
+    /// Timing packets were dropped, the time is less precise
+    Lossy,
+    /// No timing packets were dropped
+    Precise,
+}
+
+/// The time at the last preceding timing packet.
+///
+/// Returned by the `time` method of the decoders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TimeInfo {
+    /// The estimated time stamp count
+    pub tsc: u64,
+    /// The number of dropped MTC packets
+    pub lost_mtc: u32,
+    /// The number of dropped CYC packets
+    pub lost_cyc: u32,
+    /// Was there a TSC packet?
+    ///
+    /// If not, @tsc is relative to the last synchronization
+    /// and can't be correlated with other TSC-based time sources.
+    pub has_tsc: bool,
+}
+
+impl TimeInfo {
+    /// A summary of @has_tsc, @lost_mtc and @lost_cyc.
+    pub fn quality(&self) -> TimeQuality {
+        if !self.has_tsc {
+            TimeQuality::Relative
+        } else if self.lost_mtc > 0 || self.lost_cyc > 0 {
+            TimeQuality::Lossy
+        } else {
+            TimeQuality::Precise
+        }
+    }
+
+    /// The time stamp count, if there has been a TSC packet.
+    ///
+    /// Returns NoTime otherwise.
+    pub fn absolute(&self) -> Result<u64, PtError> {
+        if !self.has_tsc {
+            return Err(PtError::new(
+                PtErrorCode::NoTime,
+                "there has not been a tsc packet",
+            ));
+        }
+        Ok(self.tsc)
+    }
+
+    // wraps the pt_*_time functions, which still provide the relative time
+    // when they fail with pte_no_time
+    pub(crate) fn query(
+        time: impl FnOnce(&mut u64, &mut u32, &mut u32) -> i32,
+    ) -> Result<Self, PtError> {
+        let mut info = TimeInfo::default();
+        let code = time(&mut info.tsc, &mut info.lost_mtc, &mut info.lost_cyc);
+        match ensure_ptok(code) {
+            Ok(()) => info.has_tsc = true,
+            Err(e) if e.code() == PtErrorCode::NoTime => (),
+            Err(e) => return Err(e),
+        }
+        Ok(info)
+    }
+}
+
+/// The time calibration of a decoder
+///
+/// libipt calibrates its time tracking with the frequencies of the config
+/// and the core:bus ratio of CBR packets, and keeps the result internal.
+/// Capture it with `time_cal` after decoding a warm-up region and seed
+/// decoders starting at later synchronization points with it,
+/// e.g. the segments of a parallel or segmented decode:
+/// pass it to `ConfigBuilder::time_cal` for their configs and @cbr
+/// to the `cbr` methods of the cycle counting iterators,
+/// which otherwise count TSC ticks until the first CBR event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeCal {
+    /// The frequency values of the decoder config
+    pub freq: Frequency,
+    /// The last core:bus ratio, if there has been a CBR packet
+    pub cbr: Option<u16>,
+    /// The time at the end of the warm-up region
+    pub time: TimeInfo,
+}
+
+impl TimeCal {
+    // combines the results of the config, core_bus_ratio and time
+    // methods of a decoder, without a cbr packet there's no ratio yet
+    pub(crate) fn capture(
+        freq: Frequency,
+        cbr: Result<u32, PtError>,
+        time: Result<TimeInfo, PtError>,
+    ) -> Result<Self, PtError> {
+        let cbr = match cbr {
+            Ok(r) => Some(u16::try_from(r).map_err(|_| {
+                PtError::new(PtErrorCode::BadPacket, "core:bus ratio out of range")
+            })?),
+            Err(e) if e.code() == PtErrorCode::NoCbr => None,
+            Err(e) => return Err(e),
+        };
+        Ok(TimeCal { freq, cbr, time: time? })
+    }
+}
@@ -0,0 +1,591 @@
+use crate::budget::{Budget, Meter};
+use crate::cancel::CancelToken;
+use crate::checkpoint::{Checkpoint, Mark, Marks};
+use crate::error::{ensure_synced, DecodeError, PtError, PtErrorCode};
+use crate::event::Event;
+use crate::flags::Status;
+use crate::progress::{Progress, Reporter};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use core::mem;
+    use libipt_sys::pt_event;
+
+    // a decoder over a trace of @len one byte items with a psb every @psb bytes
+    struct Fake {
+        tracker: Tracker<'static>,
+        pos: Option<u64>,
+        sync: u64,
+        len: u64,
+        psb: u64,
+    }
+
+    impl Fake {
+        fn new(len: u64, psb: u64) -> Self {
+            Fake { tracker: Tracker::new(), pos: None, sync: 0, len, psb }
+        }
+    }
+
+    fn nosync() -> PtError {
+        PtError::new(PtErrorCode::Nosync, "fake decoder is out of sync")
+    }
+
+    fn eos() -> PtError {
+        PtError::new(PtErrorCode::Eos, "end of the fake trace")
+    }
+
+    impl RawDecoder<'static> for Fake {
+        type Item = u64;
+
+        fn tracker(&self) -> &Tracker<'static> {
+            &self.tracker
+        }
+
+        fn tracker_mut(&mut self) -> &mut Tracker<'static> {
+            &mut self.tracker
+        }
+
+        fn raw_offset(&self) -> Result<u64, PtError> {
+            self.tracker.synced()?;
+            self.pos.ok_or_else(nosync)
+        }
+
+        fn raw_sync_offset(&self) -> Result<u64, PtError> {
+            self.raw_offset().map(|_| self.sync)
+        }
+
+        fn raw_position(&self) -> Result<Progress, PtError> {
+            self.raw_offset().map(|offset| Progress { offset, size: self.len })
+        }
+
+        fn raw_psb(&self, last: bool) -> Result<u64, PtError> {
+            Ok(if last { (self.len - 1) / self.psb * self.psb } else { 0 })
+        }
+
+        fn raw_next(&mut self) -> (u64, Result<Status, PtError>) {
+            let pos = match self.pos {
+                Some(p) if p < self.len => p,
+                Some(_) => return (0, Err(eos())),
+                None => return (0, Err(nosync())),
+            };
+            self.pos = Some(pos + 1);
+            if (pos + 1) % self.psb == 0 {
+                self.sync = pos + 1;
+            }
+            (pos, Ok(Status::empty()))
+        }
+
+        fn insns(_: &u64) -> u64 {
+            1
+        }
+
+        fn raw_event(&mut self) -> (Event, Result<Status, PtError>) {
+            let evt: pt_event = unsafe { mem::zeroed() };
+            (Event(evt), Err(PtError::new(PtErrorCode::BadQuery, "no event")))
+        }
+
+        fn raw_sync(&mut self, to: SyncTo) -> Result<Status, PtError> {
+            let offset = match to {
+                SyncTo::Forward => self.pos.map_or(0, |_| self.sync + self.psb),
+                SyncTo::Backward => self.raw_psb(true)?,
+                SyncTo::Offset(o) if o % self.psb == 0 => o,
+                SyncTo::Offset(_) => return Err(nosync()),
+            };
+            if offset >= self.len {
+                return Err(eos());
+            }
+            self.pos = Some(offset);
+            self.sync = offset;
+            Ok(Status::empty())
+        }
+    }
+
+    #[test]
+    fn test_tracker_step_back() {
+        let mut d = Fake::new(100, 10);
+        d.tracker.set_checkpoint_interval(4);
+        assert_eq!(checkpoint(&d).unwrap_err().code(), PtErrorCode::Nosync);
+        assert_eq!(next(&mut d).unwrap_err().code(), PtErrorCode::Nosync);
+
+        sync(&mut d, SyncTo::Forward).unwrap();
+        for i in 0..25 {
+            assert_eq!(next(&mut d).unwrap().0, i);
+        }
+        let cp = checkpoint(&d).unwrap();
+        assert_eq!((cp.sync_offset(), cp.offset(), cp.steps()), (0, 25, 25));
+        // replayed from the checkpoint after the psb at 20
+        assert_eq!(d.tracker.marks.nearest(22).unwrap().sync_offset, 20);
+        step_back(&mut d, 3).unwrap();
+        assert_eq!(next(&mut d).unwrap().0, 22);
+        assert_eq!(step_back(&mut d, 30).unwrap_err().code(), PtErrorCode::Invalid);
+
+        restore(&mut d, &cp).unwrap();
+        assert_eq!(next(&mut d).unwrap().0, 25);
+    }
+
+    #[test]
+    fn test_tracker_limits() {
+        let mut d = Fake::new(100, 10);
+        d.tracker.set_budget(Some(Budget::new().insns(5)));
+        sync(&mut d, SyncTo::Offset(10)).unwrap();
+        for _ in 0..5 {
+            next(&mut d).unwrap();
+        }
+        let err = next(&mut d).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BudgetExhausted);
+        assert_eq!(err.offset(), Some(15));
+
+        let cancel = CancelToken::new();
+        d.tracker.set_budget(None);
+        d.tracker.set_cancel(Some(cancel.clone()));
+        next(&mut d).unwrap();
+        cancel.cancel();
+        assert_eq!(next(&mut d).unwrap_err().kind(), ErrorKind::Cancelled);
+        assert_eq!(sync(&mut d, SyncTo::Forward).unwrap_err().kind(), ErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn test_tracker_reset() {
+        let mut d = Fake::new(100, 10);
+        sync(&mut d, SyncTo::Offset(50)).unwrap();
+        next(&mut d).unwrap();
+        assert_eq!(event(&mut d).unwrap_err().code(), PtErrorCode::BadQuery);
+        // the failed event can't be replayed
+        assert_eq!(checkpoint(&d).unwrap_err().code(), PtErrorCode::BadContext);
+
+        d.tracker.reset(true);
+        assert_eq!(next(&mut d).unwrap_err().code(), PtErrorCode::Nosync);
+        assert_eq!(d.tracker.status(), Status::empty());
+        // synchronizing starts over at the first psb
+        sync(&mut d, SyncTo::Forward).unwrap();
+        assert_eq!(next(&mut d).unwrap().0, 0);
+        d.tracker.reset(true);
+        sync(&mut d, SyncTo::Backward).unwrap();
+        assert_eq!(next(&mut d).unwrap().0, 90);
+    }
+}
+
+/// Where to synchronize a decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyncTo {
+    /// The next synchronization point
+    Forward,
+    /// The previous synchronization point
+    Backward,
+    /// The PSB packet at the offset
+    Offset(u64),
+}
+
+// the libipt calls of the block and instruction flow decoders,
+// the bookkeeping the decoders share is done on top of them
+pub(crate) trait RawDecoder<'a> {
+    // a block or an instruction
+    type Item;
+
+    fn tracker(&self) -> &Tracker<'a>;
+    fn tracker_mut(&mut self) -> &mut Tracker<'a>;
+
+    // the decoder position and its last synchronization point,
+    // Nosync while the decoder is out of sync, see `Tracker::synced`
+    fn raw_offset(&self) -> Result<u64, PtError>;
+    fn raw_sync_offset(&self) -> Result<u64, PtError>;
+    // the decoder position within its trace buffer
+    fn raw_position(&self) -> Result<Progress, PtError>;
+    // the offset of the first or @last psb packet of the trace buffer
+    fn raw_psb(&self, last: bool) -> Result<u64, PtError>;
+
+    // decodes the next item, which is only valid on success
+    fn raw_next(&mut self) -> (Self::Item, Result<Status, PtError>);
+    // the number of instructions in @item
+    fn insns(item: &Self::Item) -> u64;
+    // decodes the next event, which is only valid on success
+    fn raw_event(&mut self) -> (Event, Result<Status, PtError>);
+    fn raw_sync(&mut self, to: SyncTo) -> Result<Status, PtError>;
+}
+
+// the state the decoders keep on top of libipt for checkpoints,
+// budgets, cancellation, progress reports and resetting in place
+pub(crate) struct Tracker<'a> {
+    // the sync offset of the last explicit synchronization
+    origin: Option<u64>,
+    // successful `next` and `event` calls since the last synchronization
+    steps: u64,
+    // successful `next` calls since the last synchronization
+    items: u64,
+    // the status returned by the last successful call
+    status: Status,
+    // did a `next` or `event` call fail since the last synchronization?
+    diverged: bool,
+    // reset in place and not synchronized since, see `reset`
+    rewound: bool,
+    // the checkpoints `step_back` replays from
+    pub(crate) marks: Marks,
+    // the work done under the current budget
+    meter: Option<Meter>,
+    // stops `next` and synchronizing once cancelled
+    cancel: Option<CancelToken>,
+    // reports the decoder position to the progress callback
+    progress: Option<Reporter<'a>>,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new() -> Self {
+        Tracker {
+            origin: None,
+            steps: 0,
+            items: 0,
+            status: Status::empty(),
+            diverged: false,
+            rewound: false,
+            marks: Marks::new(),
+            meter: None,
+            cancel: None,
+            progress: None,
+        }
+    }
+
+    pub(crate) fn status(&self) -> Status {
+        self.status
+    }
+
+    // fails with Nosync after resetting in place until synchronized again
+    pub(crate) fn synced(&self) -> Result<(), PtError> {
+        ensure_synced(self.rewound)
+    }
+
+    pub(crate) fn set_checkpoint_interval(&mut self, n: u64) {
+        self.marks.set_interval(n);
+    }
+
+    // stops recording checkpoints while @paused, the recorded ones are kept
+    pub(crate) fn pause_marks(&mut self, paused: bool) {
+        self.marks.pause(paused);
+    }
+
+    pub(crate) fn set_budget(&mut self, budget: Option<Budget>) {
+        self.meter = budget.map(Meter::new);
+    }
+
+    pub(crate) fn set_cancel(&mut self, cancel: Option<CancelToken>) {
+        self.cancel = cancel;
+    }
+
+    pub(crate) fn set_progress(&mut self, progress: Option<Reporter<'a>>) {
+        self.progress = progress;
+    }
+
+    // back to the state of a new decoder, @rewound if libipt's decoder
+    // was kept. the budget, cancel token and progress callback are kept
+    pub(crate) fn reset(&mut self, rewound: bool) {
+        self.rewound = rewound;
+        self.origin = None;
+        self.steps = 0;
+        self.items = 0;
+        self.status = Status::empty();
+        self.diverged = false;
+        self.marks.clear();
+        if let Some(m) = self.meter.as_mut() {
+            m.restart();
+        }
+        if let Some(p) = self.progress.as_mut() {
+            p.restart();
+        }
+    }
+}
+
+// attaches the current position of @d to an error
+pub(crate) fn locate<'a, D: RawDecoder<'a>, R>(d: &D, res: Result<R, PtError>) -> Result<R, DecodeError> {
+    res.map_err(|e| DecodeError::new(e, d.raw_offset().ok(), d.raw_sync_offset().ok()))
+}
+
+// the next item of @d, see `BlockDecoder::next`
+pub(crate) fn next<'a, D: RawDecoder<'a>>(d: &mut D) -> Result<(D::Item, Status), DecodeError> {
+    let start = d.tracker().synced().and_then(|_| metered_offset(d));
+    let start = locate(d, start)?;
+    let before = pre_mark(d, d.tracker().items + 1);
+    let (item, res) = d.raw_next();
+    let res = track_step(d, res);
+    let status = locate(d, res)?;
+
+    d.tracker_mut().items += 1;
+    mark(d, before, d.tracker().items);
+    charge(d, D::insns(&item), start);
+    report(d);
+    Ok((item, status))
+}
+
+// the next event of @d, see `BlockDecoder::event`
+pub(crate) fn event<'a, D: RawDecoder<'a>>(d: &mut D) -> Result<(Event, Status), DecodeError> {
+    locate(d, d.tracker().synced())?;
+    let (evt, res) = d.raw_event();
+    let res = track_step(d, res);
+    locate(d, res).map(|s| (evt, s))
+}
+
+// synchronizes @d, see `BlockDecoder::sync_forward`.
+// after resetting in place, libipt would continue from where it was,
+// so the first or last psb is searched in the trace buffer instead
+pub(crate) fn sync<'a, D: RawDecoder<'a>>(d: &mut D, to: SyncTo) -> Result<Status, PtError> {
+    let to = match to {
+        SyncTo::Forward | SyncTo::Backward if d.tracker().rewound => {
+            SyncTo::Offset(d.raw_psb(to == SyncTo::Backward)?)
+        }
+        to => to,
+    };
+    let start = metered_offset(d)?;
+    // only the bytes read from the PSB at @offset on are charged
+    let start = match to {
+        SyncTo::Offset(offset) => start.map(|_| offset),
+        _ => start,
+    };
+    let res = d.raw_sync(to);
+    if res.is_ok() {
+        charge(d, 0, start);
+    }
+    track_sync(d, res)
+}
+
+// a snapshot of the position of @d, see `BlockDecoder::checkpoint`
+pub(crate) fn checkpoint<'a, D: RawDecoder<'a>>(d: &D) -> Result<Checkpoint, PtError> {
+    let t = d.tracker();
+    // the decoder moves its sync offset along while decoding
+    // but replaying has to start where we synchronized
+    let sync = t.origin.ok_or(PtError::new(
+        PtErrorCode::Nosync,
+        "decoder is out of sync",
+    ))?;
+    if t.diverged {
+        return Err(PtError::new(
+            PtErrorCode::BadContext,
+            "decoder failed since the last synchronization",
+        ));
+    }
+    Ok(Checkpoint::new(sync, d.raw_offset()?, t.steps))
+}
+
+// restores @checkpoint on @d, see `BlockDecoder::restore`
+pub(crate) fn restore<'a, D: RawDecoder<'a>>(d: &mut D, checkpoint: &Checkpoint) -> Result<Status, PtError> {
+    // the checkpoints for `step_back` only hold for the same origin
+    if d.tracker().origin != Some(checkpoint.sync_offset()) {
+        d.tracker_mut().marks.clear();
+    }
+    let target = checkpoint.steps();
+    replay(d, checkpoint.sync_offset(), |t| t.steps >= target)?;
+
+    if d.raw_offset()? != checkpoint.offset() {
+        return Err(PtError::new(
+            PtErrorCode::BadContext,
+            "checkpoint does not match the trace",
+        ));
+    }
+    Ok(d.tracker().status)
+}
+
+// steps @d back by @n items, see `BlockDecoder::step_back`
+pub(crate) fn step_back<'a, D: RawDecoder<'a>>(d: &mut D, n: u64) -> Result<Status, PtError> {
+    let t = d.tracker();
+    let origin = t.origin.ok_or(PtError::new(
+        PtErrorCode::Nosync,
+        "decoder is out of sync",
+    ))?;
+    if n > t.items {
+        return Err(PtError::new(
+            PtErrorCode::Invalid,
+            "can't step back beyond the last synchronization point",
+        ));
+    }
+
+    let target = t.items - n;
+    let done = |t: &Tracker| t.items >= target;
+    if let Some(mark) = t.marks.nearest(target) {
+        // the synchronization point is the fallback if the checkpoint isn't reached
+        let res = replaying(d, |d| {
+            resume(d, origin, mark)?;
+            replay_until(d, done)
+        });
+        if res.is_ok() {
+            return res;
+        }
+    }
+    replay(d, origin, done)
+}
+
+// syncs at @origin and replays `next` and `event` calls until @done.
+fn replay<'a, D: RawDecoder<'a>>(
+    d: &mut D,
+    origin: u64,
+    done: impl Fn(&Tracker) -> bool,
+) -> Result<Status, PtError> {
+    replaying(d, |d| {
+        sync(d, SyncTo::Offset(origin))?;
+        replay_until(d, done)
+    })
+}
+
+// runs @f without counting against the budget or reporting progress.
+// the checkpoints survive the synchronization in @f.
+fn replaying<'a, D: RawDecoder<'a>>(
+    d: &mut D,
+    f: impl FnOnce(&mut D) -> Result<Status, PtError>,
+) -> Result<Status, PtError> {
+    let t = d.tracker_mut();
+    let meter = t.meter.take();
+    let progress = t.progress.take();
+    let marks = t.marks.take();
+    let res = f(d);
+    let t = d.tracker_mut();
+    t.meter = meter;
+    t.progress = progress;
+    t.marks.restore(marks);
+    res
+}
+
+fn replay_until<'a, D: RawDecoder<'a>>(
+    d: &mut D,
+    done: impl Fn(&Tracker) -> bool,
+) -> Result<Status, PtError> {
+    while !done(d.tracker()) {
+        if d.tracker().status.event_pending() {
+            event(d)?;
+        } else {
+            next(d)?;
+        }
+    }
+    Ok(d.tracker().status)
+}
+
+// syncs at the synchronization point of @mark and replays up to the `next`
+// call that reached it, the decoder is then where it was at @mark.
+fn resume<'a, D: RawDecoder<'a>>(d: &mut D, origin: u64, mark: Mark) -> Result<Status, PtError> {
+    sync(d, SyncTo::Offset(mark.sync_offset))?;
+    loop {
+        let before = d.raw_offset()?;
+        let item = !d.tracker().status.event_pending();
+        if item {
+            next(d)?;
+        } else {
+            event(d)?;
+        }
+        let offset = d.raw_offset()?;
+        if item && before < mark.offset && offset == mark.offset {
+            break;
+        }
+        if offset >= mark.offset {
+            return Err(PtError::new(
+                PtErrorCode::BadContext,
+                "checkpoint does not match the trace",
+            ));
+        }
+    }
+
+    let t = d.tracker_mut();
+    t.origin = Some(origin);
+    t.steps = mark.steps;
+    t.items = mark.items;
+    Ok(t.status)
+}
+
+// the offset before a `next` call that might record a checkpoint at @items
+fn pre_mark<'a, D: RawDecoder<'a>>(d: &D, items: u64) -> Option<u64> {
+    let t = d.tracker();
+    match t.origin {
+        Some(_) if t.marks.due(items) => d.raw_offset().ok(),
+        _ => None,
+    }
+}
+
+// records a checkpoint if the last `next` call moved on from @before.
+// the call must not have passed the synchronization point itself,
+// the decoder reads ahead of it right after synchronizing.
+fn mark<'a, D: RawDecoder<'a>>(d: &mut D, before: Option<u64>, items: u64) {
+    let (Some(origin), Some(before)) = (d.tracker().origin, before) else {
+        return;
+    };
+    if let (Ok(offset), Ok(sync_offset)) = (d.raw_offset(), d.raw_sync_offset()) {
+        if offset > before && before > sync_offset {
+            let t = d.tracker_mut();
+            let mark = Mark {
+                sync_offset,
+                offset,
+                steps: t.steps,
+                items,
+            };
+            t.marks.record(origin, mark);
+        }
+    }
+}
+
+// checks for cancellation and the budget,
+// returns the offset to charge consumed bytes from
+fn metered_offset<'a, D: RawDecoder<'a>>(d: &D) -> Result<Option<u64>, PtError> {
+    let t = d.tracker();
+    if let Some(c) = &t.cancel {
+        c.check()?;
+    }
+    match &t.meter {
+        Some(m) => m.check().map(|_| Some(d.raw_offset().unwrap_or(0))),
+        None => Ok(None),
+    }
+}
+
+fn charge<'a, D: RawDecoder<'a>>(d: &mut D, insns: u64, start: Option<u64>) {
+    if let Some(start) = start {
+        // backward synchronization moves the decoder back
+        let bytes = d.raw_offset().unwrap_or(start).abs_diff(start);
+        if let Some(m) = d.tracker_mut().meter.as_mut() {
+            m.charge(insns, bytes);
+        }
+    }
+}
+
+fn track_step<'a, D: RawDecoder<'a>>(d: &mut D, res: Result<Status, PtError>) -> Result<Status, PtError> {
+    match res {
+        Ok(s) => {
+            let t = d.tracker_mut();
+            t.steps += 1;
+            t.status = s;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(offset = ?d.raw_offset().ok(), status = ?s, "step");
+        }
+        // running out of trace does not change the decoder state
+        Err(e) if e.is_eos() => (),
+        Err(_e) => {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(offset = ?d.raw_offset().ok(), error = %_e, "decoder diverged");
+            d.tracker_mut().diverged = true;
+        }
+    }
+    res
+}
+
+// passes the current position to the progress callback, if there is one
+fn report<'a, D: RawDecoder<'a>>(d: &mut D) {
+    if d.tracker().progress.is_none() {
+        return;
+    }
+    if let Ok(pos) = d.raw_position() {
+        if let Some(p) = d.tracker_mut().progress.as_mut() {
+            p.update(pos.offset, pos.size);
+        }
+    }
+}
+
+fn track_sync<'a, D: RawDecoder<'a>>(d: &mut D, res: Result<Status, PtError>) -> Result<Status, PtError> {
+    if let Ok(s) = res {
+        let origin = d.raw_sync_offset().ok();
+        let t = d.tracker_mut();
+        t.rewound = false;
+        t.origin = origin;
+        t.steps = 0;
+        t.items = 0;
+        t.status = s;
+        t.diverged = false;
+        t.marks.clear();
+        report(d);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(origin = ?origin, "synchronized");
+    }
+    res
+}
@@ -1,4 +1,5 @@
-use std::ffi::CStr;
+use core::ffi::CStr;
+use core::fmt;
 use libipt_sys::{
     pt_version,
     pt_library_version
@@ -13,6 +14,24 @@ mod test {
         let v = Version::version();
         assert_ne!(v.major(), 0);
     }
+
+    #[test]
+    fn test_version_capabilities() {
+        let v = Version(pt_version {
+            major: 2,
+            minor: 0,
+            patch: 5,
+            build: 12,
+            ext: c"-dirty".as_ptr()
+        });
+        assert_eq!(v.to_string(), "2.0.5-12-dirty");
+        assert!(v.at_least(1, 6));
+        assert!(!v.at_least(2, 1));
+
+        let caps = v.capabilities();
+        assert!(caps.ptwrite && caps.power && caps.decoder_events);
+        assert!(!caps.event_trace);
+    }
 }
 
 /// The library version.
@@ -43,4 +62,52 @@ impl Version {
                     "this is either a bug in libipt or the bindings")
         )
     }
-}
\ No newline at end of file
+
+    /// Returns true if the version is @major.@minor or newer.
+    pub fn at_least(&self, major: u8, minor: u8) -> bool {
+        (self.0.major, self.0.minor) >= (major, minor)
+    }
+
+    /// What this version of libipt can decode.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            ptwrite: self.at_least(1, 6),
+            power: self.at_least(1, 6),
+            decoder_events: self.at_least(2, 0),
+            event_trace: self.at_least(2, 1),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    // the format of ptdump --version
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}-{}{}",
+               self.major(), self.minor(), self.patch(), self.build(), self.ext())
+    }
+}
+
+/// The trace features a libipt version understands
+///
+/// Traces of newer processors may contain packets an older libipt
+/// doesn't know, decoding them fails with BadOpc.
+/// See `Version::capabilities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// PTWRITE packets and events
+    pub ptwrite: bool,
+    /// Power event tracing: MWAIT, PWRE, PWRX and EXSTOP packets and events
+    pub power: bool,
+    /// Events of the block and instruction flow decoders,
+    /// including TICK, CBR and MNT events
+    pub decoder_events: bool,
+    /// Event tracing: CFE and EVD packets
+    pub event_trace: bool,
+}
+
+impl Capabilities {
+    /// What the linked libipt can decode.
+    pub fn detect() -> Self {
+        Version::version().capabilities()
+    }
+}
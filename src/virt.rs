@@ -0,0 +1,247 @@
+use crate::block::{Block, BlockDecoder};
+use crate::error::PtError;
+use crate::event::{Event, Payload};
+use crate::flags::Status;
+use crate::image::Image;
+use crate::time::TimeInfo;
+
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::format;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libipt_sys::{
+        pt_event, pt_event__bindgen_ty_1__bindgen_ty_10, pt_event__bindgen_ty_1__bindgen_ty_5,
+        pt_event_type_ptev_paging, pt_event_type_ptev_vmcs,
+    };
+    use core::mem;
+
+    fn paging(non_root: bool) -> Payload {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_paging;
+        evt.variant.paging = pt_event__bindgen_ty_1__bindgen_ty_5 {
+            cr3: 0x1000,
+            _bitfield_1: pt_event__bindgen_ty_1__bindgen_ty_5::new_bitfield_1(non_root as u32),
+            __bindgen_padding_0: Default::default(),
+        };
+        evt.into()
+    }
+
+    fn vmcs(base: u64) -> Payload {
+        let mut evt: pt_event = unsafe { mem::zeroed() };
+        evt.type_ = pt_event_type_ptev_vmcs;
+        evt.variant.vmcs = pt_event__bindgen_ty_1__bindgen_ty_10 { base };
+        evt.into()
+    }
+
+    #[test]
+    fn test_virt_origin_tracking() {
+        let mut st = VirtState::default();
+        assert_eq!(st.origin(), Origin::Host);
+
+        // loading a vmcs alone doesn't enter the guest
+        assert!(!st.update(&vmcs(0x5000)));
+        assert_eq!(st.origin(), Origin::Host);
+
+        assert!(st.update(&paging(true)));
+        assert_eq!(st.origin(), Origin::Guest(0x5000));
+        assert!(!st.update(&paging(true)));
+
+        assert!(st.update(&vmcs(0x6000)));
+        assert_eq!(st.origin(), Origin::Guest(0x6000));
+
+        assert!(st.update(&paging(false)));
+        assert_eq!(st.origin(), Origin::Host);
+    }
+
+    #[test]
+    fn test_virt_unknown_guest() {
+        let mut st = VirtState::default();
+        assert!(st.update(&paging(true)));
+        assert_eq!(st.origin(), Origin::Guest(0));
+    }
+}
+
+/// Where a block of instructions was executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Origin {
+    /// In VMX root operation, i.e. by the host or hypervisor
+    Host,
+    /// In VMX non-root operation, by the guest with the given VMCS base.
+    ///
+    /// The base is zero if the trace did not tell which VMCS is active.
+    Guest(u64),
+}
+
+// the virtualization state as far as the trace told us
+#[derive(Debug, Default, Clone, Copy)]
+struct VirtState {
+    vmcs: Option<u64>,
+    non_root: bool,
+}
+
+impl VirtState {
+    fn origin(&self) -> Origin {
+        if self.non_root {
+            Origin::Guest(self.vmcs.unwrap_or(0))
+        } else {
+            Origin::Host
+        }
+    }
+
+    // applies @payload, returns true if the origin changed
+    fn update(&mut self, payload: &Payload) -> bool {
+        let before = self.origin();
+        match payload {
+            Payload::Vmcs(v) => self.vmcs = Some(v.base()),
+            Payload::AsyncVmcs(v) => self.vmcs = Some(v.base()),
+            Payload::Paging(p) => self.non_root = p.non_root(),
+            Payload::AsyncPaging(p) => self.non_root = p.non_root(),
+            _ => (),
+        }
+        self.origin() != before
+    }
+}
+
+// the image of guest @vmcs, allocated on first use
+fn guest<'m>(
+    guests: &'m mut BTreeMap<u64, Image<'static>>,
+    vmcs: u64,
+) -> Result<&'m mut Image<'static>, PtError> {
+    match guests.entry(vmcs) {
+        Entry::Occupied(e) => Ok(e.into_mut()),
+        Entry::Vacant(e) => Ok(e.insert(Image::new(Some(&format!("guest {:#x}", vmcs)))?)),
+    }
+}
+
+/// A block decoder that separates host and guest execution.
+///
+/// The decoder follows VMCS and paging events in the trace to know
+/// whether it is in VMX root or non-root operation and which VMCS is active.
+/// It reads memory from the host image or from the image of the active guest
+/// and tags every block with its origin.
+///
+/// Events have to be read with `VirtDecoder::event` so the decoder sees them.
+/// Enable tracing of VMX non-root operation and PIP packets
+/// when collecting the trace, otherwise everything looks like host execution.
+pub struct VirtDecoder<'a, T> {
+    // has to be dropped before the images it reads from
+    decoder: BlockDecoder<'a, T>,
+    host: Image<'static>,
+    guests: BTreeMap<u64, Image<'static>>,
+    state: VirtState,
+}
+
+impl<'a, T> VirtDecoder<'a, T> {
+    /// Wraps @decoder, which must not be synchronized yet.
+    ///
+    /// Returns an error if the host image can't be allocated.
+    pub fn new(mut decoder: BlockDecoder<'a, T>) -> Result<Self, PtError> {
+        let host = Image::new(Some("host"))?;
+        // SAFETY: the images are owned by the virt decoder
+        // and dropped after the decoder
+        unsafe { decoder.set_image_unchecked(Some(host.inner))? };
+        Ok(VirtDecoder {
+            decoder,
+            host,
+            guests: BTreeMap::new(),
+            state: VirtState::default(),
+        })
+    }
+
+    /// Call @f with the image used for host execution.
+    ///
+    /// The image may be replaced, the decoder uses it from then on.
+    /// Returns the result of @f, or an error if the decoder can't use the image.
+    pub fn with_host_image<R>(&mut self, f: impl FnOnce(&mut Image<'static>) -> R) -> Result<R, PtError> {
+        self.detach()?;
+        let res = f(&mut self.host);
+        self.switch_image()?;
+        Ok(res)
+    }
+
+    /// Call @f with the image used for the guest with VMCS base @vmcs.
+    ///
+    /// Use a @vmcs of zero for guest execution before the trace
+    /// told which VMCS is active.
+    /// The image is created on first use.
+    /// See `with_host_image`.
+    pub fn with_guest_image<R>(
+        &mut self,
+        vmcs: u64,
+        f: impl FnOnce(&mut Image<'static>) -> R,
+    ) -> Result<R, PtError> {
+        self.detach()?;
+        let res = guest(&mut self.guests, vmcs).map(f);
+        self.switch_image()?;
+        res
+    }
+
+    /// Where the decoder currently is.
+    pub fn origin(&self) -> Origin {
+        self.state.origin()
+    }
+
+    /// The wrapped block decoder.
+    ///
+    /// The decoder uses the images of the virt decoder,
+    /// configure it before wrapping it.
+    pub fn decoder(&self) -> &BlockDecoder<'a, T> {
+        &self.decoder
+    }
+
+    /// The time at the last timing packet, see `BlockDecoder::time`.
+    pub fn time(&mut self) -> Result<TimeInfo, PtError> {
+        self.decoder.time()
+    }
+
+    /// The current core:bus ratio, see `BlockDecoder::core_bus_ratio`.
+    pub fn core_bus_ratio(&mut self) -> Result<u32, PtError> {
+        self.decoder.core_bus_ratio()
+    }
+
+    // points the decoder at its own default image,
+    // so it doesn't refer to an image that is about to be replaced
+    fn detach(&mut self) -> Result<(), PtError> {
+        // SAFETY: the default image lives as long as the decoder
+        unsafe { self.decoder.set_image_unchecked(None) }
+    }
+
+    // points the decoder at the image of the current origin
+    fn switch_image(&mut self) -> Result<(), PtError> {
+        let image = match self.state.origin() {
+            Origin::Host => self.host.inner,
+            Origin::Guest(vmcs) => guest(&mut self.guests, vmcs)?.inner,
+        };
+        // SAFETY: see `new`
+        unsafe { self.decoder.set_image_unchecked(Some(image)) }
+    }
+
+    /// Synchronize the decoder.
+    ///
+    /// The virtualization state is reset to host execution
+    /// until the trace says otherwise.
+    pub fn sync_forward(&mut self) -> Result<Status, PtError> {
+        let status = self.decoder.sync_forward()?;
+        self.state = VirtState::default();
+        self.switch_image()?;
+        Ok(status)
+    }
+
+    /// Get the next pending event and switch images if it changes the origin.
+    pub fn event(&mut self) -> Result<(Event, Status), PtError> {
+        let (evt, status) = self.decoder.event()?;
+        if self.state.update(&evt.payload()) {
+            self.switch_image()?;
+        }
+        Ok((evt, status))
+    }
+
+    /// Determine the next block of instructions and where it was executed.
+    pub fn next(&mut self) -> Result<(Block, Status, Origin), PtError> {
+        let origin = self.state.origin();
+        self.decoder.next().map(|(b, s)| (b, s, origin)).map_err(PtError::from)
+    }
+}
@@ -0,0 +1,205 @@
+use crate::config::{Config, ConfigBuilder, Frequency};
+use crate::error::{PtError, PtErrorCode};
+use crate::ring;
+
+use core::convert::TryInto;
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ring::PSB;
+
+    fn header(tid: u64, ring_offset: u32, trace: &[u8]) -> Vec<u8> {
+        let mut h = Vec::new();
+        h.extend_from_slice(&tid.to_le_bytes());
+        h.extend_from_slice(&1u32.to_le_bytes());
+        h.extend_from_slice(&3u32.to_le_bytes());
+        h.extend_from_slice(&10u32.to_le_bytes());
+        h.extend_from_slice(&ring_offset.to_le_bytes());
+        h.extend_from_slice(&(trace.len() as u32).to_le_bytes());
+        h.extend_from_slice(trace);
+        h
+    }
+
+    fn trace_data(headers: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = headers.concat();
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&1u16.to_le_bytes());
+        raw.extend_from_slice(&1u16.to_le_bytes());
+        raw.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        raw.extend_from_slice(&body);
+        raw
+    }
+
+    #[test]
+    fn test_winipt_threads() {
+        // a ring that wrapped, the write position is at 4
+        let mut ring = vec![0xaa; 4];
+        ring.extend_from_slice(&PSB);
+        ring.extend_from_slice(&[0xbb; 4]);
+        let raw = trace_data(&[header(7, 4, &ring), header(9, 0, &PSB)]);
+
+        let traces = parse(&raw).unwrap();
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[0].thread_id(), 7);
+        assert_eq!(traces[0].timing(), TimingSettings::Mtc);
+        assert_eq!(traces[0].mtc_frequency(), 3);
+        assert_eq!(&traces[0].data()[..16], &PSB);
+        assert_eq!(&traces[0].data()[16..], &[0xbb, 0xbb, 0xbb, 0xbb, 0xaa, 0xaa, 0xaa, 0xaa]);
+        assert_eq!(traces[1].thread_id(), 9);
+    }
+
+    #[test]
+    fn test_winipt_bad_data() {
+        assert_eq!(parse(&[0; 3]).unwrap_err().code(), PtErrorCode::BadFile);
+
+        let mut raw = trace_data(&[header(7, 0, &PSB)]);
+        raw[2] = 0;
+        assert_eq!(parse(&raw).unwrap_err().code(), PtErrorCode::Nosync);
+
+        let mut raw = trace_data(&[header(7, 0, &PSB)]);
+        raw.truncate(raw.len() - 1);
+        assert_eq!(parse(&raw).unwrap_err().code(), PtErrorCode::BadFile);
+    }
+}
+
+// size of IPT_TRACE_HEADER without the trace
+const HEADER_SIZE: usize = 28;
+
+/// Which timing packets the trace was configured with (IPT_TIMING_SETTINGS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingSettings {
+    None,
+    Mtc,
+    Cyc,
+    Unknown(u32),
+}
+
+impl From<u32> for TimingSettings {
+    fn from(v: u32) -> Self {
+        match v {
+            0 => TimingSettings::None,
+            1 => TimingSettings::Mtc,
+            2 => TimingSettings::Cyc,
+            v => TimingSettings::Unknown(v),
+        }
+    }
+}
+
+/// The trace of a single thread captured by the Windows ipt.sys driver
+#[derive(Debug, Clone)]
+pub struct ThreadTrace {
+    thread_id: u64,
+    timing: TimingSettings,
+    mtc_frequency: u32,
+    tsc_ratio: u32,
+    data: Vec<u8>,
+}
+
+impl ThreadTrace {
+    /// The id of the traced thread
+    #[inline]
+    pub fn thread_id(&self) -> u64 {
+        self.thread_id
+    }
+
+    /// The timing packets the trace was configured with
+    #[inline]
+    pub fn timing(&self) -> TimingSettings {
+        self.timing
+    }
+
+    /// The MTC frequency as defined in IA32_RTIT_CTL.MTCFreq
+    #[inline]
+    pub fn mtc_frequency(&self) -> u32 {
+        self.mtc_frequency
+    }
+
+    /// The ratio of the TSC to the core crystal clock frequency
+    #[inline]
+    pub fn tsc_ratio(&self) -> u32 {
+        self.tsc_ratio
+    }
+
+    /// The raw Intel PT trace, beginning at its first synchronization point
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Creates a decoder configuration for the trace.
+    ///
+    /// The timing frequencies are set from the trace header.
+    /// Returns Invalid if the trace is empty.
+    pub fn config(&mut self) -> Result<Config<'_, ()>, PtError> {
+        let freq = Frequency::new(self.mtc_frequency as u8, 0, self.tsc_ratio, 1);
+        Ok(ConfigBuilder::new(&mut self.data)?.freq(freq).finish())
+    }
+}
+
+#[inline]
+fn read_u32(raw: &[u8], off: usize) -> Result<u32, PtError> {
+    raw.get(off..off + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(PtError::new(PtErrorCode::BadFile, "ipt trace data is truncated"))
+}
+
+/// Parses the IPT_TRACE_DATA returned by the ipt.sys driver,
+/// e.g. by GetProcessIptTrace or written by ipttool.
+///
+/// The ring buffers of every thread are unrolled and stripped of their headers.
+/// Multiple buffers of the same thread are concatenated.
+/// Returns the traces in the order their threads first appear.
+/// Returns BadFile if @raw is truncated.
+/// Returns Nosync if the driver marked the trace as invalid.
+pub fn parse(raw: &[u8]) -> Result<Vec<ThreadTrace>, PtError> {
+    let valid = raw
+        .get(2..4)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(PtError::new(PtErrorCode::BadFile, "ipt trace data is truncated"))?;
+    if valid == 0 {
+        return Err(PtError::new(PtErrorCode::Nosync, "ipt trace is not valid"));
+    }
+    let size = read_u32(raw, 4)? as usize;
+    let body = raw
+        .get(8..8 + size)
+        .ok_or(PtError::new(PtErrorCode::BadFile, "ipt trace data is truncated"))?;
+
+    let mut traces: Vec<ThreadTrace> = Vec::new();
+    let mut off = 0;
+    while off < body.len() {
+        let tid = read_u32(body, off)? as u64 | (read_u32(body, off + 4)? as u64) << 32;
+        let timing = read_u32(body, off + 8)?.into();
+        let mtc_frequency = read_u32(body, off + 12)?;
+        let tsc_ratio = read_u32(body, off + 16)?;
+        let ring_offset = read_u32(body, off + 20)? as u64;
+        let trace_size = read_u32(body, off + 24)? as usize;
+        let start = off + HEADER_SIZE;
+        let trace = body
+            .get(start..start + trace_size)
+            .ok_or(PtError::new(PtErrorCode::BadFile, "ipt trace data is truncated"))?;
+        off = start + trace_size;
+
+        // the driver writes the ring at @ring_offset, the oldest data follows it
+        let data = match ring::linearize(trace, trace_size as u64 + ring_offset, 0) {
+            Ok(data) => data,
+            Err(e) if e.code() == PtErrorCode::Nosync => Vec::new(),
+            Err(e) if trace.is_empty() && e.code() == PtErrorCode::Invalid => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        match traces.iter_mut().find(|t| t.thread_id == tid) {
+            Some(t) => t.data.extend_from_slice(&data),
+            None => traces.push(ThreadTrace {
+                thread_id: tid,
+                timing,
+                mtc_frequency,
+                tsc_ratio,
+                data,
+            }),
+        }
+    }
+
+    Ok(traces)
+}